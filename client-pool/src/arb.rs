@@ -1,5 +1,5 @@
 use anchor_client::solana_client::rpc_client::RpcClient;
-use anchor_client::solana_client::rpc_config::RpcSendTransactionConfig;
+use anchor_client::solana_client::rpc_request::RpcRequest;
 
 use anchor_client::solana_sdk::pubkey::Pubkey;
 
@@ -7,22 +7,43 @@ use anchor_client::solana_sdk::signature::{Keypair, Signer};
 use anchor_client::{Cluster, Program};
 use std::collections::{HashMap, HashSet};
 
+use serde_json::{json, Value};
+use solana_sdk::hash::Hash;
 use solana_sdk::instruction::Instruction;
-use solana_sdk::transaction::Transaction;
 
 use std::borrow::Borrow;
-use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use std::vec;
 
-use log::info;
+use log::{debug, info, warn};
+use rayon::prelude::*;
 
 use tmp::accounts as tmp_accounts;
 use tmp::instruction as tmp_ix;
 
-use crate::pool::PoolOperations;
-
+use crate::bundle::independent_arbitrages;
+use crate::compute_budget::{
+    compute_unit_limit, with_compute_budget, ComputeBudgetConfig, SimulationResult,
+    TransactionFee, MAX_COMPUTE_UNIT_LIMIT,
+};
+use crate::constants::NATIVE_MINT;
+use crate::cycles::{
+    build_edges, enumerate_cycles, find_cycles_touching, par_find_cycles, Cycle, CycleIndex,
+    MAX_CYCLES_PER_START,
+};
+use crate::error::SimulationError;
+use crate::lookup_table::{select_lookup_tables, LookupTable};
+use crate::priority_fee::{writable_accounts, PriorityFeeEstimator};
+use crate::sizing::{optimal_amount_in, SizeBounds};
 use crate::utils::{derive_token_address, PoolGraph, PoolIndex, PoolQuote};
+use crate::versioned::{V0Message, VersionedTransaction, PACKET_DATA_SIZE};
+
+const LAMPORTS_PER_SOL: u128 = 1_000_000_000;
+/// how long a sent cycle isn't re-sent -- a few slots for its tx to land or
+/// fail, after which the cycle is fair game again
+pub const SENT_ARB_TTL: Duration = Duration::from_secs(2);
 
 pub struct Arbitrager {
     pub token_mints: Vec<Pubkey>,
@@ -30,101 +51,207 @@ pub struct Arbitrager {
     pub graph: PoolGraph,
     pub cluster: Cluster,
     // vv -- need to clone these explicitly -- vv
-    pub owner: Rc<Keypair>,
+    pub owner: Arc<Keypair>,
     pub program: Program,
     pub connection: RpcClient,
+    pub max_hops: usize,         // swaps per tx -- bounded by the max tx size
+    pub cycle_index: CycleIndex, // candidate cycles by pool -- see `index_cycles`
+    pub lookup_tables: Vec<LookupTable>, // accounts txs load by index
+    pub compute_budget: ComputeBudgetConfig, // how routes size + price their compute
+    pub priority_fees: Arc<RwLock<PriorityFeeEstimator>>, // recent fees of the pool accounts
+}
+
+/// A cycle which was re-quoted with exact integer amounts and is profitable
+#[derive(Debug, Clone)]
+pub struct Arbitrage {
+    pub cycle: Cycle,
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub profit: i128, // net of fees, in the start mint
+}
+
+impl Arbitrage {
+    /// profit per unit of input (profits of different start mints aren't comparable)
+    pub fn return_on_input(&self) -> f64 {
+        self.profit as f64 / self.amount_in as f64
+    }
+}
+
+/// most profitable (by return on input) first, ties broken by cycle key
+pub fn rank_arbitrages(arbs: &mut [Arbitrage]) {
+    arbs.sort_by(|a, b| {
+        b.return_on_input()
+            .total_cmp(&a.return_on_input())
+            .then_with(|| a.cycle.key().cmp(&b.cycle.key()))
+    });
 }
 
 impl Arbitrager {
-    pub fn brute_force_search(
-        &self,
-        start_mint_idx: usize,
-        init_balance: u128,
-        curr_balance: u128,
-        path: Vec<usize>,
-        pool_path: Vec<PoolQuote>,
-        sent_arbs: &mut HashSet<String>,
-    ) {
-        let src_curr = path[path.len() - 1]; // last mint
-        let src_mint = self.token_mints[src_curr];
+    /// every negative cycle of the graph through any mint, most profitable
+    /// first -- each start mint is searched on its own rayon task
+    pub fn find_cycles(&self) -> Vec<Cycle> {
+        let edges = build_edges(&self.graph, &self.token_mints);
+        let starts: Vec<usize> = (0..self.token_mints.len()).collect();
+        par_find_cycles(self.token_mints.len(), &edges, &starts, self.max_hops, None)
+    }
 
-        let out_edges = &self.graph_edges[src_curr];
+    /// negative cycles which swap through any of `pool_ids`, most profitable first
+    pub fn find_cycles_touching(&self, pool_ids: &HashSet<usize>) -> Vec<Cycle> {
+        let edges = build_edges(&self.graph, &self.token_mints);
+        find_cycles_touching(self.token_mints.len(), &edges, pool_ids, self.max_hops)
+    }
 
-        // path = 4 = A -> B -> C -> D
-        // path >= 5 == not valid bc max tx size is swaps
-        if path.len() == 4 {
-            return;
-        };
+    /// index the cycles (profitable or not) of up to `max_hops` swaps through
+    /// `starts` (the mints we can fund) by the pools they use -- at most
+    /// `MAX_CYCLES_PER_START` per start. Returns how many new cycles were
+    /// indexed
+    pub fn index_cycles(&mut self, starts: &[usize]) -> usize {
+        let edges = build_edges(&self.graph, &self.token_mints);
+        let cycles = enumerate_cycles(
+            self.token_mints.len(),
+            &edges,
+            starts,
+            self.max_hops,
+            MAX_CYCLES_PER_START,
+        );
+        self.cycle_index.extend(cycles)
+    }
 
-        for dst_mint_idx in out_edges {
-            let pools = self
-                .graph
-                .0
-                .get(&PoolIndex(src_curr))
-                .unwrap()
-                .0
-                .get(&PoolIndex(*dst_mint_idx))
-                .unwrap();
+    /// ranked arbitrages among the indexed cycles through `pool_ids` -- only
+    /// cycles which are still profitable at the margin are sized
+    pub fn opportunities(
+        &self,
+        pool_ids: &HashSet<usize>,
+        bounds: &HashMap<usize, SizeBounds>,
+        fee_percentage: f64,
+    ) -> Vec<Arbitrage> {
+        let token_mints = &self.token_mints;
+        let candidates: Vec<Cycle> = self
+            .cycle_index
+            .touching(pool_ids)
+            .into_par_iter()
+            .filter(|cycle| cycle.marginal_log_weight(token_mints) < 0.0)
+            .collect();
+        debug!(
+            "{} / {} indexed cycles through {} updated pools are candidates",
+            candidates.len(),
+            self.cycle_index.len(),
+            pool_ids.len()
+        );
 
-            if path.contains(dst_mint_idx) && *dst_mint_idx != start_mint_idx {
-                continue;
-            }
+        let mut arbs = self.verify_cycles(&candidates, bounds, fee_percentage);
+        rank_arbitrages(&mut arbs);
+        arbs
+    }
 
-            let dst_mint_idx = *dst_mint_idx;
-            let dst_mint = self.token_mints[dst_mint_idx];
-
-            for pool in pools {
-                let new_balance =
-                    pool.0
-                        .get_quote_with_amounts_scaled(curr_balance, &src_mint, &dst_mint);
-
-                let mut new_path = path.clone();
-                new_path.push(dst_mint_idx);
-
-                let mut new_pool_path = pool_path.clone();
-                new_pool_path.push(pool.clone()); // clone the pointer
-
-                if dst_mint_idx == start_mint_idx {
-                    // info!("{:?} -> {:?} (-{:?})", init_balance, new_balance, init_balance - new_balance);
-
-                    // if new_balance > init_balance - 1086310399 {
-                    if new_balance > init_balance {
-                        // ... profitable arb!
-                        info!("found arbitrage: {:?} -> {:?}", init_balance, new_balance);
-
-                        // check if arb was sent with a larger size
-                        // key = {mint_path}{pool_names}
-                        let mint_keys: Vec<String> =
-                            new_path.clone().iter_mut().map(|i| i.to_string()).collect();
-                        let pool_keys: Vec<String> =
-                            new_pool_path.iter().map(|p| p.0.get_name()).collect();
-                        let arb_key = format!("{}{}", mint_keys.join(""), pool_keys.join(""));
-                        if sent_arbs.contains(&arb_key) {
-                            info!("arb already sent...");
-                            continue; // dont re-send an already sent arb -- bad for network
-                        } else {
-                            sent_arbs.insert(arb_key);
+    /// re-quote each candidate with the exact pool math and size it, starting
+    /// from a mint we hold (`bounds` = mint idx -> input range in scaled
+    /// amounts, capped by the wallet balance). The cycle's log weight only uses
+    /// marginal prices so price impact + rounding can still make it
+    /// unprofitable at any size. Cycles are verified in parallel, the output
+    /// keeps the order of `cycles`.
+    pub fn verify_cycles(
+        &self,
+        cycles: &[Cycle],
+        bounds: &HashMap<usize, SizeBounds>,
+        fee_percentage: f64,
+    ) -> Vec<Arbitrage> {
+        let token_mints = &self.token_mints;
+        cycles
+            .par_iter()
+            .filter_map(|cycle| {
+                let mut best: Option<(Arbitrage, i128)> = None;
+                for mint_idx in &cycle.mint_idxs[..cycle.n_hops()] {
+                    let mint_bounds = match bounds.get(mint_idx) {
+                        Some(b) => b,
+                        None => continue,
+                    };
+                    let cycle = cycle.rotate_to(*mint_idx).unwrap();
+                    let sizing =
+                        match optimal_amount_in(&cycle, token_mints, mint_bounds, fee_percentage) {
+                            Some(sizing) => sizing,
+                            None => continue,
+                        };
+
+                    // compare rotations by return on input (profits are in different mints)
+                    let is_better = match &best {
+                        Some((b, b_profit)) => {
+                            sizing.profit * b.amount_in as i128
+                                > *b_profit * sizing.amount_in as i128
                         }
-
-                        let ixs = self.get_arbitrage_instructions(
-                            init_balance,
-                            &new_path,
-                            &new_pool_path,
-                        );
-                        self.send_ixs(ixs);
+                        None => true,
+                    };
+                    if is_better {
+                        let arb = Arbitrage {
+                            cycle,
+                            amount_in: sizing.amount_in,
+                            amount_out: sizing.amount_out,
+                            profit: sizing.profit,
+                        };
+                        best = Some((arb, sizing.profit));
                     }
-                } else if !path.contains(&dst_mint_idx) {
-                    // ... search deeper
-                    self.brute_force_search(
-                        start_mint_idx,
-                        init_balance,
-                        new_balance,   // !
-                        new_path,      // !
-                        new_pool_path, // !
-                        sent_arbs,
-                    );
                 }
+                best.map(|(arb, _)| arb)
+            })
+            .collect()
+    }
+
+    /// find, verify and send every profitable cycle we can fund
+    pub fn search(
+        &self,
+        bounds: &HashMap<usize, SizeBounds>,
+        fee_percentage: f64,
+        sent_arbs: &mut HashMap<String, Instant>,
+    ) {
+        let cycles = self.find_cycles();
+        info!("found {} candidate cycles", cycles.len());
+        let mut arbs = self.verify_cycles(&cycles, bounds, fee_percentage);
+        rank_arbitrages(&mut arbs);
+        self.send_arbitrages(independent_arbitrages(arbs), sent_arbs);
+    }
+
+    /// re-quote only the indexed cycles through pools which were updated and
+    /// send the profitable ones, best first
+    pub fn search_updated(
+        &self,
+        pool_ids: &HashSet<usize>,
+        bounds: &HashMap<usize, SizeBounds>,
+        fee_percentage: f64,
+        sent_arbs: &mut HashMap<String, Instant>,
+    ) {
+        let arbs = self.opportunities(pool_ids, bounds, fee_percentage);
+        // each is sent as its own tx, in no guaranteed order -- one sharing a
+        // pool with a better one was sized on reserves that tx moves
+        self.send_arbitrages(independent_arbitrages(arbs), sent_arbs);
+    }
+
+    fn send_arbitrages(&self, arbs: Vec<Arbitrage>, sent_arbs: &mut HashMap<String, Instant>) {
+        sent_arbs.retain(|_, sent| sent.elapsed() < SENT_ARB_TTL);
+        for arb in arbs {
+            // ... profitable arb!
+            info!(
+                "found arbitrage: {:?} -> {:?} ({:?})",
+                arb.amount_in, arb.amount_out, arb.cycle.mint_idxs
+            );
+
+            // dont re-send an arb still in flight -- bad for network
+            let arb_key = arb.cycle.key();
+            if sent_arbs.contains_key(&arb_key) {
+                info!("arb already sent...");
+                continue;
             }
+
+            let ixs = self.get_arbitrage_instructions(
+                arb.amount_in,
+                &arb.cycle.mint_idxs,
+                &arb.cycle.pools,
+            );
+            let ixs = match self.budget_instructions(&arb, &ixs) {
+                Some(ixs) => ixs,
+                None => continue,
+            };
+            sent_arbs.insert(arb_key, Instant::now());
+            self.send_ixs(ixs);
         }
     }
 
@@ -163,7 +290,7 @@ impl Arbitrager {
             let pool = &pools[i];
 
             let swap_ix = pool
-                .0
+                .read()
                 .swap_ix(&self.program, &self.owner.pubkey(), &mint0, &mint1);
             ixs.push(swap_ix);
         }
@@ -185,27 +312,132 @@ impl Arbitrager {
         ixs.concat()
     }
 
-    fn send_ixs(&self, ixs: Vec<Instruction>) {
+    /// value of a lamport in `mint_idx` (scaled), from the best direct quote
+    /// of 1 SOL -- None without a loaded SOL pool to quote
+    pub fn lamport_price(&self, mint_idx: usize) -> Option<f64> {
+        let mint = self.token_mints[mint_idx];
+        if mint == *NATIVE_MINT {
+            return Some(1.0);
+        }
+        let sol_idx = self.token_mints.iter().position(|m| *m == *NATIVE_MINT)?;
+        let pools = self.graph.0.get(&PoolIndex(sol_idx))?.0.get(&PoolIndex(mint_idx))?;
+        let amount_out = pools
+            .iter()
+            .filter_map(|pool| pool.read().get_quote(LAMPORTS_PER_SOL, &NATIVE_MINT, &mint).ok())
+            .map(|quote| quote.amount_out)
+            .max()
+            .filter(|amount_out| *amount_out > 0)?;
+        Some(amount_out as f64 / LAMPORTS_PER_SOL as f64)
+    }
+
+    /// `ixs` with a compute budget sized from a simulation of the route and
+    /// priced by the priority fee policy -- None if the route fails in
+    /// simulation or its profit doesn't cover the fees
+    fn budget_instructions(
+        &self,
+        arb: &Arbitrage,
+        ixs: &[Instruction],
+    ) -> Option<Vec<Instruction>> {
+        let start_mint_idx = arb.cycle.mint_idxs[0];
+        let lamport_price = match self.lamport_price(start_mint_idx) {
+            Some(lamport_price) => lamport_price,
+            None => {
+                warn!("can't price fees in mint {}", self.token_mints[start_mint_idx]);
+                return None;
+            }
+        };
+
+        // the budget ixs use units too -- simulated with them (+ the max limit),
+        // the simulation replaces the blockhash
+        let probe = self.versioned_tx(
+            &with_compute_budget(ixs, MAX_COMPUTE_UNIT_LIMIT, 0),
+            Hash::default(),
+        )?;
+        let units_consumed = match self.simulate(&probe).and_then(|res| res.units_consumed()) {
+            Ok(units_consumed) => units_consumed,
+            Err(e) => {
+                info!("dropping arbitrage: {}", e);
+                return None;
+            }
+        };
+        let units = compute_unit_limit(units_consumed, self.compute_budget.unit_margin);
+
+        let profit = (arb.profit.max(0) as f64 / lamport_price) as u64; // in lamports
+        let base_fee = TransactionFee::new(1, units, 0).base_fee;
+        let policy = &self.compute_budget.priority_fee;
+        let unit_price = policy.unit_price(units, profit.saturating_sub(base_fee), |urgency| {
+            let accounts = writable_accounts(ixs);
+            self.priority_fees.read().unwrap().price_for(&accounts, urgency)
+        });
+        let fee = TransactionFee::new(1, units, unit_price);
+        if profit <= fee.total() {
+            info!(
+                "dropping arbitrage: profit of {} lamports doesn't cover {:?}",
+                profit, fee
+            );
+            return None;
+        }
+        debug!("{} units at {} micro lamports: {:?}", units, unit_price, fee);
+        Some(with_compute_budget(ixs, units, unit_price))
+    }
+
+    /// `ixs` as a signed v0 transaction, loading the accounts it can from the
+    /// `lookup_tables` which cover the route -- None if it can't be sent
+    fn versioned_tx(&self, ixs: &[Instruction], blockhash: Hash) -> Option<VersionedTransaction> {
         let owner: &Keypair = self.owner.borrow();
-        let tx = Transaction::new_signed_with_payer(
-            &ixs,
-            Some(&owner.pubkey()),
-            &[owner],
-            self.connection.get_latest_blockhash().unwrap(),
-        );
+        let lookup_tables = select_lookup_tables(ixs, &self.lookup_tables);
+        let tx = V0Message::try_compile(&owner.pubkey(), ixs, &lookup_tables, blockhash)
+            .and_then(|message| VersionedTransaction::try_new(message, &[owner]));
+        let tx = match tx {
+            Ok(tx) => tx,
+            Err(e) => {
+                warn!("can't build arbitrage tx: {}", e);
+                return None;
+            }
+        };
+        let tx_size = tx.message.transaction_size();
+        if tx_size > PACKET_DATA_SIZE {
+            warn!("arbitrage tx too large: {} bytes", tx_size);
+            return None;
+        }
+        Some(tx)
+    }
+
+    fn simulate(&self, tx: &VersionedTransaction) -> Result<SimulationResult, SimulationError> {
+        let config = json!({
+            "encoding": "base64",
+            "sigVerify": false,
+            "replaceRecentBlockhash": true,
+        });
+        let response: Value = self
+            .connection
+            .send(RpcRequest::SimulateTransaction, json!([tx.to_base64(), config]))
+            .map_err(|e| SimulationError::Rpc(e.to_string()))?;
+        SimulationResult::from_response(&response)
+    }
+
+    fn send_ixs(&self, ixs: Vec<Instruction>) {
+        let blockhash = match self.connection.get_latest_blockhash() {
+            Ok(blockhash) => blockhash,
+            Err(e) => {
+                warn!("can't fetch a blockhash for the arbitrage tx: {}", e);
+                return;
+            }
+        };
+        let tx = match self.versioned_tx(&ixs, blockhash) {
+            Some(tx) => tx,
+            None => return,
+        };
 
         if self.cluster == Cluster::Localnet {
-            let res = self.connection.simulate_transaction(&tx).unwrap();
+            let res = self.simulate(&tx);
             println!("{:#?}", res);
         } else if self.cluster == Cluster::Mainnet {
-            let signature = self
+            let signature: String = self
                 .connection
-                .send_transaction_with_config(
-                    &tx,
-                    RpcSendTransactionConfig {
-                        skip_preflight: true,
-                        ..RpcSendTransactionConfig::default()
-                    },
+                .send(
+                    RpcRequest::SendTransaction,
+                    json!([tx.to_base64(), { "encoding": "base64", "skipPreflight": true }]),
                 )
                 .unwrap();
             println!("signature: {:?}", signature);
@@ -0,0 +1,310 @@
+//! Associated token accounts for every mint the loaded pools trade
+//!
+//! Every swap reads + writes the owner's ATA of both its mints, so each one
+//! has to exist before the bot starts. The planner derives the ATAs (with the
+//! mint's own token program), checks which already exist and batches the
+//! missing ones' `CreateIdempotent` instructions into as few transactions as
+//! fit -- an ATA created in the meantime then doesn't fail the transaction.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::system_program;
+
+use anyhow::Result;
+use log::warn;
+use std::collections::HashSet;
+
+use crate::constants::*;
+use crate::discover::AccountSource;
+use crate::pool_utils::token_2022::{unpack_mint, Mint};
+use crate::serialize::token::{unpack_token_account, ACCOUNT_LEN};
+use crate::utils::derive_token_address_with_program;
+use crate::versioned::compact_u16_len;
+
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+// associated token account program instruction
+const CREATE_IDEMPOTENT: u8 = 1;
+// extensions the Token-2022 program adds to the accounts of a mint: immutable
+// owner (always, for ATAs) + the withheld amount of a transfer fee mint
+const IMMUTABLE_OWNER_LEN: usize = 4;
+const TRANSFER_FEE_AMOUNT_LEN: usize = 4 + 8;
+
+/// The owner's ATA of one mint
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequiredAta {
+    pub mint: Pubkey,
+    pub token_program: Pubkey,
+    pub address: Pubkey,
+    pub account_len: usize, // rent is paid for this many bytes
+}
+
+/// Which ATAs exist (+ their balances) and which have to be created
+#[derive(Debug, Clone, Default)]
+pub struct AtaPlan {
+    pub existing: Vec<(RequiredAta, u64)>,
+    pub missing: Vec<RequiredAta>,
+}
+
+/// size of a token account of `mint`
+pub fn token_account_len(mint: &Mint) -> usize {
+    if mint.token_program != *TOKEN_2022_PROGRAM_ID {
+        return ACCOUNT_LEN;
+    }
+    let mut len = ACCOUNT_LEN + 1 + IMMUTABLE_OWNER_LEN; // + account type
+    if mint.has_transfer_fee() {
+        len += TRANSFER_FEE_AMOUNT_LEN;
+    }
+    len
+}
+
+/// `owner`'s ATA of every mint in `mints` (deduped, in order) -- mints which
+/// can't be fetched or aren't token mints are skipped
+pub fn required_atas(
+    owner: &Pubkey,
+    mints: &[Pubkey],
+    source: &impl AccountSource,
+) -> Result<Vec<RequiredAta>> {
+    let mut seen = HashSet::new();
+    let mints: Vec<Pubkey> = mints.iter().filter(|mint| seen.insert(**mint)).cloned().collect();
+    let accounts = source.accounts(&mints)?;
+
+    let mut atas = vec![];
+    for (mint, account) in mints.iter().zip(accounts) {
+        let mint_info = account.and_then(|account| unpack_mint(&account.owner, &account.data));
+        let mint_info = match mint_info {
+            Some(mint_info) => mint_info,
+            None => {
+                warn!("skipping mint {}: not a token mint", mint);
+                continue;
+            }
+        };
+        atas.push(RequiredAta {
+            mint: *mint,
+            token_program: mint_info.token_program,
+            address: derive_token_address_with_program(owner, mint, &mint_info.token_program),
+            account_len: token_account_len(&mint_info),
+        });
+    }
+    Ok(atas)
+}
+
+/// split `atas` into the ones which exist and the ones to create
+pub fn plan_atas(atas: Vec<RequiredAta>, source: &impl AccountSource) -> Result<AtaPlan> {
+    let addresses: Vec<Pubkey> = atas.iter().map(|ata| ata.address).collect();
+    let accounts = source.accounts(&addresses)?;
+
+    let mut plan = AtaPlan::default();
+    for (ata, account) in atas.into_iter().zip(accounts) {
+        match account {
+            Some(account) if account.data.len() >= ACCOUNT_LEN => {
+                let amount = unpack_token_account(&account.data).amount;
+                plan.existing.push((ata, amount));
+            }
+            _ => plan.missing.push(ata),
+        }
+    }
+    Ok(plan)
+}
+
+impl AtaPlan {
+    /// rent of the missing ATAs -- `rent_exemption` is the minimum balance of an account of a size
+    pub fn rent(&self, rent_exemption: impl Fn(usize) -> u64) -> u64 {
+        self.missing.iter().map(|ata| rent_exemption(ata.account_len)).sum()
+    }
+
+    /// instructions creating the missing ATAs (`payer` pays the rent)
+    pub fn create_instructions(&self, payer: &Pubkey, owner: &Pubkey) -> Vec<Instruction> {
+        self.missing
+            .iter()
+            .map(|ata| create_ata_idempotent_ix(payer, owner, ata))
+            .collect()
+    }
+}
+
+/// creates `ata` unless it already exists
+pub fn create_ata_idempotent_ix(payer: &Pubkey, owner: &Pubkey, ata: &RequiredAta) -> Instruction {
+    Instruction {
+        program_id: *ASSOCIATED_TOKEN_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(ata.address, false),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new_readonly(ata.mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(ata.token_program, false),
+        ],
+        data: vec![CREATE_IDEMPOTENT],
+    }
+}
+
+/// serialized size of a (legacy) transaction of `ixs` paid for by `payer`
+pub fn transaction_size(ixs: &[Instruction], payer: &Pubkey) -> usize {
+    let mut keys = HashSet::new();
+    let mut signers = HashSet::new();
+    keys.insert(*payer);
+    signers.insert(*payer);
+    for ix in ixs {
+        keys.insert(ix.program_id);
+        for meta in &ix.accounts {
+            keys.insert(meta.pubkey);
+            if meta.is_signer {
+                signers.insert(meta.pubkey);
+            }
+        }
+    }
+
+    let signatures = compact_u16_len(signers.len()) + 64 * signers.len();
+    // header + account keys + recent blockhash
+    let mut message = 3 + compact_u16_len(keys.len()) + 32 * keys.len() + 32;
+    message += compact_u16_len(ixs.len());
+    for ix in ixs {
+        message += 1 // program id index
+            + compact_u16_len(ix.accounts.len())
+            + ix.accounts.len()
+            + compact_u16_len(ix.data.len())
+            + ix.data.len();
+    }
+    signatures + message
+}
+
+/// `ixs` (in order) packed into transactions of at most `max_tx_size` bytes --
+/// an instruction too large on its own gets a transaction to itself
+pub fn batch_instructions(
+    ixs: Vec<Instruction>,
+    payer: &Pubkey,
+    max_tx_size: usize,
+) -> Vec<Vec<Instruction>> {
+    let mut batches = vec![];
+    let mut batch: Vec<Instruction> = vec![];
+    for ix in ixs {
+        batch.push(ix);
+        if batch.len() > 1 && transaction_size(&batch, payer) > max_tx_size {
+            let ix = batch.pop().unwrap();
+            batches.push(batch);
+            batch = vec![ix];
+        }
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discover::FixtureAccounts;
+    use crate::versioned::PACKET_DATA_SIZE;
+    use crate::serialize::token::{
+        AccountType, ACCOUNT_TYPE_OFFSET, EXTENSION_TRANSFER_FEE_CONFIG, MINT_LEN,
+    };
+    use solana_sdk::account::Account;
+
+    fn account(owner: &Pubkey, data: Vec<u8>) -> Account {
+        Account {
+            lamports: 1_000_000,
+            data,
+            owner: *owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn mint_account(token_program: &Pubkey, transfer_fee: bool) -> Account {
+        let mut data = vec![0; MINT_LEN];
+        data[44] = 6;
+        data[45] = 1; // is_initialized
+        if transfer_fee {
+            data.resize(ACCOUNT_TYPE_OFFSET, 0);
+            data.push(AccountType::Mint as u8);
+            data.extend_from_slice(&EXTENSION_TRANSFER_FEE_CONFIG.to_le_bytes());
+            data.extend_from_slice(&108_u16.to_le_bytes());
+            data.extend_from_slice(&[0; 108]);
+        }
+        account(token_program, data)
+    }
+
+    fn token_account(amount: u64) -> Account {
+        let mut data = vec![0; ACCOUNT_LEN];
+        data[64..72].copy_from_slice(&amount.to_le_bytes());
+        data[108] = 1; // initialized
+        account(&TOKEN_PROGRAM_ID, data)
+    }
+
+    #[test]
+    fn plans_the_missing_atas() {
+        let owner = Pubkey::new_unique();
+        let (usdc, sol, fee_token, not_a_mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut accounts = FixtureAccounts::default();
+        accounts.0.insert(usdc, mint_account(&TOKEN_PROGRAM_ID, false));
+        accounts.0.insert(sol, mint_account(&TOKEN_PROGRAM_ID, false));
+        accounts.0.insert(fee_token, mint_account(&TOKEN_2022_PROGRAM_ID, true));
+        let usdc_ata = derive_token_address_with_program(&owner, &usdc, &TOKEN_PROGRAM_ID);
+        accounts.0.insert(usdc_ata, token_account(42));
+
+        let mints = [usdc, sol, usdc, fee_token, not_a_mint];
+        let atas = required_atas(&owner, &mints, &accounts).unwrap();
+        assert_eq!(atas.len(), 3);
+        assert_eq!(atas[2].token_program, *TOKEN_2022_PROGRAM_ID);
+        assert_eq!(
+            atas[2].address,
+            derive_token_address_with_program(&owner, &fee_token, &TOKEN_2022_PROGRAM_ID)
+        );
+        assert_eq!(atas[2].account_len, ACCOUNT_LEN + 1 + 4 + 12);
+
+        let plan = plan_atas(atas, &accounts).unwrap();
+        assert_eq!(plan.existing.len(), 1);
+        assert_eq!((plan.existing[0].0.mint, plan.existing[0].1), (usdc, 42));
+        let missing: Vec<Pubkey> = plan.missing.iter().map(|ata| ata.mint).collect();
+        assert_eq!(missing, vec![sol, fee_token]);
+        assert_eq!(plan.rent(|len| len as u64), (ACCOUNT_LEN * 2 + 17) as u64);
+
+        let ixs = plan.create_instructions(&owner, &owner);
+        assert_eq!(ixs.len(), 2);
+        assert_eq!(ixs[1].data, vec![CREATE_IDEMPOTENT]);
+        assert_eq!(ixs[1].accounts[5].pubkey, *TOKEN_2022_PROGRAM_ID);
+    }
+
+    #[test]
+    fn batches_fill_transactions() {
+        let payer = Pubkey::new_unique();
+        let atas: Vec<RequiredAta> = (0..40)
+            .map(|_| RequiredAta {
+                mint: Pubkey::new_unique(),
+                token_program: *TOKEN_PROGRAM_ID,
+                address: Pubkey::new_unique(),
+                account_len: ACCOUNT_LEN,
+            })
+            .collect();
+        let ixs: Vec<Instruction> = atas
+            .iter()
+            .map(|ata| create_ata_idempotent_ix(&payer, &payer, ata))
+            .collect();
+
+        // payer, ata program, system program, token program + 2 keys per ata
+        let one = transaction_size(&ixs[..1], &payer);
+        assert_eq!(one, 1 + 64 + 3 + 1 + 32 * 6 + 32 + 1 + (1 + 1 + 6 + 1 + 1));
+        let two = transaction_size(&ixs[..2], &payer);
+        assert_eq!(two - one, 32 * 2 + 10);
+
+        let batches = batch_instructions(ixs.clone(), &payer, PACKET_DATA_SIZE);
+        assert_eq!(batches.iter().map(|b| b.len()).sum::<usize>(), 40);
+        for batch in &batches {
+            assert!(transaction_size(batch, &payer) <= PACKET_DATA_SIZE);
+        }
+        // every batch but the last is full
+        for (batch, next) in batches.iter().zip(&batches[1..]) {
+            let mut fuller = batch.clone();
+            fuller.push(next[0].clone());
+            assert!(transaction_size(&fuller, &payer) > PACKET_DATA_SIZE);
+        }
+        assert_eq!(batch_instructions(ixs[..1].to_vec(), &payer, 10).len(), 1);
+        assert!(batch_instructions(vec![], &payer, PACKET_DATA_SIZE).is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+//! Picking the arbitrages to send together
+//!
+//! Arbitrages are found and sized against the streamed pools, each as if it
+//! were the only trade. Two of them swapping through the same pool both count
+//! on its reserves, so the second one quoted that way overestimates (and may
+//! revert). Each is sent as its own tx with no order to count on, so only
+//! arbitrages which share no pool with a better one are kept.
+
+use std::collections::HashSet;
+
+use crate::arb::Arbitrage;
+
+/// the arbitrages of `arbs` (ranked best first) which share no pool with a
+/// better one -- for sending each as its own tx, when none can count on
+/// another having traded (or not) before it
+pub fn independent_arbitrages(arbs: Vec<Arbitrage>) -> Vec<Arbitrage> {
+    let mut used_pools = HashSet::new();
+    arbs.into_iter()
+        .filter(|arb| {
+            let pool_ids: Vec<usize> = arb.cycle.pools.iter().map(|pool| pool.1).collect();
+            if pool_ids.iter().any(|id| used_pools.contains(id)) {
+                return false;
+            }
+            used_pools.extend(pool_ids);
+            true
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arb::rank_arbitrages;
+    use crate::cycles::{build_edges, enumerate_cycles, MAX_CYCLES_PER_START};
+    use crate::sizing::{optimal_amount_in, SizeBounds};
+    use crate::tests::mock::{mock_graph, MockPool};
+
+    const BOUNDS: SizeBounds = SizeBounds {
+        min_amount_in: 1_000,
+        max_amount_in: 1_000_000_000,
+    };
+
+    /// every profitable cycle through mint 0, sized on its own
+    fn sized_arbitrages(pools: Vec<MockPool>, n_mints: usize) -> Vec<Arbitrage> {
+        let (graph, mints) = mock_graph(n_mints, pools);
+        let edges = build_edges(&graph, &mints);
+        let mut arbs: Vec<Arbitrage> = enumerate_cycles(mints.len(), &edges, &[0], 4, MAX_CYCLES_PER_START)
+            .into_iter()
+            .filter_map(|cycle| {
+                let cycle = cycle.rotate_to(0)?;
+                let sizing = optimal_amount_in(&cycle, &mints, &BOUNDS, 0.0)?;
+                Some(Arbitrage {
+                    cycle,
+                    amount_in: sizing.amount_in,
+                    amount_out: sizing.amount_out,
+                    profit: sizing.profit,
+                })
+            })
+            .collect();
+        rank_arbitrages(&mut arbs);
+        arbs
+    }
+
+    #[test]
+    fn drops_arbitrages_sharing_a_pool() {
+        // pool 0 is mispriced, both 0 -> 1 -> 0 and 0 -> 1 -> 2 -> 0 drain it
+        let arbs = sized_arbitrages(
+            vec![
+                MockPool::new(0, 1, 1_000_000_000, 1_200_000_000),
+                MockPool::new(1, 0, 1_000_000_000, 1_000_000_000),
+                MockPool::new(1, 2, 1_000_000_000, 1_000_000_000),
+                MockPool::new(2, 0, 1_000_000_000, 1_000_000_000),
+            ],
+            3,
+        );
+        assert_eq!(arbs.len(), 2);
+        assert!(arbs.iter().all(|arb| arb.cycle.pools.iter().any(|p| p.1 == 0)));
+
+        // the one sharing pool 0 with a better one isn't sent
+        let independent = independent_arbitrages(arbs.clone());
+        assert_eq!(independent.len(), 1);
+        assert_eq!(independent[0].cycle.key(), arbs[0].cycle.key());
+    }
+
+    #[test]
+    fn disjoint_arbitrages_are_kept() {
+        // two mispriced pairs which share no pool
+        let arbs = sized_arbitrages(
+            vec![
+                MockPool::new(0, 1, 1_000_000_000, 1_200_000_000),
+                MockPool::new(1, 0, 1_000_000_000, 1_000_000_000),
+                MockPool::new(0, 2, 1_000_000_000, 1_300_000_000),
+                MockPool::new(2, 0, 1_000_000_000, 1_000_000_000),
+            ],
+            3,
+        );
+        assert_eq!(arbs.len(), 2);
+        assert_eq!(independent_arbitrages(arbs).len(), 2);
+    }
+}
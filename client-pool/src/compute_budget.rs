@@ -0,0 +1,315 @@
+//! Compute budget instructions (mirrors solana's compute budget program)
+//!
+//! A transaction is given 200k compute units per instruction (1.4M at most)
+//! unless it sets a limit, and only pays the base fee per signature unless it
+//! sets a unit price. Routes are simulated to size their limit (units
+//! consumed + a margin) and priced by a priority fee policy -- the priority
+//! fee is paid on the whole limit, used or not, so a tight limit is cheaper.
+//! An estimated policy bids what recently landed on the route's accounts (see
+//! `priority_fee`).
+
+use serde::Deserialize;
+use serde_json::Value;
+use solana_sdk::instruction::Instruction;
+
+use crate::ata::LAMPORTS_PER_SIGNATURE;
+use crate::constants::*;
+use crate::error::SimulationError;
+use crate::priority_fee::Urgency;
+
+/// most compute units a transaction can use
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+const MICRO_LAMPORTS_PER_LAMPORT: u128 = 1_000_000;
+// instruction tags (borsh enum variant indexes)
+const SET_COMPUTE_UNIT_LIMIT: u8 = 2;
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+
+pub fn set_compute_unit_limit_ix(units: u32) -> Instruction {
+    let mut data = vec![SET_COMPUTE_UNIT_LIMIT];
+    data.extend_from_slice(&units.to_le_bytes());
+    Instruction {
+        program_id: *COMPUTE_BUDGET_PROGRAM_ID,
+        accounts: vec![],
+        data,
+    }
+}
+
+/// priority fee of `micro_lamports` per compute unit
+pub fn set_compute_unit_price_ix(micro_lamports: u64) -> Instruction {
+    let mut data = vec![SET_COMPUTE_UNIT_PRICE];
+    data.extend_from_slice(&micro_lamports.to_le_bytes());
+    Instruction {
+        program_id: *COMPUTE_BUDGET_PROGRAM_ID,
+        accounts: vec![],
+        data,
+    }
+}
+
+/// `ixs` with a limit of `units` at `micro_lamports` per unit in front
+pub fn with_compute_budget(
+    ixs: &[Instruction],
+    units: u32,
+    micro_lamports: u64,
+) -> Vec<Instruction> {
+    let mut budget_ixs = vec![
+        set_compute_unit_limit_ix(units),
+        set_compute_unit_price_ix(micro_lamports),
+    ];
+    budget_ixs.extend_from_slice(ixs);
+    budget_ixs
+}
+
+/// the limit for a transaction which consumed `units_consumed` in simulation
+/// -- `margin` more (0.1 = 10%) as the pools can move before it lands
+pub fn compute_unit_limit(units_consumed: u64, margin: f64) -> u32 {
+    let extra_units = (units_consumed as f64 * margin.max(0.0)).round() as u64;
+    units_consumed
+        .saturating_add(extra_units)
+        .min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+}
+
+/// What a transaction pays to land, in lamports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionFee {
+    pub base_fee: u64,
+    pub priority_fee: u64,
+}
+
+impl TransactionFee {
+    /// fee of a transaction with `n_signatures`, a limit of `units` at
+    /// `micro_lamports` per unit
+    pub fn new(n_signatures: usize, units: u32, micro_lamports: u64) -> Self {
+        let micro_lamports = units as u128 * micro_lamports as u128;
+        let priority_fee = match micro_lamports % MICRO_LAMPORTS_PER_LAMPORT {
+            0 => micro_lamports / MICRO_LAMPORTS_PER_LAMPORT,
+            _ => micro_lamports / MICRO_LAMPORTS_PER_LAMPORT + 1,
+        };
+        Self {
+            base_fee: n_signatures as u64 * LAMPORTS_PER_SIGNATURE,
+            priority_fee: priority_fee.min(u64::MAX as u128) as u64,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.base_fee.saturating_add(self.priority_fee)
+    }
+}
+
+/// How much a route bids per compute unit
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PriorityFeePolicy {
+    /// the same price for every route
+    Fixed { micro_lamports: u64 },
+    /// spend `share` of the route's profit (net of the base fee) on its
+    /// priority fee, at most `max_micro_lamports` per unit
+    ProfitShare { share: f64, max_micro_lamports: u64 },
+    /// the recent fees of the route's writable accounts at `urgency`, at most
+    /// `max_micro_lamports` per unit
+    Estimated {
+        urgency: Urgency,
+        max_micro_lamports: u64,
+    },
+}
+
+impl Default for PriorityFeePolicy {
+    fn default() -> Self {
+        PriorityFeePolicy::Fixed { micro_lamports: 0 }
+    }
+}
+
+impl PriorityFeePolicy {
+    /// the urgency to estimate the route's price at, if the policy estimates
+    pub fn urgency(&self) -> Option<Urgency> {
+        match self {
+            PriorityFeePolicy::Estimated { urgency, .. } => Some(*urgency),
+            _ => None,
+        }
+    }
+
+    /// price (micro lamports per unit) of a route with a limit of `units`
+    /// which is worth `profit` lamports after its base fee -- `estimate` is
+    /// the route's recent price at an urgency
+    pub fn unit_price<F>(&self, units: u32, profit: u64, estimate: F) -> u64
+    where
+        F: FnOnce(Urgency) -> u64,
+    {
+        match self {
+            PriorityFeePolicy::Fixed { micro_lamports } => *micro_lamports,
+            PriorityFeePolicy::Estimated {
+                urgency,
+                max_micro_lamports,
+            } => estimate(*urgency).min(*max_micro_lamports),
+            PriorityFeePolicy::ProfitShare {
+                share,
+                max_micro_lamports,
+            } => {
+                if units == 0 {
+                    return 0;
+                }
+                let budget = profit as f64 * share.clamp(0.0, 1.0);
+                let price = budget * MICRO_LAMPORTS_PER_LAMPORT as f64 / units as f64;
+                (price.floor() as u64).min(*max_micro_lamports)
+            }
+        }
+    }
+}
+
+fn default_unit_margin() -> f64 {
+    0.1
+}
+
+/// How routes size + price their compute budget
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ComputeBudgetConfig {
+    // extra units over the simulated ones (0.1 = 10%)
+    #[serde(default = "default_unit_margin")]
+    pub unit_margin: f64,
+    #[serde(default)]
+    pub priority_fee: PriorityFeePolicy,
+}
+
+impl Default for ComputeBudgetConfig {
+    fn default() -> Self {
+        Self {
+            unit_margin: default_unit_margin(),
+            priority_fee: PriorityFeePolicy::default(),
+        }
+    }
+}
+
+/// The `simulateTransaction` result we size budgets from
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationResult {
+    pub err: Option<Value>,
+    #[serde(default)]
+    pub logs: Option<Vec<String>>,
+    #[serde(default)]
+    pub units_consumed: Option<u64>,
+}
+
+impl SimulationResult {
+    /// the result of a `simulateTransaction` response (its `value`)
+    pub fn from_response(response: &Value) -> Result<Self, SimulationError> {
+        serde_json::from_value(response["value"].clone())
+            .map_err(|e| SimulationError::Rpc(e.to_string()))
+    }
+
+    /// units the transaction consumed, if it succeeded
+    pub fn units_consumed(&self) -> Result<u64, SimulationError> {
+        if let Some(err) = &self.err {
+            return Err(SimulationError::Failed(err.to_string()));
+        }
+        self.units_consumed.ok_or(SimulationError::MissingUnits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn builds_budget_instructions() {
+        let limit_ix = set_compute_unit_limit_ix(300_000);
+        assert_eq!(limit_ix.program_id, *COMPUTE_BUDGET_PROGRAM_ID);
+        assert_eq!(limit_ix.data, vec![2, 0xe0, 0x93, 0x04, 0x00]);
+        let price_ix = set_compute_unit_price_ix(1_000);
+        assert_eq!(price_ix.data, vec![3, 0xe8, 0x03, 0, 0, 0, 0, 0, 0]);
+
+        let swap_ix = Instruction {
+            data: vec![42],
+            ..Instruction::default()
+        };
+        let ixs = with_compute_budget(std::slice::from_ref(&swap_ix), 300_000, 1_000);
+        assert_eq!(ixs, vec![limit_ix, price_ix, swap_ix]);
+    }
+
+    #[test]
+    fn sizes_limits_and_fees() {
+        assert_eq!(compute_unit_limit(100_000, 0.1), 110_000);
+        assert_eq!(compute_unit_limit(100_001, 0.0), 100_001);
+        assert_eq!(compute_unit_limit(100_000, -1.0), 100_000);
+        assert_eq!(compute_unit_limit(1_300_000, 0.5), MAX_COMPUTE_UNIT_LIMIT);
+
+        let fee = TransactionFee::new(1, 200_000, 10_000);
+        assert_eq!((fee.base_fee, fee.priority_fee), (5_000, 2_000));
+        assert_eq!(fee.total(), 7_000);
+        // a part of a lamport is a lamport
+        assert_eq!(TransactionFee::new(1, 3, 1).priority_fee, 1);
+        assert_eq!(TransactionFee::new(2, 100, 0).total(), 10_000);
+    }
+
+    #[test]
+    fn prices_by_policy() {
+        let fixed = PriorityFeePolicy::Fixed { micro_lamports: 42 };
+        assert_eq!(fixed.unit_price(200_000, 0, |_| 0), 42);
+
+        let share = PriorityFeePolicy::ProfitShare {
+            share: 0.5,
+            max_micro_lamports: 1_000_000,
+        };
+        // half of 10k lamports over 100k units = 50k micro lamports per unit
+        assert_eq!(share.unit_price(100_000, 10_000, |_| 0), 50_000);
+        assert_eq!(TransactionFee::new(1, 100_000, 50_000).priority_fee, 5_000);
+        assert_eq!(share.unit_price(100, 10_000_000, |_| 0), 1_000_000);
+        assert_eq!(share.unit_price(0, 10_000, |_| 0), 0);
+        assert_eq!(share.urgency(), None);
+
+        let estimated = PriorityFeePolicy::Estimated {
+            urgency: Urgency::High,
+            max_micro_lamports: 5_000,
+        };
+        assert_eq!(estimated.urgency(), Some(Urgency::High));
+        let price = estimated.unit_price(100_000, 0, |urgency| match urgency {
+            Urgency::High => 1_000,
+            _ => 0,
+        });
+        assert_eq!(price, 1_000);
+        assert_eq!(estimated.unit_price(100_000, 0, |_| 1_000_000), 5_000);
+
+        let config: ComputeBudgetConfig = serde_json::from_value(json!({
+            "priority_fee": { "type": "profit_share", "share": 0.2, "max_micro_lamports": 10 }
+        }))
+        .unwrap();
+        assert_eq!(config.unit_margin, 0.1);
+        assert_eq!(
+            config.priority_fee,
+            PriorityFeePolicy::ProfitShare {
+                share: 0.2,
+                max_micro_lamports: 10
+            }
+        );
+        let config: ComputeBudgetConfig = serde_json::from_value(json!({
+            "priority_fee": { "type": "estimated", "urgency": "max", "max_micro_lamports": 10 }
+        }))
+        .unwrap();
+        assert_eq!(config.priority_fee.urgency(), Some(Urgency::Max));
+        let config: ComputeBudgetConfig = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(config, ComputeBudgetConfig::default());
+    }
+
+    #[test]
+    fn parses_simulations() {
+        let response = json!({
+            "context": { "slot": 218 },
+            "value": { "err": null, "logs": ["Program log: ok"], "unitsConsumed": 2366 }
+        });
+        let result = SimulationResult::from_response(&response).unwrap();
+        assert_eq!(result.units_consumed(), Ok(2366));
+
+        let response = json!({
+            "context": { "slot": 218 },
+            "value": { "err": { "InstructionError": [2, { "Custom": 1 }] }, "unitsConsumed": 1000 }
+        });
+        let result = SimulationResult::from_response(&response).unwrap();
+        assert!(matches!(result.units_consumed(), Err(SimulationError::Failed(_))));
+
+        let response = json!({ "context": { "slot": 218 }, "value": { "err": null } });
+        let result = SimulationResult::from_response(&response).unwrap();
+        assert_eq!(result.units_consumed(), Err(SimulationError::MissingUnits));
+        assert!(SimulationResult::from_response(&json!({})).is_err());
+    }
+}
@@ -4,12 +4,38 @@ use std::str::FromStr;
 lazy_static! {
     pub static ref TOKEN_PROGRAM_ID: Pubkey = Pubkey::from_str("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA").unwrap();
 
+    pub static ref TOKEN_2022_PROGRAM_ID: Pubkey = Pubkey::from_str("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb").unwrap();
+
+    pub static ref ADDRESS_LOOKUP_TABLE_PROGRAM_ID: Pubkey = Pubkey::from_str("AddressLookupTab1e1111111111111111111111111").unwrap();
+
+    pub static ref COMPUTE_BUDGET_PROGRAM_ID: Pubkey = Pubkey::from_str("ComputeBudget111111111111111111111111111111").unwrap();
+
+    // wrapped SOL
+    pub static ref NATIVE_MINT: Pubkey = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
+
     pub static ref ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey = Pubkey::from_str("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL").unwrap();
+
+    // SPL memo v2 -- whirlpool swap_v2 takes it
+    pub static ref MEMO_PROGRAM_ID: Pubkey = Pubkey::from_str("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr").unwrap();
     
-    pub static ref RAYDIUM_PROGRAM_ID: Pubkey = Pubkey::from_str("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL").unwrap();
+    // AMM v4
+    pub static ref RAYDIUM_PROGRAM_ID: Pubkey = Pubkey::from_str("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8").unwrap();
     
     pub static ref ORCA_PROGRAM_ID: Pubkey = Pubkey::from_str("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP").unwrap();
 
+    pub static ref WHIRLPOOL_PROGRAM_ID: Pubkey = Pubkey::from_str("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc").unwrap();
+
+    // DLMM
+    pub static ref METEORA_PROGRAM_ID: Pubkey = Pubkey::from_str("LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo").unwrap();
+
+    pub static ref PHOENIX_PROGRAM_ID: Pubkey = Pubkey::from_str("PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY").unwrap();
+
+    // v2
+    pub static ref LIFINITY_PROGRAM_ID: Pubkey = Pubkey::from_str("2wT8Yq49kHgDzXuPxZSaeLaH1qbmGXtEyPy64bL7aD3c").unwrap();
+
+    // v6
+    pub static ref JUPITER_PROGRAM_ID: Pubkey = Pubkey::from_str("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4").unwrap();
+
     pub static ref MERCURIAL_PROGRAM_ID: Pubkey = Pubkey::from_str("MERLuDFBMmsHnsBPZw2sDQZHvXFMwp8EdjudcU2HKky").unwrap();
 
     pub static ref ARB_PROGRAM_ID: Pubkey = Pubkey::from_str("CRQXfRGq3wTkjt7JkqhojPLiKLYLjHPGLebnfiiQB46T").unwrap();
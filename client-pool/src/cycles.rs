@@ -0,0 +1,818 @@
+//! Negative-cycle detection over the pool graph
+//!
+//! Every (pool, direction) pair becomes an edge weighted by `-ln(rate)` where
+//! `rate` is the marginal exchange rate quoted for a small probe amount. A
+//! cycle whose weights sum to < 0 multiplies its input by > 1, so profitable
+//! arbitrages are exactly the negative cycles of the graph (Bellman-Ford).
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use log::warn;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::pool::PoolOperations;
+use crate::utils::{PoolGraph, PoolQuote};
+
+/// pool id -> copy of the pool with the trades simulated on it so far
+pub type PoolSnapshots = HashMap<usize, Box<dyn PoolOperations>>;
+
+/// Directed, weighted edge of the exchange graph (one per pool + direction)
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub src: usize,
+    pub dst: usize,
+    pub weight: f64,
+    pub pool: PoolQuote,
+}
+
+/// Candidate arbitrage: `mint_idxs` starts and ends at the same mint,
+/// `pools[i]` swaps `mint_idxs[i] -> mint_idxs[i + 1]`
+#[derive(Debug, Clone)]
+pub struct Cycle {
+    pub mint_idxs: Vec<usize>,
+    pub pools: Vec<PoolQuote>,
+    /// sum of the edge weights -- more negative == more profitable
+    pub log_weight: f64,
+}
+
+impl Cycle {
+    pub fn n_hops(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// rotate the cycle so that it starts (and ends) at `mint_idx`
+    pub fn rotate_to(&self, mint_idx: usize) -> Option<Cycle> {
+        let n = self.n_hops();
+        let start = self.mint_idxs[..n].iter().position(|i| *i == mint_idx)?;
+
+        let mut mint_idxs: Vec<usize> = (0..n).map(|i| self.mint_idxs[(start + i) % n]).collect();
+        mint_idxs.push(mint_idx);
+        let pools = (0..n)
+            .map(|i| self.pools[(start + i) % n].clone())
+            .collect();
+
+        Some(Cycle {
+            mint_idxs,
+            pools,
+            log_weight: self.log_weight,
+        })
+    }
+
+    /// rotation-independent key = {mint_path}{pool_ids} starting at the smallest mint
+    pub fn key(&self) -> String {
+        let n = self.n_hops();
+        let start = (0..n)
+            .min_by_key(|i| (self.mint_idxs[*i], self.pools[*i].1))
+            .unwrap();
+        let mint_keys: Vec<String> = (0..n)
+            .map(|i| self.mint_idxs[(start + i) % n].to_string())
+            .collect();
+        let pool_keys: Vec<String> = (0..n)
+            .map(|i| self.pools[(start + i) % n].1.to_string())
+            .collect();
+        format!("{}|{}", mint_keys.join("-"), pool_keys.join("-"))
+    }
+
+    /// current log weight of the cycle using the same probes as `build_edges`
+    /// (infinite if a hop can't trade) -- >= 0 means unprofitable at any size
+    pub fn marginal_log_weight(&self, token_mints: &[Pubkey]) -> f64 {
+        let mut log_weight = 0.0;
+        for (i, pool) in self.pools.iter().enumerate() {
+            let mint_in = &token_mints[self.mint_idxs[i]];
+            let mint_out = &token_mints[self.mint_idxs[i + 1]];
+            match probe_weight(pool, mint_in, mint_out) {
+                Some(weight) => log_weight += weight,
+                None => return f64::INFINITY,
+            }
+        }
+        log_weight
+    }
+
+    /// does the cycle swap through a pool more than once
+    pub fn reuses_pools(&self) -> bool {
+        let mut pool_ids = HashSet::new();
+        !self.pools.iter().all(|pool| pool_ids.insert(pool.1))
+    }
+
+    /// exact integer quote of `amount_in` (scaled) through every hop -- a
+    /// hop through a pool the cycle already swapped in sees that trade
+    pub fn quote(&self, token_mints: &[Pubkey], amount_in: u128) -> u128 {
+        if self.reuses_pools() {
+            return self.quote_with_snapshots(token_mints, amount_in, &mut PoolSnapshots::new());
+        }
+        let mut amount = amount_in;
+        for (i, pool) in self.pools.iter().enumerate() {
+            let mint_in = &token_mints[self.mint_idxs[i]];
+            let mint_out = &token_mints[self.mint_idxs[i + 1]];
+            amount = pool
+                .read()
+                .get_quote_with_amounts_scaled(amount, mint_in, mint_out);
+            if amount == 0 {
+                break;
+            }
+        }
+        amount
+    }
+
+    /// `quote` against `snapshots` (pools missing from it are copied in),
+    /// every hop is simulated on its snapshot so later quotes see the trade.
+    /// Hops before a failed one (quoted 0) stay applied
+    pub fn quote_with_snapshots(
+        &self,
+        token_mints: &[Pubkey],
+        amount_in: u128,
+        snapshots: &mut PoolSnapshots,
+    ) -> u128 {
+        let mut amount = amount_in;
+        for (i, pool) in self.pools.iter().enumerate() {
+            let mint_in = &token_mints[self.mint_idxs[i]];
+            let mint_out = &token_mints[self.mint_idxs[i + 1]];
+            let snapshot = snapshots
+                .entry(pool.1)
+                .or_insert_with(|| dyn_clone::clone_box(&**pool.read()));
+            amount = snapshot
+                .simulate_swap(amount, mint_in, mint_out)
+                .map_or(0, |quote| quote.amount_out);
+            if amount == 0 {
+                break;
+            }
+        }
+        amount
+    }
+}
+
+/// `-ln(rate)` of a swap of one token (10^decimals) of `mint_in`, None if the
+/// pool can't trade
+fn probe_weight(pool: &PoolQuote, mint_in: &Pubkey, mint_out: &Pubkey) -> Option<f64> {
+    let pool = pool.read();
+    if !pool.can_trade(mint_in, mint_out) {
+        return None;
+    }
+    let probe = 10_u128.pow(pool.mint_2_scale(mint_in).ok()? as u32);
+    let out = pool.get_quote_with_amounts_scaled(probe, mint_in, mint_out);
+    if out == 0 {
+        return None;
+    }
+    let rate = out as f64 / probe as f64;
+    Some(-rate.ln())
+}
+
+/// build a cycle from edge indexs in traversal order
+fn cycle_from_edges(edges: &[Edge], edge_idxs: &[usize]) -> Cycle {
+    let mut mint_idxs: Vec<usize> = edge_idxs.iter().map(|e| edges[*e].src).collect();
+    mint_idxs.push(mint_idxs[0]);
+    Cycle {
+        mint_idxs,
+        pools: edge_idxs.iter().map(|e| edges[*e].pool.clone()).collect(),
+        log_weight: edge_idxs.iter().map(|e| edges[*e].weight).sum(),
+    }
+}
+
+/// weight every (pool, direction) of the graph using a probe of one token
+/// (10^decimals) of the source mint -- pools which can't trade are dropped
+pub fn build_edges(graph: &PoolGraph, token_mints: &[Pubkey]) -> Vec<Edge> {
+    let mut edges = vec![];
+    let mut srcs: Vec<_> = graph.0.keys().collect();
+    srcs.sort_by_key(|idx| idx.0); // keep edge order (and results) deterministic
+
+    for src in srcs {
+        let pool_edge = &graph.0[src];
+        let mut dsts: Vec<_> = pool_edge.0.keys().collect();
+        dsts.sort_by_key(|idx| idx.0);
+
+        for dst in dsts {
+            let src_mint = &token_mints[src.0];
+            let dst_mint = &token_mints[dst.0];
+
+            for pool in &pool_edge.0[dst] {
+                if let Some(weight) = probe_weight(pool, src_mint, dst_mint) {
+                    edges.push(Edge {
+                        src: src.0,
+                        dst: dst.0,
+                        weight,
+                        pool: pool.clone(),
+                    });
+                }
+            }
+        }
+    }
+    edges
+}
+
+/// Bellman-Ford from `sources` (all at distance 0), returns every negative
+/// cycle reachable from them as a list of edge indexs in traversal order
+fn bellman_ford_cycles(
+    n_mints: usize,
+    edges: &[Edge],
+    banned: &HashSet<usize>,
+    sources: &[usize],
+) -> Vec<Vec<usize>> {
+    let mut dist = vec![f64::INFINITY; n_mints];
+    let mut pred: Vec<Option<usize>> = vec![None; n_mints];
+    for s in sources {
+        dist[*s] = 0.0;
+    }
+
+    // n - 1 relaxation rounds, stop early once nothing changes
+    for _ in 0..n_mints.saturating_sub(1) {
+        let mut changed = false;
+        for (e_idx, e) in edges.iter().enumerate() {
+            if banned.contains(&e_idx) || dist[e.src] == f64::INFINITY {
+                continue;
+            }
+            let new_dist = dist[e.src] + e.weight;
+            if new_dist < dist[e.dst] - f64::EPSILON {
+                dist[e.dst] = new_dist;
+                pred[e.dst] = Some(e_idx);
+                changed = true;
+            }
+        }
+        if !changed {
+            return vec![];
+        }
+    }
+
+    // anything still relaxing is reachable from a negative cycle
+    let mut relaxed = vec![];
+    for (e_idx, e) in edges.iter().enumerate() {
+        if banned.contains(&e_idx) || dist[e.src] == f64::INFINITY {
+            continue;
+        }
+        if dist[e.src] + e.weight < dist[e.dst] - f64::EPSILON {
+            dist[e.dst] = dist[e.src] + e.weight;
+            pred[e.dst] = Some(e_idx);
+            relaxed.push(e.dst);
+        }
+    }
+
+    // walk the predecessors back from each relaxed mint until we loop
+    let mut cycles = vec![];
+    let mut visited = vec![usize::MAX; n_mints]; // = walk id which visited the mint
+    for (walk_id, start) in relaxed.into_iter().enumerate() {
+        let mut v = start;
+        while visited[v] == usize::MAX {
+            visited[v] = walk_id;
+            v = match pred[v] {
+                Some(e_idx) => edges[e_idx].src,
+                None => break,
+            };
+        }
+        if visited[v] != walk_id || pred[v].is_none() {
+            continue; // dead end or a cycle found by a previous walk
+        }
+
+        let mut cycle = vec![];
+        let mut u = v;
+        loop {
+            let e_idx = pred[u].unwrap();
+            cycle.push(e_idx);
+            u = edges[e_idx].src;
+            if u == v {
+                break;
+            }
+        }
+        cycle.reverse();
+        cycles.push(cycle);
+    }
+    cycles
+}
+
+/// Find the profitable cycles reachable from `sources`, ranked by log weight
+/// (most profitable first). Bellman-Ford only reports one cycle per mint per
+/// run, so after each run the weakest edge of every found cycle is banned and
+/// the search repeats (up to `max_rounds`) to uncover the cycles hiding
+/// behind it. Cycles longer than `max_hops` are dropped (but still banned).
+pub fn find_negative_cycles(
+    n_mints: usize,
+    edges: &[Edge],
+    sources: &[usize],
+    max_hops: usize,
+    max_rounds: usize,
+) -> Vec<Cycle> {
+    let mut banned = HashSet::new();
+    let mut seen = HashSet::new();
+    let mut cycles = vec![];
+
+    for _ in 0..max_rounds {
+        let found = bellman_ford_cycles(n_mints, edges, &banned, sources);
+        if found.is_empty() {
+            break;
+        }
+
+        for edge_idxs in found {
+            let weakest = *edge_idxs
+                .iter()
+                .max_by(|a, b| edges[**a].weight.total_cmp(&edges[**b].weight))
+                .unwrap();
+            banned.insert(weakest);
+
+            let cycle = cycle_from_edges(edges, &edge_idxs);
+            if edge_idxs.len() > max_hops || cycle.log_weight >= 0.0 {
+                continue;
+            }
+            if seen.insert(cycle.key()) {
+                cycles.push(cycle);
+            }
+        }
+    }
+
+    cycles.sort_by(|a, b| {
+        a.log_weight
+            .total_cmp(&b.log_weight)
+            .then_with(|| a.key().cmp(&b.key()))
+    });
+    cycles
+}
+
+/// rounds of `find_negative_cycles` (edge bans) per start mint in `par_find_cycles`
+pub const MAX_BAN_ROUNDS: usize = 8;
+
+/// Run `find_negative_cycles` from every start mint in parallel (one rayon
+/// task per start mint) -- each start bans its own edges, so together they
+/// uncover cycles a single run would hide behind a shared weakest edge. If
+/// `pool_ids` is set only cycles swapping through one of those pools are
+/// kept. The same cycle is usually found from several starts so results are
+/// de-duplicated by `Cycle::key` and sorted by (log weight, key) -- the output
+/// doesn't depend on the thread count or scheduling.
+pub fn par_find_cycles(
+    n_mints: usize,
+    edges: &[Edge],
+    starts: &[usize],
+    max_hops: usize,
+    pool_ids: Option<&HashSet<usize>>,
+) -> Vec<Cycle> {
+    let cycles = starts
+        .par_iter()
+        .flat_map_iter(|start| {
+            find_negative_cycles(n_mints, edges, &[*start], max_hops, MAX_BAN_ROUNDS)
+                .into_iter()
+                .filter(|cycle| match pool_ids {
+                    Some(ids) => cycle.pools.iter().any(|pool| ids.contains(&pool.1)),
+                    None => true,
+                })
+        })
+        .collect();
+    dedup_cycles(cycles)
+}
+
+/// rotate every cycle to its smallest mint, drop duplicate keys and sort by
+/// (log weight, key)
+fn dedup_cycles(cycles: Vec<Cycle>) -> Vec<Cycle> {
+    let mut cycles: Vec<(String, Cycle)> = cycles
+        .into_par_iter()
+        .map(|cycle| {
+            // canonical rotation so duplicates compare equal
+            let min_mint = *cycle.mint_idxs.iter().min().unwrap();
+            let cycle = cycle.rotate_to(min_mint).unwrap();
+            (cycle.key(), cycle)
+        })
+        .collect();
+
+    cycles.sort_by(|(a_key, a), (b_key, b)| {
+        a_key.cmp(b_key).then(a.log_weight.total_cmp(&b.log_weight))
+    });
+    cycles.dedup_by(|a, b| a.0 == b.0);
+    cycles.sort_by(|(a_key, a), (b_key, b)| {
+        a.log_weight
+            .total_cmp(&b.log_weight)
+            .then_with(|| a_key.cmp(b_key))
+    });
+    cycles.into_iter().map(|(_, cycle)| cycle).collect()
+}
+
+/// Cycles which swap through at least one of `pool_ids` (e.g. pools whose
+/// reserves just changed) -- every such cycle passes through the mints the
+/// pools trade, so only those are searched from.
+pub fn find_cycles_touching(
+    n_mints: usize,
+    edges: &[Edge],
+    pool_ids: &HashSet<usize>,
+    max_hops: usize,
+) -> Vec<Cycle> {
+    let mut starts: Vec<usize> = edges
+        .iter()
+        .filter(|e| pool_ids.contains(&e.pool.1))
+        .map(|e| e.src)
+        .collect();
+    starts.sort_unstable();
+    starts.dedup();
+    par_find_cycles(n_mints, edges, &starts, max_hops, Some(pool_ids))
+}
+
+/// cycles `enumerate_cycles` keeps per start mint
+pub const MAX_CYCLES_PER_START: usize = 20_000;
+/// edges a single start's walk may follow before it gives up
+pub const MAX_WALK_STEPS_PER_START: usize = 2_000_000;
+
+/// Simple cycles of at most `max_hops` swaps through one of `starts`,
+/// profitable or not (depth-first, one rayon task per start mint). These are
+/// the candidates worth re-quoting when one of their pools changes.
+///
+/// The walk is exhaustive on small graphs only -- each start keeps at most
+/// `max_cycles` cycles and follows at most `MAX_WALK_STEPS_PER_START` edges,
+/// lightest edges first so a truncated walk keeps the most promising cycles
+/// (a warning is logged when it's cut short).
+pub fn enumerate_cycles(
+    n_mints: usize,
+    edges: &[Edge],
+    starts: &[usize],
+    max_hops: usize,
+    max_cycles: usize,
+) -> Vec<Cycle> {
+    let mut out_edges = vec![vec![]; n_mints];
+    for (e_idx, e) in edges.iter().enumerate() {
+        out_edges[e.src].push(e_idx);
+    }
+    for out in out_edges.iter_mut() {
+        out.sort_by(|a: &usize, b: &usize| edges[*a].weight.total_cmp(&edges[*b].weight));
+    }
+
+    let cycles = starts
+        .par_iter()
+        .flat_map_iter(|start| {
+            let mut walk = CycleWalk {
+                start: *start,
+                edges,
+                out_edges: &out_edges,
+                max_hops,
+                max_cycles,
+                steps_left: MAX_WALK_STEPS_PER_START,
+                path: vec![],
+                on_path: vec![false; n_mints],
+                cycles: vec![],
+            };
+            walk.on_path[*start] = true;
+            walk.visit(*start);
+            if walk.is_done() {
+                warn!(
+                    "cycle walk from mint {} cut short: {} cycles, {} steps",
+                    start,
+                    walk.cycles.len(),
+                    MAX_WALK_STEPS_PER_START - walk.steps_left
+                );
+            }
+            walk.cycles
+        })
+        .collect();
+    dedup_cycles(cycles)
+}
+
+struct CycleWalk<'a> {
+    start: usize,
+    edges: &'a [Edge],
+    out_edges: &'a [Vec<usize>],
+    max_hops: usize,
+    max_cycles: usize,
+    steps_left: usize,
+    path: Vec<usize>, // edge idxs from the start
+    on_path: Vec<bool>,
+    cycles: Vec<Cycle>,
+}
+
+impl CycleWalk<'_> {
+    fn is_done(&self) -> bool {
+        self.cycles.len() >= self.max_cycles || self.steps_left == 0
+    }
+
+    fn visit(&mut self, mint_idx: usize) {
+        for e_idx in self.out_edges[mint_idx].iter() {
+            if self.is_done() {
+                return;
+            }
+            self.steps_left -= 1;
+            let e = &self.edges[*e_idx];
+            // swapping straight back through the same pool is never profitable
+            if let Some(last) = self.path.last() {
+                if self.edges[*last].pool.1 == e.pool.1 {
+                    continue;
+                }
+            }
+
+            if e.dst == self.start {
+                self.path.push(*e_idx);
+                self.cycles.push(cycle_from_edges(self.edges, &self.path));
+                self.path.pop();
+            } else if !self.on_path[e.dst] && self.path.len() + 2 <= self.max_hops {
+                self.on_path[e.dst] = true;
+                self.path.push(*e_idx);
+                self.visit(e.dst);
+                self.path.pop();
+                self.on_path[e.dst] = false;
+            }
+        }
+    }
+}
+
+/// Candidate cycles indexed by the pools they swap through, so a pool update
+/// only re-quotes the cycles it can affect
+#[derive(Debug, Default)]
+pub struct CycleIndex {
+    cycles: Vec<Cycle>,
+    keys: HashSet<String>,
+    by_pool: HashMap<usize, Vec<usize>>, // pool id -> positions in `cycles`
+}
+
+impl CycleIndex {
+    pub fn new(cycles: Vec<Cycle>) -> Self {
+        let mut index = Self::default();
+        index.extend(cycles);
+        index
+    }
+
+    /// false if the cycle (any rotation) is already indexed
+    pub fn insert(&mut self, cycle: Cycle) -> bool {
+        if !self.keys.insert(cycle.key()) {
+            return false;
+        }
+        let position = self.cycles.len();
+        for pool in &cycle.pools {
+            self.by_pool.entry(pool.1).or_default().push(position);
+        }
+        self.cycles.push(cycle);
+        true
+    }
+
+    /// returns how many cycles were new
+    pub fn extend(&mut self, cycles: Vec<Cycle>) -> usize {
+        let mut n_new = 0;
+        for cycle in cycles {
+            if self.insert(cycle) {
+                n_new += 1;
+            }
+        }
+        n_new
+    }
+
+    pub fn len(&self) -> usize {
+        self.cycles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cycles.is_empty()
+    }
+
+    pub fn cycles(&self) -> &[Cycle] {
+        &self.cycles
+    }
+
+    /// indexed cycles through any of `pool_ids` (in insertion order)
+    pub fn touching(&self, pool_ids: &HashSet<usize>) -> Vec<Cycle> {
+        let mut positions: Vec<usize> = pool_ids
+            .iter()
+            .filter_map(|id| self.by_pool.get(id))
+            .flatten()
+            .cloned()
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+        positions
+            .into_iter()
+            .map(|i| self.cycles[i].clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::mock::{mock_graph, mock_reserve_account, MockPool};
+    use crate::utils::PoolIndex;
+    use anchor_client::Cluster;
+
+    #[test]
+    fn finds_cycle_not_touching_start_mint() {
+        // 0 <-> 1 is fair, 1 -> 2 -> 3 -> 1 is mispriced
+        let (graph, mints) = mock_graph(
+            4,
+            vec![
+                MockPool::new(0, 1, 1_000_000_000, 1_000_000_000),
+                MockPool::new(1, 2, 1_000_000_000, 1_000_000_000),
+                MockPool::new(2, 3, 1_000_000_000, 1_000_000_000),
+                MockPool::new(3, 1, 1_000_000_000, 1_200_000_000),
+            ],
+        );
+        let edges = build_edges(&graph, &mints);
+        let cycles = find_negative_cycles(mints.len(), &edges, &[0, 1, 2, 3], 4, 8);
+
+        assert!(!cycles.is_empty());
+        let best = &cycles[0];
+        assert_eq!(best.n_hops(), 3);
+        assert!(!best.mint_idxs.contains(&0));
+
+        let rotated = best.rotate_to(3).unwrap();
+        assert_eq!(rotated.mint_idxs[0], 3);
+        assert_eq!(rotated.key(), best.key());
+        assert!(rotated.quote(&mints, 1_000_000) > 1_000_000);
+    }
+
+    #[test]
+    fn reused_pools_see_earlier_hops() {
+        // 0 -> 1 through pool 0 twice, back through pools 1 and 2
+        let (graph, mints) = mock_graph(
+            2,
+            vec![
+                MockPool::new(0, 1, 1_000_000_000, 1_200_000_000),
+                MockPool::new(0, 1, 1_000_000_000, 1_000_000_000),
+                MockPool::new(0, 1, 1_000_000_000, 1_000_000_000),
+            ],
+        );
+        let pool = |id: usize| {
+            graph.0[&PoolIndex(0)].0[&PoolIndex(1)]
+                .iter()
+                .find(|p| p.1 == id)
+                .unwrap()
+                .clone()
+        };
+        let cycle = Cycle {
+            mint_idxs: vec![0, 1, 0, 1, 0],
+            pools: vec![pool(0), pool(1), pool(0), pool(2)],
+            log_weight: 0.0,
+        };
+        assert!(cycle.reuses_pools());
+
+        // hop by hop against the streamed reserves
+        let amount_in = 10_000_000;
+        let mut naive = amount_in;
+        for (i, pool) in cycle.pools.iter().enumerate() {
+            naive = pool.read().get_quote_with_amounts_scaled(
+                naive,
+                &mints[cycle.mint_idxs[i]],
+                &mints[cycle.mint_idxs[i + 1]],
+            );
+        }
+        let quoted = cycle.quote(&mints, amount_in);
+        assert!(quoted < naive);
+
+        // the second swap through pool 0 gets its moved reserves, the streamed pool is untouched
+        let mut snapshots = PoolSnapshots::new();
+        assert_eq!(cycle.quote_with_snapshots(&mints, amount_in, &mut snapshots), quoted);
+        assert_eq!(snapshots.len(), 3);
+        let reserve_0 = |pool: &dyn PoolOperations| {
+            pool.get_constant_product_reserves(&mints[0], &mints[1]).unwrap().0
+        };
+        assert!(reserve_0(&**pool(0).read()) < reserve_0(&*snapshots[&0]));
+        assert_eq!(cycle.quote(&mints, amount_in), quoted);
+    }
+
+    #[test]
+    fn fair_graph_has_no_cycles() {
+        let (graph, mints) = mock_graph(
+            3,
+            vec![
+                MockPool::new(0, 1, 1_000_000_000, 2_000_000_000),
+                MockPool::new(1, 2, 1_000_000_000, 1_000_000_000),
+                MockPool::new(2, 0, 2_000_000_000, 1_000_000_000),
+            ],
+        );
+        let edges = build_edges(&graph, &mints);
+        let cycles = find_negative_cycles(mints.len(), &edges, &[0, 1, 2], 4, 8);
+        assert!(cycles.is_empty());
+    }
+
+    #[test]
+    fn finds_overlapping_cycles_and_respects_max_hops() {
+        let (graph, mints) = mock_graph(
+            4,
+            vec![
+                MockPool::new(0, 1, 1_000_000_000, 1_100_000_000),
+                MockPool::new(1, 0, 1_000_000_000, 1_000_000_000),
+                MockPool::new(1, 2, 1_000_000_000, 1_000_000_000),
+                MockPool::new(2, 3, 1_000_000_000, 1_000_000_000),
+                MockPool::new(3, 0, 1_000_000_000, 1_000_000_000),
+            ],
+        );
+        let edges = build_edges(&graph, &mints);
+
+        let cycles = find_negative_cycles(mints.len(), &edges, &[0], 4, 8);
+        let lengths: HashSet<usize> = cycles.iter().map(|c| c.n_hops()).collect();
+        assert!(lengths.contains(&2));
+        assert!(lengths.contains(&4));
+
+        let short = find_negative_cycles(mints.len(), &edges, &[0], 3, 8);
+        assert!(short.iter().all(|c| c.n_hops() <= 3));
+    }
+
+    #[test]
+    fn parallel_search_is_deterministic() {
+        let (graph, mints) = mock_graph(
+            5,
+            vec![
+                MockPool::new(0, 1, 1_000_000_000, 1_100_000_000),
+                MockPool::new(1, 0, 1_000_000_000, 1_000_000_000),
+                MockPool::new(1, 2, 1_000_000_000, 1_000_000_000),
+                MockPool::new(2, 3, 1_000_000_000, 1_000_000_000),
+                MockPool::new(3, 0, 1_000_000_000, 1_000_000_000),
+                MockPool::new(2, 4, 1_000_000_000, 1_000_000_000),
+                MockPool::new(4, 1, 1_000_000_000, 1_150_000_000),
+            ],
+        );
+        let edges = build_edges(&graph, &mints);
+        let starts: Vec<usize> = (0..mints.len()).collect();
+        let search = |n_threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(n_threads)
+                .build()
+                .unwrap()
+                .install(|| par_find_cycles(mints.len(), &edges, &starts, 4, None))
+        };
+
+        let keys = |cycles: &[Cycle]| cycles.iter().map(|c| c.key()).collect::<Vec<_>>();
+        let serial = search(1);
+        let parallel = search(4);
+        assert_eq!(keys(&serial), keys(&parallel));
+
+        // no duplicates and both mispriced loops are found
+        let unique: HashSet<String> = keys(&serial).into_iter().collect();
+        assert_eq!(unique.len(), serial.len());
+        assert!(serial
+            .iter()
+            .any(|c| c.n_hops() == 2 && c.mint_idxs.contains(&0)));
+        assert!(serial
+            .iter()
+            .any(|c| c.n_hops() == 3 && c.mint_idxs.contains(&4)));
+        assert!(serial.iter().all(|c| c.log_weight < 0.0));
+        assert!(serial
+            .windows(2)
+            .all(|w| w[0].log_weight <= w[1].log_weight));
+    }
+
+    #[test]
+    fn parallel_search_finds_cycles_sharing_all_mints() {
+        // 0 -> 1 through pool 0, back through pool 2 (best) or pool 1 -- the
+        // same mints and hop count, only the lightest walk of which a
+        // one-walk-per-hop search would keep
+        let (graph, mints) = mock_graph(
+            2,
+            vec![
+                MockPool::new(0, 1, 1_000_000_000, 1_200_000_000),
+                MockPool::new(0, 1, 1_000_000_000, 1_000_000_000),
+                MockPool::new(0, 1, 1_000_000_000, 980_000_000),
+            ],
+        );
+        let edges = build_edges(&graph, &mints);
+        let cycles = par_find_cycles(mints.len(), &edges, &[0, 1], 2, None);
+
+        let mut pool_ids: Vec<Vec<usize>> = cycles
+            .iter()
+            .map(|c| c.pools.iter().map(|p| p.1).collect())
+            .collect();
+        pool_ids.sort();
+        assert_eq!(pool_ids, vec![vec![0, 1], vec![0, 2]]);
+        assert!(cycles.iter().all(|c| c.log_weight < 0.0));
+    }
+
+    #[test]
+    fn index_requotes_cycles_touching_updated_pools() {
+        // square 0-1-2-3-0 with a 0-2 diagonal (pool 4)
+        let (graph, mints) = mock_graph(
+            4,
+            vec![
+                MockPool::new(0, 1, 1_000_000_000, 1_000_000_000),
+                MockPool::new(1, 2, 1_000_000_000, 1_000_000_000),
+                MockPool::new(2, 3, 1_000_000_000, 1_000_000_000),
+                MockPool::new(3, 0, 1_000_000_000, 1_000_000_000),
+                MockPool::new(0, 2, 1_000_000_000, 1_000_000_000),
+            ],
+        );
+        let edges = build_edges(&graph, &mints);
+
+        // both triangles + the square, in both directions
+        let cycles = enumerate_cycles(mints.len(), &edges, &[0], 4, MAX_CYCLES_PER_START);
+        assert_eq!(cycles.len(), 6);
+        assert!(cycles.iter().all(|c| c.mint_idxs.contains(&0)));
+        assert!(enumerate_cycles(mints.len(), &edges, &[0], 3, MAX_CYCLES_PER_START)
+            .iter()
+            .all(|c| c.n_hops() == 3));
+
+        // a capped walk keeps the first cycles it finds (lightest edges first)
+        let capped = enumerate_cycles(mints.len(), &edges, &[0], 4, 2);
+        assert_eq!(capped.len(), 2);
+        assert!(capped.iter().all(|c| cycles.iter().any(|d| d.key() == c.key())));
+
+        let mut index = CycleIndex::new(cycles);
+        let touching = index.touching(&HashSet::from([4]));
+        assert_eq!(touching.len(), 4);
+        assert!(touching.iter().all(|c| c.pools.iter().any(|p| p.1 == 4)));
+        assert!(!index.insert(touching[0].rotate_to(2).unwrap()));
+        assert_eq!(index.len(), 6);
+
+        // fair pools only lose fees, repricing the diagonal opens a triangle
+        assert!(touching.iter().all(|c| c.marginal_log_weight(&mints) > 0.0));
+        let diagonal = &graph.0[&PoolIndex(0)].0[&PoolIndex(2)][0];
+        diagonal.0.write().unwrap().set_update_accounts(
+            vec![
+                Some(mock_reserve_account(1_000_000_000)),
+                Some(mock_reserve_account(1_200_000_000)),
+            ],
+            Cluster::Localnet,
+        );
+        let profitable: Vec<&Cycle> = touching
+            .iter()
+            .filter(|c| c.marginal_log_weight(&mints) < 0.0)
+            .collect();
+        assert_eq!(profitable.len(), 2);
+        assert!(profitable
+            .iter()
+            .all(|c| c.quote(&mints, 1_000_000) > 1_000_000));
+    }
+}
@@ -0,0 +1,778 @@
+//! Pool discovery from on-chain program accounts
+//!
+//! Scans the Orca (token swap), Raydium AMM v4 and Serum programs with
+//! `getProgramAccounts`, decodes each pool's state, resolves its vaults and
+//! mint decimals and produces the same JSON the loaders in `pools/` read.
+//! Accounts come from an `AccountSource` -- an RPC node (mainnet or a local
+//! validator) or a directory of recorded `solana account --output json` files.
+
+use anchor_client::solana_client::rpc_client::RpcClient;
+use anchor_client::solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use anchor_client::solana_client::rpc_filter::RpcFilterType;
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_sdk::account::Account;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::constants::*;
+use crate::pool::{PoolType, POOL_TYPE_FIELD};
+use crate::pool_utils::common::{read_pubkey, read_u64};
+use crate::pool_utils::raydium::{unpack_amm_info, AmmInfo, AMM_INFO_LEN};
+use crate::pools::{OrcaPool, RaydiumPool, SerumPool};
+use crate::serialize::pool::{Fraction, JSONFeeStructure};
+use crate::serialize::token::{unpack_token_account, Token, WrappedPubkey};
+use crate::utils::read_json_dir;
+
+pub const TOKEN_SWAP_LEN: usize = 324;
+pub const RAYDIUM_AMM_V4_LEN: usize = AMM_INFO_LEN;
+pub const SERUM_MARKET_LEN: usize = 388;
+
+const TOKEN_ACCOUNT_LEN: usize = 165;
+const MINT_LEN: usize = 82;
+const MINT_DECIMALS_OFFSET: usize = 44;
+
+const RAYDIUM_AUTHORITY_SEED: &[u8] = b"amm authority";
+const SERUM_MARKET_FLAGS: u64 = 0b11; // initialized | market
+const SERUM_DISABLED_FLAG: u64 = 1 << 7;
+const SERUM_BASE_TAKER_FEE: f64 = 0.0004; // FeeTier::Base
+
+/// Where discovery reads accounts from
+pub trait AccountSource {
+    /// every account owned by `program_id` which is `data_size` bytes long
+    fn program_accounts(
+        &self,
+        program_id: &Pubkey,
+        data_size: usize,
+    ) -> Result<Vec<(Pubkey, Account)>>;
+    fn accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>>;
+}
+
+impl AccountSource for RpcClient {
+    fn program_accounts(
+        &self,
+        program_id: &Pubkey,
+        data_size: usize,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::DataSize(data_size as u64)]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(self.commitment()),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+        Ok(self.get_program_accounts_with_config(program_id, config)?)
+    }
+
+    fn accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        let mut accounts = vec![];
+        // max 100 accounts per get_multiple_accounts
+        for chunk in pubkeys.chunks(99) {
+            accounts.extend(self.get_multiple_accounts(chunk)?);
+        }
+        Ok(accounts)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct FixtureAccount {
+    pubkey: String,
+    account: UiAccount,
+}
+
+/// Accounts recorded with `solana account <pubkey> --output json` (the same
+/// files `solana-test-validator --account` loads)
+#[derive(Debug, Default, Clone)]
+pub struct FixtureAccounts(pub HashMap<Pubkey, Account>);
+
+impl FixtureAccounts {
+    pub fn load(dir: &str) -> Result<Self> {
+        let mut fixtures = Self::default();
+        for path in read_json_dir(&dir.to_string()) {
+            let json_str = std::fs::read_to_string(&path)?;
+            let fixture: FixtureAccount = serde_json::from_str(&json_str)
+                .map_err(|e| anyhow!("Failed to parse fixture {}: {}", path, e))?;
+            let pubkey = Pubkey::from_str(&fixture.pubkey)
+                .map_err(|_| anyhow!("Invalid pubkey in fixture {}", path))?;
+            let account = fixture
+                .account
+                .decode::<Account>()
+                .ok_or_else(|| anyhow!("Failed to decode fixture {}", path))?;
+            fixtures.0.insert(pubkey, account);
+        }
+        Ok(fixtures)
+    }
+
+    /// write `account` as a fixture file in `dir`
+    pub fn save(dir: &str, pubkey: &Pubkey, account: &Account) -> Result<()> {
+        let fixture = FixtureAccount {
+            pubkey: pubkey.to_string(),
+            account: UiAccount::encode(pubkey, account, UiAccountEncoding::Base64, None, None),
+        };
+        let path = std::path::Path::new(dir).join(format!("{}.json", pubkey));
+        std::fs::write(path, serde_json::to_string_pretty(&fixture)?)?;
+        Ok(())
+    }
+}
+
+impl AccountSource for FixtureAccounts {
+    fn program_accounts(
+        &self,
+        program_id: &Pubkey,
+        data_size: usize,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        let mut accounts: Vec<(Pubkey, Account)> = self
+            .0
+            .iter()
+            .filter(|(_, account)| account.owner == *program_id && account.data.len() == data_size)
+            .map(|(pubkey, account)| (*pubkey, account.clone()))
+            .collect();
+        accounts.sort_by_key(|(pubkey, _)| *pubkey);
+        Ok(accounts)
+    }
+
+    fn accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(pubkeys.iter().map(|pk| self.0.get(pk).cloned()).collect())
+    }
+}
+
+/// Which discovered pools are worth writing out
+#[derive(Debug, Clone, Default)]
+pub struct PoolFilter {
+    pub min_liquidity: f64,             // per side, in whole tokens (unscaled)
+    pub mints: Option<HashSet<Pubkey>>, // both mints must be in the allow-list
+}
+
+impl PoolFilter {
+    pub fn allows_mints(&self, mints: &[Pubkey; 2]) -> bool {
+        match &self.mints {
+            Some(allowed) => mints.iter().all(|mint| allowed.contains(mint)),
+            None => true,
+        }
+    }
+
+    pub fn has_liquidity(&self, amount: u64, decimals: u8) -> bool {
+        amount as f64 / 10_f64.powi(decimals as i32) >= self.min_liquidity
+    }
+}
+
+/// A discovered pool and its loader-compatible JSON
+#[derive(Debug, Clone)]
+pub struct DiscoveredPool {
+    pub tipe: PoolType,
+    pub address: Pubkey,
+    pub json: serde_json::Value,
+}
+
+impl DiscoveredPool {
+    fn new<T: Serialize>(tipe: PoolType, address: Pubkey, pool: &T) -> Result<Self> {
+        Ok(Self {
+            tipe,
+            address,
+            json: serde_json::to_value(pool)?,
+        })
+    }
+
+    /// the JSON with its `"type"` -- for mixed dirs and snapshots
+    pub fn tagged_json(&self) -> serde_json::Value {
+        let mut json = self.json.clone();
+        if let serde_json::Value::Object(fields) = &mut json {
+            fields.insert(POOL_TYPE_FIELD.to_string(), self.tipe.tag().into());
+        }
+        json
+    }
+}
+
+/// SPL token-swap state (Orca), `SwapVersion::SwapV1`
+#[derive(Debug, Clone)]
+pub struct TokenSwapState {
+    pub nonce: u8,
+    pub token_a: Pubkey,
+    pub token_b: Pubkey,
+    pub pool_mint: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub fee_account: Pubkey,
+    pub trade_fee: (u64, u64),
+    pub owner_trade_fee: (u64, u64),
+    pub curve_type: u8,
+    pub amp: u64, // only for the stable curve
+}
+
+pub fn decode_token_swap(data: &[u8]) -> Option<TokenSwapState> {
+    // [version, is_initialized, nonce, token_program, ...]
+    if data.len() != TOKEN_SWAP_LEN || data[0] != 1 || data[1] != 1 {
+        return None;
+    }
+    Some(TokenSwapState {
+        nonce: data[2],
+        token_a: read_pubkey(data, 35),
+        token_b: read_pubkey(data, 67),
+        pool_mint: read_pubkey(data, 99),
+        mint_a: read_pubkey(data, 131),
+        mint_b: read_pubkey(data, 163),
+        fee_account: read_pubkey(data, 195),
+        trade_fee: (read_u64(data, 227), read_u64(data, 235)),
+        owner_trade_fee: (read_u64(data, 243), read_u64(data, 251)),
+        curve_type: data[291],
+        amp: read_u64(data, 292),
+    })
+}
+
+/// Serum v3 `MarketState` (with its 5 byte "serum" head padding)
+#[derive(Debug, Clone)]
+pub struct SerumMarketState {
+    pub own_address: Pubkey,
+    pub vault_signer_nonce: u64,
+    pub coin_mint: Pubkey,
+    pub pc_mint: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+}
+
+pub fn decode_serum_market(data: &[u8]) -> Option<SerumMarketState> {
+    if data.len() != SERUM_MARKET_LEN || &data[..5] != b"serum" {
+        return None;
+    }
+    let flags = read_u64(data, 5);
+    if flags & SERUM_MARKET_FLAGS != SERUM_MARKET_FLAGS || flags & SERUM_DISABLED_FLAG != 0 {
+        return None;
+    }
+    Some(SerumMarketState {
+        own_address: read_pubkey(data, 13),
+        vault_signer_nonce: read_u64(data, 45),
+        coin_mint: read_pubkey(data, 53),
+        pc_mint: read_pubkey(data, 85),
+        coin_vault: read_pubkey(data, 117),
+        pc_vault: read_pubkey(data, 165),
+        request_queue: read_pubkey(data, 221),
+        event_queue: read_pubkey(data, 253),
+        bids: read_pubkey(data, 285),
+        asks: read_pubkey(data, 317),
+    })
+}
+
+fn serum_vault_signer(market: &Pubkey, nonce: u64, market_program: &Pubkey) -> Option<Pubkey> {
+    Pubkey::create_program_address(&[&market.to_bytes(), &nonce.to_le_bytes()], market_program).ok()
+}
+
+/// vault + mint accounts the pools reference
+struct ResolvedAccounts(HashMap<Pubkey, Account>);
+
+impl ResolvedAccounts {
+    fn fetch(source: &impl AccountSource, pubkeys: HashSet<Pubkey>) -> Result<Self> {
+        let mut pubkeys: Vec<Pubkey> = pubkeys.into_iter().collect();
+        pubkeys.sort();
+        let accounts = source.accounts(&pubkeys)?;
+        Ok(Self(
+            pubkeys
+                .into_iter()
+                .zip(accounts)
+                .filter_map(|(pubkey, account)| account.map(|account| (pubkey, account)))
+                .collect(),
+        ))
+    }
+
+    fn decimals(&self, mint: &Pubkey) -> Option<u8> {
+        let data = &self.0.get(mint)?.data;
+        if data.len() < MINT_LEN {
+            return None;
+        }
+        Some(data[MINT_DECIMALS_OFFSET])
+    }
+
+    fn token_amount(&self, vault: &Pubkey) -> Option<u64> {
+        let data = &self.0.get(vault)?.data;
+        if data.len() < TOKEN_ACCOUNT_LEN {
+            return None;
+        }
+        Some(unpack_token_account(data).amount)
+    }
+
+    /// decimals of each mint if both vaults pass the liquidity filter
+    fn liquid_decimals(
+        &self,
+        filter: &PoolFilter,
+        mints: &[Pubkey; 2],
+        vaults: &[Pubkey; 2],
+    ) -> Option<[u8; 2]> {
+        let mut decimals = [0; 2];
+        for (i, (mint, vault)) in mints.iter().zip(vaults).enumerate() {
+            decimals[i] = self.decimals(mint)?;
+            let amount = self.token_amount(vault)?;
+            if !filter.has_liquidity(amount, decimals[i]) {
+                return None;
+            }
+        }
+        Some(decimals)
+    }
+}
+
+fn token(mint: &Pubkey, vault: &Pubkey, decimals: u8) -> Token {
+    Token {
+        tag: mint.to_string(), // no on-chain symbol
+        name: mint.to_string(),
+        mint: WrappedPubkey(*mint),
+        scale: decimals as u64,
+        addr: WrappedPubkey(*vault),
+    }
+}
+
+fn tokens(mints: &[Pubkey; 2], vaults: &[Pubkey; 2], decimals: &[u8; 2]) -> HashMap<String, Token> {
+    (0..2)
+        .map(|i| {
+            (
+                mints[i].to_string(),
+                token(&mints[i], &vaults[i], decimals[i]),
+            )
+        })
+        .collect()
+}
+
+fn fraction((numerator, denominator): (u64, u64)) -> Fraction {
+    Fraction {
+        numerator,
+        denominator,
+    }
+}
+
+pub fn discover_orca_pools(
+    source: &impl AccountSource,
+    filter: &PoolFilter,
+) -> Result<Vec<DiscoveredPool>> {
+    let swaps: Vec<(Pubkey, TokenSwapState)> = source
+        .program_accounts(&ORCA_PROGRAM_ID, TOKEN_SWAP_LEN)?
+        .into_iter()
+        .filter_map(|(address, account)| Some((address, decode_token_swap(&account.data)?)))
+        .filter(|(_, swap)| filter.allows_mints(&[swap.mint_a, swap.mint_b]))
+        .collect();
+
+    let mut pubkeys = HashSet::new();
+    for (_, swap) in &swaps {
+        pubkeys.extend([
+            swap.mint_a,
+            swap.mint_b,
+            swap.token_a,
+            swap.token_b,
+            swap.pool_mint,
+        ]);
+    }
+    let resolved = ResolvedAccounts::fetch(source, pubkeys)?;
+
+    let mut pools = vec![];
+    for (address, swap) in swaps {
+        let mints = [swap.mint_a, swap.mint_b];
+        let vaults = [swap.token_a, swap.token_b];
+        let (decimals, pool_token_decimals) = match (
+            resolved.liquid_decimals(filter, &mints, &vaults),
+            resolved.decimals(&swap.pool_mint),
+        ) {
+            (Some(decimals), Some(pool_token_decimals)) => (decimals, pool_token_decimals),
+            _ => continue,
+        };
+        let authority = match Pubkey::create_program_address(
+            &[&address.to_bytes(), &[swap.nonce]],
+            &ORCA_PROGRAM_ID,
+        ) {
+            Ok(authority) => authority,
+            Err(_) => {
+                warn!("Skipping orca pool {} with an invalid nonce", address);
+                continue;
+            }
+        };
+
+        let pool = OrcaPool {
+            address: WrappedPubkey(address),
+            nonce: swap.nonce as u64,
+            authority: WrappedPubkey(authority),
+            pool_token_mint: WrappedPubkey(swap.pool_mint),
+            pool_token_decimals: pool_token_decimals as u64,
+            fee_account: WrappedPubkey(swap.fee_account),
+            token_ids: mints.iter().map(|mint| mint.to_string()).collect(),
+            tokens: tokens(&mints, &vaults, &decimals),
+            fee_structure: JSONFeeStructure {
+                trader_fee: fraction(swap.trade_fee),
+                owner_fee: fraction(swap.owner_trade_fee),
+            },
+            curve_type: swap.curve_type,
+            amp: swap.amp,
+            pool_amounts: HashMap::new(),
+        };
+        pools.push(DiscoveredPool::new(PoolType::OrcaPoolType, address, &pool)?);
+    }
+    Ok(pools)
+}
+
+pub fn discover_raydium_pools(
+    source: &impl AccountSource,
+    filter: &PoolFilter,
+) -> Result<Vec<DiscoveredPool>> {
+    let amms: Vec<(Pubkey, AmmInfo)> = source
+        .program_accounts(&RAYDIUM_PROGRAM_ID, RAYDIUM_AMM_V4_LEN)?
+        .into_iter()
+        .filter_map(|(address, account)| Some((address, unpack_amm_info(&account.data)?)))
+        .filter(|(_, amm)| filter.allows_mints(&[amm.coin_mint, amm.pc_mint]))
+        .collect();
+
+    // + the serum/openbook market each amm is attached to
+    let mut pubkeys = HashSet::new();
+    for (_, amm) in &amms {
+        pubkeys.extend([
+            amm.coin_mint,
+            amm.pc_mint,
+            amm.coin_vault,
+            amm.pc_vault,
+            amm.market,
+        ]);
+    }
+    let resolved = ResolvedAccounts::fetch(source, pubkeys)?;
+
+    let mut pools = vec![];
+    for (address, amm) in amms {
+        let mints = [amm.coin_mint, amm.pc_mint];
+        let vaults = [amm.coin_vault, amm.pc_vault];
+        let decimals = match resolved.liquid_decimals(filter, &mints, &vaults) {
+            Some(decimals) => decimals,
+            None => continue,
+        };
+        let market = match resolved
+            .0
+            .get(&amm.market)
+            .and_then(|account| decode_serum_market(&account.data))
+        {
+            Some(market) => market,
+            None => {
+                warn!("Skipping raydium amm {} without a live market", address);
+                continue;
+            }
+        };
+        let authority = Pubkey::create_program_address(
+            &[RAYDIUM_AUTHORITY_SEED, &[amm.nonce as u8]],
+            &RAYDIUM_PROGRAM_ID,
+        );
+        let vault_signer =
+            serum_vault_signer(&amm.market, market.vault_signer_nonce, &amm.market_program);
+        let (authority, vault_signer) = match (authority, vault_signer) {
+            (Ok(authority), Some(vault_signer)) => (authority, vault_signer),
+            _ => {
+                warn!("Skipping raydium amm {} with an invalid nonce", address);
+                continue;
+            }
+        };
+
+        let pool = RaydiumPool {
+            address: WrappedPubkey(address),
+            authority: WrappedPubkey(authority),
+            open_orders: WrappedPubkey(amm.open_orders),
+            target_orders: WrappedPubkey(amm.target_orders),
+            lp_mint: WrappedPubkey(amm.lp_mint),
+            coin_mint: WrappedPubkey(amm.coin_mint),
+            pc_mint: WrappedPubkey(amm.pc_mint),
+            coin_decimals: decimals[0] as u64,
+            pc_decimals: decimals[1] as u64,
+            coin_vault: WrappedPubkey(amm.coin_vault),
+            pc_vault: WrappedPubkey(amm.pc_vault),
+            market_program: WrappedPubkey(amm.market_program),
+            market: WrappedPubkey(amm.market),
+            market_bids: WrappedPubkey(market.bids),
+            market_asks: WrappedPubkey(market.asks),
+            market_event_queue: WrappedPubkey(market.event_queue),
+            market_coin_vault: WrappedPubkey(market.coin_vault),
+            market_pc_vault: WrappedPubkey(market.pc_vault),
+            market_vault_signer: WrappedPubkey(vault_signer),
+            fee_numerator: amm.swap_fee_numerator,
+            fee_denominator: amm.swap_fee_denominator,
+            coin_reserve: 0,
+            pc_reserve: 0,
+        };
+        pools.push(DiscoveredPool::new(
+            PoolType::RaydiumPoolType,
+            address,
+            &pool,
+        )?);
+    }
+    Ok(pools)
+}
+
+pub fn discover_serum_markets(
+    source: &impl AccountSource,
+    filter: &PoolFilter,
+) -> Result<Vec<DiscoveredPool>> {
+    let markets: Vec<(Pubkey, SerumMarketState)> = source
+        .program_accounts(&SERUM_PROGRAM_ID, SERUM_MARKET_LEN)?
+        .into_iter()
+        .filter_map(|(address, account)| Some((address, decode_serum_market(&account.data)?)))
+        .filter(|(_, market)| filter.allows_mints(&[market.coin_mint, market.pc_mint]))
+        .collect();
+
+    let mut pubkeys = HashSet::new();
+    for (_, market) in &markets {
+        pubkeys.extend([
+            market.coin_mint,
+            market.pc_mint,
+            market.coin_vault,
+            market.pc_vault,
+        ]);
+    }
+    let resolved = ResolvedAccounts::fetch(source, pubkeys)?;
+
+    let mut pools = vec![];
+    for (address, market) in markets {
+        let mints = [market.coin_mint, market.pc_mint];
+        let vaults = [market.coin_vault, market.pc_vault];
+        let decimals = match resolved.liquid_decimals(filter, &mints, &vaults) {
+            Some(decimals) => decimals,
+            None => continue,
+        };
+        let vault_signer =
+            match serum_vault_signer(&address, market.vault_signer_nonce, &SERUM_PROGRAM_ID) {
+                Some(vault_signer) => vault_signer,
+                None => {
+                    warn!("Skipping serum market {} with an invalid nonce", address);
+                    continue;
+                }
+            };
+
+        let pool = SerumPool {
+            own_address: WrappedPubkey(address),
+            base_mint: WrappedPubkey(market.coin_mint),
+            quote_mint: WrappedPubkey(market.pc_mint),
+            base_scale: decimals[0] as u64,
+            quote_scale: decimals[1] as u64,
+            base_vault: WrappedPubkey(market.coin_vault),
+            quote_vault: WrappedPubkey(market.pc_vault),
+            request_queue: WrappedPubkey(market.request_queue),
+            event_queue: WrappedPubkey(market.event_queue),
+            bids: WrappedPubkey(market.bids),
+            asks: WrappedPubkey(market.asks),
+            vault_signer: WrappedPubkey(vault_signer),
+            taker_fee_pct: SERUM_BASE_TAKER_FEE,
+            accounts: None,
+            open_orders: None,
+        };
+        pools.push(DiscoveredPool::new(
+            PoolType::SerumPoolType,
+            address,
+            &pool,
+        )?);
+    }
+    Ok(pools)
+}
+
+/// every pool the discoverable programs own, in (type, address) order
+pub fn discover_pools(
+    source: &impl AccountSource,
+    filter: &PoolFilter,
+) -> Result<Vec<DiscoveredPool>> {
+    let mut pools = discover_orca_pools(source, filter)?;
+    pools.extend(discover_raydium_pools(source, filter)?);
+    pools.extend(discover_serum_markets(source, filter)?);
+    Ok(pools)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool::POOL_REGISTRY;
+
+    fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn account(owner: &Pubkey, data: Vec<u8>) -> Account {
+        Account {
+            lamports: 1_000_000,
+            data,
+            owner: *owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn mint_account(decimals: u8) -> Account {
+        let mut data = vec![0; MINT_LEN];
+        data[MINT_DECIMALS_OFFSET] = decimals;
+        data[45] = 1; // is_initialized
+        account(&TOKEN_PROGRAM_ID, data)
+    }
+
+    fn token_account(mint: &Pubkey, amount: u64) -> Account {
+        let mut data = vec![0; TOKEN_ACCOUNT_LEN];
+        put(&mut data, 0, &mint.to_bytes());
+        put(&mut data, 64, &amount.to_le_bytes());
+        data[108] = 1; // initialized
+        account(&TOKEN_PROGRAM_ID, data)
+    }
+
+    struct Fixture {
+        accounts: FixtureAccounts,
+        usdc: Pubkey,
+        sol: Pubkey,
+        orca_pool: Pubkey,
+        raydium_amm: Pubkey,
+        serum_market: Pubkey,
+    }
+
+    /// one pool per DEX over (sol, usdc) with `usdc_reserve` in each vault
+    fn fixture(usdc_reserve: u64) -> Fixture {
+        let mut accounts = FixtureAccounts::default();
+        let (sol, usdc, lp) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        accounts.0.insert(sol, mint_account(9));
+        accounts.0.insert(usdc, mint_account(6));
+        accounts.0.insert(lp, mint_account(6));
+
+        let mut vaults = vec![];
+        for _ in 0..3 {
+            let (sol_vault, usdc_vault) = (Pubkey::new_unique(), Pubkey::new_unique());
+            accounts
+                .0
+                .insert(sol_vault, token_account(&sol, 10_000 * 10_u64.pow(9)));
+            accounts
+                .0
+                .insert(usdc_vault, token_account(&usdc, usdc_reserve));
+            vaults.push((sol_vault, usdc_vault));
+        }
+
+        // orca: find a nonce which makes a valid authority
+        let orca_pool = Pubkey::new_unique();
+        let (_, nonce) = Pubkey::find_program_address(&[&orca_pool.to_bytes()], &ORCA_PROGRAM_ID);
+        let mut data = vec![0; TOKEN_SWAP_LEN];
+        data[0] = 1;
+        data[1] = 1;
+        data[2] = nonce;
+        put(&mut data, 35, &vaults[0].0.to_bytes());
+        put(&mut data, 67, &vaults[0].1.to_bytes());
+        put(&mut data, 99, &lp.to_bytes());
+        put(&mut data, 131, &sol.to_bytes());
+        put(&mut data, 163, &usdc.to_bytes());
+        put(&mut data, 227, &25_u64.to_le_bytes());
+        put(&mut data, 235, &10_000_u64.to_le_bytes());
+        accounts
+            .0
+            .insert(orca_pool, account(&ORCA_PROGRAM_ID, data));
+
+        let serum_market = Pubkey::new_unique();
+        let nonce = (0..u64::MAX)
+            .find(|nonce| {
+                Pubkey::create_program_address(
+                    &[&serum_market.to_bytes(), &nonce.to_le_bytes()],
+                    &SERUM_PROGRAM_ID,
+                )
+                .is_ok()
+            })
+            .unwrap();
+        let mut data = vec![0; SERUM_MARKET_LEN];
+        put(&mut data, 0, b"serum");
+        put(&mut data, 5, &SERUM_MARKET_FLAGS.to_le_bytes());
+        put(&mut data, 13, &serum_market.to_bytes());
+        put(&mut data, 45, &nonce.to_le_bytes());
+        put(&mut data, 53, &sol.to_bytes());
+        put(&mut data, 85, &usdc.to_bytes());
+        put(&mut data, 117, &vaults[2].0.to_bytes());
+        put(&mut data, 165, &vaults[2].1.to_bytes());
+        accounts
+            .0
+            .insert(serum_market, account(&SERUM_PROGRAM_ID, data));
+
+        let raydium_amm = Pubkey::new_unique();
+        let (_, nonce) =
+            Pubkey::find_program_address(&[RAYDIUM_AUTHORITY_SEED], &RAYDIUM_PROGRAM_ID);
+        let mut data = vec![0; RAYDIUM_AMM_V4_LEN];
+        put(&mut data, 0, &6_u64.to_le_bytes());
+        put(&mut data, 8, &(nonce as u64).to_le_bytes());
+        put(&mut data, 176, &25_u64.to_le_bytes());
+        put(&mut data, 184, &10_000_u64.to_le_bytes());
+        put(&mut data, 336, &vaults[1].0.to_bytes());
+        put(&mut data, 368, &vaults[1].1.to_bytes());
+        put(&mut data, 400, &sol.to_bytes());
+        put(&mut data, 432, &usdc.to_bytes());
+        put(&mut data, 464, &lp.to_bytes());
+        put(&mut data, 496, &Pubkey::new_unique().to_bytes()); // open orders
+        put(&mut data, 528, &serum_market.to_bytes());
+        put(&mut data, 560, &SERUM_PROGRAM_ID.to_bytes());
+        put(&mut data, 592, &Pubkey::new_unique().to_bytes()); // target orders
+        accounts
+            .0
+            .insert(raydium_amm, account(&RAYDIUM_PROGRAM_ID, data));
+
+        Fixture {
+            accounts,
+            usdc,
+            sol,
+            orca_pool,
+            raydium_amm,
+            serum_market,
+        }
+    }
+
+    #[test]
+    fn discovers_loadable_pools_from_fixtures() {
+        let f = fixture(5_000 * 10_u64.pow(6));
+        let dir = std::env::temp_dir().join("discover_pools_fixtures");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_str().unwrap();
+        for (pubkey, account) in &f.accounts.0 {
+            FixtureAccounts::save(dir, pubkey, account).unwrap();
+        }
+        let source = FixtureAccounts::load(dir).unwrap();
+        assert_eq!(source.0.len(), f.accounts.0.len());
+
+        let pools = discover_pools(&source, &PoolFilter::default()).unwrap();
+        let addresses: Vec<Pubkey> = pools.iter().map(|p| p.address).collect();
+        assert_eq!(addresses, vec![f.orca_pool, f.raydium_amm, f.serum_market]);
+
+        for pool in &pools {
+            // what discover_pools writes == what the pool loaders read
+            let json_str = pool.tagged_json().to_string();
+            let loaded = POOL_REGISTRY
+                .parse_tagged(None, "discovered.json", &json_str)
+                .unwrap();
+            let mut mints = vec![f.sol, f.usdc];
+            mints.sort();
+            assert_eq!(loaded.get_mints(), mints);
+            assert_eq!(loaded.mint_2_scale(&f.sol), Ok(9));
+            assert_eq!(loaded.mint_2_scale(&f.usdc), Ok(6));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn filters_by_liquidity_and_mints() {
+        let f = fixture(5_000 * 10_u64.pow(6));
+        let filter = PoolFilter {
+            min_liquidity: 1_000.0,
+            mints: None,
+        };
+        assert_eq!(discover_pools(&f.accounts, &filter).unwrap().len(), 3);
+
+        let thin = fixture(500 * 10_u64.pow(6));
+        assert!(discover_pools(&thin.accounts, &filter).unwrap().is_empty());
+
+        let filter = PoolFilter {
+            min_liquidity: 0.0,
+            mints: Some(HashSet::from([f.usdc])),
+        };
+        assert!(discover_pools(&f.accounts, &filter).unwrap().is_empty());
+
+        let filter = PoolFilter {
+            min_liquidity: 0.0,
+            mints: Some(HashSet::from([f.usdc, f.sol])),
+        };
+        assert_eq!(discover_pools(&f.accounts, &filter).unwrap().len(), 3);
+    }
+}
@@ -2,6 +2,7 @@
 
 use num_derive::FromPrimitive;
 use solana_program::{decode_error::DecodeError, program_error::ProgramError};
+use anchor_client::solana_sdk::pubkey::Pubkey;
 use thiserror::Error;
 
 /// Errors that may be returned by the TokenSwap program.
@@ -114,5 +115,77 @@ impl<T> DecodeError<T> for SwapError {
     }
 }
 
+/// Errors loading a pool from its JSON file
+#[derive(Debug, Error)]
+pub enum PoolLoadError {
+    #[error("Failed to read pool file {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse pool file {path}: {source}")]
+    Parse {
+        path: String,
+        source: serde_json::Error,
+    },
+    #[error("Unknown pool type {tag:?} for pool file {path}")]
+    UnknownType { path: String, tag: String },
+    #[error("Pool file {path} has no \"type\" and no default pool type")]
+    MissingType { path: String },
+}
+
+/// Why a pool can't quote a swap
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum QuoteError {
+    #[error("Pool doesn't swap {mint_in} for {mint_out}")]
+    InvalidMints { mint_in: Pubkey, mint_out: Pubkey },
+    #[error("Pool doesn't hold {0}")]
+    UnknownMint(Pubkey),
+    #[error("Pool state hasn't been loaded")]
+    NotLoaded,
+    #[error("Pool can't trade: {0}")]
+    Untradable(String),
+    #[error("Pool doesn't have the liquidity to fill the swap")]
+    InsufficientLiquidity,
+    #[error("Swap calculation failed (overflow or nothing out)")]
+    CalculationFailure,
+}
 
+/// Why instructions can't be compiled into a signed v0 transaction
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum MessageCompileError {
+    #[error("Message references {0} accounts, at most 256 can be indexed")]
+    TooManyAccounts(usize),
+    #[error("Missing a signer for {0}")]
+    MissingSigner(Pubkey),
+}
 
+/// Why a route's simulation can't size its compute budget
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum SimulationError {
+    #[error("Simulation request failed: {0}")]
+    Rpc(String),
+    #[error("Transaction failed in simulation: {0}")]
+    Failed(String),
+    #[error("Simulation didn't report the compute units consumed")]
+    MissingUnits,
+}
+
+/// Why account streaming stopped (or never started)
+#[derive(Debug, Error)]
+pub enum StreamError {
+    #[error("Websocket error: {0}")]
+    Websocket(Box<tungstenite::Error>), // boxed, it's large
+    #[error("Can't parse notification: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Subscription rejected: {0}")]
+    Rejected(String),
+    #[error("Unexpected message: {0}")]
+    UnexpectedMessage(String),
+}
+
+impl From<tungstenite::Error> for StreamError {
+    fn from(e: tungstenite::Error) -> Self {
+        StreamError::Websocket(Box::new(e))
+    }
+}
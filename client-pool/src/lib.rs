@@ -1,6 +1,17 @@
 pub mod arb;
+pub mod ata;
+pub mod bundle;
+pub mod compute_budget;
+pub mod cycles;
+pub mod sizing;
+pub mod stream;
+pub mod discover;
+pub mod lookup_table;
+pub mod open_orders;
+pub mod priority_fee;
 pub mod serialize;
 pub mod utils;
+pub mod versioned;
 pub mod pool_utils; 
 pub mod error; 
 pub mod pool; 
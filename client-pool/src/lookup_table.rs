@@ -0,0 +1,657 @@
+//! Address lookup tables (mirrors solana's address lookup table program)
+//!
+//! A v0 transaction can reference an account by its (table, index) instead
+//! of its 32 byte key, so a route's pool + vault accounts cost a byte each
+//! once they're in a table. Tables are created by an authority at a recent
+//! slot (which seeds their address) and extended with more addresses, 256 at
+//! most. Extended addresses are usable from the slot after they're added.
+//!
+//! `setup_lookup_table` packs the pools' accounts into tables (keeping each
+//! pool's accounts in a single table) and records them in a table index; the
+//! bot loads the indexed tables and picks the ones which cover each route.
+
+use anchor_client::solana_client::rpc_client::RpcClient;
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::Program;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::system_program;
+
+use anyhow::{anyhow, Result};
+use arrayref::array_ref;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use crate::constants::*;
+use crate::pool::PoolOperations;
+
+/// most addresses a table can hold
+pub const LOOKUP_TABLE_MAX_ADDRESSES: usize = 256;
+/// table state before its addresses
+pub const LOOKUP_TABLE_META_SIZE: usize = 56;
+/// addresses per extend instruction which fit in a (legacy) transaction
+pub const MAX_EXTEND_ADDRESSES: usize = 30;
+/// slots a deactivated table stays in the slot hashes (+ can't be closed)
+pub const DEACTIVATION_COOLDOWN_SLOTS: u64 = 513;
+/// where the table index is kept by default
+pub const LOOKUP_TABLE_INDEX_PATH: &str = "./lookup_tables.json";
+
+const LOOKUP_TABLE_DISCRIMINATOR: u32 = 1;
+// instruction tags (bincode enum variant indexes)
+const CREATE_LOOKUP_TABLE: u32 = 0;
+const EXTEND_LOOKUP_TABLE: u32 = 2;
+const DEACTIVATE_LOOKUP_TABLE: u32 = 3;
+const CLOSE_LOOKUP_TABLE: u32 = 4;
+
+/// A lookup table + the addresses it holds, in order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LookupTable {
+    pub address: Pubkey,
+    pub addresses: Vec<Pubkey>,
+}
+
+/// The on-chain state of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct LookupTableAccount {
+    pub deactivation_slot: u64, // u64::MAX while active
+    pub last_extended_slot: u64,
+    pub authority: Option<Pubkey>, // None once frozen
+    pub table: LookupTable,
+}
+
+impl LookupTableAccount {
+    pub fn is_active(&self) -> bool {
+        self.deactivation_slot == u64::MAX
+    }
+
+    /// deactivated long enough ago to be closed
+    pub fn is_closable(&self, current_slot: u64) -> bool {
+        !self.is_active()
+            && current_slot > self.deactivation_slot.saturating_add(DEACTIVATION_COOLDOWN_SLOTS)
+    }
+}
+
+/// None if `data` isn't a lookup table
+pub fn unpack_lookup_table(address: &Pubkey, data: &[u8]) -> Option<LookupTableAccount> {
+    if data.len() < LOOKUP_TABLE_META_SIZE {
+        return None;
+    }
+    let discriminator = u32::from_le_bytes(*array_ref![data, 0, 4]);
+    if discriminator != LOOKUP_TABLE_DISCRIMINATOR {
+        return None;
+    }
+    // last extended slot start index @ 20
+    let authority = match data[21] {
+        1 => Some(Pubkey::new_from_array(*array_ref![data, 22, 32])),
+        _ => None,
+    };
+    let addresses = data[LOOKUP_TABLE_META_SIZE..]
+        .chunks_exact(32)
+        .map(|key| Pubkey::new_from_array(*array_ref![key, 0, 32]))
+        .collect();
+    Some(LookupTableAccount {
+        deactivation_slot: u64::from_le_bytes(*array_ref![data, 4, 8]),
+        last_extended_slot: u64::from_le_bytes(*array_ref![data, 12, 8]),
+        authority,
+        table: LookupTable {
+            address: *address,
+            addresses,
+        },
+    })
+}
+
+/// the on-chain state of each of `addresses`, None if it isn't a table
+pub fn fetch_lookup_table_accounts(
+    connection: &RpcClient,
+    addresses: &[Pubkey],
+) -> Vec<Option<LookupTableAccount>> {
+    let mut tables = vec![];
+    // max 100 accounts per get_multiple_accounts
+    for chunk in addresses.chunks(99) {
+        let accounts = connection.get_multiple_accounts(chunk).unwrap();
+        for (address, account) in chunk.iter().zip(accounts) {
+            tables.push(account.and_then(|account| unpack_lookup_table(address, &account.data)));
+        }
+    }
+    tables
+}
+
+/// the active tables of `addresses` (the others are skipped)
+pub fn fetch_lookup_tables(connection: &RpcClient, addresses: &[Pubkey]) -> Vec<LookupTable> {
+    let accounts = fetch_lookup_table_accounts(connection, addresses);
+    let mut tables = vec![];
+    for (address, account) in addresses.iter().zip(accounts) {
+        match account {
+            Some(account) if account.is_active() => tables.push(account.table),
+            _ => warn!("skipping lookup table {}: not an active table", address),
+        }
+    }
+    tables
+}
+
+/// address (+ bump) of the table `authority` creates at `recent_slot`
+pub fn derive_lookup_table_address(authority: &Pubkey, recent_slot: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[&authority.to_bytes(), &recent_slot.to_le_bytes()],
+        &ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+    )
+}
+
+/// creates the table of `authority` at `recent_slot` (a slot which isn't
+/// older than the slot hashes sysvar), + the table's address
+pub fn create_lookup_table_ix(
+    authority: &Pubkey,
+    payer: &Pubkey,
+    recent_slot: u64,
+) -> (Instruction, Pubkey) {
+    let (table, bump_seed) = derive_lookup_table_address(authority, recent_slot);
+    let mut data = CREATE_LOOKUP_TABLE.to_le_bytes().to_vec();
+    data.extend_from_slice(&recent_slot.to_le_bytes());
+    data.push(bump_seed);
+    let ix = Instruction {
+        program_id: *ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(table, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data,
+    };
+    (ix, table)
+}
+
+/// appends `new_addresses` to `table` (`payer` funds the extra rent)
+pub fn extend_lookup_table_ix(
+    table: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    new_addresses: &[Pubkey],
+) -> Instruction {
+    let mut data = EXTEND_LOOKUP_TABLE.to_le_bytes().to_vec();
+    data.extend_from_slice(&(new_addresses.len() as u64).to_le_bytes());
+    for address in new_addresses {
+        data.extend_from_slice(&address.to_bytes());
+    }
+    Instruction {
+        program_id: *ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*table, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data,
+    }
+}
+
+/// instruction `tag` on `table`, signed by its `authority`
+fn table_ix(
+    tag: u32,
+    table: &Pubkey,
+    authority: &Pubkey,
+    recipient: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*table, false),
+        AccountMeta::new_readonly(*authority, true),
+    ];
+    if let Some(recipient) = recipient {
+        accounts.push(AccountMeta::new(*recipient, false));
+    }
+    Instruction {
+        program_id: *ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+        accounts,
+        data: tag.to_le_bytes().to_vec(),
+    }
+}
+
+/// starts the cooldown after which `table` can be closed (it can't be used
+/// by new transactions from then on)
+pub fn deactivate_lookup_table_ix(table: &Pubkey, authority: &Pubkey) -> Instruction {
+    table_ix(DEACTIVATE_LOOKUP_TABLE, table, authority, None)
+}
+
+/// closes a deactivated `table`, its rent goes to `recipient`
+pub fn close_lookup_table_ix(
+    table: &Pubkey,
+    authority: &Pubkey,
+    recipient: &Pubkey,
+) -> Instruction {
+    table_ix(CLOSE_LOOKUP_TABLE, table, authority, Some(recipient))
+}
+
+/// the accounts each of `pools`' swaps can load from a table: every non signer
+/// account (minus the invoked program), in first use order -- only the
+/// directions the (loaded) pools can trade are swapped
+pub fn pool_account_groups(
+    pools: &[Box<dyn PoolOperations>],
+    program: &Program,
+    owner: &Pubkey,
+) -> Vec<Vec<Pubkey>> {
+    let mut groups = vec![];
+    for pool in pools {
+        let mints = pool.get_mints();
+        if mints.len() != 2 {
+            continue;
+        }
+        let mut seen = HashSet::new();
+        let mut addresses = vec![];
+        for (mint_in, mint_out) in [(mints[0], mints[1]), (mints[1], mints[0])] {
+            if !pool.can_trade(&mint_in, &mint_out) {
+                continue;
+            }
+            for ix in pool.swap_ix(program, owner, &mint_in, &mint_out) {
+                let keys = ix
+                    .accounts
+                    .iter()
+                    .filter(|meta| !meta.is_signer && meta.pubkey != ix.program_id)
+                    .map(|meta| meta.pubkey);
+                for key in keys {
+                    if seen.insert(key) {
+                        addresses.push(key);
+                    }
+                }
+            }
+        }
+        groups.push(addresses);
+    }
+    groups
+}
+
+/// A table of a packed set -- an existing one (+ the addresses to extend it
+/// with) or a new one to create
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TablePlan {
+    pub address: Option<Pubkey>, // None until it's created
+    pub addresses: Vec<Pubkey>,  // already stored
+    pub new_addresses: Vec<Pubkey>,
+}
+
+impl TablePlan {
+    pub fn len(&self) -> usize {
+        self.addresses.len() + self.new_addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PackedTables {
+    pub tables: Vec<TablePlan>,
+    pub stale: Vec<Pubkey>, // existing tables none of the groups use
+    pub skipped: usize,     // groups which don't fit in a table
+}
+
+/// packs each of `groups` (a pool's accounts) whole into a table: one of
+/// `existing` which already holds it, else the first table with room for
+/// it, else a new one -- largest groups first, which keeps the table count
+/// close to the fewest possible. Existing tables which hold none of the
+/// groups are stale, they aren't extended (only deactivated + closed)
+pub fn pack_lookup_tables(groups: &[Vec<Pubkey>], existing: &[LookupTable]) -> PackedTables {
+    let mut groups: Vec<&Vec<Pubkey>> = groups.iter().filter(|group| !group.is_empty()).collect();
+    groups.sort_by_key(|group| Reverse(group.len()));
+
+    let mut tables = vec![];
+    let mut stale = vec![];
+    let mut unstored = groups.clone();
+    for table in existing {
+        let set: HashSet<Pubkey> = table.addresses.iter().cloned().collect();
+        let holds = |group: &&Vec<Pubkey>| group.iter().all(|address| set.contains(address));
+        if !groups.iter().any(holds) {
+            stale.push(table.address);
+            continue;
+        }
+        unstored.retain(|group| !holds(group));
+        let plan = TablePlan {
+            address: Some(table.address),
+            addresses: table.addresses.clone(),
+            new_addresses: vec![],
+        };
+        tables.push((plan, set));
+    }
+
+    let mut skipped = 0;
+    for group in unstored {
+        let missing = |set: &HashSet<Pubkey>| -> Vec<Pubkey> {
+            let mut seen = HashSet::new();
+            group
+                .iter()
+                .filter(|address| !set.contains(*address) && seen.insert(**address))
+                .cloned()
+                .collect()
+        };
+        let fit = tables
+            .iter()
+            .position(|(_, set)| set.len() + missing(set).len() <= LOOKUP_TABLE_MAX_ADDRESSES);
+        let t = match fit {
+            Some(t) => t,
+            None if missing(&HashSet::new()).len() <= LOOKUP_TABLE_MAX_ADDRESSES => {
+                tables.push((TablePlan::default(), HashSet::new()));
+                tables.len() - 1
+            }
+            None => {
+                warn!("skipping {} accounts: too many for a lookup table", group.len());
+                skipped += 1;
+                continue;
+            }
+        };
+        let (plan, set) = &mut tables[t];
+        let new_addresses = missing(set);
+        set.extend(&new_addresses);
+        plan.new_addresses.extend(new_addresses);
+    }
+
+    PackedTables {
+        tables: tables.into_iter().map(|(plan, _)| plan).collect(),
+        stale,
+        skipped,
+    }
+}
+
+/// the fewest of `tables` which load (greedily) the most of the accounts
+/// `ixs` can load from a table
+pub fn select_lookup_tables(ixs: &[Instruction], tables: &[LookupTable]) -> Vec<LookupTable> {
+    let programs: HashSet<Pubkey> = ixs.iter().map(|ix| ix.program_id).collect();
+    let mut remaining: HashSet<Pubkey> = ixs
+        .iter()
+        .flat_map(|ix| &ix.accounts)
+        .filter(|meta| !meta.is_signer && !programs.contains(&meta.pubkey))
+        .map(|meta| meta.pubkey)
+        .collect();
+    let sets: Vec<HashSet<&Pubkey>> = tables
+        .iter()
+        .map(|table| table.addresses.iter().collect())
+        .collect();
+
+    let mut selected = vec![];
+    loop {
+        let best = sets
+            .iter()
+            .enumerate()
+            .map(|(t, set)| (remaining.iter().filter(|key| set.contains(key)).count(), t))
+            .max_by_key(|(covered, t)| (*covered, Reverse(*t)));
+        match best {
+            // a lookup (32 byte table key + index lists) only pays off for 2+ accounts
+            Some((covered, t)) if covered >= 2 => {
+                remaining.retain(|key| !sets[t].contains(key));
+                selected.push(tables[t].clone());
+            }
+            _ => break,
+        }
+    }
+    selected
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexedTableJSON {
+    address: String,
+    addresses: Vec<String>,
+    #[serde(default)]
+    stale: bool,
+}
+
+/// A table managed by `setup_lookup_table`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexedTable {
+    pub table: LookupTable,
+    pub stale: bool, // unused by the pool set -- to deactivate + close
+}
+
+/// The tables `setup_lookup_table` manages, in the order they're picked from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LookupTableIndex(pub Vec<IndexedTable>);
+
+impl LookupTableIndex {
+    /// read the index at `path` -- empty if there's no file yet
+    pub fn load(path: &str) -> Result<Self> {
+        let json_str = match std::fs::read_to_string(path) {
+            Ok(json_str) => json_str,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(anyhow!("Failed to read lookup table index {}: {}", path, e)),
+        };
+        let entries: Vec<IndexedTableJSON> = serde_json::from_str(&json_str)
+            .map_err(|e| anyhow!("Failed to parse lookup table index {}: {}", path, e))?;
+
+        let parse = |pubkey: &str| {
+            Pubkey::from_str(pubkey)
+                .map_err(|_| anyhow!("Invalid pubkey {:?} in lookup table index {}", pubkey, path))
+        };
+        let mut index = Self::default();
+        for entry in entries {
+            let table = LookupTable {
+                address: parse(&entry.address)?,
+                addresses: entry
+                    .addresses
+                    .iter()
+                    .map(|address| parse(address))
+                    .collect::<Result<Vec<Pubkey>>>()?,
+            };
+            index.0.push(IndexedTable {
+                table,
+                stale: entry.stale,
+            });
+        }
+        Ok(index)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let entries: Vec<IndexedTableJSON> = self
+            .0
+            .iter()
+            .map(|indexed| IndexedTableJSON {
+                address: indexed.table.address.to_string(),
+                addresses: indexed.table.addresses.iter().map(|a| a.to_string()).collect(),
+                stale: indexed.stale,
+            })
+            .collect();
+        std::fs::write(path, serde_json::to_string_pretty(&entries)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, table: &Pubkey) -> Option<&IndexedTable> {
+        self.0.iter().find(|indexed| indexed.table.address == *table)
+    }
+
+    /// add or replace the entry of `table`
+    pub fn upsert(&mut self, table: LookupTable, stale: bool) {
+        let indexed = IndexedTable { table, stale };
+        match self.0.iter().position(|i| i.table.address == indexed.table.address) {
+            Some(i) => self.0[i] = indexed,
+            None => self.0.push(indexed),
+        }
+    }
+
+    pub fn remove(&mut self, table: &Pubkey) -> Option<IndexedTable> {
+        let i = self.0.iter().position(|indexed| indexed.table.address == *table)?;
+        Some(self.0.remove(i))
+    }
+
+    /// the tables routes are sent against
+    pub fn active_tables(&self) -> Vec<Pubkey> {
+        self.0
+            .iter()
+            .filter(|indexed| !indexed.stale)
+            .map(|indexed| indexed.table.address)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_data(authority: Option<&Pubkey>, addresses: &[Pubkey]) -> Vec<u8> {
+        let mut data = vec![0; LOOKUP_TABLE_META_SIZE];
+        data[..4].copy_from_slice(&LOOKUP_TABLE_DISCRIMINATOR.to_le_bytes());
+        data[4..12].copy_from_slice(&u64::MAX.to_le_bytes());
+        data[12..20].copy_from_slice(&42_u64.to_le_bytes());
+        if let Some(authority) = authority {
+            data[21] = 1;
+            data[22..54].copy_from_slice(&authority.to_bytes());
+        }
+        for address in addresses {
+            data.extend_from_slice(&address.to_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn unpacks_tables() {
+        let (address, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let addresses: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+
+        let account = unpack_lookup_table(&address, &table_data(Some(&authority), &addresses));
+        let account = account.unwrap();
+        assert!(account.is_active());
+        assert_eq!(account.last_extended_slot, 42);
+        assert_eq!(account.authority, Some(authority));
+        assert_eq!(account.table.address, address);
+        assert_eq!(account.table.addresses, addresses);
+
+        let frozen = unpack_lookup_table(&address, &table_data(None, &[])).unwrap();
+        assert_eq!((frozen.authority, frozen.table.addresses.len()), (None, 0));
+        assert!(unpack_lookup_table(&address, &[0; LOOKUP_TABLE_META_SIZE]).is_none());
+        assert!(unpack_lookup_table(&address, &[1, 0, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn builds_table_instructions() {
+        let authority = Pubkey::new_unique();
+        let (create_ix, table) = create_lookup_table_ix(&authority, &authority, 1_000);
+        assert_eq!(table, derive_lookup_table_address(&authority, 1_000).0);
+        assert_eq!(create_ix.accounts[0].pubkey, table);
+        assert_eq!(create_ix.data.len(), 4 + 8 + 1);
+        assert_eq!(create_ix.data[4..12], 1_000_u64.to_le_bytes());
+
+        let new_addresses: Vec<Pubkey> = (0..2).map(|_| Pubkey::new_unique()).collect();
+        let extend_ix = extend_lookup_table_ix(&table, &authority, &authority, &new_addresses);
+        assert_eq!(extend_ix.data[..4], [2, 0, 0, 0]);
+        assert_eq!(extend_ix.data[4..12], 2_u64.to_le_bytes());
+        assert_eq!(extend_ix.data[12..44], new_addresses[0].to_bytes());
+        assert_eq!(extend_ix.data.len(), 4 + 8 + 64);
+
+        let deactivate_ix = deactivate_lookup_table_ix(&table, &authority);
+        assert_eq!((deactivate_ix.data, deactivate_ix.accounts.len()), (vec![3, 0, 0, 0], 2));
+        let close_ix = close_lookup_table_ix(&table, &authority, &authority);
+        assert_eq!((close_ix.data, close_ix.accounts.len()), (vec![4, 0, 0, 0], 3));
+        assert!(close_ix.accounts[2].is_writable);
+
+        let mut account = unpack_lookup_table(&table, &table_data(Some(&authority), &[])).unwrap();
+        assert!(!account.is_closable(u64::MAX));
+        account.deactivation_slot = 1_000;
+        assert!(!account.is_closable(1_000 + DEACTIVATION_COOLDOWN_SLOTS));
+        assert!(account.is_closable(1_001 + DEACTIVATION_COOLDOWN_SLOTS));
+    }
+
+    fn keys(n: usize) -> Vec<Pubkey> {
+        (0..n).map(|_| Pubkey::new_unique()).collect()
+    }
+
+    #[test]
+    fn packs_pool_groups() {
+        let shared = Pubkey::new_unique(); // e.g. the token program
+        let groups: Vec<Vec<Pubkey>> = [100, 150, 60, 120]
+            .iter()
+            .map(|n| [vec![shared], keys(*n)].concat())
+            .collect();
+        let stale = LookupTable {
+            address: Pubkey::new_unique(),
+            addresses: keys(10),
+        };
+        let existing = LookupTable {
+            address: Pubkey::new_unique(),
+            addresses: [groups[2].clone(), keys(5)].concat(),
+        };
+
+        let packed = pack_lookup_tables(&groups, &[stale.clone(), existing.clone()]);
+        assert_eq!(packed.stale, vec![stale.address]);
+        assert_eq!(packed.skipped, 0);
+        // the existing table is topped up with the 150 group, the others share a new one
+        assert_eq!(packed.tables.len(), 2);
+        assert_eq!(packed.tables[0].address, Some(existing.address));
+        assert_eq!(packed.tables[0].addresses, existing.addresses);
+        assert_eq!(packed.tables[0].new_addresses, groups[1][1..].to_vec());
+        assert_eq!(packed.tables[1].address, None);
+        assert_eq!(packed.tables[1].len(), 1 + 120 + 100);
+        for plan in &packed.tables {
+            assert!(plan.len() <= LOOKUP_TABLE_MAX_ADDRESSES);
+        }
+        // every group is whole in one table
+        for group in &groups {
+            assert!(packed.tables.iter().any(|plan| {
+                let stored: HashSet<&Pubkey> =
+                    plan.addresses.iter().chain(&plan.new_addresses).collect();
+                group.iter().all(|address| stored.contains(address))
+            }));
+        }
+
+        let too_large = vec![keys(LOOKUP_TABLE_MAX_ADDRESSES + 1)];
+        let packed = pack_lookup_tables(&too_large, &[]);
+        assert_eq!((packed.tables.len(), packed.skipped), (0, 1));
+    }
+
+    #[test]
+    fn selects_route_tables() {
+        let (owner, program) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let accounts = keys(6);
+        let ix = Instruction {
+            program_id: program,
+            accounts: [
+                vec![AccountMeta::new_readonly(owner, true)],
+                accounts.iter().map(|a| AccountMeta::new(*a, false)).collect(),
+                vec![AccountMeta::new_readonly(program, false)],
+            ]
+            .concat(),
+            data: vec![],
+        };
+        let table = |addresses: Vec<Pubkey>| LookupTable {
+            address: Pubkey::new_unique(),
+            addresses,
+        };
+        let tables = [
+            table(accounts[..2].to_vec()),
+            table([accounts[1..5].to_vec(), vec![owner, program]].concat()),
+            table(accounts[..1].to_vec()),
+            table([accounts[5..].to_vec(), keys(3)].concat()), // a single account isn't worth it
+        ];
+        let selected = select_lookup_tables(&[ix], &tables);
+        assert_eq!(selected, vec![tables[1].clone()]);
+        assert!(select_lookup_tables(&[], &tables).is_empty());
+    }
+
+    #[test]
+    fn index_roundtrips() {
+        let path = std::env::temp_dir().join("lookup_table_index_test.json");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        assert!(LookupTableIndex::load(path).unwrap().0.is_empty());
+
+        let tables: Vec<LookupTable> = (0..2)
+            .map(|_| LookupTable {
+                address: Pubkey::new_unique(),
+                addresses: keys(3),
+            })
+            .collect();
+        let mut index = LookupTableIndex::default();
+        index.upsert(tables[0].clone(), false);
+        index.upsert(tables[1].clone(), true);
+        index.save(path).unwrap();
+        let mut loaded = LookupTableIndex::load(path).unwrap();
+        assert_eq!(loaded, index);
+        assert_eq!(loaded.active_tables(), vec![tables[0].address]);
+
+        loaded.upsert(tables[1].clone(), false);
+        assert_eq!(loaded.active_tables().len(), 2);
+        assert!(loaded.remove(&tables[0].address).is_some());
+        assert_eq!(loaded.get(&tables[1].address).unwrap().table, tables[1]);
+
+        std::fs::write(path, "[{\"address\": \"nope\", \"addresses\": []}]").unwrap();
+        assert!(LookupTableIndex::load(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -3,18 +3,38 @@ use anchor_client::solana_sdk::commitment_config::CommitmentConfig;
 use anchor_client::solana_sdk::pubkey::Pubkey;
 use anchor_client::solana_sdk::signature::read_keypair_file;
 use anchor_client::solana_sdk::signature::{Keypair, Signer};
+use anchor_client::solana_sdk::transaction::Transaction;
 use anchor_client::{Client, Cluster};
 use clap::Parser;
-use log::{debug, info, warn};
+use log::{info, warn};
 use reqwest::blocking::get;
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::rc::Rc;
+use std::sync::{Arc, RwLock};
 use std::str::FromStr;
-use solana_sdk::account::Account;
 use std::fs::File;
 use std::io::{self, Read};
+use serde::Deserialize;
+use std::borrow::Borrow;
+
+use client::arb::*;
+use client::compute_budget::ComputeBudgetConfig;
+use client::cycles::CycleIndex;
+use client::constants::*;
+use client::lookup_table::{fetch_lookup_tables, LookupTableIndex, LOOKUP_TABLE_INDEX_PATH};
+use client::open_orders::{OpenOrdersRegistry, OPEN_ORDERS_PATH};
+use client::pool::{default_pool_dirs, load_pools, PoolOperations};
+use client::pool_utils::jupiter::{refresh_routes, spawn_route_refresher};
+use client::pools::{
+    fetch_mints, initialize_raydium_swap_state_ix, raydium_swap_state, wrap_token_2022,
+};
+use client::priority_fee::{spawn_refresher, PriorityFeeEstimator};
+use client::serialize::token::unpack_token_account;
+use client::sizing::SizeBounds;
+use client::stream::{reconnect_accounts, Backoff, PoolStateCache};
+use client::utils::{derive_token_address, PoolEdge, PoolGraph, PoolIndex, PoolQuote};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -29,6 +49,38 @@ pub struct Args {
 struct Config {
     fee_percentage: f64,
     dex_urls: Vec<String>,
+    // swap input range (scaled, in the start mint) -- capped by the wallet balance
+    #[serde(default = "default_min_swap_amount")]
+    min_swap_amount: u128,
+    #[serde(default)]
+    max_swap_amount: Option<u128>,
+    // self-describing pool snapshots (mixed dirs or .jsonl files) loaded on top of the per-DEX dirs
+    #[serde(default)]
+    pool_snapshots: Vec<String>,
+    // serum open orders registry written by `setup_open_orders`
+    #[serde(default = "default_open_orders_path")]
+    open_orders_path: String,
+    // address lookup tables (see `setup_lookup_table`) -- routes load their accounts from them
+    #[serde(default)]
+    lookup_tables: Vec<String>,
+    // the pool set's tables managed by `setup_lookup_table`, loaded on top of `lookup_tables`
+    #[serde(default = "default_lookup_table_index")]
+    lookup_table_index: String,
+    // compute unit margin over the simulated units + the priority fee policy
+    #[serde(default)]
+    compute_budget: ComputeBudgetConfig,
+}
+
+fn default_min_swap_amount() -> u128 {
+    10_u128.pow(6_u32) // scaled! -- 1 USDC
+}
+
+fn default_open_orders_path() -> String {
+    OPEN_ORDERS_PATH.to_string()
+}
+
+fn default_lookup_table_index() -> String {
+    LOOKUP_TABLE_INDEX_PATH.to_string()
 }
 
 fn fetch_tokens() -> Vec<String> {
@@ -59,11 +111,6 @@ fn add_pool_to_graph<'a>(
     quotes.push(quote.clone());
 }
 
-fn calculate_fees(amount: u128, fee_percentage: f64) -> u128 {
-    let fee = (amount as f64 * fee_percentage).round() as u128;
-    fee
-}
-
 fn load_config(file_path: &str) -> Config {
     let mut file = File::open(file_path).expect("Could not open config file");
     let mut contents = String::new();
@@ -105,110 +152,72 @@ fn main() {
         RpcClient::new_with_commitment(cluster.url(), CommitmentConfig::confirmed());
 
     let owner = read_keypair_file(owner_kp_path.clone()).unwrap();
-    let rc_owner = Rc::new(owner);
+    let arc_owner = Arc::new(owner);
+    // anchor's client wants an Rc -- the arbitrager shares its own copy across threads
     let provider = Client::new_with_options(
         cluster.clone(),
-        rc_owner.clone(),
+        Rc::new(Keypair::from_bytes(&arc_owner.to_bytes()).unwrap()),
         CommitmentConfig::confirmed(),
     );
     let program = provider.program(*ARB_PROGRAM_ID);
 
-    let mut pool_dirs = vec![];
-
-// Add DEX directories to the pool directories list
-    let orca_dir = PoolDir {
-        tipe: PoolType::OrcaPoolType,
-        dir_path: "../pools/orca".to_string(),
-    };
-    pool_dirs.push(orca_dir);
-
-    let raydium_dir = PoolDir {
-        tipe: PoolType::SaberPoolType,
-        dir_path: "../pools/raydium/".to_string(),
-    };
-    pool_dirs.push(saber_dir);
+    // raydium swaps write the owner's swap state -- created on the first run
+    let swap_state = raydium_swap_state(&program.id(), &arc_owner.pubkey());
+    if connection.get_account(&swap_state).is_err() {
+        info!("Initializing the raydium swap state {}", swap_state);
+        let ixs = initialize_raydium_swap_state_ix(&program, &arc_owner.pubkey());
+        let tx = Transaction::new_signed_with_payer(
+            &ixs,
+            Some(&arc_owner.pubkey()),
+            &[arc_owner.as_ref()],
+            connection.get_latest_blockhash().unwrap(),
+        );
+        let signature = connection.send_and_confirm_transaction(&tx).unwrap();
+        info!("signature: {}", signature);
+    }
 
-    let jupiter_dir = PoolDir {
-        tipe: PoolType::JupiterPoolType,
-        dir_path: "../pools/jupiter/".to_string(),
-    };
-    pool_dirs.push(saber_dir);
-
-    // let meteora_dir = PoolDir {
-    //     tipe: PoolType::SaberPoolType,
-    //     dir_path: "../pools/meteora/".to_string(),
-    // };
-    // pool_dirs.push(saber_dir);
-
-    // let phoenix_dir = PoolDir {
-    //     tipe: PoolType::PhoenixPoolType,
-    //     dir_path: "../pools/phoenix/".to_string(),
-    // };
-    // pool_dirs.push(saber_dir);
-
-    // let lifinity_dir = PoolDir {
-    //     tipe: PoolType::LifinityPoolType,
-    //     dir_path: "../pools/lifinity/".to_string(),
-    // };
-    // pool_dirs.push(saber_dir);
+    // serum markets are only traded through a registered open orders account
+    let open_orders = OpenOrdersRegistry::load(&config.open_orders_path).unwrap();
+    info!("Loaded {:?} serum open orders accounts", open_orders.len());
 
     let mut token_mints = fetch_tokens(); // Fetch token mints dynamically
     let mut pools = vec![];
 
-    let mut update_pks = vec![];
-    let mut update_pks_lengths = vec![];
     let mut all_mint_idxs = vec![];
 
     let mut mint2idx = HashMap::new();
     let mut graph_edges = vec![];
 
     info!("Extracting pool + mints...");
-    for pool_dir in pool_dirs {
-        debug!("Pool dir: {:#?}", pool_dir);
-        let pool_paths = read_json_dir(&pool_dir.dir_path);
-
-        for pool_path in pool_paths {
-            let json_str = std::fs::read_to_string(&pool_path).unwrap();
-            let pool = pool_factory(&pool_dir.tipe, &json_str);
-
-            let pool_mints = pool.get_mints();
-            if pool_mints.len() != 2 {
-                warn!("Skipping pool with mints != 2: {:?}", pool_path);
-                continue;
-            }
-
-            let mut mint_idxs = vec![];
-            for mint in pool_mints {
-                let idx;
-                if !token_mints.contains(&mint) {
-                    idx = token_mints.len();
-                    mint2idx.insert(mint, idx);
-                    token_mints.push(mint);
-                    graph_edges.push(HashSet::new());
-                } else {
-                    idx = *mint2idx.get(&mint).unwrap();
-                }
-                mint_idxs.push(idx);
+    for mut pool in load_pools(&default_pool_dirs(&config.pool_snapshots)) {
+        let mut mint_idxs = vec![];
+        for mint in pool.get_mints() {
+            let idx;
+            if !token_mints.contains(&mint) {
+                idx = token_mints.len();
+                mint2idx.insert(mint, idx);
+                token_mints.push(mint);
+                graph_edges.push(HashSet::new());
+            } else {
+                idx = *mint2idx.get(&mint).unwrap();
             }
+            mint_idxs.push(idx);
+        }
 
-            let update_accounts = pool.get_update_accounts();
-            update_pks_lengths.push(update_accounts.len());
-            update_pks.push(update_accounts);
-
-            let mint0_idx = mint_idxs[0];
-            let mint1_idx = mint_idxs[1];
-
-            if !graph_edges[mint0_idx].contains(&mint1_idx) {
-                graph_edges[mint0_idx].insert(mint1_idx);
-            }
-            if !graph_edges[mint1_idx].contains(&mint0_idx) {
-                graph_edges[mint1_idx].insert(mint0_idx);
-            }
+        all_mint_idxs.extend(&mint_idxs);
+        let mint0_idx = mint_idxs[0];
+        let mint1_idx = mint_idxs[1];
 
-            pools.push(pool);
+        if !graph_edges[mint0_idx].contains(&mint1_idx) {
+            graph_edges[mint0_idx].insert(mint1_idx);
+        }
+        if !graph_edges[mint1_idx].contains(&mint0_idx) {
+            graph_edges[mint1_idx].insert(mint0_idx);
         }
+
+        pool.set_open_orders(&open_orders);
+        pools.push(pool);
     }
-    let mut update_pks = update_pks.concat();
 
     info!("Added {:?} mints", token_mints.len());
     info!("Added {:?} pools", pools.len());
@@ -217,86 +226,178 @@ fn main() {
     let start_mint = usdc_mint;
     let start_mint_idx = *mint2idx.get(&start_mint).unwrap();
 
-    let owner: &Keypair = rc_owner.borrow();
+    let owner: &Keypair = arc_owner.borrow();
     let owner_start_addr = derive_token_address(&owner.pubkey(), &start_mint);
-    update_pks.push(owner_start_addr);
 
-    info!("Getting pool amounts...");
-    let mut update_accounts = vec![];
-    for token_addr_chunk in update_pks.chunks(99) {
-        let accounts = connection.get_multiple_accounts(token_addr_chunk).unwrap_or_else(|e| {
-            warn!("Failed to get accounts: {}", e);
-            vec![None; token_addr_chunk.len()]
-        });
-        update_accounts.push(accounts);
-    }
-    let mut update_accounts = update_accounts
-        .concat()
+    // transfer fees + the token program of Token-2022 mints (the fees picked
+    // by the epoch of the latest streamed slot)
+    let pool_mints: HashSet<Pubkey> = pools.iter().flat_map(|pool| pool.get_mints()).collect();
+    let mints = fetch_mints(&connection, &pool_mints.into_iter().collect::<Vec<_>>());
+    let epoch_schedule = connection.get_epoch_schedule().unwrap();
+    let slot = connection.get_slot().unwrap();
+    let pools: Vec<Box<dyn PoolOperations>> = pools
         .into_iter()
-        .filter(|s| s.is_some())
-        .collect::<Vec<Option<Account>>>();
+        .map(|pool| wrap_token_2022(pool, &mints, &epoch_schedule, slot))
+        .collect();
 
-    info!("Update accounts count: {:?}", update_accounts.len());
-    let init_token_acc = update_accounts.pop().unwrap().unwrap();
-    let init_token_balance = unpack_token_account(&init_token_acc.data).amount as u128;
+    // pool id == position in the loaded pool list
+    let pools: Vec<PoolQuote> = pools
+        .into_iter()
+        .enumerate()
+        .map(|(pool_id, pool)| PoolQuote::new(Arc::new(RwLock::new(pool)), pool_id))
+        .collect();
+    let mut pool_cache = PoolStateCache::new(pools.clone(), cluster.clone());
+
+    info!("Getting pool amounts...");
+    let loaded = pool_cache.fetch(&connection);
+    info!("Loaded {:?} / {:?} pools", loaded.len(), pools.len());
+    // jupiter routes aren't streamed -- fetched now, then on a timer
+    let n_routes = refresh_routes(&pools, &JUPITER_PROGRAM_ID);
+    info!("Fetched {:?} jupiter routes", n_routes);
+    spawn_route_refresher(&pools, *JUPITER_PROGRAM_ID);
+
+    let init_token_acc = connection.get_account(&owner_start_addr).unwrap();
+    let mut token_balance = unpack_token_account(&init_token_acc.data).amount as u128;
     info!(
         "Init token acc: {:?}, balance: {:#}",
-        init_token_acc, init_token_balance
+        init_token_acc, token_balance
     );
-    info!("Starting balance = {}", init_token_balance);
+    info!("Starting balance = {}", token_balance);
 
     info!("Setting up exchange graph...");
     let mut graph = PoolGraph::new();
-    let mut pool_count = 0;
-    let mut account_ptr = 0;
-
-    for pool in pools.into_iter() {
-        let length = update_pks_lengths[pool_count];
-        let _account_slice = &update_accounts[account_ptr..account_ptr + length].to_vec();
-        account_ptr += length;
-
-        let idxs = &all_mint_idxs[pool_count * 2..(pool_count + 1) * 2].to_vec();
+    for pool in &pools {
+        // can't quote a pool which is missing its accounts
+        if !pool_cache.is_loaded(pool.1) {
+            continue;
+        }
+        let idxs = &all_mint_idxs[pool.1 * 2..(pool.1 + 1) * 2];
         let idx0 = PoolIndex(idxs[0]);
         let idx1 = PoolIndex(idxs[1]);
 
-        let mut pool_ptr = PoolQuote::new(Rc::new(pool));
-        add_pool_to_graph(&mut graph, idx0, idx1, &mut pool_ptr.clone());
-        add_pool_to_graph(&mut graph, idx1, idx0, &mut pool_ptr);
+        add_pool_to_graph(&mut graph, idx0, idx1, pool);
+        add_pool_to_graph(&mut graph, idx1, idx0, pool);
+    }
 
-        pool_count += 1;
+    let mut lookup_table_addresses: Vec<Pubkey> = config
+        .lookup_tables
+        .iter()
+        .map(|address| Pubkey::from_str(address).expect("invalid lookup table"))
+        .collect();
+    let lookup_table_index = LookupTableIndex::load(&config.lookup_table_index).unwrap();
+    for address in lookup_table_index.active_tables() {
+        if !lookup_table_addresses.contains(&address) {
+            lookup_table_addresses.push(address);
+        }
+    }
+    let lookup_tables = fetch_lookup_tables(&connection, &lookup_table_addresses);
+    info!("Loaded {:?} lookup tables", lookup_tables.len());
+    // loading the pool accounts from tables leaves room for longer routes
+    let max_hops = if lookup_tables.is_empty() { 3 } else { 6 };
+
+    // priced routes read the fees a thread keeps fresh, the send path never queries them
+    let priority_fees = Arc::new(RwLock::new(PriorityFeeEstimator::default()));
+    if config.compute_budget.priority_fee.urgency().is_some() {
+        let fee_connection =
+            RpcClient::new_with_commitment(connection_url, CommitmentConfig::confirmed());
+        spawn_refresher(fee_connection, priority_fees.clone(), pool_cache.update_accounts());
     }
 
-    let arbitrager = Arbitrager {
+    let ws_url = cluster.ws_url().to_string();
+    let mut arbitrager = Arbitrager {
         token_mints,
         graph_edges,
         graph,
         cluster,
-        owner: rc_owner,
+        owner: arc_owner,
         program,
         connection: send_tx_connection,
+        max_hops,
+        cycle_index: CycleIndex::default(),
+        lookup_tables,
+        compute_budget: config.compute_budget.clone(),
+        priority_fees,
     };
 
-    info!("Searching for arbitrages...");
-    let min_swap_amount = 10_u128.pow(6_u32); // scaled! -- 1 USDC
-    let mut swap_start_amount = init_token_balance; // scaled!
-    let mut sent_arbs = HashSet::new();
+    // swap input range for the start mint -- capped by the wallet balance (scaled!)
+    let swap_bounds = |balance: u128| {
+        let max_swap_amount = config.max_swap_amount.unwrap_or(balance).min(balance);
+        HashMap::from([(
+            start_mint_idx,
+            SizeBounds {
+                min_amount_in: config.min_swap_amount,
+                max_amount_in: max_swap_amount,
+            },
+        )])
+    };
+    let mut bounds = swap_bounds(token_balance);
+    let mut sent_arbs = HashMap::new();
 
-    for _ in 0..4 {
-        let fees = calculate_fees(swap_start_amount, config.fee_percentage);
-        let net_amount = swap_start_amount - fees;
+    info!("Indexing candidate cycles...");
+    let starts: Vec<usize> = bounds.keys().cloned().collect();
+    let n_cycles = arbitrager.index_cycles(&starts);
+    info!("Indexed {:?} cycles", n_cycles);
 
-        arbitrager.brute_force_search(
-            start_mint_idx,
-            net_amount,
-            swap_start_amount,
-            vec![start_mint_idx],
-            vec![],
-            &mut sent_arbs,
+    info!("Searching for arbitrages...");
+    arbitrager.search(&bounds, config.fee_percentage, &mut sent_arbs);
+
+    info!("Streaming pool updates...");
+    let mut stream_pks = pool_cache.update_accounts();
+    stream_pks.push(owner_start_addr);
+    let mut backoff = Backoff::default();
+    let mut reconnected = false;
+    loop {
+        let (_subscriptions, updates) = reconnect_accounts(
+            &ws_url,
+            &stream_pks,
+            &[],
+            CommitmentConfig::confirmed(),
+            &mut backoff,
         );
+        // the updates missed while the stream was down never come -- re-fetch
+        // everything (notifications older than the snapshot are dropped)
+        if reconnected {
+            info!("Re-fetching pool state...");
+            let updated_pools = pool_cache.fetch(&connection);
+            match connection.get_account(&owner_start_addr) {
+                Ok(account) => {
+                    token_balance = unpack_token_account(&account.data).amount as u128;
+                    bounds = swap_bounds(token_balance);
+                }
+                Err(e) => warn!("can't re-fetch the start token account: {}", e),
+            }
+            arbitrager.search_updated(
+                &updated_pools,
+                &bounds,
+                config.fee_percentage,
+                &mut sent_arbs,
+            );
+        }
+        reconnected = true;
+
+        // wait for an update then drain the backlog so a burst == one search
+        while let Ok(update) = updates.recv() {
+            let mut updated_pools = HashSet::new();
+            for update in std::iter::once(update).chain(updates.try_iter()) {
+                if update.pubkey == owner_start_addr {
+                    if let Some(account) = &update.account {
+                        token_balance = unpack_token_account(&account.data).amount as u128;
+                        bounds = swap_bounds(token_balance);
+                    }
+                    continue;
+                }
+                updated_pools.extend(pool_cache.apply(update));
+            }
 
-        swap_start_amount /= 2; // half input amount and search again
-        if swap_start_amount < min_swap_amount {
-            break;
+            if !updated_pools.is_empty() {
+                arbitrager.search_updated(
+                    &updated_pools,
+                    &bounds,
+                    config.fee_percentage,
+                    &mut sent_arbs,
+                );
+            }
         }
+        warn!("Update stream closed, reconnecting");
     }
 }
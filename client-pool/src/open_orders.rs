@@ -0,0 +1,213 @@
+//! The owner's Serum open orders accounts, one per market
+//!
+//! Every Serum swap settles through an open orders account of the market
+//! owned by the swapper. `setup_open_orders` creates them (and closes them to
+//! reclaim their rent) and records each one in a JSON registry of
+//! `{ market: open orders }`; the bot loads that registry at startup and sets
+//! each `SerumPool`'s account from it (`PoolOperations::set_open_orders`).
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::{anyhow, Result};
+use arrayref::array_ref;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// where the registry is kept by default
+pub const OPEN_ORDERS_PATH: &str = "./serum_open_orders.json";
+/// open orders account size (dex v3 layout, with its padding)
+pub const OPEN_ORDERS_LEN: usize = 3228;
+
+const ACCOUNT_FLAG_INITIALIZED: u64 = 1;
+const ACCOUNT_FLAG_OPEN_ORDERS: u64 = 1 << 2;
+const ACCOUNT_FLAG_CLOSED: u64 = 1 << 7;
+
+/// Open orders account of each market, by market
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OpenOrdersRegistry(pub BTreeMap<Pubkey, Pubkey>);
+
+impl OpenOrdersRegistry {
+    /// read the registry at `path` -- empty if there's no file yet
+    pub fn load(path: &str) -> Result<Self> {
+        let json_str = match std::fs::read_to_string(path) {
+            Ok(json_str) => json_str,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(anyhow!("Failed to read open orders registry {}: {}", path, e)),
+        };
+        let entries: HashMap<String, String> = serde_json::from_str(&json_str)
+            .map_err(|e| anyhow!("Failed to parse open orders registry {}: {}", path, e))?;
+
+        let mut registry = Self::default();
+        for (market, open_orders) in entries {
+            let parse = |pubkey: &str| {
+                Pubkey::from_str(pubkey).map_err(|_| {
+                    anyhow!("Invalid pubkey {:?} in open orders registry {}", pubkey, path)
+                })
+            };
+            registry.0.insert(parse(&market)?, parse(&open_orders)?);
+        }
+        Ok(registry)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let entries: BTreeMap<String, String> = self
+            .0
+            .iter()
+            .map(|(market, open_orders)| (market.to_string(), open_orders.to_string()))
+            .collect();
+        std::fs::write(path, serde_json::to_string_pretty(&entries)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, market: &Pubkey) -> Option<Pubkey> {
+        self.0.get(market).cloned()
+    }
+
+    pub fn insert(&mut self, market: Pubkey, open_orders: Pubkey) {
+        self.0.insert(market, open_orders);
+    }
+
+    pub fn remove(&mut self, market: &Pubkey) -> Option<Pubkey> {
+        self.0.remove(market)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// What an open orders account holds, as far as closing it goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenOrdersState {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub native_coin_free: u64,
+    pub native_coin_total: u64,
+    pub native_pc_free: u64,
+    pub native_pc_total: u64,
+}
+
+impl OpenOrdersState {
+    /// funds still locked in orders (settling can't free them)
+    pub fn has_open_orders(&self) -> bool {
+        self.native_coin_total > self.native_coin_free || self.native_pc_total > self.native_pc_free
+    }
+
+    pub fn has_unsettled_funds(&self) -> bool {
+        self.native_coin_free > 0 || self.native_pc_free > 0
+    }
+}
+
+/// None if `data` isn't an initialized (+ not closed) open orders account
+pub fn unpack_open_orders(data: &[u8]) -> Option<OpenOrdersState> {
+    // "serum" head padding, then the account flags
+    if data.len() < OPEN_ORDERS_LEN || &data[..5] != b"serum" {
+        return None;
+    }
+    let flags = u64::from_le_bytes(*array_ref![data, 5, 8]);
+    let expected = ACCOUNT_FLAG_INITIALIZED | ACCOUNT_FLAG_OPEN_ORDERS;
+    if flags & expected != expected || flags & ACCOUNT_FLAG_CLOSED != 0 {
+        return None;
+    }
+    let read_u64 = |offset: usize| u64::from_le_bytes(*array_ref![data, offset, 8]);
+    Some(OpenOrdersState {
+        market: Pubkey::new_from_array(*array_ref![data, 13, 32]),
+        owner: Pubkey::new_from_array(*array_ref![data, 45, 32]),
+        native_coin_free: read_u64(77),
+        native_coin_total: read_u64(85),
+        native_pc_free: read_u64(93),
+        native_pc_total: read_u64(101),
+    })
+}
+
+/// the state of the registered account of `market`, checked against what's
+/// on chain -- `account` is its (owning program, data), None if it doesn't exist
+pub fn verify_open_orders(
+    market: &Pubkey,
+    owner: &Pubkey,
+    dex_program: &Pubkey,
+    account: Option<(&Pubkey, &[u8])>,
+) -> Result<OpenOrdersState> {
+    let (account_owner, data) = account.ok_or_else(|| anyhow!("account doesn't exist"))?;
+    if account_owner != dex_program {
+        return Err(anyhow!("account isn't owned by the dex program"));
+    }
+    let state = unpack_open_orders(data).ok_or_else(|| anyhow!("account isn't open orders"))?;
+    if state.market != *market {
+        return Err(anyhow!("account is for market {}", state.market));
+    }
+    if state.owner != *owner {
+        return Err(anyhow!("account is owned by {}", state.owner));
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_orders_data(market: &Pubkey, owner: &Pubkey, flags: u64) -> Vec<u8> {
+        let mut data = vec![0; OPEN_ORDERS_LEN];
+        data[..5].copy_from_slice(b"serum");
+        data[5..13].copy_from_slice(&flags.to_le_bytes());
+        data[13..45].copy_from_slice(&market.to_bytes());
+        data[45..77].copy_from_slice(&owner.to_bytes());
+        data[77..85].copy_from_slice(&5_u64.to_le_bytes());
+        data[85..93].copy_from_slice(&5_u64.to_le_bytes());
+        data[101..109].copy_from_slice(&7_u64.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn registry_roundtrips() {
+        let path = std::env::temp_dir().join("open_orders_registry_test.json");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        assert!(OpenOrdersRegistry::load(path).unwrap().is_empty());
+
+        let mut registry = OpenOrdersRegistry::default();
+        let (market, open_orders) = (Pubkey::new_unique(), Pubkey::new_unique());
+        registry.insert(market, open_orders);
+        registry.insert(Pubkey::new_unique(), Pubkey::new_unique());
+        registry.save(path).unwrap();
+        let loaded = OpenOrdersRegistry::load(path).unwrap();
+        assert_eq!(loaded, registry);
+        assert_eq!(loaded.get(&market), Some(open_orders));
+
+        // the plain `{ market: open orders }` JSON of earlier runs
+        std::fs::write(path, format!("{{\"{}\": \"{}\"}}", market, open_orders)).unwrap();
+        assert_eq!(OpenOrdersRegistry::load(path).unwrap().len(), 1);
+        std::fs::write(path, "{\"nope\": \"nope\"}").unwrap();
+        assert!(OpenOrdersRegistry::load(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn verifies_open_orders_accounts() {
+        let (market, owner, dex) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let flags = ACCOUNT_FLAG_INITIALIZED | ACCOUNT_FLAG_OPEN_ORDERS;
+        let data = open_orders_data(&market, &owner, flags);
+
+        let state = verify_open_orders(&market, &owner, &dex, Some((&dex, &data))).unwrap();
+        assert_eq!((state.native_coin_free, state.native_pc_total), (5, 7));
+        assert!(state.has_unsettled_funds());
+        assert!(state.has_open_orders()); // the pc is all in orders
+
+        assert!(verify_open_orders(&market, &owner, &dex, None).is_err());
+        assert!(verify_open_orders(&market, &owner, &dex, Some((&owner, &data))).is_err());
+        let other_market = Pubkey::new_unique();
+        assert!(verify_open_orders(&other_market, &owner, &dex, Some((&dex, &data))).is_err());
+        assert!(verify_open_orders(&market, &dex, &dex, Some((&dex, &data))).is_err());
+
+        let closed = open_orders_data(&market, &owner, flags | ACCOUNT_FLAG_CLOSED);
+        assert!(unpack_open_orders(&closed).is_none());
+        assert!(unpack_open_orders(&data[..100]).is_none());
+    }
+}
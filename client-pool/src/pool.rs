@@ -1,65 +1,453 @@
 use anchor_client::solana_sdk::pubkey::Pubkey;
 use anchor_client::Program;
+use serde::de::DeserializeOwned;
 use solana_sdk::account::Account;
 use solana_sdk::instruction::Instruction;
 
+use crate::error::{PoolLoadError, QuoteError};
+use crate::open_orders::OpenOrdersRegistry;
+use crate::pool_utils::base::SwapResult;
+use crate::pool_utils::exact_out::{min_amount_in, MAX_AMOUNT_IN};
+use crate::pool_utils::jupiter::{FetchedRoute, RouteRequest};
 use crate::pools::*;
+use crate::utils::read_json_dir;
+use log::warn;
+use std::collections::HashMap;
 use std::fmt::Debug;
 
 use anchor_client::Cluster;
+use dyn_clone::{clone_trait_object, DynClone};
 
 #[derive(Debug)]
 pub struct PoolDir {
-    pub tipe: PoolType,
-    pub dir_path: String,
+    pub tipe: Option<PoolType>, // None == every file names its own `"type"`
+    pub dir_path: String,       // directory of pool JSONs or a JSON-lines file
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PoolType {
     OrcaPoolType,
-    RaydiumType,
-    MeteoraType,
-    PhoenixType,
-    LifinityType,
-    JupiterType,
+    RaydiumPoolType,
+    SerumPoolType,
+    JupiterPoolType,
+    WhirlpoolPoolType,
+    MeteoraPoolType,
+    PhoenixPoolType,
+    LifinityPoolType,
 }
 
-pub fn pool_factory(tipe: &PoolType, json_str: &String) -> Box<dyn PoolOperations> {
-    match tipe {
-        PoolType::OrcaPoolType => {
-            let pool: OrcaPool = serde_json::from_str(json_str).unwrap();
-            Box::new(pool)
+impl PoolType {
+    /// registry key of the pool type
+    pub fn tag(&self) -> &'static str {
+        match self {
+            PoolType::OrcaPoolType => "orca",
+            PoolType::RaydiumPoolType => "raydium",
+            PoolType::SerumPoolType => "serum",
+            PoolType::JupiterPoolType => "jupiter",
+            PoolType::WhirlpoolPoolType => "whirlpool",
+            PoolType::MeteoraPoolType => "meteora",
+            PoolType::PhoenixPoolType => "phoenix",
+            PoolType::LifinityPoolType => "lifinity",
         }
-        PoolType::RaydiumType => {
-            let pool: RaydiumPool = serde_json::from_str(json_str).unwrap();
-            Box::new(pool)
+    }
+
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "orca" => Some(PoolType::OrcaPoolType),
+            "raydium" => Some(PoolType::RaydiumPoolType),
+            "serum" => Some(PoolType::SerumPoolType),
+            "jupiter" => Some(PoolType::JupiterPoolType),
+            "whirlpool" => Some(PoolType::WhirlpoolPoolType),
+            "meteora" => Some(PoolType::MeteoraPoolType),
+            "phoenix" => Some(PoolType::PhoenixPoolType),
+            "lifinity" => Some(PoolType::LifinityPoolType),
+            _ => None,
         }
-        PoolType::SerumPoolType => {
-            let pool: SerumPool = serde_json::from_str(json_str).unwrap();
-            Box::new(pool)
+    }
+}
+
+/// parses a pool JSON file into its pool implementation
+pub type PoolDeserializer = fn(&str) -> serde_json::Result<Box<dyn PoolOperations>>;
+
+fn deserialize_pool<T: PoolOperations + DeserializeOwned + 'static>(
+    json_str: &str,
+) -> serde_json::Result<Box<dyn PoolOperations>> {
+    let pool: T = serde_json::from_str(json_str)?;
+    Ok(Box::new(pool))
+}
+
+/// Pool deserializers keyed by pool type tag (see `PoolType::tag`)
+#[derive(Debug, Clone)]
+pub struct PoolRegistry {
+    deserializers: HashMap<String, PoolDeserializer>,
+}
+
+impl PoolRegistry {
+    pub fn empty() -> Self {
+        Self {
+            deserializers: HashMap::new(),
         }
-        PoolType::JupiterPoolType => {
-            let pool: JupiterPool = serde_json::from_str(json_str).unwrap();
-            Box::new(pool)
+    }
+
+    pub fn register(&mut self, tag: &str, deserializer: PoolDeserializer) {
+        self.deserializers.insert(tag.to_string(), deserializer);
+    }
+
+    pub fn tags(&self) -> Vec<&str> {
+        let mut tags: Vec<&str> = self.deserializers.keys().map(|t| t.as_str()).collect();
+        tags.sort();
+        tags
+    }
+
+    /// parse `json_str` (read from `pool_path`) as a pool of type `tag`
+    pub fn parse(
+        &self,
+        tag: &str,
+        pool_path: &str,
+        json_str: &str,
+    ) -> Result<Box<dyn PoolOperations>, PoolLoadError> {
+        let deserializer =
+            self.deserializers
+                .get(tag)
+                .ok_or_else(|| PoolLoadError::UnknownType {
+                    path: pool_path.to_string(),
+                    tag: tag.to_string(),
+                })?;
+        deserializer(json_str).map_err(|source| PoolLoadError::Parse {
+            path: pool_path.to_string(),
+            source,
+        })
+    }
+
+    /// parse a pool JSON which can name its own type (`{"type": "orca", ...}`),
+    /// pools without a `"type"` field are parsed as `default_tag`
+    pub fn parse_tagged(
+        &self,
+        default_tag: Option<&str>,
+        pool_path: &str,
+        json_str: &str,
+    ) -> Result<Box<dyn PoolOperations>, PoolLoadError> {
+        let value: serde_json::Value =
+            serde_json::from_str(json_str).map_err(|source| PoolLoadError::Parse {
+                path: pool_path.to_string(),
+                source,
+            })?;
+        let tag = match value.get(POOL_TYPE_FIELD) {
+            Some(serde_json::Value::String(tag)) => tag.as_str(),
+            Some(other) => {
+                return Err(PoolLoadError::UnknownType {
+                    path: pool_path.to_string(),
+                    tag: other.to_string(),
+                })
+            }
+            None => default_tag.ok_or_else(|| PoolLoadError::MissingType {
+                path: pool_path.to_string(),
+            })?,
+        };
+        self.parse(tag, pool_path, json_str)
+    }
+
+    /// read + parse the pool file at `pool_path`
+    pub fn load(
+        &self,
+        tag: &str,
+        pool_path: &str,
+    ) -> Result<Box<dyn PoolOperations>, PoolLoadError> {
+        let json_str = read_pool_file(pool_path)?;
+        self.parse_tagged(Some(tag), pool_path, &json_str)
+    }
+
+    /// read + parse every pool in `pool_path` -- one pool per `.json` file or
+    /// one per line of a `.jsonl` file (errors are reported per pool)
+    pub fn load_all(
+        &self,
+        default_tag: Option<&str>,
+        pool_path: &str,
+    ) -> Vec<Result<Box<dyn PoolOperations>, PoolLoadError>> {
+        let json_str = match read_pool_file(pool_path) {
+            Ok(json_str) => json_str,
+            Err(e) => return vec![Err(e)],
+        };
+        if !is_json_lines(pool_path) {
+            return vec![self.parse_tagged(default_tag, pool_path, &json_str)];
         }
+        json_str
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let line_path = format!("{}:{}", pool_path, i + 1);
+                self.parse_tagged(default_tag, &line_path, line)
+            })
+            .collect()
     }
 }
 
-pub trait PoolOperations: Debug {
+/// field naming the pool type in a self-describing pool JSON
+pub const POOL_TYPE_FIELD: &str = "type";
+
+pub fn is_json_lines(pool_path: &str) -> bool {
+    matches!(std::path::Path::new(pool_path).extension(), Some(ex) if ex == "jsonl")
+}
+
+fn read_pool_file(pool_path: &str) -> Result<String, PoolLoadError> {
+    std::fs::read_to_string(pool_path).map_err(|source| PoolLoadError::Io {
+        path: pool_path.to_string(),
+        source,
+    })
+}
+
+impl Default for PoolRegistry {
+    /// every implemented pool type
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(PoolType::OrcaPoolType.tag(), deserialize_pool::<OrcaPool>);
+        registry.register(
+            PoolType::RaydiumPoolType.tag(),
+            deserialize_pool::<RaydiumPool>,
+        );
+        registry.register(PoolType::SerumPoolType.tag(), deserialize_pool::<SerumPool>);
+        registry.register(
+            PoolType::JupiterPoolType.tag(),
+            deserialize_pool::<JupiterPool>,
+        );
+        registry.register(
+            PoolType::WhirlpoolPoolType.tag(),
+            deserialize_pool::<WhirlpoolPool>,
+        );
+        registry.register(
+            PoolType::MeteoraPoolType.tag(),
+            deserialize_pool::<MeteoraPool>,
+        );
+        registry.register(
+            PoolType::PhoenixPoolType.tag(),
+            deserialize_pool::<PhoenixPool>,
+        );
+        registry.register(
+            PoolType::LifinityPoolType.tag(),
+            deserialize_pool::<LifinityPool>,
+        );
+        registry
+    }
+}
+
+lazy_static! {
+    pub static ref POOL_REGISTRY: PoolRegistry = PoolRegistry::default();
+}
+
+/// load the pool file at `pool_path` as a `tipe` pool
+pub fn pool_factory(
+    tipe: &PoolType,
+    pool_path: &str,
+) -> Result<Box<dyn PoolOperations>, PoolLoadError> {
+    POOL_REGISTRY.load(tipe.tag(), pool_path)
+}
+
+/// load every pool in the file at `pool_path` (a pool JSON or a JSON-lines
+/// snapshot), untagged pools are loaded as `tipe`
+pub fn load_pool_file(
+    tipe: Option<&PoolType>,
+    pool_path: &str,
+) -> Vec<Result<Box<dyn PoolOperations>, PoolLoadError>> {
+    POOL_REGISTRY.load_all(tipe.map(|t| t.tag()), pool_path)
+}
+
+/// the per-DEX pool dirs, then each of `pool_snapshots` (self-describing
+/// dirs or .jsonl files) -- shared by the bot + the setup binaries
+pub fn default_pool_dirs(pool_snapshots: &[String]) -> Vec<PoolDir> {
+    let mut pool_dirs = vec![
+        PoolDir {
+            tipe: Some(PoolType::OrcaPoolType),
+            dir_path: "../pools/orca".to_string(),
+        },
+        PoolDir {
+            tipe: Some(PoolType::RaydiumPoolType),
+            dir_path: "../pools/raydium/".to_string(),
+        },
+        PoolDir {
+            tipe: Some(PoolType::SerumPoolType),
+            dir_path: "../pools/serum/".to_string(),
+        },
+        PoolDir {
+            tipe: Some(PoolType::JupiterPoolType),
+            dir_path: "../pools/jupiter/".to_string(),
+        },
+        PoolDir {
+            tipe: Some(PoolType::WhirlpoolPoolType),
+            dir_path: "../pools/whirlpool/".to_string(),
+        },
+        PoolDir {
+            tipe: Some(PoolType::MeteoraPoolType),
+            dir_path: "../pools/meteora/".to_string(),
+        },
+        PoolDir {
+            tipe: Some(PoolType::PhoenixPoolType),
+            dir_path: "../pools/phoenix/".to_string(),
+        },
+        PoolDir {
+            tipe: Some(PoolType::LifinityPoolType),
+            dir_path: "../pools/lifinity/".to_string(),
+        },
+    ];
+    for snapshot_path in pool_snapshots {
+        pool_dirs.push(PoolDir {
+            tipe: None,
+            dir_path: snapshot_path.clone(),
+        });
+    }
+    pool_dirs
+}
+
+/// the (2 mint) pools of `pool_dirs`, in order -- missing dirs + pools which
+/// fail to load are skipped
+pub fn load_pools(pool_dirs: &[PoolDir]) -> Vec<Box<dyn PoolOperations>> {
+    let mut pools = vec![];
+    for pool_dir in pool_dirs {
+        if !std::path::Path::new(&pool_dir.dir_path).exists() {
+            warn!("skipping missing pool dir: {}", pool_dir.dir_path);
+            continue;
+        }
+        for pool_path in read_json_dir(&pool_dir.dir_path) {
+            for pool in load_pool_file(pool_dir.tipe.as_ref(), &pool_path) {
+                let pool = match pool {
+                    Ok(pool) => pool,
+                    Err(e) => {
+                        warn!("skipping pool: {}", e);
+                        continue;
+                    }
+                };
+                if pool.get_mints().len() != 2 {
+                    // only support 2 mint pools
+                    warn!("skipping pool with mints != 2: {:?}", pool_path);
+                    continue;
+                }
+                pools.push(pool);
+            }
+        }
+    }
+    pools
+}
+
+/// result of quoting an exact-in swap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quote {
+    pub amount_out: u128,
+    pub fee_paid: u128,        // in the input token
+    pub price_impact_bps: u64, // execution price vs the pool's spot price
+    pub new_reserves: Option<(u128, u128)>, // (reserve_in, reserve_out) after the swap -- None when the pool isn't reserve based
+}
+
+impl Quote {
+    /// quote of a curve swap -- `spot_rate` is the amount out per amount in
+    /// of an infinitesimal swap
+    pub fn from_swap_result(result: &SwapResult, spot_rate: f64) -> Self {
+        let fee_paid = result.trade_fee + result.owner_fee;
+        Self {
+            amount_out: result.destination_amount_swapped,
+            fee_paid,
+            price_impact_bps: price_impact_bps(
+                result.source_amount_swapped.saturating_sub(fee_paid),
+                result.destination_amount_swapped,
+                spot_rate,
+            ),
+            new_reserves: Some((
+                result.new_swap_source_amount,
+                result.new_swap_destination_amount,
+            )),
+        }
+    }
+}
+
+/// how much worse (in bps) swapping `amount_in` (fees excluded) for
+/// `amount_out` is than the spot rate, 0 if the rate isn't known
+pub fn price_impact_bps(amount_in: u128, amount_out: u128, spot_rate: f64) -> u64 {
+    if amount_in == 0 || !spot_rate.is_finite() || spot_rate <= 0. {
+        return 0;
+    }
+    let rate = amount_out as f64 / amount_in as f64;
+    let impact = (1. - rate / spot_rate) * 10_000.;
+    impact.round().clamp(0., 10_000.) as u64
+}
+
+pub trait PoolOperations: DynClone + Debug + Send + Sync {
     fn get_name(&self) -> String;
     fn get_update_accounts(&self) -> Vec<Pubkey>;
     fn set_update_accounts(&mut self, accounts: Vec<Option<Account>>, cluster: Cluster);
+    // update accounts past the first n may not exist (yet) and are passed as None -- eg. tick arrays
+    fn n_required_update_accounts(&self) -> usize {
+        self.get_update_accounts().len()
+    }
+    // latest slot the pool's accounts were seen at, set before each update -- for
+    // pools whose quotes depend on the clock (eg. oracle staleness)
+    fn set_slot(&mut self, _slot: u64) {}
+    // the owner's open orders accounts, set once the pools are loaded -- for
+    // pools which settle through one (serum)
+    fn set_open_orders(&mut self, _registry: &OpenOrdersRegistry) {}
+    // what a pool quoted off-chain (jupiter) fetches its route for, None for
+    // pools which are streamed -- see `spawn_route_refresher`
+    fn route_request(&self) -> Option<RouteRequest> {
+        None
+    }
+    // the route fetched for `route_request`, None when the fetch failed
+    fn set_route(&mut self, _route: Option<FetchedRoute>) {}
+    // does the swap take a token program per mint (whirlpool swap_v2, dlmm)
+    // rather than one for both -- see `Token2022Pool`
+    fn has_token_program_per_mint(&self) -> bool {
+        false
+    }
+    // mint -> its token program (classic when missing), set once the mints
+    // are fetched -- for pools with a token program per mint
+    fn set_token_programs(&mut self, _token_programs: &HashMap<Pubkey, Pubkey>) {}
 
-    fn mint_2_addr(&self, mint: &Pubkey) -> Pubkey;
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError>;
     fn get_mints(&self) -> Vec<Pubkey>;
-    fn mint_2_scale(&self, mint: &Pubkey) -> u64;
+    fn mint_2_scale(&self, mint: &Pubkey) -> Result<u64, QuoteError>;
 
+    fn get_quote(
+        &self,
+        amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError>;
+    // amount out of `get_quote`, 0 when the pool can't quote the swap
     fn get_quote_with_amounts_scaled(
         &self,
         amount_in: u128,
         mint_in: &Pubkey,
         mint_out: &Pubkey,
-    ) -> u128;
+    ) -> u128 {
+        self.get_quote(amount_in, mint_in, mint_out)
+            .map_or(0, |quote| quote.amount_out)
+    }
+    // `get_quote` + leave the pool in its post-trade state (reserves moved, order book levels
+    // consumed, ...) -- run on a snapshot (see `with_applied_trade`), never on the streamed pool
+    fn simulate_swap(
+        &mut self,
+        amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError>;
+    // smallest amount in which quotes at least `amount_out` (None if the pool can't provide it) --
+    // used to size flash loan repayments + to solve routes backwards. The default searches
+    // `get_quote_with_amounts_scaled`, pools which invert their math in closed form override it
+    fn get_quote_exact_out(
+        &self,
+        amount_out: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Option<u128> {
+        min_amount_in(amount_out, MAX_AMOUNT_IN, |amount_in| {
+            self.get_quote_with_amounts_scaled(amount_in, mint_in, mint_out)
+        })
+    }
+    // (reserve_in, reserve_out, fee fraction) if the pool is x * y = k -- used for closed-form sizing
+    fn get_constant_product_reserves(
+        &self,
+        _mint_in: &Pubkey,
+        _mint_out: &Pubkey,
+    ) -> Option<(u128, u128, f64)> {
+        None
+    }
     fn swap_ix(
         &self,
         program: &Program,
@@ -71,4 +459,175 @@ pub trait PoolOperations: Debug {
     fn can_trade(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> bool; // used for tests
 }
 
-// clone_trait_object!(PoolOperations);
+clone_trait_object!(PoolOperations);
+
+impl dyn PoolOperations {
+    /// snapshot of the pool after swapping `amount_in` (+ the swap's quote) --
+    /// used to quote trades which follow it in the same block
+    pub fn with_applied_trade(
+        &self,
+        amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<(Box<dyn PoolOperations>, Quote), QuoteError> {
+        let mut pool = dyn_clone::clone_box(self);
+        let quote = pool.simulate_swap(amount_in, mint_in, mint_out)?;
+        Ok((pool, quote))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::mock::{mock_mint, MockPool};
+
+    fn mock_deserializer(json_str: &str) -> serde_json::Result<Box<dyn PoolOperations>> {
+        let [reserve0, reserve1]: [u128; 2] = serde_json::from_str(json_str)?;
+        Ok(Box::new(MockPool::new(0, 1, reserve0, reserve1)))
+    }
+
+    fn tagged_mock_deserializer(json_str: &str) -> serde_json::Result<Box<dyn PoolOperations>> {
+        #[derive(serde::Deserialize)]
+        struct TaggedMock {
+            reserves: [u128; 2],
+        }
+        let pool: TaggedMock = serde_json::from_str(json_str)?;
+        mock_deserializer(&serde_json::to_string(&pool.reserves)?)
+    }
+
+    #[test]
+    fn registry_reports_bad_pool_files() {
+        let mut registry = PoolRegistry::empty();
+        registry.register("mock", mock_deserializer);
+        assert_eq!(registry.tags(), vec!["mock"]);
+
+        let pool = registry.parse("mock", "good.json", "[10, 20]").unwrap();
+        assert_eq!(pool.get_name(), "Mock");
+
+        match registry.parse("mock", "bad.json", "{\"oops\": 1}") {
+            Err(PoolLoadError::Parse { path, .. }) => assert_eq!(path, "bad.json"),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        match registry.parse("nope", "good.json", "[10, 20]") {
+            Err(PoolLoadError::UnknownType { tag, .. }) => assert_eq!(tag, "nope"),
+            other => panic!("expected an unknown type error, got {:?}", other),
+        }
+        match registry.load("mock", "./does/not/exist.json") {
+            Err(e @ PoolLoadError::Io { .. }) => {
+                assert!(e.to_string().contains("./does/not/exist.json"))
+            }
+            other => panic!("expected an io error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn loads_mixed_json_lines_snapshot() {
+        let mut registry = PoolRegistry::empty();
+        registry.register("mock", mock_deserializer);
+        registry.register("tagged_mock", tagged_mock_deserializer);
+
+        // the file's own type wins over the default
+        let tagged = r#"{"type": "tagged_mock", "reserves": [1, 2]}"#;
+        assert!(registry
+            .parse_tagged(Some("mock"), "tagged.json", tagged)
+            .is_ok());
+        match registry.parse_tagged(None, "untagged.json", "[10, 20]") {
+            Err(PoolLoadError::MissingType { path }) => assert_eq!(path, "untagged.json"),
+            other => panic!("expected a missing type error, got {:?}", other),
+        }
+        assert!(registry
+            .parse_tagged(Some("mock"), "untagged.json", "[10, 20]")
+            .is_ok());
+
+        let snapshot_path = std::env::temp_dir().join("pool_snapshot_test.jsonl");
+        let snapshot = format!("[1, 2]\n\n{}\n{{\"type\": \"nope\"}}\n", tagged);
+        std::fs::write(&snapshot_path, snapshot).unwrap();
+        let snapshot_path = snapshot_path.to_str().unwrap();
+
+        let pools = registry.load_all(Some("mock"), snapshot_path);
+        assert_eq!(pools.len(), 3);
+        assert!(pools[0].is_ok() && pools[1].is_ok());
+        match &pools[2] {
+            Err(PoolLoadError::UnknownType { path, tag }) => {
+                assert_eq!(path, &format!("{}:4", snapshot_path));
+                assert_eq!(tag, "nope");
+            }
+            other => panic!("expected an unknown type error, got {:?}", other),
+        }
+        std::fs::remove_file(snapshot_path).unwrap();
+    }
+
+    #[test]
+    fn exact_out_quotes_invert_exact_in_quotes() {
+        let pool = MockPool::new(0, 1, 1_000_000, 2_000_000);
+        let (mint_in, mint_out) = (mock_mint(0), mock_mint(1));
+        for amount_out in [1, 1_000, 500_000, 1_999_000] {
+            let amount_in = pool.get_quote_exact_out(amount_out, &mint_in, &mint_out).unwrap();
+            assert!(pool.get_quote_with_amounts_scaled(amount_in, &mint_in, &mint_out) >= amount_out);
+            assert!(pool.get_quote_with_amounts_scaled(amount_in - 1, &mint_in, &mint_out) < amount_out);
+        }
+        // the reserve out can't be bought
+        assert_eq!(pool.get_quote_exact_out(2_000_000, &mint_in, &mint_out), None);
+    }
+
+    #[test]
+    fn quotes_report_fees_impact_and_reserves() {
+        let pool = MockPool::new(0, 1, 1_000_000, 1_000_000);
+        let (mint_in, mint_out) = (mock_mint(0), mock_mint(1));
+        let quote = pool.get_quote(10_000, &mint_in, &mint_out).unwrap();
+        assert_eq!(quote.amount_out, 9_871);
+        assert_eq!(pool.get_quote_with_amounts_scaled(10_000, &mint_in, &mint_out), 9_871);
+        assert_eq!(quote.fee_paid, 30);
+        assert_eq!(quote.new_reserves, Some((1_010_000, 1_000_000 - 9_871)));
+        // 9_970 in after fees for 9_871 out at a spot rate of 1
+        assert_eq!(quote.price_impact_bps, 99);
+
+        match pool.get_quote(10_000, &mock_mint(5), &mint_out) {
+            Err(QuoteError::InvalidMints { mint_in, .. }) => assert_eq!(mint_in, mock_mint(5)),
+            other => panic!("expected an invalid mints error, got {:?}", other),
+        }
+        assert_eq!(pool.get_quote_with_amounts_scaled(10_000, &mock_mint(5), &mint_out), 0);
+    }
+
+    #[test]
+    fn applied_trades_move_the_snapshot_only() {
+        let pool: Box<dyn PoolOperations> = Box::new(MockPool::new(0, 1, 1_000_000, 1_000_000));
+        let (mint_in, mint_out) = (mock_mint(0), mock_mint(1));
+        let (snapshot, quote) = pool.with_applied_trade(10_000, &mint_in, &mint_out).unwrap();
+        assert_eq!(quote, pool.get_quote(10_000, &mint_in, &mint_out).unwrap());
+
+        // the same trade again gets a worse price, the original pool is untouched
+        let second = snapshot.get_quote(10_000, &mint_in, &mint_out).unwrap();
+        assert!(second.amount_out < quote.amount_out);
+        assert_eq!(second.new_reserves, Some((1_020_000, 1_000_000 - 9_871 - second.amount_out)));
+        assert_eq!(pool.get_quote(10_000, &mint_in, &mint_out).unwrap(), quote);
+    }
+
+    #[test]
+    fn price_impact_is_clamped() {
+        assert_eq!(price_impact_bps(0, 10, 1.), 0);
+        assert_eq!(price_impact_bps(100, 10, f64::NAN), 0);
+        assert_eq!(price_impact_bps(100, 99, 1.), 100);
+        // better than spot (eg. rounding) isn't negative impact
+        assert_eq!(price_impact_bps(100, 101, 1.), 0);
+        assert_eq!(price_impact_bps(100, 0, 1.), 10_000);
+    }
+
+    #[test]
+    fn default_registry_has_every_pool_type() {
+        let tags = POOL_REGISTRY.tags();
+        for tipe in [
+            PoolType::OrcaPoolType,
+            PoolType::RaydiumPoolType,
+            PoolType::SerumPoolType,
+            PoolType::JupiterPoolType,
+            PoolType::WhirlpoolPoolType,
+            PoolType::MeteoraPoolType,
+            PoolType::PhoenixPoolType,
+            PoolType::LifinityPoolType,
+        ] {
+            assert!(tags.contains(&tipe.tag()));
+            assert_eq!(PoolType::from_tag(tipe.tag()), Some(tipe));
+        }
+    }
+}
@@ -9,6 +9,7 @@ use crate::pool_utils::{
     calculator::{CurveCalculator, SwapWithoutFeesResult, TradeDirection},
     constant_price::ConstantPriceCurve,
     constant_product::ConstantProductCurve,
+    exact_out::{min_amount_in, MAX_AMOUNT_IN},
     fees::Fees,
     offset::OffsetCurve,
     stable::StableCurve,
@@ -105,6 +106,32 @@ impl SwapCurve {
         })
     }
 
+    /// Calculate the smallest amount of source token, fees included, which
+    /// `swap` turns into at least `destination_amount` of destination token.
+    pub fn swap_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+        fees: &Fees,
+    ) -> Option<u128> {
+        let source_amount_less_fees = self.calculator.swap_without_fees_exact_out(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_direction,
+        )?;
+        // the fees are debited from the source amount, so add them back
+        min_amount_in(source_amount_less_fees, MAX_AMOUNT_IN, |source_amount| {
+            let total_fees = fees
+                .trading_fee(source_amount)
+                .zip(fees.owner_trading_fee(source_amount))
+                .map_or(source_amount, |(trade_fee, owner_fee)| trade_fee + owner_fee);
+            source_amount.saturating_sub(total_fees)
+        })
+    }
+
     /// Get the amount of pool tokens for the deposited amount of token A or B
     pub fn deposit_single_token_type(
         &self,
@@ -398,4 +425,35 @@ mod tests {
         assert_eq!(result.destination_amount_swapped, 4545);
         assert_eq!(result.new_swap_destination_amount, 45455);
     }
+
+    #[test]
+    fn constant_product_swap_exact_out() {
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 100,
+            owner_trade_fee_numerator: 1,
+            owner_trade_fee_denominator: 1_000,
+            ..Fees::default()
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Arc::new(ConstantProductCurve {}),
+        };
+        let destination_amount_swapped = |source_amount| {
+            swap_curve
+                .swap(source_amount, 1_000, 50_000, TradeDirection::AtoB, &fees)
+                .map_or(0, |result| result.destination_amount_swapped)
+        };
+        for destination_amount in [1, 4_504, 25_000, 49_999] {
+            let source_amount = swap_curve
+                .swap_exact_out(destination_amount, 1_000, 50_000, TradeDirection::AtoB, &fees)
+                .unwrap();
+            assert!(destination_amount_swapped(source_amount) >= destination_amount);
+            assert!(destination_amount_swapped(source_amount - 1) < destination_amount);
+        }
+        assert_eq!(
+            swap_curve.swap_exact_out(50_000, 1_000, 50_000, TradeDirection::AtoB, &fees),
+            None
+        );
+    }
 }
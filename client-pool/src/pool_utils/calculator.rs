@@ -1,6 +1,11 @@
 //! Swap calculations
 
-use {crate::error::SwapError, spl_math::precise_number::PreciseNumber, std::fmt::Debug};
+use {
+    crate::error::SwapError,
+    crate::pool_utils::exact_out::{min_amount_in, MAX_AMOUNT_IN},
+    spl_math::precise_number::PreciseNumber,
+    std::fmt::Debug,
+};
 
 #[cfg(feature = "fuzz")]
 use arbitrary::Arbitrary;
@@ -95,6 +100,28 @@ pub trait CurveCalculator: Debug + DynPack {
         trade_direction: TradeDirection,
     ) -> Option<SwapWithoutFeesResult>;
 
+    /// Calculate the smallest amount of source token which provides at least
+    /// `destination_amount` of destination token.
+    /// The default implementation searches `swap_without_fees`, curves with a
+    /// closed-form inverse override it.
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<u128> {
+        min_amount_in(destination_amount, MAX_AMOUNT_IN, |source_amount| {
+            self.swap_without_fees(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                trade_direction,
+            )
+            .map_or(0, |result| result.destination_amount_swapped)
+        })
+    }
+
     /// Get the supply for a new pool
     /// The default implementation is a Balancer-style fixed initial supply
     fn new_pool_supply(&self) -> u128 {
@@ -436,6 +463,40 @@ pub mod test {
         assert!(difference <= epsilon);
     }
 
+    /// Test function checking that an exact-out swap is the smallest source
+    /// amount providing at least `destination_amount`.
+    pub fn check_swap_exact_out(
+        curve: &dyn CurveCalculator,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) {
+        let destination_amount_swapped = |source_amount: u128| {
+            curve
+                .swap_without_fees(
+                    source_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_direction,
+                )
+                .map_or(0, |results| results.destination_amount_swapped)
+        };
+        let source_amount = match curve.swap_without_fees_exact_out(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_direction,
+        ) {
+            Some(source_amount) => source_amount,
+            None => return,
+        };
+        assert!(destination_amount_swapped(source_amount) >= destination_amount);
+        if source_amount > 0 {
+            assert!(destination_amount_swapped(source_amount - 1) < destination_amount);
+        }
+    }
+
     /// Test function checking that a deposit never reduces the value of pool
     /// tokens.
     ///
@@ -0,0 +1,79 @@
+//! Helpers shared by the pool layouts + swap math: little-endian readers at a
+//! byte offset of an account's data (panic if it's too short -- callers check
+//! the account length first) and integer rounding.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use arrayref::array_ref;
+use spl_math::uint::U256;
+use std::ops::{Add, Div, Rem};
+
+pub fn read_pubkey(data: &[u8], offset: usize) -> Pubkey {
+    Pubkey::new_from_array(*array_ref![data, offset, 32])
+}
+
+pub fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(*array_ref![data, offset, 2])
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(*array_ref![data, offset, 4])
+}
+
+pub fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(*array_ref![data, offset, 4])
+}
+
+pub fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(*array_ref![data, offset, 8])
+}
+
+pub fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(*array_ref![data, offset, 8])
+}
+
+pub fn read_u128(data: &[u8], offset: usize) -> u128 {
+    u128::from_le_bytes(*array_ref![data, offset, 16])
+}
+
+/// numerator / denominator rounded up (u128 or U256)
+pub fn ceil_div<T>(numerator: T, denominator: T) -> T
+where
+    T: Copy + PartialEq + From<u8> + Add<Output = T> + Div<Output = T> + Rem<Output = T>,
+{
+    let quotient = numerator / denominator;
+    if numerator % denominator == T::from(0) {
+        quotient
+    } else {
+        quotient + T::from(1)
+    }
+}
+
+/// `value` clamped to u128::MAX
+pub fn saturating_u128(value: U256) -> u128 {
+    if value > U256::from(u128::MAX) {
+        u128::MAX
+    } else {
+        value.as_u128()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_rounds() {
+        let mut data = vec![0; 8];
+        data.extend_from_slice(&(-2_i64).to_le_bytes());
+        data.extend_from_slice(&7_u16.to_le_bytes());
+        assert_eq!(read_i64(&data, 8), -2);
+        assert_eq!(read_u16(&data, 16), 7);
+        assert_eq!(read_u32(&data, 0), 0);
+
+        assert_eq!(ceil_div(10_u128, 5), 2);
+        assert_eq!(ceil_div(11_u128, 5), 3);
+        assert_eq!(ceil_div(U256::from(11), U256::from(5)), U256::from(3));
+        assert_eq!(saturating_u128(U256::from(u128::MAX) + 1), u128::MAX);
+        assert_eq!(saturating_u128(U256::from(42)), 42);
+    }
+}
@@ -98,6 +98,25 @@ impl CurveCalculator for ConstantPriceCurve {
         })
     }
 
+    /// Constant price swaps invert to a multiplication (A to B) or a rounded
+    /// up division (B to A)
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        _swap_source_amount: u128,
+        _swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<u128> {
+        let token_b_price = map_zero_to_none(self.token_b_price as u128)?;
+        match trade_direction {
+            TradeDirection::AtoB => destination_amount.checked_mul(token_b_price),
+            TradeDirection::BtoA => match destination_amount.checked_rem(token_b_price)? {
+                0 => destination_amount.checked_div(token_b_price),
+                _ => Some(destination_amount / token_b_price + 1),
+            },
+        }
+    }
+
     /// Get the amount of trading tokens for the given amount of pool tokens,
     /// provided the total trading tokens and supply of pool tokens.
     /// For the constant price curve, the total value of the pool is weighted
@@ -264,7 +283,7 @@ mod tests {
     use super::*;
     use crate::pool_utils::calculator::{
         test::{
-            check_curve_value_from_swap, check_deposit_token_conversion,
+            check_curve_value_from_swap, check_deposit_token_conversion, check_swap_exact_out,
             check_withdraw_token_conversion, total_and_intermediate,
             CONVERSION_BASIS_POINTS_GUARANTEE,
         },
@@ -653,4 +672,17 @@ mod tests {
             assert!(new_value.checked_mul(&pool_token_supply).unwrap().greater_than_or_equal(&value.checked_mul(&new_pool_token_supply).unwrap()));
         }
     }
+
+    proptest! {
+        #[test]
+        fn swap_exact_out(
+            destination_amount in 1..u32::MAX as u128,
+            token_b_price in 1..u32::MAX as u64,
+        ) {
+            let curve = ConstantPriceCurve { token_b_price };
+            for trade_direction in [TradeDirection::AtoB, TradeDirection::BtoA] {
+                check_swap_exact_out(&curve, destination_amount, 0, 0, trade_direction);
+            }
+        }
+    }
 }
@@ -45,6 +45,30 @@ pub fn swap(
     })
 }
 
+/// The constant product swap calculation backwards: the smallest source
+/// amount which `swap` turns into at least `destination_amount`.
+///
+/// `swap` leaves `ceil(k / (x + a_in))` in the pool, which is at most
+/// `y - amount_out` once `x + a_in >= ceil(k / (y - amount_out))`.
+pub fn swap_exact_out(
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+) -> Option<u128> {
+    if destination_amount == 0 {
+        return Some(0);
+    }
+    let invariant = swap_source_amount.checked_mul(swap_destination_amount)?; // k = x * y
+    // the pool can't be emptied
+    let new_swap_destination_amount =
+        map_zero_to_none(swap_destination_amount.checked_sub(destination_amount)?)?; // y' = y - amount_out
+    let new_swap_source_amount = match invariant.checked_rem(new_swap_destination_amount)? {
+        0 => invariant / new_swap_destination_amount,
+        _ => invariant / new_swap_destination_amount + 1,
+    }; // x' = ceil(k / y')
+    new_swap_source_amount.checked_sub(swap_source_amount)
+}
+
 /// Get the amount of trading tokens for the given amount of pool tokens,
 /// provided the total trading tokens and supply of pool tokens.
 ///
@@ -182,6 +206,17 @@ impl CurveCalculator for ConstantProductCurve {
         swap(source_amount, swap_source_amount, swap_destination_amount)
     }
 
+    /// Constant product swaps invert in closed form
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        _trade_direction: TradeDirection,
+    ) -> Option<u128> {
+        swap_exact_out(destination_amount, swap_source_amount, swap_destination_amount)
+    }
+
     /// The constant product implementation is a simple ratio calculation for how many
     /// trading tokens correspond to a certain number of pool tokens
     fn pool_tokens_to_trading_tokens(
@@ -280,7 +315,7 @@ mod tests {
         test::{
             check_curve_value_from_swap, check_deposit_token_conversion,
             check_pool_value_from_deposit, check_pool_value_from_withdraw,
-            check_withdraw_token_conversion, total_and_intermediate,
+            check_swap_exact_out, check_withdraw_token_conversion, total_and_intermediate,
             CONVERSION_BASIS_POINTS_GUARANTEE,
         },
         RoundDirection, INITIAL_SWAP_POOL_AMOUNT,
@@ -546,4 +581,33 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn constant_product_swap_exact_out() {
+        let curve = ConstantProductCurve {};
+        // 1_000 * 1_000 = k, leaving 900 needs ceil(k / 900) = 1_112
+        assert_eq!(curve.swap_without_fees_exact_out(100, 1_000, 1_000, TradeDirection::AtoB), Some(112));
+        assert_eq!(curve.swap_without_fees_exact_out(0, 1_000, 1_000, TradeDirection::AtoB), Some(0));
+        // the pool can't be emptied
+        assert_eq!(curve.swap_without_fees_exact_out(1_000, 1_000, 1_000, TradeDirection::AtoB), None);
+        assert_eq!(curve.swap_without_fees_exact_out(1_001, 1_000, 1_000, TradeDirection::AtoB), None);
+    }
+
+    proptest! {
+        #[test]
+        fn swap_exact_out(
+            destination_amount in 1..u32::MAX as u128,
+            swap_source_amount in 1..u32::MAX as u128,
+            swap_destination_amount in 1..u32::MAX as u128,
+        ) {
+            let curve = ConstantProductCurve {};
+            check_swap_exact_out(
+                &curve,
+                destination_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+            );
+        }
+    }
 }
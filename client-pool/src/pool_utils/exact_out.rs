@@ -0,0 +1,111 @@
+//! Exact-out quoting by searching an exact-in quote
+//!
+//! Quotes are non-decreasing in the amount in up to what the pool can fill,
+//! past which some of them drop to 0 (eg. a whirlpool running out of loaded
+//! tick arrays). The smallest amount in which gets at least some amount out
+//! is then found with an exponential search for an upper bound followed by
+//! a binary search.
+
+/// most the search will put in -- token amounts are u64s
+pub const MAX_AMOUNT_IN: u128 = u64::MAX as u128;
+
+/// smallest `amount_in <= max_amount_in` with `quote(amount_in) >= amount_out`,
+/// None if no such amount exists
+pub fn min_amount_in<F: Fn(u128) -> u128>(
+    amount_out: u128,
+    max_amount_in: u128,
+    quote: F,
+) -> Option<u128> {
+    if amount_out == 0 {
+        return Some(0);
+    }
+    if max_amount_in == 0 {
+        return None;
+    }
+
+    // upper bound: quote(lo) < amount_out <= quote(hi)
+    let (mut lo, mut lo_quote) = (0, 0);
+    let mut hi = 1;
+    loop {
+        let hi_quote = quote(hi);
+        if hi_quote >= amount_out {
+            break;
+        }
+        if hi_quote < lo_quote {
+            // past what the pool can fill: search what's left below it
+            hi = last_fillable(lo, hi, lo_quote, &quote);
+            if quote(hi) < amount_out {
+                return None;
+            }
+            break;
+        }
+        if hi == max_amount_in {
+            return None;
+        }
+        lo = hi;
+        lo_quote = hi_quote;
+        hi = hi.saturating_mul(2).min(max_amount_in);
+    }
+
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if quote(mid) >= amount_out {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    Some(hi)
+}
+
+/// largest amount in `[lo, hi)` still quoting at least `lo_quote`
+fn last_fillable<F: Fn(u128) -> u128>(mut lo: u128, mut hi: u128, lo_quote: u128, quote: &F) -> u128 {
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if quote(mid) >= lo_quote {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_smallest_amount_in() {
+        // 2 out per 3 in
+        let quote = |amount_in: u128| amount_in * 2 / 3;
+        assert_eq!(min_amount_in(0, MAX_AMOUNT_IN, quote), Some(0));
+        assert_eq!(min_amount_in(2, MAX_AMOUNT_IN, quote), Some(3));
+        assert_eq!(min_amount_in(1_000, MAX_AMOUNT_IN, quote), Some(1_500));
+        assert_eq!(min_amount_in(1_001, MAX_AMOUNT_IN, quote), Some(1_502));
+        assert_eq!(min_amount_in(1_001, 1_501, quote), None);
+
+        for amount_out in 1..500 {
+            let amount_in = min_amount_in(amount_out, MAX_AMOUNT_IN, quote).unwrap();
+            assert!(quote(amount_in) >= amount_out);
+            assert!(quote(amount_in - 1) < amount_out);
+        }
+    }
+
+    #[test]
+    fn stops_at_what_the_pool_can_fill() {
+        // fills up to 1_000 in, quotes 0 past that
+        let quote = |amount_in: u128| match amount_in {
+            0..=1_000 => amount_in / 2,
+            _ => 0,
+        };
+        assert_eq!(min_amount_in(400, MAX_AMOUNT_IN, quote), Some(800));
+        assert_eq!(min_amount_in(500, MAX_AMOUNT_IN, quote), Some(1_000));
+        assert_eq!(min_amount_in(501, MAX_AMOUNT_IN, quote), None);
+
+        // an order book running out of liquidity plateaus instead
+        let quote = |amount_in: u128| amount_in.min(10);
+        assert_eq!(min_amount_in(10, MAX_AMOUNT_IN, quote), Some(10));
+        assert_eq!(min_amount_in(11, MAX_AMOUNT_IN, quote), None);
+    }
+}
@@ -0,0 +1,588 @@
+//! Jupiter v6 routes: quote + shared accounts route instruction
+//!
+//! A route is what the quote API hands back: the quote (`/quote`) and the
+//! `sharedAccountsRoute` instruction built for it (`/swap-instructions`).
+//! It comes from a `RouteSource` -- the HTTP API (or a local mock of it) or
+//! a JSON fixture with both parts.
+//!
+//! Routes aren't streamed: a refresher thread (`spawn_route_refresher`)
+//! re-fetches every pool's route on a timer, the pool is only locked to read
+//! its `RouteRequest` and to set what came back.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::instruction::AccountMeta;
+use log::{debug, warn};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::error::QuoteError;
+use crate::pool::Quote;
+use crate::serialize::token::WrappedPubkey;
+use crate::pool_utils::common::read_u64;
+use crate::utils::PoolQuote;
+
+// sha256("global:shared_accounts_route")[..8]
+pub const SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR: [u8; 8] = [193, 32, 155, 51, 65, 214, 156, 129];
+// in_amount u64 + quoted_out_amount u64 + slippage_bps u16 + platform_fee_bps u8
+const ROUTE_ARGS_TAIL_LEN: usize = 19;
+
+// token_program, program_authority, user_transfer_authority, source_token_account,
+// program_source_token_account, program_destination_token_account,
+// destination_token_account, source_mint, destination_mint, platform_fee_account,
+// token_2022_program, event_authority, program -- then the route's accounts
+pub const SHARED_ACCOUNTS_ROUTE_FIXED_ACCOUNTS: usize = 13;
+pub const USER_TRANSFER_AUTHORITY_INDEX: usize = 2;
+pub const SOURCE_TOKEN_ACCOUNT_INDEX: usize = 3;
+pub const DESTINATION_TOKEN_ACCOUNT_INDEX: usize = 6;
+
+/// how long the quote API gets to answer a request
+pub const QUOTE_API_TIMEOUT: Duration = Duration::from_secs(5);
+/// how long a route is used before it's fetched again
+pub const ROUTE_REFRESH: Duration = Duration::from_secs(10);
+
+/// a route + the instruction decoded from it, what a pool swaps through
+pub type FetchedRoute = (JupiterRoute, SharedAccountsRoute);
+
+/// the API's u64s are JSON strings
+mod string_u64 {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SwapInfo {
+    pub amm_key: WrappedPubkey,
+    #[serde(default)]
+    pub label: String,
+    pub input_mint: WrappedPubkey,
+    pub output_mint: WrappedPubkey,
+    #[serde(with = "string_u64")]
+    pub in_amount: u64,
+    #[serde(with = "string_u64")]
+    pub out_amount: u64,
+    #[serde(default, with = "string_u64")]
+    pub fee_amount: u64,
+    #[serde(default)]
+    pub fee_mint: Option<WrappedPubkey>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutePlanStep {
+    pub swap_info: SwapInfo,
+    pub percent: u8,
+}
+
+/// `/quote` response (the fields we use, the rest is passed back as is)
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteResponse {
+    pub input_mint: WrappedPubkey,
+    pub output_mint: WrappedPubkey,
+    #[serde(with = "string_u64")]
+    pub in_amount: u64,
+    #[serde(with = "string_u64")]
+    pub out_amount: u64,
+    #[serde(with = "string_u64")]
+    pub other_amount_threshold: u64,
+    pub slippage_bps: u16,
+    pub route_plan: Vec<RoutePlanStep>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct JupiterAccountMeta {
+    pub pubkey: WrappedPubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// an instruction as the API serializes it (`data` is base64)
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct JupiterInstruction {
+    pub program_id: WrappedPubkey,
+    pub accounts: Vec<JupiterAccountMeta>,
+    pub data: String,
+}
+
+/// a quote + the instruction which swaps it, also the fixture file format
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct JupiterRoute {
+    pub quote: QuoteResponse,
+    pub swap_instruction: JupiterInstruction,
+}
+
+/// `sharedAccountsRoute` instruction data, decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedAccountsRoute {
+    pub data: Vec<u8>,
+    pub in_amount: u64,
+    pub quoted_out_amount: u64,
+    pub slippage_bps: u16,
+    pub platform_fee_bps: u8,
+}
+
+/// None if `data` isn't a `sharedAccountsRoute` instruction's data
+pub fn decode_shared_accounts_route(data: &[u8]) -> Option<SharedAccountsRoute> {
+    if data.len() < SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR.len() + ROUTE_ARGS_TAIL_LEN
+        || data[..8] != SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR
+    {
+        return None;
+    }
+    // id @ 8, route plan @ 9 -- the fixed size args are at the end
+    let tail = data.len() - ROUTE_ARGS_TAIL_LEN;
+    Some(SharedAccountsRoute {
+        data: data.to_vec(),
+        in_amount: read_u64(data, tail),
+        quoted_out_amount: read_u64(data, tail + 8),
+        slippage_bps: u16::from_le_bytes([data[tail + 16], data[tail + 17]]),
+        platform_fee_bps: data[tail + 18],
+    })
+}
+
+impl JupiterRoute {
+    /// the decoded route instruction, checked against the quote it was built for
+    pub fn shared_accounts_route(&self, program_id: &Pubkey) -> Result<SharedAccountsRoute> {
+        let ix = &self.swap_instruction;
+        if ix.program_id.0 != *program_id {
+            return Err(anyhow!("route instruction is for program {}", ix.program_id.0));
+        }
+        if ix.accounts.len() < SHARED_ACCOUNTS_ROUTE_FIXED_ACCOUNTS {
+            return Err(anyhow!("route instruction has {} accounts", ix.accounts.len()));
+        }
+        let data = base64::decode(&ix.data).context("route instruction data isn't base64")?;
+        let route = decode_shared_accounts_route(&data)
+            .ok_or_else(|| anyhow!("route instruction isn't a shared accounts route"))?;
+        if route.in_amount != self.quote.in_amount {
+            return Err(anyhow!(
+                "route instruction swaps {} but the quote is for {}",
+                route.in_amount,
+                self.quote.in_amount
+            ));
+        }
+        Ok(route)
+    }
+
+    /// the route instruction's accounts in order, with the user's signer +
+    /// token accounts swapped in (everything else is shared by all users)
+    pub fn account_metas(
+        &self,
+        owner: &Pubkey,
+        source_token_account: &Pubkey,
+        destination_token_account: &Pubkey,
+    ) -> Vec<AccountMeta> {
+        self.swap_instruction
+            .accounts
+            .iter()
+            .enumerate()
+            .map(|(i, meta)| {
+                let pubkey = match i {
+                    USER_TRANSFER_AUTHORITY_INDEX => *owner,
+                    SOURCE_TOKEN_ACCOUNT_INDEX => *source_token_account,
+                    DESTINATION_TOKEN_ACCOUNT_INDEX => *destination_token_account,
+                    _ => meta.pubkey.0,
+                };
+                // only the user signs, the CPI passes on our signature
+                let is_signer = i == USER_TRANSFER_AUTHORITY_INDEX;
+                if meta.is_writable {
+                    AccountMeta::new(pubkey, is_signer)
+                } else {
+                    AccountMeta::new_readonly(pubkey, is_signer)
+                }
+            })
+            .collect()
+    }
+
+    /// amount out for `amount_in`, scaled down from the quoted size -- a route
+    /// is only good up to the amount it was quoted for
+    pub fn get_amount_out(&self, amount_in: u128) -> u128 {
+        let quote = &self.quote;
+        if quote.in_amount == 0 || amount_in > quote.in_amount as u128 {
+            return 0;
+        }
+        amount_in * quote.out_amount as u128 / quote.in_amount as u128
+    }
+
+    /// `get_amount_out` with the route's fees paid in the input token and
+    /// the API's price impact, both scaled down from the quoted size
+    pub fn get_quote(&self, amount_in: u128) -> std::result::Result<Quote, QuoteError> {
+        let quote = &self.quote;
+        if quote.in_amount == 0 || amount_in > quote.in_amount as u128 {
+            return Err(QuoteError::InsufficientLiquidity);
+        }
+        let quoted_fee: u128 = quote
+            .route_plan
+            .iter()
+            .map(|step| &step.swap_info)
+            .filter(|info| matches!(&info.fee_mint, Some(mint) if mint.0 == quote.input_mint.0))
+            .map(|info| info.fee_amount as u128)
+            .sum();
+        // "priceImpactPct" is a fraction (0.01 == 1%), as a string or a number
+        let price_impact = match quote.extra.get("priceImpactPct") {
+            Some(serde_json::Value::String(pct)) => pct.parse::<f64>().unwrap_or(0.),
+            Some(serde_json::Value::Number(pct)) => pct.as_f64().unwrap_or(0.),
+            _ => 0.,
+        };
+        Ok(Quote {
+            amount_out: self.get_amount_out(amount_in),
+            fee_paid: quoted_fee * amount_in / quote.in_amount as u128,
+            price_impact_bps: (price_impact * 10_000.).round().clamp(0., 10_000.) as u64,
+            new_reserves: None, // spread over the route's pools
+        })
+    }
+
+    /// takes a trade of `amount_in` for `amount_out` off the quoted size, the
+    /// rest of the route keeps its rate
+    pub fn consume(&mut self, amount_in: u128, amount_out: u128) {
+        let quote = &mut self.quote;
+        if quote.in_amount == 0 {
+            return;
+        }
+        let in_amount = quote.in_amount as u128;
+        let amount_in = amount_in.min(in_amount);
+        for step in quote.route_plan.iter_mut() {
+            let fee_amount = step.swap_info.fee_amount as u128;
+            step.swap_info.fee_amount -= (fee_amount * amount_in / in_amount) as u64;
+        }
+        quote.in_amount -= amount_in as u64;
+        quote.out_amount = quote.out_amount.saturating_sub(amount_out.min(u64::MAX as u128) as u64);
+    }
+
+    /// smallest amount in quoting at least `amount_out`, None past the
+    /// quoted size
+    pub fn get_amount_in(&self, amount_out: u128) -> Option<u128> {
+        let quote = &self.quote;
+        if quote.out_amount == 0 {
+            return None;
+        }
+        let amount_in = amount_out * quote.in_amount as u128;
+        let amount_in = match amount_in % quote.out_amount as u128 {
+            0 => amount_in / quote.out_amount as u128,
+            _ => amount_in / quote.out_amount as u128 + 1,
+        };
+        if amount_in > quote.in_amount as u128 {
+            return None;
+        }
+        Some(amount_in)
+    }
+}
+
+/// where routes come from
+pub trait RouteSource {
+    fn route(&self, input_mint: &Pubkey, output_mint: &Pubkey, amount: u64) -> Result<JupiterRoute>;
+}
+
+/// a JSON fixture with the route to use whatever the amount
+pub struct FileRouteSource {
+    pub path: String,
+}
+
+impl RouteSource for FileRouteSource {
+    fn route(&self, input_mint: &Pubkey, output_mint: &Pubkey, _amount: u64) -> Result<JupiterRoute> {
+        let route_str = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("reading route file {}", self.path))?;
+        let route: JupiterRoute = serde_json::from_str(&route_str)
+            .with_context(|| format!("parsing route file {}", self.path))?;
+        if route.quote.input_mint.0 != *input_mint || route.quote.output_mint.0 != *output_mint {
+            return Err(anyhow!("route file {} is for another pair", self.path));
+        }
+        Ok(route)
+    }
+}
+
+/// the quote API (`/quote` then `/swap-instructions`) at `url`
+pub struct HttpRouteSource {
+    pub url: String,
+    pub slippage_bps: u16,
+    // the routes' shared accounts don't depend on the user, ours are swapped in later
+    pub user: Pubkey,
+}
+
+impl RouteSource for HttpRouteSource {
+    fn route(&self, input_mint: &Pubkey, output_mint: &Pubkey, amount: u64) -> Result<JupiterRoute> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(QUOTE_API_TIMEOUT)
+            .build()
+            .context("building the quote client")?;
+        let quote: QuoteResponse = client
+            .get(format!("{}/quote", self.url))
+            .query(&[
+                ("inputMint", input_mint.to_string()),
+                ("outputMint", output_mint.to_string()),
+                ("amount", amount.to_string()),
+                ("slippageBps", self.slippage_bps.to_string()),
+            ])
+            .send()
+            .and_then(|response| response.error_for_status())
+            .context("requesting quote")?
+            .json()
+            .context("parsing quote")?;
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct SwapInstructionsResponse {
+            swap_instruction: JupiterInstruction,
+        }
+        let response: SwapInstructionsResponse = client
+            .post(format!("{}/swap-instructions", self.url))
+            .json(&json!({
+                "quoteResponse": quote,
+                "userPublicKey": self.user.to_string(),
+                "useSharedAccounts": true,
+                "wrapAndUnwrapSol": false,
+            }))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .context("requesting swap instructions")?
+            .json()
+            .context("parsing swap instructions")?;
+
+        Ok(JupiterRoute {
+            quote,
+            swap_instruction: response.swap_instruction,
+        })
+    }
+}
+
+/// the `RouteSource` for a pool's `routeFile` / `quoteApiUrl`
+pub fn route_source(
+    route_file: &Option<String>,
+    quote_api_url: &Option<String>,
+    slippage_bps: u16,
+) -> Option<Box<dyn RouteSource>> {
+    match (route_file, quote_api_url) {
+        (Some(path), _) => Some(Box::new(FileRouteSource { path: path.clone() })),
+        (None, Some(url)) => Some(Box::new(HttpRouteSource {
+            url: url.trim_end_matches('/').to_string(),
+            slippage_bps,
+            user: Pubkey::default(),
+        })),
+        (None, None) => None,
+    }
+}
+
+/// What a pool's route is fetched for -- copied out of the pool so the
+/// (slow) fetch doesn't hold it
+#[derive(Debug, Clone)]
+pub struct RouteRequest {
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount: u64,
+    pub slippage_bps: u16,
+    pub route_file: Option<String>,
+    pub quote_api_url: Option<String>,
+}
+
+impl RouteRequest {
+    pub fn fetch(&self, program_id: &Pubkey) -> Result<FetchedRoute> {
+        let source = route_source(&self.route_file, &self.quote_api_url, self.slippage_bps)
+            .ok_or_else(|| anyhow!("no route file or quote api url"))?;
+        let route = source.route(&self.input_mint, &self.output_mint, self.amount)?;
+        let instruction = route.shared_accounts_route(program_id)?;
+        Ok((route, instruction))
+    }
+}
+
+/// fetch the route of every pool of `pools` which takes one, returns how many
+/// were fetched -- a pool whose fetch fails has no route until the next one
+pub fn refresh_routes(pools: &[PoolQuote], program_id: &Pubkey) -> usize {
+    let mut n_fetched = 0;
+    for pool in pools {
+        let request = match pool.read().route_request() {
+            Some(request) => request,
+            None => continue,
+        };
+        let route = match request.fetch(program_id) {
+            Ok(route) => {
+                n_fetched += 1;
+                Some(route)
+            }
+            Err(e) => {
+                warn!(
+                    "failed to get jupiter route {} -> {}: {:#}",
+                    request.input_mint, request.output_mint, e
+                );
+                None
+            }
+        };
+        pool.0.write().unwrap().set_route(route);
+    }
+    n_fetched
+}
+
+/// re-fetch the routes of `pools` every `ROUTE_REFRESH` on a thread of its
+/// own -- None if none of them take a route
+pub fn spawn_route_refresher(pools: &[PoolQuote], program_id: Pubkey) -> Option<JoinHandle<()>> {
+    let pools: Vec<PoolQuote> = pools
+        .iter()
+        .filter(|pool| pool.read().route_request().is_some())
+        .cloned()
+        .collect();
+    if pools.is_empty() {
+        return None;
+    }
+    Some(thread::spawn(move || loop {
+        thread::sleep(ROUTE_REFRESH);
+        let n_fetched = refresh_routes(&pools, &program_id);
+        debug!("refreshed {} / {} jupiter routes", n_fetched, pools.len());
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool::PoolOperations;
+    use crate::pools::JupiterPool;
+    use crate::tests::http::{route_json, MockQuoteServer};
+    use crate::tests::mock::MockPool;
+    use std::sync::{Arc, RwLock};
+
+    fn pubkey(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    #[test]
+    fn decodes_shared_accounts_route() {
+        let route: JupiterRoute =
+            serde_json::from_value(route_json(&pubkey(9), &pubkey(1), &pubkey(2), 1_000, 990)).unwrap();
+        let decoded = route.shared_accounts_route(&pubkey(9)).unwrap();
+        assert_eq!(decoded.in_amount, 1_000);
+        assert_eq!(decoded.quoted_out_amount, 990);
+        assert_eq!(decoded.slippage_bps, 50);
+        assert!(route.shared_accounts_route(&pubkey(8)).is_err());
+
+        assert!(decode_shared_accounts_route(&decoded.data[..20]).is_none());
+        let mut data = decoded.data.clone();
+        data[0] = 0;
+        assert!(decode_shared_accounts_route(&data).is_none());
+    }
+
+    #[test]
+    fn swaps_in_the_users_accounts() {
+        let route: JupiterRoute =
+            serde_json::from_value(route_json(&pubkey(9), &pubkey(1), &pubkey(2), 1_000, 990)).unwrap();
+        let metas = route.account_metas(&pubkey(20), &pubkey(21), &pubkey(22));
+        assert_eq!(metas.len(), route.swap_instruction.accounts.len());
+        assert_eq!(metas[USER_TRANSFER_AUTHORITY_INDEX].pubkey, pubkey(20));
+        assert!(metas[USER_TRANSFER_AUTHORITY_INDEX].is_signer);
+        assert_eq!(metas[SOURCE_TOKEN_ACCOUNT_INDEX].pubkey, pubkey(21));
+        assert_eq!(metas[DESTINATION_TOKEN_ACCOUNT_INDEX].pubkey, pubkey(22));
+        assert_eq!(metas[0].pubkey, route.swap_instruction.accounts[0].pubkey.0);
+        assert_eq!(metas.iter().filter(|m| m.is_signer).count(), 1);
+
+        assert_eq!(route.get_amount_out(500), 495);
+        assert_eq!(route.get_amount_out(1_001), 0);
+        assert_eq!(route.get_amount_in(495), Some(500));
+        assert_eq!(route.get_amount_in(990), Some(1_000));
+        assert_eq!(route.get_amount_in(991), None);
+    }
+
+    #[test]
+    fn quotes_input_fees_and_price_impact() {
+        let mut json = route_json(&pubkey(9), &pubkey(1), &pubkey(2), 1_000, 990);
+        json["quote"]["priceImpactPct"] = json!("0.0012");
+        json["quote"]["routePlan"][0]["swapInfo"]["feeAmount"] = json!("4");
+        let route: JupiterRoute = serde_json::from_value(json.clone()).unwrap();
+        let quote = route.get_quote(500).unwrap();
+        assert_eq!(quote.amount_out, 495);
+        assert_eq!(quote.fee_paid, 2);
+        assert_eq!(quote.price_impact_bps, 12);
+        assert_eq!(quote.new_reserves, None);
+        assert_eq!(route.get_quote(1_001), Err(QuoteError::InsufficientLiquidity));
+
+        // fees taken in the output token aren't paid out of the amount in
+        json["quote"]["routePlan"][0]["swapInfo"]["feeMint"] = json!(pubkey(2).to_string());
+        let route: JupiterRoute = serde_json::from_value(json).unwrap();
+        assert_eq!(route.get_quote(500).unwrap().fee_paid, 0);
+    }
+
+    #[test]
+    fn consumed_routes_shrink() {
+        let mut json = route_json(&pubkey(9), &pubkey(1), &pubkey(2), 1_000, 990);
+        json["quote"]["routePlan"][0]["swapInfo"]["feeAmount"] = json!("4");
+        let mut route: JupiterRoute = serde_json::from_value(json).unwrap();
+        route.consume(600, 594);
+        assert_eq!((route.quote.in_amount, route.quote.out_amount), (400, 396));
+        assert_eq!(route.get_amount_out(400), 396);
+        assert_eq!(route.get_quote(401), Err(QuoteError::InsufficientLiquidity));
+        assert_eq!(route.get_quote(400).unwrap().fee_paid, 2);
+    }
+
+    #[test]
+    fn fetches_routes_from_the_quote_api() {
+        let server = MockQuoteServer::start(route_json(&pubkey(9), &pubkey(1), &pubkey(2), 1_000, 990));
+        let source = route_source(&None, &Some(server.url()), 50).unwrap();
+        let route = source.route(&pubkey(1), &pubkey(2), 1_000).unwrap();
+        assert_eq!(route.quote.out_amount, 990);
+        assert!(route.shared_accounts_route(&pubkey(9)).is_ok());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with("GET /quote?"));
+        assert!(requests[0].contains(&format!("inputMint={}", pubkey(1))));
+        assert!(requests[0].contains("amount=1000"));
+        assert!(requests[1].starts_with("POST /swap-instructions"));
+        assert!(requests[1].contains("\"useSharedAccounts\":true"));
+    }
+
+    #[test]
+    fn refreshes_pool_routes() {
+        let path = std::env::temp_dir().join("jupiter_refresh_fixture.json");
+        let json = route_json(&pubkey(9), &pubkey(1), &pubkey(2), 1_000, 990);
+        std::fs::write(&path, json.to_string()).unwrap();
+        let pool: JupiterPool = serde_json::from_value(json!({
+            "inputMint": pubkey(1).to_string(),
+            "outputMint": pubkey(2).to_string(),
+            "inputDecimals": 6,
+            "outputDecimals": 6,
+            "quoteAmount": 1_000,
+            "routeFile": path.to_str().unwrap(),
+        }))
+        .unwrap();
+        let pools: Vec<PoolQuote> = vec![
+            Box::new(pool) as Box<dyn PoolOperations>,
+            Box::new(MockPool::new(0, 1, 1_000, 1_000)),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(id, pool)| PoolQuote::new(Arc::new(RwLock::new(pool)), id))
+        .collect();
+
+        // streamed pools don't take a route
+        assert_eq!(refresh_routes(&pools, &pubkey(9)), 1);
+        assert!(pools[0].read().can_trade(&pubkey(1), &pubkey(2)));
+
+        // a failed fetch leaves the pool without a route
+        assert_eq!(refresh_routes(&pools, &pubkey(8)), 0);
+        assert!(!pools[0].read().can_trade(&pubkey(1), &pubkey(2)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reads_routes_from_a_fixture() {
+        let path = std::env::temp_dir().join("jupiter_route_fixture.json");
+        let json = route_json(&pubkey(9), &pubkey(1), &pubkey(2), 1_000, 990);
+        std::fs::write(&path, json.to_string()).unwrap();
+        let source = route_source(&Some(path.to_str().unwrap().to_string()), &None, 50).unwrap();
+        assert!(source.route(&pubkey(1), &pubkey(2), 1).is_ok());
+        assert!(source.route(&pubkey(2), &pubkey(1), 1).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
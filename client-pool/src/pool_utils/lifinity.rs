@@ -0,0 +1,319 @@
+//! Lifinity v2 `Amm` state, Pyth price accounts + the oracle-anchored swap curve
+//!
+//! Lifinity quotes around the oracle rather than its own reserves: the pool
+//! value at the oracle price is split evenly into virtual reserves
+//! `X = c * V / 2p` and `Y = c * V / 2` (`c` == the curve's concentration),
+//! which are then traded as x * y = k. The marginal price is the oracle's and
+//! a higher concentration means less slippage, but the pool can never pay out
+//! more than it actually holds.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use spl_math::uint::U256;
+
+use crate::pool_utils::base::SwapResult;
+use crate::pool_utils::common::{read_i32, read_i64, read_pubkey, read_u32, read_u64};
+
+pub const AMM_LEN: usize = 911;
+// sha256("account:Amm")[..8]
+pub const AMM_DISCRIMINATOR: [u8; 8] = [143, 245, 200, 17, 74, 214, 196, 135];
+
+pub const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+pub const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+pub const PYTH_STATUS_TRADING: u32 = 1;
+const PYTH_AGGREGATE_OFFSET: usize = 208;
+/// a price published longer ago than this is refused (about 10s)
+pub const MAX_PYTH_STALENESS_SLOTS: u64 = 25;
+
+/// The `Amm` fields we quote + swap with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Amm {
+    pub freeze_trade: u8,
+    pub token_a_account: Pubkey,
+    pub token_b_account: Pubkey,
+    pub pool_mint: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub fee_account: Pubkey,
+    pub oracle_main_account: Pubkey,
+    pub oracle_sub_account: Pubkey,
+    pub oracle_pc_account: Pubkey,
+    pub trade_fee_numerator: u64,
+    pub trade_fee_denominator: u64,
+    pub owner_trade_fee_numerator: u64,
+    pub owner_trade_fee_denominator: u64,
+    pub curve_type: u8,
+    pub concentration: u64, // the curve's parameter
+}
+
+/// A Pyth price account's aggregate price, `price * 10^expo` quote units per base unit
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PythPrice {
+    pub expo: i32,
+    pub price: i64,
+    pub conf: u64,
+    pub status: u32,
+    pub publish_slot: u64,
+}
+
+/// None if `data` isn't an `Amm` account
+pub fn unpack_amm(data: &[u8]) -> Option<Amm> {
+    if data.len() != AMM_LEN || data[..8] != AMM_DISCRIMINATOR {
+        return None;
+    }
+    Some(Amm {
+        // initializer keys + amounts @ 8, is_initialized @ 120, bump @ 121
+        freeze_trade: data[122],
+        // freeze deposit/withdraw @ 123, base decimals @ 125, token program @ 126
+        token_a_account: read_pubkey(data, 158),
+        token_b_account: read_pubkey(data, 190),
+        pool_mint: read_pubkey(data, 222),
+        token_a_mint: read_pubkey(data, 254),
+        token_b_mint: read_pubkey(data, 286),
+        fee_account: read_pubkey(data, 318),
+        oracle_main_account: read_pubkey(data, 350),
+        oracle_sub_account: read_pubkey(data, 382),
+        oracle_pc_account: read_pubkey(data, 414),
+        trade_fee_numerator: read_u64(data, 446),
+        trade_fee_denominator: read_u64(data, 454),
+        owner_trade_fee_numerator: read_u64(data, 462),
+        owner_trade_fee_denominator: read_u64(data, 470),
+        // withdraw + host fees @ 478
+        curve_type: data[510],
+        concentration: read_u64(data, 511),
+        // config @ 519
+    })
+}
+
+/// None if `data` isn't a Pyth (v2) price account
+pub fn unpack_pyth_price(data: &[u8]) -> Option<PythPrice> {
+    if data.len() < PYTH_AGGREGATE_OFFSET + 32
+        || read_u32(data, 0) != PYTH_MAGIC
+        || read_u32(data, 8) != PYTH_PRICE_ACCOUNT_TYPE
+    {
+        return None;
+    }
+    Some(PythPrice {
+        expo: read_i32(data, 20),
+        price: read_i64(data, PYTH_AGGREGATE_OFFSET),
+        conf: read_u64(data, PYTH_AGGREGATE_OFFSET + 8),
+        status: read_u32(data, PYTH_AGGREGATE_OFFSET + 16),
+        // corporate action @ 212
+        publish_slot: read_u64(data, PYTH_AGGREGATE_OFFSET + 24),
+    })
+}
+
+/// the program's signer for the amm's vaults
+pub fn authority_address(amm: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[amm.as_ref()], program_id).0
+}
+
+impl PythPrice {
+    /// trading and published at most `MAX_PYTH_STALENESS_SLOTS` before
+    /// `current_slot` (0 == unknown, the price is taken as fresh)
+    pub fn is_tradable(&self, current_slot: u64) -> bool {
+        self.status == PYTH_STATUS_TRADING
+            && self.price > 0
+            && current_slot.saturating_sub(self.publish_slot) <= MAX_PYTH_STALENESS_SLOTS
+    }
+
+    /// (numerator, denominator) of the price in b atoms per a atom
+    pub fn atom_price(
+        &self,
+        decimals_a: u64,
+        decimals_b: u64,
+        current_slot: u64,
+    ) -> Option<(U256, U256)> {
+        if !self.is_tradable(current_slot) {
+            return None;
+        }
+        let ten = U256::from(10);
+        let mut numerator = U256::from(self.price as u64) * ten.pow(U256::from(decimals_b));
+        let mut denominator = ten.pow(U256::from(decimals_a));
+        let expo = U256::from(self.expo.unsigned_abs());
+        if self.expo >= 0 {
+            numerator *= ten.pow(expo);
+        } else {
+            denominator *= ten.pow(expo);
+        }
+        Some((numerator, denominator))
+    }
+}
+
+impl Amm {
+    /// (trade fee, owner fee) of `amount_in`
+    fn fees(&self, amount_in: u128) -> (u128, u128) {
+        let fee = |numerator: u64, denominator: u64| {
+            if denominator == 0 {
+                0
+            } else {
+                amount_in * numerator as u128 / denominator as u128
+            }
+        };
+        (
+            fee(self.trade_fee_numerator, self.trade_fee_denominator),
+            fee(self.owner_trade_fee_numerator, self.owner_trade_fee_denominator),
+        )
+    }
+}
+
+fn u256_to_f64(value: U256) -> f64 {
+    value
+        .0
+        .iter()
+        .rev()
+        .fold(0., |acc, limb| acc * 18_446_744_073_709_551_616. + *limb as f64)
+}
+
+/// amount out per amount in of a small swap: the oracle's price
+pub fn spot_rate(oracle_price: (U256, U256), a_to_b: bool) -> f64 {
+    let (numerator, denominator) = oracle_price;
+    let price = u256_to_f64(numerator) / u256_to_f64(denominator);
+    if a_to_b {
+        price
+    } else {
+        1. / price
+    }
+}
+
+/// swap `amount_in` a for b (`a_to_b`) or b for a, priced at `oracle_price`
+/// (b atoms per a atom, as a fraction). None without a price or reserves
+pub fn swap(
+    amm: &Amm,
+    oracle_price: (U256, U256),
+    reserve_a: u128,
+    reserve_b: u128,
+    amount_in: u128,
+    a_to_b: bool,
+) -> Option<SwapResult> {
+    let (numerator, denominator) = oracle_price;
+    if numerator.is_zero() || denominator.is_zero() {
+        return None;
+    }
+    let (trade_fee, owner_fee) = amm.fees(amount_in);
+    let amount_in_after_fees = U256::from(amount_in.saturating_sub(trade_fee + owner_fee));
+    let concentration = U256::from(amm.concentration.max(1));
+    // c * pool value in b atoms, scaled by the price's denominator
+    let scaled_value =
+        concentration * (U256::from(reserve_a) * numerator + U256::from(reserve_b) * denominator);
+    if scaled_value.is_zero() {
+        return None;
+    }
+
+    let (amount_out, reserve_in, reserve_out) = if a_to_b {
+        // Y * dx / (X + dx)
+        let amount_out = scaled_value * numerator * amount_in_after_fees
+            / (denominator * (scaled_value + U256::from(2) * numerator * amount_in_after_fees));
+        (amount_out, reserve_a, reserve_b)
+    } else {
+        // X * dy / (Y + dy)
+        let amount_out = scaled_value * denominator * amount_in_after_fees
+            / (numerator * (scaled_value + U256::from(2) * denominator * amount_in_after_fees));
+        (amount_out, reserve_b, reserve_a)
+    };
+    // can't pay out more than the vault holds
+    let amount_out = amount_out.min(U256::from(reserve_out)).as_u128();
+    Some(SwapResult {
+        new_swap_source_amount: reserve_in + amount_in,
+        new_swap_destination_amount: reserve_out - amount_out,
+        source_amount_swapped: amount_in,
+        destination_amount_swapped: amount_out,
+        trade_fee,
+        owner_fee,
+    })
+}
+
+/// amount out of `swap`, 0 without a price or reserves
+pub fn get_amount_out(
+    amm: &Amm,
+    oracle_price: (U256, U256),
+    reserve_a: u128,
+    reserve_b: u128,
+    amount_in: u128,
+    a_to_b: bool,
+) -> u128 {
+    swap(amm, oracle_price, reserve_a, reserve_b, amount_in, a_to_b)
+        .map_or(0, |result| result.destination_amount_swapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amm(concentration: u64) -> Amm {
+        Amm {
+            trade_fee_numerator: 0,
+            trade_fee_denominator: 10_000,
+            concentration,
+            ..Amm::default()
+        }
+    }
+
+    fn price(numerator: u64, denominator: u64) -> (U256, U256) {
+        (U256::from(numerator), U256::from(denominator))
+    }
+
+    #[test]
+    fn matches_constant_product_at_the_balanced_price() {
+        // 100 a + 100 b at a price of 1 == x * y = k on the real reserves
+        let out = get_amount_out(&amm(1), price(1, 1), 100_000, 100_000, 10_000, true);
+        assert_eq!(out, 100_000 * 10_000 / 110_000);
+        let out = get_amount_out(&amm(1), price(1, 1), 100_000, 100_000, 10_000, false);
+        assert_eq!(out, 100_000 * 10_000 / 110_000);
+    }
+
+    #[test]
+    fn trades_at_the_oracle_price_not_the_reserve_ratio() {
+        // reserves say 1 a == 1 b, the oracle says 2 b
+        let out = get_amount_out(&amm(1_000), price(2, 1), 1_000_000, 1_000_000, 1_000, true);
+        assert!((1_990..2_000).contains(&out), "{}", out);
+        let out = get_amount_out(&amm(1_000), price(2, 1), 1_000_000, 1_000_000, 2_000, false);
+        assert!((995..1_000).contains(&out), "{}", out);
+        // capped by the vault
+        assert_eq!(get_amount_out(&amm(1_000), price(2, 1), 1_000_000, 1_000, 10_000, true), 1_000);
+    }
+
+    #[test]
+    fn takes_trade_and_owner_fees() {
+        let mut amm = amm(1);
+        amm.trade_fee_numerator = 20;
+        amm.owner_trade_fee_numerator = 5;
+        amm.owner_trade_fee_denominator = 10_000;
+        // 10_000 - 25 in
+        let out = get_amount_out(&amm, price(1, 1), 100_000, 100_000, 10_000, true);
+        assert_eq!(out, 100_000 * 9_975 / 109_975);
+
+        let result = swap(&amm, price(1, 1), 100_000, 100_000, 10_000, true).unwrap();
+        assert_eq!((result.trade_fee, result.owner_fee), (20, 5));
+        assert_eq!(
+            (result.new_swap_source_amount, result.new_swap_destination_amount),
+            (110_000, 100_000 - out)
+        );
+        assert_eq!(spot_rate(price(2, 1), true), 2.);
+        assert_eq!(spot_rate(price(2, 1), false), 0.5);
+    }
+
+    #[test]
+    fn unpacks_pyth_price() {
+        let mut data = vec![0_u8; 3312];
+        data[..4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+        data[8..12].copy_from_slice(&PYTH_PRICE_ACCOUNT_TYPE.to_le_bytes());
+        data[20..24].copy_from_slice(&(-8_i32).to_le_bytes());
+        data[208..216].copy_from_slice(&2_000_000_000_i64.to_le_bytes()); // 20.0
+        data[224..228].copy_from_slice(&PYTH_STATUS_TRADING.to_le_bytes());
+        data[232..240].copy_from_slice(&1_000_u64.to_le_bytes());
+        let pyth = unpack_pyth_price(&data).unwrap();
+        assert_eq!(pyth.price, 2_000_000_000);
+        assert_eq!(pyth.publish_slot, 1_000);
+        assert!(pyth.is_tradable(1_000 + MAX_PYTH_STALENESS_SLOTS));
+        assert!(!pyth.is_tradable(1_001 + MAX_PYTH_STALENESS_SLOTS)); // stale
+        // 9 decimal a, 6 decimal b: 20 * 1e6 / 1e9 b atoms per a atom
+        let (numerator, denominator) = pyth.atom_price(9, 6, 1_010).unwrap();
+        assert_eq!(numerator * U256::from(1_000), denominator * U256::from(20));
+        assert!(pyth.atom_price(9, 6, 2_000).is_none());
+
+        data[224..228].copy_from_slice(&0_u32.to_le_bytes());
+        assert!(unpack_pyth_price(&data).unwrap().atom_price(9, 6, 1_010).is_none());
+        data[..4].copy_from_slice(&0_u32.to_le_bytes());
+        assert!(unpack_pyth_price(&data).is_none());
+    }
+}
@@ -0,0 +1,523 @@
+//! Meteora DLMM state + bin-by-bin swap math (mirrors lb_clmm's `lb_pair.rs`,
+//! `bin.rs` and its `swap` instruction)
+//!
+//! Liquidity sits in discrete bins of constant price (`price` is Q64.64, y
+//! per x). A swap drains the active bin then moves to the next one with
+//! liquidity. The fee is a base fee plus a variable fee which grows with the
+//! number of bins crossed since the volatility reference.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use spl_math::uint::U256;
+use std::collections::BTreeMap;
+use crate::pool_utils::common::{
+    ceil_div, read_i32, read_i64, read_pubkey, read_u128, read_u16, read_u32, read_u64,
+    saturating_u128,
+};
+
+pub const LB_PAIR_LEN: usize = 904;
+pub const BIN_ARRAY_LEN: usize = 10136;
+// sha256("account:<name>")[..8]
+pub const LB_PAIR_DISCRIMINATOR: [u8; 8] = [33, 11, 49, 98, 181, 101, 177, 13];
+pub const BIN_ARRAY_DISCRIMINATOR: [u8; 8] = [92, 142, 92, 220, 5, 148, 70, 181];
+
+pub const MAX_BIN_PER_ARRAY: i32 = 70;
+const BIN_LEN: usize = 144;
+
+pub const BASIS_POINT_MAX: u64 = 10_000;
+pub const FEE_PRECISION: u128 = 1_000_000_000;
+pub const MAX_FEE_RATE: u128 = 100_000_000;
+
+/// The `LbPair` fields we quote + swap with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LbPair {
+    // static parameters
+    pub base_factor: u16,
+    pub filter_period: u16,
+    pub decay_period: u16,
+    pub reduction_factor: u16,
+    pub variable_fee_control: u32,
+    pub max_volatility_accumulator: u32,
+    // variable parameters
+    pub volatility_accumulator: u32,
+    pub volatility_reference: u32,
+    pub index_reference: i32,
+    pub last_update_timestamp: i64,
+
+    pub active_id: i32,
+    pub bin_step: u16,
+    pub status: u8,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub reserve_x: Pubkey,
+    pub reserve_y: Pubkey,
+    pub oracle: Pubkey,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bin {
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub price: u128,
+}
+
+/// `MAX_BIN_PER_ARRAY` bins, `bins[i]` is bin `index * MAX_BIN_PER_ARRAY + i`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BinArray {
+    pub index: i64,
+    pub lb_pair: Pubkey,
+    pub bins: Vec<Bin>,
+}
+
+/// None if `data` isn't an `LbPair` account
+pub fn unpack_lb_pair(data: &[u8]) -> Option<LbPair> {
+    if data.len() != LB_PAIR_LEN || data[..8] != LB_PAIR_DISCRIMINATOR {
+        return None;
+    }
+    Some(LbPair {
+        base_factor: read_u16(data, 8),
+        filter_period: read_u16(data, 10),
+        decay_period: read_u16(data, 12),
+        reduction_factor: read_u16(data, 14),
+        variable_fee_control: read_u32(data, 16),
+        max_volatility_accumulator: read_u32(data, 20),
+        volatility_accumulator: read_u32(data, 40),
+        volatility_reference: read_u32(data, 44),
+        index_reference: read_i32(data, 48),
+        last_update_timestamp: read_i64(data, 56),
+        active_id: read_i32(data, 76),
+        bin_step: read_u16(data, 80),
+        status: data[82],
+        token_x_mint: read_pubkey(data, 88),
+        token_y_mint: read_pubkey(data, 120),
+        reserve_x: read_pubkey(data, 152),
+        reserve_y: read_pubkey(data, 184),
+        // protocol fee @ 216, reward infos @ 264
+        oracle: read_pubkey(data, 552),
+    })
+}
+
+/// None if `data` isn't a `BinArray` account
+pub fn unpack_bin_array(data: &[u8]) -> Option<BinArray> {
+    if data.len() != BIN_ARRAY_LEN || data[..8] != BIN_ARRAY_DISCRIMINATOR {
+        return None;
+    }
+    let bins = (0..MAX_BIN_PER_ARRAY as usize)
+        .map(|i| {
+            let offset = 56 + i * BIN_LEN;
+            Bin {
+                amount_x: read_u64(data, offset),
+                amount_y: read_u64(data, offset + 8),
+                price: read_u128(data, offset + 16),
+            }
+        })
+        .collect();
+    Some(BinArray {
+        index: read_i64(data, 8),
+        // version @ 16
+        lb_pair: read_pubkey(data, 24),
+        bins,
+    })
+}
+
+/// index of the bin array holding `bin_id`
+pub fn bin_array_index(bin_id: i32) -> i64 {
+    bin_id.div_euclid(MAX_BIN_PER_ARRAY) as i64
+}
+
+pub fn bin_array_address(lb_pair: &Pubkey, index: i64, program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"bin_array", lb_pair.as_ref(), &index.to_le_bytes()],
+        program_id,
+    )
+    .0
+}
+
+pub fn event_authority_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"__event_authority"], program_id).0
+}
+
+impl LbPair {
+    /// (volatility_reference, index_reference) once the swap has updated
+    /// its references at `current_timestamp`
+    pub fn swap_references(&self, current_timestamp: i64) -> (u32, i32) {
+        let elapsed = current_timestamp - self.last_update_timestamp;
+        if elapsed < self.filter_period as i64 {
+            // high frequency trades keep the old references
+            return (self.volatility_reference, self.index_reference);
+        }
+        let volatility_reference = if elapsed < self.decay_period as i64 {
+            (self.volatility_accumulator as u64 * self.reduction_factor as u64 / BASIS_POINT_MAX)
+                as u32
+        } else {
+            0
+        };
+        (volatility_reference, self.active_id)
+    }
+
+    pub fn volatility_accumulator(&self, references: (u32, i32), active_id: i32) -> u32 {
+        let (volatility_reference, index_reference) = references;
+        let delta_id = (index_reference as i64 - active_id as i64).unsigned_abs();
+        let volatility_accumulator = volatility_reference as u64 + delta_id * BASIS_POINT_MAX;
+        volatility_accumulator.min(self.max_volatility_accumulator as u64) as u32
+    }
+
+    pub fn base_fee_rate(&self) -> u128 {
+        self.base_factor as u128 * self.bin_step as u128 * 10
+    }
+
+    pub fn variable_fee_rate(&self, volatility_accumulator: u32) -> u128 {
+        if self.variable_fee_control == 0 {
+            return 0;
+        }
+        let square_vfa_bin = (volatility_accumulator as u128 * self.bin_step as u128).pow(2);
+        let variable_fee = self.variable_fee_control as u128 * square_vfa_bin;
+        // scale down to FEE_PRECISION (rounded up)
+        saturating_u128(ceil_div(
+            U256::from(variable_fee),
+            U256::from(100_000_000_000_u128),
+        ))
+    }
+
+    /// fee rate (out of `FEE_PRECISION`) charged in a bin
+    pub fn total_fee_rate(&self, volatility_accumulator: u32) -> u128 {
+        (self.base_fee_rate() + self.variable_fee_rate(volatility_accumulator)).min(MAX_FEE_RATE)
+    }
+}
+
+/// (amount_in incl. fees, amount_out, fee) of swapping at most `amount_in` in one bin
+pub fn swap_in_bin(
+    bin: &Bin,
+    amount_in: u128,
+    fee_rate: u128,
+    swap_for_y: bool,
+) -> (u128, u128, u128) {
+    let price = U256::from(bin.price);
+    let max_amount_out = if swap_for_y {
+        bin.amount_y
+    } else {
+        bin.amount_x
+    } as u128;
+    let max_amount_in = if swap_for_y {
+        ceil_div(U256::from(max_amount_out) << 64, price)
+    } else {
+        ceil_div(U256::from(max_amount_out) * price, U256::one() << 64)
+    };
+    // fee on top of the amount which drains the bin
+    let max_fee = ceil_div(
+        max_amount_in * U256::from(fee_rate),
+        U256::from(FEE_PRECISION - fee_rate),
+    );
+    let max_amount_in_with_fees = saturating_u128(max_amount_in + max_fee);
+    if amount_in >= max_amount_in_with_fees {
+        return (max_amount_in_with_fees, max_amount_out, saturating_u128(max_fee));
+    }
+
+    // fee out of the amount in
+    let fee = ceil_div(
+        U256::from(amount_in) * U256::from(fee_rate),
+        U256::from(FEE_PRECISION),
+    );
+    let amount_in_after_fee = U256::from(amount_in) - fee;
+    let amount_out = if swap_for_y {
+        (amount_in_after_fee * price) >> 64
+    } else {
+        (amount_in_after_fee << 64) / price
+    };
+    (
+        amount_in,
+        saturating_u128(amount_out).min(max_amount_out),
+        saturating_u128(fee),
+    )
+}
+
+/// totals of an exact-in swap over the bins
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BinsSwap {
+    pub amount_out: u128,
+    pub fee_amount: u128,
+    pub start_price: u128, // Q64.64 price (y per x) of the first bin swapped in
+    pub last_bin_id: i32,  // the active bin once the swap is done
+    pub bin_amounts: Vec<(i32, u128, u128)>, // (bin id, amount in incl. fees, amount out)
+}
+
+/// exact-in swap walking the loaded bins from the active bin, the swap can
+/// only use the bins in `[bin_range.0, bin_range.1]`. None if they don't hold
+/// enough liquidity (the swap would fail on chain)
+pub fn swap(
+    lb_pair: &LbPair,
+    bins: &BTreeMap<i32, Bin>,
+    bin_range: (i32, i32),
+    amount_in: u128,
+    swap_for_y: bool,
+    current_timestamp: i64,
+) -> Option<BinsSwap> {
+    // swap amounts are u64 on chain
+    if amount_in > u64::MAX as u128 {
+        return None;
+    }
+    let (lower, upper) = bin_range;
+    let active_id = lb_pair.active_id;
+    if active_id < lower || active_id > upper {
+        return None;
+    }
+    let bins_in_order: Vec<(&i32, &Bin)> = if swap_for_y {
+        bins.range(lower..=active_id).rev().collect()
+    } else {
+        bins.range(active_id..=upper).collect()
+    };
+
+    let references = lb_pair.swap_references(current_timestamp);
+    let mut amount_left = amount_in;
+    let mut total = BinsSwap::default();
+    for (bin_id, bin) in bins_in_order {
+        let liquidity = if swap_for_y {
+            bin.amount_y
+        } else {
+            bin.amount_x
+        };
+        if liquidity == 0 || bin.price == 0 {
+            continue;
+        }
+        let volatility_accumulator = lb_pair.volatility_accumulator(references, *bin_id);
+        let fee_rate = lb_pair.total_fee_rate(volatility_accumulator);
+        let (bin_amount_in, bin_amount_out, bin_fee) =
+            swap_in_bin(bin, amount_left, fee_rate, swap_for_y);
+        if total.start_price == 0 {
+            total.start_price = bin.price;
+        }
+        amount_left -= bin_amount_in;
+        total.amount_out += bin_amount_out;
+        total.fee_amount += bin_fee;
+        total.last_bin_id = *bin_id;
+        total.bin_amounts.push((*bin_id, bin_amount_in, bin_amount_out));
+        if amount_left == 0 {
+            return Some(total);
+        }
+    }
+    None
+}
+
+/// amount out of `swap`
+pub fn get_amount_out(
+    lb_pair: &LbPair,
+    bins: &BTreeMap<i32, Bin>,
+    bin_range: (i32, i32),
+    amount_in: u128,
+    swap_for_y: bool,
+    current_timestamp: i64,
+) -> Option<u128> {
+    swap(lb_pair, bins, bin_range, amount_in, swap_for_y, current_timestamp)
+        .map(|total| total.amount_out)
+}
+
+/// moves `swap`'s amounts into the bins + the active bin and volatility
+/// state along like the swap instruction does. The fees stay in the bins
+/// (the protocol's share of them is ignored)
+pub fn apply_swap(
+    lb_pair: &mut LbPair,
+    bins: &mut BTreeMap<i32, Bin>,
+    total: &BinsSwap,
+    swap_for_y: bool,
+    current_timestamp: i64,
+) {
+    let to_u64 = |amount: u128| amount.min(u64::MAX as u128) as u64;
+    for (bin_id, amount_in, amount_out) in &total.bin_amounts {
+        if let Some(bin) = bins.get_mut(bin_id) {
+            let (bin_in, bin_out) = if swap_for_y {
+                (&mut bin.amount_x, &mut bin.amount_y)
+            } else {
+                (&mut bin.amount_y, &mut bin.amount_x)
+            };
+            *bin_in = bin_in.saturating_add(to_u64(*amount_in));
+            *bin_out = bin_out.saturating_sub(to_u64(*amount_out));
+        }
+    }
+
+    let references = lb_pair.swap_references(current_timestamp);
+    if !total.bin_amounts.is_empty() {
+        lb_pair.volatility_accumulator = lb_pair.volatility_accumulator(references, total.last_bin_id);
+        lb_pair.active_id = total.last_bin_id;
+    }
+    lb_pair.volatility_reference = references.0;
+    lb_pair.index_reference = references.1;
+    lb_pair.last_update_timestamp = current_timestamp;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const Q64: u128 = 1 << 64;
+
+    fn pair() -> LbPair {
+        LbPair {
+            base_factor: 10_000,
+            filter_period: 30,
+            decay_period: 600,
+            reduction_factor: 5_000,
+            variable_fee_control: 40_000,
+            max_volatility_accumulator: 350_000,
+            bin_step: 10,
+            ..LbPair::default()
+        }
+    }
+
+    fn bin(amount_x: u64, amount_y: u64) -> Bin {
+        Bin {
+            amount_x,
+            amount_y,
+            price: Q64, // price 1
+        }
+    }
+
+    #[test]
+    fn fees_grow_with_bins_crossed() {
+        let mut lb_pair = pair();
+        // 10_000 * 10 * 10 / 1e9 == 0.1%
+        assert_eq!(lb_pair.base_fee_rate(), 1_000_000);
+        assert_eq!(lb_pair.total_fee_rate(0), 1_000_000);
+
+        let references = lb_pair.swap_references(1_000);
+        assert_eq!(references, (0, 0));
+        assert_eq!(lb_pair.volatility_accumulator(references, -2), 20_000);
+        // (20_000 * 10)^2 * 40_000 / 1e11
+        assert_eq!(lb_pair.variable_fee_rate(20_000), 16_000);
+        assert_eq!(lb_pair.total_fee_rate(20_000), 1_016_000);
+        // capped accumulator, then capped fee
+        assert_eq!(lb_pair.volatility_accumulator(references, -100), 350_000);
+        assert_eq!(lb_pair.total_fee_rate(350_000), 5_900_000);
+        lb_pair.bin_step = 100;
+        assert_eq!(lb_pair.total_fee_rate(350_000), MAX_FEE_RATE);
+
+        // recent swaps decay the accumulator instead of resetting it
+        lb_pair.volatility_accumulator = 30_000;
+        lb_pair.last_update_timestamp = 900;
+        lb_pair.active_id = 5;
+        assert_eq!(lb_pair.swap_references(1_000), (15_000, 5));
+        assert_eq!(lb_pair.swap_references(2_000), (0, 5));
+        lb_pair.last_update_timestamp = 990;
+        assert_eq!(lb_pair.swap_references(1_000), (0, 0));
+    }
+
+    #[test]
+    fn walks_bins_from_the_active_one() {
+        let mut lb_pair = pair();
+        lb_pair.base_factor = 0;
+        lb_pair.variable_fee_control = 0;
+        let bins: BTreeMap<i32, Bin> =
+            [(-1, bin(0, 1_000)), (0, bin(500, 500)), (1, bin(1_000, 0))]
+                .into_iter()
+                .collect();
+
+        // x -> y drains bin 0 then moves left
+        assert_eq!(
+            get_amount_out(&lb_pair, &bins, (-70, 69), 400, true, 0),
+            Some(400)
+        );
+        assert_eq!(
+            get_amount_out(&lb_pair, &bins, (-70, 69), 1_200, true, 0),
+            Some(1_200)
+        );
+        assert_eq!(
+            get_amount_out(&lb_pair, &bins, (-70, 69), 1_600, true, 0),
+            None
+        );
+        // ... unless the bin arrays on the left aren't passed
+        assert_eq!(get_amount_out(&lb_pair, &bins, (0, 69), 600, true, 0), None);
+        // y -> x moves right
+        assert_eq!(
+            get_amount_out(&lb_pair, &bins, (-70, 69), 1_500, false, 0),
+            Some(1_500)
+        );
+    }
+
+    #[test]
+    fn charges_fees_per_bin() {
+        let lb_pair = pair(); // 0.1% base fee
+                              // 1_000 in, 1 fee (rounded up)
+        assert_eq!(
+            swap_in_bin(&bin(0, 10_000), 1_000, 1_000_000, true),
+            (1_000, 999, 1)
+        );
+        // draining the bin: 1_000 + ceil(1_000 * 0.1% / 99.9%)
+        assert_eq!(
+            swap_in_bin(&bin(0, 1_000), 5_000, 1_000_000, true),
+            (1_002, 1_000, 2)
+        );
+
+        // the second bin pays the variable fee for 1 bin crossed
+        let bins: BTreeMap<i32, Bin> = [(-1, bin(0, 1_000_000)), (0, bin(0, 1_000))]
+            .into_iter()
+            .collect();
+        let out = get_amount_out(&lb_pair, &bins, (-70, 69), 101_002, true, 0).unwrap();
+        assert_eq!(lb_pair.total_fee_rate(10_000), 1_004_000);
+        // 100_000 left for bin -1, ceil(100.4) fee
+        assert_eq!(out, 1_000 + 99_899);
+        let total = swap(&lb_pair, &bins, (-70, 69), 101_002, true, 0).unwrap();
+        assert_eq!((total.fee_amount, total.start_price), (2 + 101, Q64));
+        assert_eq!(total.last_bin_id, -1);
+        assert_eq!(total.bin_amounts, vec![(0, 1_002, 1_000), (-1, 100_000, 99_899)]);
+    }
+
+    #[test]
+    fn applies_swaps_to_the_bins() {
+        let lb_pair = pair();
+        let mut bins: BTreeMap<i32, Bin> = [(-1, bin(0, 1_000_000)), (0, bin(0, 1_000))]
+            .into_iter()
+            .collect();
+        let total = swap(&lb_pair, &bins, (-70, 69), 101_002, true, 1_000).unwrap();
+
+        let mut after = lb_pair.clone();
+        apply_swap(&mut after, &mut bins, &total, true, 1_000);
+        assert_eq!(bins[&0], bin(1_002, 0));
+        assert_eq!(bins[&-1], bin(100_000, 1_000_000 - 99_899));
+        assert_eq!((after.active_id, after.last_update_timestamp), (-1, 1_000));
+        assert_eq!(after.volatility_accumulator, 10_000);
+
+        // the next swap starts in bin -1 and pays for the bin already crossed
+        let next = swap(&after, &bins, (-70, 69), 1_000, true, 1_000).unwrap();
+        assert_eq!(next.bin_amounts, vec![(-1, 1_000, 998)]);
+    }
+
+    #[test]
+    fn unpacks_accounts() {
+        let mut data = vec![0; LB_PAIR_LEN];
+        assert!(unpack_lb_pair(&data).is_none());
+
+        let token_x_mint = Pubkey::new_unique();
+        data[..8].copy_from_slice(&LB_PAIR_DISCRIMINATOR);
+        data[8..10].copy_from_slice(&10_000_u16.to_le_bytes());
+        data[40..44].copy_from_slice(&123_u32.to_le_bytes());
+        data[76..80].copy_from_slice(&(-42_i32).to_le_bytes());
+        data[80..82].copy_from_slice(&25_u16.to_le_bytes());
+        data[88..120].copy_from_slice(&token_x_mint.to_bytes());
+
+        let lb_pair = unpack_lb_pair(&data).unwrap();
+        assert_eq!(lb_pair.base_factor, 10_000);
+        assert_eq!(lb_pair.volatility_accumulator, 123);
+        assert_eq!((lb_pair.active_id, lb_pair.bin_step), (-42, 25));
+        assert_eq!(lb_pair.token_x_mint, token_x_mint);
+
+        let mut data = vec![0; BIN_ARRAY_LEN];
+        data[..8].copy_from_slice(&BIN_ARRAY_DISCRIMINATOR);
+        data[8..16].copy_from_slice(&(-1_i64).to_le_bytes());
+        let bin = 56 + 3 * BIN_LEN;
+        data[bin..bin + 8].copy_from_slice(&7_u64.to_le_bytes());
+        data[bin + 16..bin + 32].copy_from_slice(&Q64.to_le_bytes());
+
+        let bin_array = unpack_bin_array(&data).unwrap();
+        assert_eq!(bin_array.index, -1);
+        assert_eq!(bin_array.bins.len(), MAX_BIN_PER_ARRAY as usize);
+        assert_eq!(
+            bin_array.bins[3],
+            Bin {
+                amount_x: 7,
+                amount_y: 0,
+                price: Q64
+            }
+        );
+        assert_eq!(bin_array_index(-1), -1);
+        assert_eq!(bin_array_index(69), 0);
+        assert_eq!(bin_array_index(-71), -2);
+    }
+}
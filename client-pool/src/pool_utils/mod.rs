@@ -2,13 +2,21 @@
 //! taken from solana's token-swap program 
 pub mod base;
 pub mod calculator;
+pub mod common;
 pub mod constant_price;
 pub mod constant_product;
+pub mod exact_out;
 pub mod fees;
 pub mod offset;
 pub mod stable;
 
 // pool specific details 
+pub mod jupiter;
+pub mod lifinity;
+pub mod meteora;
 pub mod orca;
+pub mod phoenix;
 pub mod raydium;
-pub mod serum;
\ No newline at end of file
+pub mod serum;
+pub mod token_2022;
+pub mod whirlpool;
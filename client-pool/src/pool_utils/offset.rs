@@ -9,7 +9,7 @@ use {
             },
             constant_product::{
                 deposit_single_token_type, normalized_value, pool_tokens_to_trading_tokens, swap,
-                withdraw_single_token_type_exact_out,
+                swap_exact_out, withdraw_single_token_type_exact_out,
             },
         },
         error::SwapError,
@@ -56,6 +56,26 @@ impl CurveCalculator for OffsetCurve {
         swap(source_amount, swap_source_amount, swap_destination_amount)
     }
 
+    /// The constant product inverse on the same offset amounts
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<u128> {
+        let token_b_offset = self.token_b_offset as u128;
+        let swap_source_amount = match trade_direction {
+            TradeDirection::AtoB => swap_source_amount,
+            TradeDirection::BtoA => swap_source_amount.checked_add(token_b_offset)?,
+        };
+        let swap_destination_amount = match trade_direction {
+            TradeDirection::AtoB => swap_destination_amount.checked_add(token_b_offset)?,
+            TradeDirection::BtoA => swap_destination_amount,
+        };
+        swap_exact_out(destination_amount, swap_source_amount, swap_destination_amount)
+    }
+
     /// The conversion for the offset curve needs to take into account the
     /// offset
     fn pool_tokens_to_trading_tokens(
@@ -190,7 +210,7 @@ mod tests {
         test::{
             check_curve_value_from_swap, check_deposit_token_conversion,
             check_pool_value_from_deposit, check_pool_value_from_withdraw,
-            check_withdraw_token_conversion, total_and_intermediate,
+            check_swap_exact_out, check_withdraw_token_conversion, total_and_intermediate,
             CONVERSION_BASIS_POINTS_GUARANTEE,
         },
         INITIAL_SWAP_POOL_AMOUNT,
@@ -578,4 +598,25 @@ mod tests {
             );
         }
     }
+
+    proptest! {
+        #[test]
+        fn swap_exact_out(
+            destination_amount in 1..u32::MAX as u128,
+            swap_source_amount in 1..u32::MAX as u128,
+            swap_destination_amount in 1..u32::MAX as u128,
+            token_b_offset in 1..u32::MAX as u64,
+        ) {
+            let curve = OffsetCurve { token_b_offset };
+            for trade_direction in [TradeDirection::AtoB, TradeDirection::BtoA] {
+                check_swap_exact_out(
+                    &curve,
+                    destination_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_direction,
+                );
+            }
+        }
+    }
 }
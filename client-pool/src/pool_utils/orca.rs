@@ -1,69 +1,95 @@
-use anyhow::Result;
 use crate::{
-    pool_utils,
+    error::QuoteError,
+    pool::Quote,
     pool_utils::base::{SwapCurve, CurveType},
+    pool_utils::calculator::{CurveCalculator, TradeDirection},
     pool_utils::fees::Fees,
     pool_utils::{constant_product::ConstantProductCurve, stable::StableCurve},
 };
-use core::panic;
 use std::sync::Arc;
 
-pub fn get_pool_quote_with_amounts(
-    amount_in: u128, 
-    curve_type: CurveType, 
-    amp: u64,
-    fees: &Fees,
-    input_token_pool_amount: u128,
-    output_token_pool_amount: u128,
-    slippage_percent: Option<[u128;2]>,
-) -> Result<u128> {
-    let mut quote;
-    let trade_direction = pool_utils::calculator::TradeDirection::AtoB;
-    
-    if curve_type == CurveType::ConstantProduct { // constant product (1 for orca)
-        let swap_curve = SwapCurve {
+fn swap_curve(curve_type: CurveType, amp: u64) -> Result<SwapCurve, QuoteError> {
+    match curve_type {
+        CurveType::ConstantProduct => Ok(SwapCurve {
             curve_type: CurveType::ConstantProduct,
             calculator: Arc::new(ConstantProductCurve {}),
-        };
-        let swap_quote = swap_curve.swap(
-            amount_in, 
-            input_token_pool_amount, 
-            output_token_pool_amount, 
-            trade_direction, 
-            fees
-        );
-        quote = match swap_quote {
-            Some(v) => { v.destination_amount_swapped },
-            None => {
-                // println!("swap err: {} {} {}", amount_in, 
-                //     input_token_pool_amount, 
-                //     output_token_pool_amount);
-                0
-            }
-        }
-
-    } else if curve_type == CurveType::Stable { // stableswap (2 for orca)
-        let swap_curve = SwapCurve {
+        }),
+        CurveType::Stable => Ok(SwapCurve {
             curve_type: CurveType::Stable,
             calculator: Arc::new(StableCurve { amp }),
-        };
-        quote = swap_curve.swap(
-            amount_in, 
-            input_token_pool_amount, 
-            output_token_pool_amount, 
-            trade_direction, 
-            fees
-        ).unwrap().destination_amount_swapped;
-
-    } else {
-        panic!("invalid curve type for swap: {:?}", curve_type);
+        }),
+        _ => Err(QuoteError::Untradable(format!(
+            "invalid curve type for swap: {:?}",
+            curve_type
+        ))),
     }
-            
-    // add slippage amount if its given 
-    if let Some([num, denom]) = slippage_percent {
-        quote = quote * (denom - num) / denom
+}
+
+/// amount out per amount in of a small swap (fees excluded)
+fn spot_rate(
+    swap_curve: &SwapCurve,
+    input_token_pool_amount: u128,
+    output_token_pool_amount: u128,
+) -> f64 {
+    if swap_curve.curve_type == CurveType::ConstantProduct {
+        return output_token_pool_amount as f64 / input_token_pool_amount as f64;
     }
+    // no closed form for the stable curve -- probe with 1bp of the reserve in
+    let probe = (input_token_pool_amount / 10_000).max(1);
+    swap_curve
+        .calculator
+        .swap_without_fees(
+            probe,
+            input_token_pool_amount,
+            output_token_pool_amount,
+            TradeDirection::AtoB,
+        )
+        .map_or(0., |r| {
+            r.destination_amount_swapped as f64 / r.source_amount_swapped as f64
+        })
+}
 
+pub fn get_pool_quote(
+    amount_in: u128,
+    curve_type: CurveType,
+    amp: u64,
+    fees: &Fees,
+    input_token_pool_amount: u128,
+    output_token_pool_amount: u128,
+) -> Result<Quote, QuoteError> {
+    if input_token_pool_amount == 0 || output_token_pool_amount == 0 {
+        return Err(QuoteError::InsufficientLiquidity);
+    }
+    let swap_curve = swap_curve(curve_type, amp)?;
+    let result = swap_curve
+        .swap(
+            amount_in,
+            input_token_pool_amount,
+            output_token_pool_amount,
+            TradeDirection::AtoB,
+            fees,
+        )
+        .ok_or(QuoteError::CalculationFailure)?;
+    let spot_rate = spot_rate(&swap_curve, input_token_pool_amount, output_token_pool_amount);
+    Ok(Quote::from_swap_result(&result, spot_rate))
+}
 
-    Ok(quote)
+/// smallest amount in which `get_pool_quote` turns into at least
+/// `amount_out`, None if the pool can't provide it
+pub fn get_pool_quote_exact_out(
+    amount_out: u128,
+    curve_type: CurveType,
+    amp: u64,
+    fees: &Fees,
+    input_token_pool_amount: u128,
+    output_token_pool_amount: u128,
+) -> Option<u128> {
+    let swap_curve = swap_curve(curve_type, amp).ok()?;
+    swap_curve.swap_exact_out(
+        amount_out,
+        input_token_pool_amount,
+        output_token_pool_amount,
+        TradeDirection::AtoB,
+        fees,
+    )
 }
@@ -0,0 +1,472 @@
+//! Phoenix market state + IOC taker fill simulation (mirrors phoenix-v1's
+//! `MarketHeader`, `FIFOMarket` and its `match_order`)
+//!
+//! A market account is the `MarketHeader` followed by the `FIFOMarket`,
+//! whose bids and asks are sokoban red-black trees of resting orders keyed
+//! by (price in ticks, order sequence number). Sizes are in base lots,
+//! prices in ticks; a fill of `b` base lots at `p` ticks is worth
+//! `b * p * tick_size_in_quote_lots_per_base_unit / base_lots_per_base_unit`
+//! quote lots. Takers pay `taker_fee_bps` on the quote lots matched.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use std::cmp::Reverse;
+use crate::pool_utils::common::{read_pubkey, read_u32, read_u64};
+
+pub const MARKET_HEADER_LEN: usize = 576;
+// 256 bytes of padding + 6 u64s
+const FIFO_MARKET_FIXED_LEN: usize = 304;
+// root + padding, then the node allocator's size, bump index + free list head
+const TREE_HEADER_LEN: usize = 32;
+// registers (left, right, parent, color) + `FIFOOrderId` + `FIFORestingOrder`
+const ORDER_NODE_LEN: usize = 64;
+
+pub const MARKET_STATUS_ACTIVE: u64 = 1;
+pub const BPS_MAX: u128 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Bid, // quote -> base
+    Ask, // base -> quote
+}
+
+/// The `MarketHeader` fields we quote + swap with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MarketHeader {
+    pub status: u64,
+    pub bids_size: u64,
+    pub asks_size: u64,
+    pub num_seats: u64,
+    pub base_decimals: u32,
+    pub base_mint: Pubkey,
+    pub base_vault: Pubkey,
+    pub base_lot_size: u64, // base atoms per base lot
+    pub quote_decimals: u32,
+    pub quote_mint: Pubkey,
+    pub quote_vault: Pubkey,
+    pub quote_lot_size: u64, // quote atoms per quote lot
+    pub tick_size_in_quote_atoms_per_base_unit: u64,
+    pub raw_base_units_per_base_unit: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestingOrder {
+    pub price_in_ticks: u64,
+    pub num_base_lots: u64,
+    // 0 == no expiry
+    pub last_valid_unix_timestamp_in_seconds: u64,
+}
+
+/// An IOC taker fill, amounts in atoms
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fill {
+    pub amount_out: u128,
+    pub fee: u128, // in the input token
+    // what was matched against the book before fees
+    pub amount_in_matched: u128,
+    pub amount_out_matched: u128,
+}
+
+/// A decoded market, `bids` best (highest) first and `asks` best (lowest) first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhoenixMarket {
+    pub header: MarketHeader,
+    pub base_lots_per_base_unit: u64,
+    pub tick_size_in_quote_lots_per_base_unit: u64,
+    pub taker_fee_bps: u64,
+    pub bids: Vec<RestingOrder>,
+    pub asks: Vec<RestingOrder>,
+}
+
+fn unpack_header(data: &[u8]) -> MarketHeader {
+    MarketHeader {
+        status: read_u64(data, 8),
+        bids_size: read_u64(data, 16),
+        asks_size: read_u64(data, 24),
+        num_seats: read_u64(data, 32),
+        base_decimals: read_u32(data, 40),
+        base_mint: read_pubkey(data, 48),
+        base_vault: read_pubkey(data, 80),
+        base_lot_size: read_u64(data, 112),
+        quote_decimals: read_u32(data, 120),
+        quote_mint: read_pubkey(data, 128),
+        quote_vault: read_pubkey(data, 160),
+        quote_lot_size: read_u64(data, 192),
+        tick_size_in_quote_atoms_per_base_unit: read_u64(data, 200),
+        // authority @ 208, fee recipient @ 240, sequence number @ 272, successor @ 280
+        raw_base_units_per_base_unit: read_u32(data, 312),
+    }
+}
+
+fn tree_len(max_size: u64) -> usize {
+    TREE_HEADER_LEN + max_size as usize * ORDER_NODE_LEN
+}
+
+/// the orders in the order tree at `tree` (nodes are 1-indexed, 0 == nil)
+fn unpack_orders(tree: &[u8], max_size: u64) -> Vec<RestingOrder> {
+    let mut orders = vec![];
+    let mut stack = vec![read_u32(tree, 0)];
+    while let Some(index) = stack.pop() {
+        // a corrupt tree can't send us out of bounds or around in circles
+        if index == 0 || index as u64 > max_size || orders.len() as u64 >= max_size {
+            continue;
+        }
+        let node = TREE_HEADER_LEN + (index as usize - 1) * ORDER_NODE_LEN;
+        stack.push(read_u32(tree, node)); // left
+        stack.push(read_u32(tree, node + 4)); // right
+        orders.push(RestingOrder {
+            price_in_ticks: read_u64(tree, node + 16),
+            // order sequence number @ 24, trader index @ 32
+            num_base_lots: read_u64(tree, node + 40),
+            // last valid slot @ 48
+            last_valid_unix_timestamp_in_seconds: read_u64(tree, node + 56),
+        });
+    }
+    orders
+}
+
+/// None if `data` is too short to be a market with its header's sizes
+pub fn unpack_market(data: &[u8]) -> Option<PhoenixMarket> {
+    if data.len() < MARKET_HEADER_LEN + FIFO_MARKET_FIXED_LEN {
+        return None;
+    }
+    let header = unpack_header(data);
+    let market = MARKET_HEADER_LEN;
+    let bids_offset = market + FIFO_MARKET_FIXED_LEN;
+    let asks_offset = bids_offset + tree_len(header.bids_size);
+    // the trader seats tree follows the asks
+    if header.bids_size == 0
+        || header.asks_size == 0
+        || data.len() < asks_offset + tree_len(header.asks_size)
+    {
+        return None;
+    }
+
+    let mut bids = unpack_orders(&data[bids_offset..], header.bids_size);
+    let mut asks = unpack_orders(&data[asks_offset..], header.asks_size);
+    // stable sorts keep the tree's time priority within a price level
+    bids.sort_by_key(|order| Reverse(order.price_in_ticks));
+    asks.sort_by_key(|order| order.price_in_ticks);
+
+    Some(PhoenixMarket {
+        base_lots_per_base_unit: read_u64(data, market + 256),
+        tick_size_in_quote_lots_per_base_unit: read_u64(data, market + 264),
+        // order sequence number @ 272
+        taker_fee_bps: read_u64(data, market + 280),
+        header,
+        bids,
+        asks,
+    })
+}
+
+pub fn log_authority_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"log"], program_id).0
+}
+
+fn is_expired(order: &RestingOrder, current_timestamp: i64) -> bool {
+    order.last_valid_unix_timestamp_in_seconds != 0
+        && (order.last_valid_unix_timestamp_in_seconds as i64) < current_timestamp
+}
+
+impl PhoenixMarket {
+    /// quote lots `num_base_lots` are worth at `price_in_ticks` (rounded down)
+    fn quote_lots(&self, num_base_lots: u64, price_in_ticks: u64) -> u128 {
+        num_base_lots as u128 * price_in_ticks as u128
+            * self.tick_size_in_quote_lots_per_base_unit as u128
+            / self.base_lots_per_base_unit as u128
+    }
+
+    /// the taker fee on `quote_lots`, rounded up like phoenix does
+    pub fn taker_fee(&self, quote_lots: u128) -> u128 {
+        let exact_fee = quote_lots * self.taker_fee_bps as u128;
+        match exact_fee % BPS_MAX {
+            0 => exact_fee / BPS_MAX,
+            _ => exact_fee / BPS_MAX + 1,
+        }
+    }
+
+    /// resting orders a taker on `side` matches against, best first
+    pub fn book(&self, side: Side, current_timestamp: i64) -> impl Iterator<Item = &RestingOrder> {
+        let orders = match side {
+            Side::Bid => &self.asks,
+            Side::Ask => &self.bids,
+        };
+        orders
+            .iter()
+            .filter(move |order| order.num_base_lots > 0 && !is_expired(order, current_timestamp))
+    }
+
+    // bid: quote -> base
+    fn bid_fill(&self, quote_amount_in: u64, current_timestamp: i64) -> Fill {
+        // the budget has to cover the matched quote lots plus their fee
+        let quote_lots = (quote_amount_in / self.header.quote_lot_size) as u128;
+        let mut quote_lots_remaining = quote_lots * BPS_MAX / (BPS_MAX + self.taker_fee_bps as u128);
+        let mut quote_lots_matched = 0_u128;
+        let mut base_lots_filled = 0_u128;
+
+        for order in self.book(Side::Bid, current_timestamp) {
+            let quote_lots_per_base_unit =
+                order.price_in_ticks as u128 * self.tick_size_in_quote_lots_per_base_unit as u128;
+            if quote_lots_per_base_unit == 0 {
+                continue;
+            }
+            let affordable = quote_lots_remaining * self.base_lots_per_base_unit as u128
+                / quote_lots_per_base_unit;
+            let trade_base_lots = affordable.min(order.num_base_lots as u128);
+            if trade_base_lots == 0 {
+                break;
+            }
+            let trade_quote_lots = self.quote_lots(trade_base_lots as u64, order.price_in_ticks);
+            quote_lots_remaining -= trade_quote_lots;
+            quote_lots_matched += trade_quote_lots;
+            base_lots_filled += trade_base_lots;
+        }
+        let quote_lot_size = self.header.quote_lot_size as u128;
+        let amount_out = base_lots_filled * self.header.base_lot_size as u128;
+        Fill {
+            amount_out,
+            fee: self.taker_fee(quote_lots_matched) * quote_lot_size,
+            amount_in_matched: quote_lots_matched * quote_lot_size,
+            amount_out_matched: amount_out,
+        }
+    }
+
+    // ask: base -> quote
+    fn ask_fill(&self, base_amount_in: u64, current_timestamp: i64) -> Fill {
+        let base_lots_in = base_amount_in / self.header.base_lot_size;
+        let mut base_lots_remaining = base_lots_in;
+        let mut quote_lots_matched = 0_u128;
+
+        for order in self.book(Side::Ask, current_timestamp) {
+            if base_lots_remaining == 0 {
+                break;
+            }
+            let trade_base_lots = base_lots_remaining.min(order.num_base_lots);
+            base_lots_remaining -= trade_base_lots;
+            quote_lots_matched += self.quote_lots(trade_base_lots, order.price_in_ticks);
+        }
+        // fees applied after
+        let fee_quote_lots = self.taker_fee(quote_lots_matched);
+        let quote_lots_out = quote_lots_matched - fee_quote_lots;
+        let quote_lot_size = self.header.quote_lot_size as u128;
+        let amount_in_matched =
+            (base_lots_in - base_lots_remaining) as u128 * self.header.base_lot_size as u128;
+        // the fee is taken in quote, priced in base at the fill's average price
+        let fee = match quote_lots_matched {
+            0 => 0,
+            _ => fee_quote_lots * amount_in_matched / quote_lots_matched,
+        };
+        Fill {
+            amount_out: quote_lots_out * quote_lot_size,
+            fee,
+            amount_in_matched,
+            amount_out_matched: quote_lots_matched * quote_lot_size,
+        }
+    }
+
+    fn has_lot_sizes(&self) -> bool {
+        self.header.base_lot_size != 0
+            && self.header.quote_lot_size != 0
+            && self.base_lots_per_base_unit != 0
+    }
+
+    /// immediate-or-cancel swap of `amount_in`, whatever the book can't fill
+    /// stays with the taker. None if the market has no lot sizes
+    pub fn fill(&self, amount_in: u64, side: Side, current_timestamp: i64) -> Option<Fill> {
+        if !self.has_lot_sizes() {
+            return None;
+        }
+        Some(match side {
+            Side::Bid => self.bid_fill(amount_in, current_timestamp),
+            Side::Ask => self.ask_fill(amount_in, current_timestamp),
+        })
+    }
+
+    /// amount out of `fill`, 0 if the market has no lot sizes
+    pub fn get_amount_out(&self, amount_in: u64, side: Side, current_timestamp: i64) -> u128 {
+        self.fill(amount_in, side, current_timestamp)
+            .map_or(0, |fill| fill.amount_out)
+    }
+
+    /// takes `fill`'s base lots off the resting orders it matched, leaving
+    /// the book as the swap would
+    pub fn apply_fill(&mut self, fill: &Fill, side: Side, current_timestamp: i64) {
+        if !self.has_lot_sizes() {
+            return;
+        }
+        let base_amount = match side {
+            Side::Bid => fill.amount_out_matched,
+            Side::Ask => fill.amount_in_matched,
+        };
+        let mut base_lots_remaining = (base_amount / self.header.base_lot_size as u128) as u64;
+        let orders = match side {
+            Side::Bid => &mut self.asks,
+            Side::Ask => &mut self.bids,
+        };
+        for order in orders.iter_mut() {
+            if base_lots_remaining == 0 {
+                break;
+            }
+            if order.num_base_lots == 0 || is_expired(order, current_timestamp) {
+                continue;
+            }
+            let trade_base_lots = base_lots_remaining.min(order.num_base_lots);
+            order.num_base_lots -= trade_base_lots;
+            base_lots_remaining -= trade_base_lots;
+        }
+    }
+
+    /// atoms out per atom in at the top of the book (fees excluded), None if
+    /// the side of the book a taker would match is empty
+    pub fn spot_rate(&self, side: Side, current_timestamp: i64) -> Option<f64> {
+        if !self.has_lot_sizes() {
+            return None;
+        }
+        let best = self.book(side, current_timestamp).next()?;
+        // quote atoms per base atom
+        let price = (best.price_in_ticks as u128
+            * self.tick_size_in_quote_lots_per_base_unit as u128
+            * self.header.quote_lot_size as u128) as f64
+            / (self.base_lots_per_base_unit as u128 * self.header.base_lot_size as u128) as f64;
+        Some(match side {
+            Side::Bid => 1. / price,
+            Side::Ask => price,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(price_in_ticks: u64, num_base_lots: u64) -> RestingOrder {
+        RestingOrder {
+            price_in_ticks,
+            num_base_lots,
+            last_valid_unix_timestamp_in_seconds: 0,
+        }
+    }
+
+    fn market() -> PhoenixMarket {
+        PhoenixMarket {
+            header: MarketHeader {
+                status: MARKET_STATUS_ACTIVE,
+                base_lot_size: 1_000,
+                quote_lot_size: 1,
+                ..MarketHeader::default()
+            },
+            // 1 tick == 1 quote lot per base unit of 1_000 lots
+            base_lots_per_base_unit: 1_000,
+            tick_size_in_quote_lots_per_base_unit: 1,
+            taker_fee_bps: 10,
+            bids: vec![order(2_000_000, 100), order(1_900_000, 1_000)],
+            asks: vec![order(2_100_000, 100), order(2_200_000, 1_000)],
+        }
+    }
+
+    fn write_order(data: &mut [u8], tree: usize, index: u32, left: u32, right: u32, o: &RestingOrder) {
+        let node = tree + TREE_HEADER_LEN + (index as usize - 1) * ORDER_NODE_LEN;
+        data[node..node + 4].copy_from_slice(&left.to_le_bytes());
+        data[node + 4..node + 8].copy_from_slice(&right.to_le_bytes());
+        data[node + 16..node + 24].copy_from_slice(&o.price_in_ticks.to_le_bytes());
+        data[node + 40..node + 48].copy_from_slice(&o.num_base_lots.to_le_bytes());
+    }
+
+    #[test]
+    fn unpacks_header_and_ladder() {
+        let (bids_size, asks_size) = (4_u64, 4_u64);
+        let bids_offset = MARKET_HEADER_LEN + FIFO_MARKET_FIXED_LEN;
+        let asks_offset = bids_offset + tree_len(bids_size);
+        let mut data = vec![0_u8; asks_offset + tree_len(asks_size)];
+        data[8..16].copy_from_slice(&MARKET_STATUS_ACTIVE.to_le_bytes());
+        data[16..24].copy_from_slice(&bids_size.to_le_bytes());
+        data[24..32].copy_from_slice(&asks_size.to_le_bytes());
+        data[112..120].copy_from_slice(&1_000_u64.to_le_bytes());
+        data[192..200].copy_from_slice(&1_u64.to_le_bytes());
+        data[MARKET_HEADER_LEN + 256..MARKET_HEADER_LEN + 264].copy_from_slice(&1_000_u64.to_le_bytes());
+        data[MARKET_HEADER_LEN + 264..MARKET_HEADER_LEN + 272].copy_from_slice(&1_u64.to_le_bytes());
+        data[MARKET_HEADER_LEN + 280..MARKET_HEADER_LEN + 288].copy_from_slice(&10_u64.to_le_bytes());
+
+        // bids: root 2 with children 1 and 3, node 4 is free
+        data[bids_offset..bids_offset + 4].copy_from_slice(&2_u32.to_le_bytes());
+        write_order(&mut data, bids_offset, 1, 0, 0, &order(1_900_000, 1_000));
+        write_order(&mut data, bids_offset, 2, 1, 3, &order(1_950_000, 5));
+        write_order(&mut data, bids_offset, 3, 0, 0, &order(2_000_000, 100));
+        write_order(&mut data, bids_offset, 4, 0, 0, &order(9_999_999, 1));
+        // asks: a single order at the root
+        data[asks_offset..asks_offset + 4].copy_from_slice(&1_u32.to_le_bytes());
+        write_order(&mut data, asks_offset, 1, 0, 0, &order(2_100_000, 100));
+
+        let market = unpack_market(&data).unwrap();
+        assert_eq!(market.header.status, MARKET_STATUS_ACTIVE);
+        assert_eq!(market.header.base_lot_size, 1_000);
+        assert_eq!(market.base_lots_per_base_unit, 1_000);
+        assert_eq!(market.taker_fee_bps, 10);
+        assert_eq!(
+            market.bids,
+            vec![order(2_000_000, 100), order(1_950_000, 5), order(1_900_000, 1_000)]
+        );
+        assert_eq!(market.asks, vec![order(2_100_000, 100)]);
+
+        assert!(unpack_market(&data[..asks_offset]).is_none());
+    }
+
+    #[test]
+    fn ask_fill_walks_bids_then_takes_fee() {
+        let market = market();
+        // 100 lots @ 2_000 + 50 lots @ 1_900 quote lots per lot
+        // == 200_000 + 95_000, less a 10 bps fee (295 rounded up)
+        assert_eq!(market.get_amount_out(150_000, Side::Ask, 0), 294_705);
+        // a partial lot is left with the taker
+        assert_eq!(market.get_amount_out(150_999, Side::Ask, 0), 294_705);
+        // the book can only fill 1_100 lots
+        assert_eq!(
+            market.get_amount_out(10_000_000, Side::Ask, 0),
+            market.get_amount_out(1_100_000, Side::Ask, 0)
+        );
+
+        // the 295 quote lot fee is worth 150 base lots * 295 / 295_000 in base
+        let fill = market.fill(150_999, Side::Ask, 0).unwrap();
+        assert_eq!((fill.amount_in_matched, fill.amount_out_matched), (150_000, 295_000));
+        assert_eq!(fill.fee, 150);
+        // 2_000 quote atoms per base lot of 1_000 atoms
+        assert_eq!(market.spot_rate(Side::Ask, 0), Some(2.));
+    }
+
+    #[test]
+    fn bid_fill_reserves_the_fee_from_the_budget() {
+        let market = market();
+        // 210_210 * 10_000 / 10_010 == 210_000 quote lots buys exactly the best ask
+        assert_eq!(market.get_amount_out(210_210, Side::Bid, 0), 100_000);
+        // then 2_200 quote lots per lot at the next level
+        assert_eq!(market.get_amount_out(232_232, Side::Bid, 0), 110_000);
+        assert_eq!(market.get_amount_out(100, Side::Bid, 0), 0);
+
+        let fill = market.fill(210_210, Side::Bid, 0).unwrap();
+        assert_eq!((fill.amount_in_matched, fill.fee), (210_000, 210));
+        assert_eq!(market.spot_rate(Side::Bid, 0), Some(1. / 2.1));
+    }
+
+    #[test]
+    fn applied_fills_consume_the_book() {
+        let mut market = market();
+        let fill = market.fill(150_000, Side::Ask, 0).unwrap();
+        market.apply_fill(&fill, Side::Ask, 0);
+        assert_eq!(market.bids, vec![order(2_000_000, 0), order(1_900_000, 950)]);
+        // the best bid is gone
+        assert_eq!(market.spot_rate(Side::Ask, 0), Some(1.9));
+
+        let fill = market.fill(210_210, Side::Bid, 0).unwrap();
+        market.apply_fill(&fill, Side::Bid, 0);
+        assert_eq!(market.asks, vec![order(2_100_000, 0), order(2_200_000, 1_000)]);
+        assert_eq!(market.get_amount_out(220_220, Side::Bid, 0), 100_000);
+    }
+
+    #[test]
+    fn expired_orders_are_skipped() {
+        let mut market = market();
+        market.asks[0].last_valid_unix_timestamp_in_seconds = 100;
+        assert_eq!(market.get_amount_out(210_210, Side::Bid, 50), 100_000);
+        // only the 2_200 level is left
+        assert_eq!(market.get_amount_out(220_220, Side::Bid, 101), 100_000);
+    }
+}
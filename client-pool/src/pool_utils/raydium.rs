@@ -1,69 +1,209 @@
-use anyhow::Result;
-use crate::{
-    pool_utils,
-    pool_utils::base::{SwapCurve, CurveType},
-    pool_utils::fees::Fees,
-    pool_utils::{constant_product::ConstantProductCurve, stable::StableCurve},
-};
-use core::panic;
-use std::sync::Arc;
-
-pub fn get_pool_quote_with_amounts(
-    amount_in: u128, 
-    curve_type: CurveType, 
-    amp: u64,
-    fees: &Fees,
-    input_token_pool_amount: u128,
-    output_token_pool_amount: u128,
-    slippage_percent: Option<[u128;2]>,
-) -> Result<u128> {
-    let mut quote;
-    let trade_direction = pool_utils::calculator::TradeDirection::AtoB;
-    
-    if curve_type == CurveType::ConstantProduct { // constant product (1 for orca)
-        let swap_curve = SwapCurve {
-            curve_type: CurveType::ConstantProduct,
-            calculator: Arc::new(ConstantProductCurve {}),
-        };
-        let swap_quote = swap_curve.swap(
-            amount_in, 
-            input_token_pool_amount, 
-            output_token_pool_amount, 
-            trade_direction, 
-            fees
+//! Raydium AMM v4 state + swap math (mirrors raydium-amm's `processor.rs`)
+//!
+//! Swaps are priced on the PnL-adjusted reserves:
+//! `vault + open orders total - need_take_pnl` on each side, with the swap
+//! fee (rounded up) taken from the input first.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+
+use crate::pool_utils::base::SwapResult;
+use crate::pool_utils::common::{ceil_div, read_pubkey, read_u64};
+
+pub const AMM_INFO_LEN: usize = 752;
+pub const OPEN_ORDERS_LEN: usize = 3228;
+
+/// The `AmmInfo` fields we quote + swap with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AmmInfo {
+    pub status: u64,
+    pub nonce: u64,
+    pub coin_decimals: u64,
+    pub pc_decimals: u64,
+    pub swap_fee_numerator: u64,
+    pub swap_fee_denominator: u64,
+    pub need_take_pnl_coin: u64,
+    pub need_take_pnl_pc: u64,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub coin_mint: Pubkey,
+    pub pc_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub open_orders: Pubkey,
+    pub market: Pubkey,
+    pub market_program: Pubkey,
+    pub target_orders: Pubkey,
+}
+
+/// None if `data` isn't an initialized `AmmInfo`
+pub fn unpack_amm_info(data: &[u8]) -> Option<AmmInfo> {
+    if data.len() != AMM_INFO_LEN {
+        return None;
+    }
+    let status = read_u64(data, 0);
+    if status == 0 {
+        return None;
+    }
+    Some(AmmInfo {
+        status,
+        nonce: read_u64(data, 8),
+        coin_decimals: read_u64(data, 32),
+        pc_decimals: read_u64(data, 40),
+        // fees @ 128: min_separate, trade (orderbook), pnl, swap
+        swap_fee_numerator: read_u64(data, 176),
+        swap_fee_denominator: read_u64(data, 184),
+        // state_data @ 192
+        need_take_pnl_coin: read_u64(data, 192),
+        need_take_pnl_pc: read_u64(data, 200),
+        coin_vault: read_pubkey(data, 336),
+        pc_vault: read_pubkey(data, 368),
+        coin_mint: read_pubkey(data, 400),
+        pc_mint: read_pubkey(data, 432),
+        lp_mint: read_pubkey(data, 464),
+        open_orders: read_pubkey(data, 496),
+        market: read_pubkey(data, 528),
+        market_program: read_pubkey(data, 560),
+        target_orders: read_pubkey(data, 592),
+    })
+}
+
+/// (native_coin_total, native_pc_total) of the AMM's serum open orders
+pub fn unpack_open_orders_totals(data: &[u8]) -> Option<(u64, u64)> {
+    if data.len() != OPEN_ORDERS_LEN || &data[..5] != b"serum" {
+        return None;
+    }
+    Some((read_u64(data, 85), read_u64(data, 101)))
+}
+
+/// reserve the AMM prices with: vault + open orders - pnl owed to the protocol
+pub fn total_without_take_pnl(
+    vault_amount: u64,
+    open_orders_total: u64,
+    need_take_pnl: u64,
+) -> u128 {
+    (vault_amount as u128 + open_orders_total as u128).saturating_sub(need_take_pnl as u128)
+}
+
+/// base-in swap: fee off the input (rounded up) then x * y = k, None if the
+/// pool is empty
+pub fn swap(
+    amount_in: u128,
+    reserve_in: u128,
+    reserve_out: u128,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Option<SwapResult> {
+    if fee_denominator == 0 || reserve_in == 0 || reserve_out == 0 {
+        return None;
+    }
+    let swap_fee =
+        ceil_div(amount_in * fee_numerator as u128, fee_denominator as u128).min(amount_in);
+    let amount_in_less_fee = amount_in - swap_fee;
+    let amount_out = reserve_out * amount_in_less_fee / (reserve_in + amount_in_less_fee);
+    Some(SwapResult {
+        new_swap_source_amount: reserve_in + amount_in,
+        new_swap_destination_amount: reserve_out - amount_out,
+        source_amount_swapped: amount_in,
+        destination_amount_swapped: amount_out,
+        trade_fee: swap_fee,
+        owner_fee: 0,
+    })
+}
+
+/// amount out of `swap`, 0 if the pool is empty
+pub fn get_amount_out(
+    amount_in: u128,
+    reserve_in: u128,
+    reserve_out: u128,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> u128 {
+    swap(amount_in, reserve_in, reserve_out, fee_numerator, fee_denominator)
+        .map_or(0, |result| result.destination_amount_swapped)
+}
+
+/// `get_amount_out` backwards: the smallest amount in quoting at least
+/// `amount_out`, None if the reserves can't provide it
+pub fn get_amount_in(
+    amount_out: u128,
+    reserve_in: u128,
+    reserve_out: u128,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Option<u128> {
+    if amount_out == 0 {
+        return Some(0);
+    }
+    if fee_numerator >= fee_denominator || reserve_in == 0 || amount_out >= reserve_out {
+        return None;
+    }
+    // x * y = k: reserve_out * a / (reserve_in + a) >= amount_out
+    let amount_in_less_fee = ceil_div(amount_out * reserve_in, reserve_out - amount_out);
+    // the fee is rounded up, so what's left is floor(amount_in * (d - n) / d)
+    let fee_denominator = fee_denominator as u128;
+    let fee_numerator = fee_numerator as u128;
+    Some(ceil_div(
+        amount_in_less_fee * fee_denominator,
+        fee_denominator - fee_numerator,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fee_rounds_up_and_pnl_is_removed() {
+        // 25 / 10_000 of 1_000 == 2.5 -> 3
+        assert_eq!(get_amount_out(1_000, 1_000_000, 1_000_000, 25, 10_000), 996);
+        assert_eq!(get_amount_out(1_000, 1_000_000, 1_000_000, 0, 10_000), 999);
+        assert_eq!(get_amount_out(1_000, 0, 1_000_000, 25, 10_000), 0);
+
+        let result = swap(1_000, 1_000_000, 1_000_000, 25, 10_000).unwrap();
+        assert_eq!((result.trade_fee, result.destination_amount_swapped), (3, 996));
+        assert_eq!(
+            (result.new_swap_source_amount, result.new_swap_destination_amount),
+            (1_001_000, 999_004)
         );
-        quote = match swap_quote {
-            Some(v) => { v.destination_amount_swapped },
-            None => {
-                // println!("swap err: {} {} {}", amount_in, 
-                //     input_token_pool_amount, 
-                //     output_token_pool_amount);
-                0
-            }
-        }
+        assert!(swap(1_000, 1_000_000, 0, 25, 10_000).is_none());
 
-    } else if curve_type == CurveType::Stable { // stableswap (2 for orca)
-        let swap_curve = SwapCurve {
-            curve_type: CurveType::Stable,
-            calculator: Arc::new(StableCurve { amp }),
-        };
-        quote = swap_curve.swap(
-            amount_in, 
-            input_token_pool_amount, 
-            output_token_pool_amount, 
-            trade_direction, 
-            fees
-        ).unwrap().destination_amount_swapped;
-
-    } else {
-        panic!("invalid curve type for swap: {:?}", curve_type);
+        assert_eq!(total_without_take_pnl(1_000, 50, 30), 1_020);
+        assert_eq!(total_without_take_pnl(10, 0, 30), 0);
     }
 
-    // add slippage amount if its given 
-    if let Some([num, denom]) = slippage_percent {
-        quote = quote * (denom - num) / denom
+    #[test]
+    fn amount_in_inverts_the_quote() {
+        assert_eq!(get_amount_in(996, 1_000_000, 1_000_000, 25, 10_000), Some(1_000));
+        assert_eq!(get_amount_in(0, 1_000_000, 1_000_000, 25, 10_000), Some(0));
+        assert_eq!(get_amount_in(1_000_000, 1_000_000, 1_000_000, 25, 10_000), None);
+
+        for (reserve_in, reserve_out) in [(1_000_000, 1_000_000), (3_000, 70_000_000), (90_000_000, 7)] {
+            for amount_out in (1..reserve_out).step_by((reserve_out as usize / 500).max(1)) {
+                let amount_in = get_amount_in(amount_out, reserve_in, reserve_out, 25, 10_000).unwrap();
+                assert!(get_amount_out(amount_in, reserve_in, reserve_out, 25, 10_000) >= amount_out);
+                assert!(get_amount_out(amount_in - 1, reserve_in, reserve_out, 25, 10_000) < amount_out);
+            }
+        }
     }
 
+    #[test]
+    fn unpacks_amm_info() {
+        let mut data = vec![0; AMM_INFO_LEN];
+        assert!(unpack_amm_info(&data).is_none());
+
+        let coin_vault = Pubkey::new_unique();
+        data[0..8].copy_from_slice(&6_u64.to_le_bytes());
+        data[176..184].copy_from_slice(&25_u64.to_le_bytes());
+        data[184..192].copy_from_slice(&10_000_u64.to_le_bytes());
+        data[200..208].copy_from_slice(&7_u64.to_le_bytes());
+        data[336..368].copy_from_slice(&coin_vault.to_bytes());
 
-    Ok(quote)
+        let amm = unpack_amm_info(&data).unwrap();
+        assert_eq!(amm.status, 6);
+        assert_eq!(
+            (amm.swap_fee_numerator, amm.swap_fee_denominator),
+            (25, 10_000)
+        );
+        assert_eq!(amm.need_take_pnl_pc, 7);
+        assert_eq!(amm.coin_vault, coin_vault);
+    }
 }
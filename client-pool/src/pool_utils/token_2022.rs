@@ -0,0 +1,265 @@
+//! Token-2022 mints + transfer fee math (mirrors spl-token-2022's `Mint` and
+//! its `transfer_fee` extension)
+//!
+//! A mint with the transfer fee extension withholds a fee from every
+//! transfer, so a swap into a pool delivers less than the amount sent and a
+//! swap out of one delivers less than the pool quotes. The fee is a number of
+//! bps of the amount (rounded up) capped at `maximum_fee`, and the config
+//! holds two of them: the newer one applies from its epoch on.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+
+use crate::constants::*;
+use crate::serialize::token::{
+    find_extension, unpack_account_type, AccountType, EXTENSION_TRANSFER_FEE_CONFIG, MINT_LEN,
+};
+use crate::pool_utils::common::{read_u16, read_u64};
+
+pub const MAX_FEE_BASIS_POINTS: u128 = 10_000;
+const TRANSFER_FEE_CONFIG_LEN: usize = 108;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransferFee {
+    pub epoch: u64, // first epoch the fee applies
+    pub maximum_fee: u64,
+    pub transfer_fee_basis_points: u16,
+}
+
+/// The `TransferFeeConfig` fields we quote with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransferFeeConfig {
+    pub withheld_amount: u64,
+    pub older_transfer_fee: TransferFee,
+    pub newer_transfer_fee: TransferFee,
+}
+
+/// A mint + the extensions we care about
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mint {
+    pub token_program: Pubkey, // the mint account's owner
+    pub decimals: u8,
+    pub transfer_fee_config: Option<TransferFeeConfig>,
+}
+
+fn unpack_transfer_fee(data: &[u8], offset: usize) -> TransferFee {
+    TransferFee {
+        epoch: read_u64(data, offset),
+        maximum_fee: read_u64(data, offset + 8),
+        transfer_fee_basis_points: read_u16(data, offset + 16),
+    }
+}
+
+/// None if `data` is too short to be the extension
+pub fn unpack_transfer_fee_config(data: &[u8]) -> Option<TransferFeeConfig> {
+    if data.len() < TRANSFER_FEE_CONFIG_LEN {
+        return None;
+    }
+    Some(TransferFeeConfig {
+        // config authority @ 0, withdraw withheld authority @ 32
+        withheld_amount: read_u64(data, 64),
+        older_transfer_fee: unpack_transfer_fee(data, 72),
+        newer_transfer_fee: unpack_transfer_fee(data, 90),
+    })
+}
+
+/// None if `data` (owned by `owner`) isn't an initialized mint of either token program
+pub fn unpack_mint(owner: &Pubkey, data: &[u8]) -> Option<Mint> {
+    let is_token_program = *owner == *TOKEN_PROGRAM_ID || *owner == *TOKEN_2022_PROGRAM_ID;
+    // mint authority @ 0, supply @ 36
+    if !is_token_program || data.len() < MINT_LEN || data[45] == 0 {
+        return None;
+    }
+    let transfer_fee_config = match unpack_account_type(data) {
+        Some(AccountType::Mint) if *owner == *TOKEN_2022_PROGRAM_ID => {
+            find_extension(data, EXTENSION_TRANSFER_FEE_CONFIG)
+                .and_then(unpack_transfer_fee_config)
+        }
+        _ => None,
+    };
+    Some(Mint {
+        token_program: *owner,
+        decimals: data[44],
+        transfer_fee_config,
+    })
+}
+
+impl TransferFee {
+    /// fee withheld from a transfer of `amount`
+    pub fn calculate_fee(&self, amount: u64) -> u64 {
+        let basis_points = self.transfer_fee_basis_points as u128;
+        if basis_points == 0 || amount == 0 {
+            return 0;
+        }
+        let exact_fee = amount as u128 * basis_points;
+        let fee = match exact_fee % MAX_FEE_BASIS_POINTS {
+            0 => exact_fee / MAX_FEE_BASIS_POINTS,
+            _ => exact_fee / MAX_FEE_BASIS_POINTS + 1,
+        };
+        fee.min(self.maximum_fee as u128) as u64
+    }
+
+    /// smallest transfer which delivers at least `post_fee_amount`, None if
+    /// it doesn't fit in a u64
+    pub fn calculate_pre_fee_amount(&self, post_fee_amount: u64) -> Option<u64> {
+        let basis_points = self.transfer_fee_basis_points as u128;
+        if basis_points == 0 || post_fee_amount == 0 {
+            return Some(post_fee_amount);
+        }
+        if basis_points == MAX_FEE_BASIS_POINTS {
+            return post_fee_amount.checked_add(self.maximum_fee);
+        }
+        // amount - ceil(amount * bps / MAX) >= post_fee_amount, or the capped fee
+        let numerator = post_fee_amount as u128 * MAX_FEE_BASIS_POINTS;
+        let denominator = MAX_FEE_BASIS_POINTS - basis_points;
+        let raw_amount = match numerator % denominator {
+            0 => numerator / denominator,
+            _ => numerator / denominator + 1,
+        };
+        let capped_amount = post_fee_amount as u128 + self.maximum_fee as u128;
+        let mut amount = u64::try_from(raw_amount.min(capped_amount)).ok()?;
+        // ceil rounding of the fee can take one more atom
+        while amount - self.calculate_fee(amount) < post_fee_amount {
+            amount = amount.checked_add(1)?;
+        }
+        Some(amount)
+    }
+}
+
+impl TransferFeeConfig {
+    pub fn get_epoch_fee(&self, epoch: u64) -> &TransferFee {
+        if epoch >= self.newer_transfer_fee.epoch {
+            &self.newer_transfer_fee
+        } else {
+            &self.older_transfer_fee
+        }
+    }
+}
+
+impl Mint {
+    /// fee withheld from a transfer of `amount` during `epoch`
+    pub fn transfer_fee(&self, amount: u64, epoch: u64) -> u64 {
+        self.transfer_fee_config
+            .as_ref()
+            .map_or(0, |config| config.get_epoch_fee(epoch).calculate_fee(amount))
+    }
+
+    /// what a transfer of `amount` delivers during `epoch`
+    pub fn post_fee_amount(&self, amount: u64, epoch: u64) -> u64 {
+        amount - self.transfer_fee(amount, epoch)
+    }
+
+    /// smallest transfer delivering at least `amount` during `epoch`
+    pub fn pre_fee_amount(&self, amount: u64, epoch: u64) -> Option<u64> {
+        match &self.transfer_fee_config {
+            Some(config) => config.get_epoch_fee(epoch).calculate_pre_fee_amount(amount),
+            None => Some(amount),
+        }
+    }
+
+    pub fn has_transfer_fee(&self) -> bool {
+        self.transfer_fee_config.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialize::token::{unpack_extensions, ACCOUNT_TYPE_OFFSET};
+
+    fn fee(transfer_fee_basis_points: u16, maximum_fee: u64) -> TransferFee {
+        TransferFee {
+            epoch: 0,
+            maximum_fee,
+            transfer_fee_basis_points,
+        }
+    }
+
+    #[test]
+    fn fees_round_up_and_cap() {
+        // 1% of 1_001 == 10.01 -> 11
+        assert_eq!(fee(100, u64::MAX).calculate_fee(1_001), 11);
+        assert_eq!(fee(100, u64::MAX).calculate_fee(1_000), 10);
+        assert_eq!(fee(100, 5).calculate_fee(1_000), 5);
+        assert_eq!(fee(0, 5).calculate_fee(1_000), 0);
+        assert_eq!(fee(100, 5).calculate_fee(0), 0);
+
+        for transfer_fee in [fee(100, u64::MAX), fee(100, 5), fee(333, 1_000), fee(10_000, 7)] {
+            for post_fee_amount in [0, 1, 99, 1_000, 1_001, 123_457] {
+                let amount = transfer_fee.calculate_pre_fee_amount(post_fee_amount).unwrap();
+                assert!(amount - transfer_fee.calculate_fee(amount) >= post_fee_amount);
+                if amount > 0 {
+                    let less = amount - 1;
+                    assert!(less - transfer_fee.calculate_fee(less) < post_fee_amount);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn newer_fee_applies_from_its_epoch() {
+        let config = TransferFeeConfig {
+            withheld_amount: 0,
+            older_transfer_fee: fee(100, u64::MAX),
+            newer_transfer_fee: TransferFee {
+                epoch: 500,
+                ..fee(50, u64::MAX)
+            },
+        };
+        let mint = Mint {
+            token_program: *TOKEN_2022_PROGRAM_ID,
+            decimals: 6,
+            transfer_fee_config: Some(config),
+        };
+        assert_eq!(mint.transfer_fee(10_000, 499), 100);
+        assert_eq!(mint.transfer_fee(10_000, 500), 50);
+        assert_eq!(mint.post_fee_amount(10_000, 500), 9_950);
+        assert_eq!(mint.pre_fee_amount(9_950, 500), Some(10_000));
+    }
+
+    #[test]
+    fn unpacks_extended_mints() {
+        let mut data = vec![0; MINT_LEN];
+        data[44] = 9;
+        assert!(unpack_mint(&TOKEN_PROGRAM_ID, &data).is_none()); // not initialized
+        data[45] = 1;
+        let mint = unpack_mint(&TOKEN_PROGRAM_ID, &data).unwrap();
+        assert_eq!((mint.decimals, mint.transfer_fee_config), (9, None));
+        assert!(unpack_mint(&Pubkey::default(), &data).is_none());
+        assert!(unpack_extensions(&data).is_empty());
+
+        // padding, account type, then an unknown extension before the fee config
+        data.resize(ACCOUNT_TYPE_OFFSET, 0);
+        data.push(AccountType::Mint as u8);
+        data.extend_from_slice(&99_u16.to_le_bytes());
+        data.extend_from_slice(&3_u16.to_le_bytes());
+        data.extend_from_slice(&[7, 7, 7]);
+        let mut config = vec![0; TRANSFER_FEE_CONFIG_LEN];
+        config[64..72].copy_from_slice(&42_u64.to_le_bytes());
+        config[90..98].copy_from_slice(&10_u64.to_le_bytes());
+        config[98..106].copy_from_slice(&1_000_u64.to_le_bytes());
+        config[106..108].copy_from_slice(&25_u16.to_le_bytes());
+        data.extend_from_slice(&EXTENSION_TRANSFER_FEE_CONFIG.to_le_bytes());
+        data.extend_from_slice(&(TRANSFER_FEE_CONFIG_LEN as u16).to_le_bytes());
+        data.extend_from_slice(&config);
+        data.extend_from_slice(&[0; 8]); // unused space
+
+        let extensions = unpack_extensions(&data);
+        assert_eq!(extensions.len(), 2);
+        assert_eq!(extensions[0], (99, &[7_u8, 7, 7][..]));
+
+        let mint = unpack_mint(&TOKEN_2022_PROGRAM_ID, &data).unwrap();
+        assert_eq!(mint.token_program, *TOKEN_2022_PROGRAM_ID);
+        let config = mint.transfer_fee_config.unwrap();
+        assert_eq!(config.withheld_amount, 42);
+        assert_eq!(
+            config.newer_transfer_fee,
+            TransferFee {
+                epoch: 10,
+                maximum_fee: 1_000,
+                transfer_fee_basis_points: 25
+            }
+        );
+        // a classic mint can't have extensions
+        assert_eq!(unpack_mint(&TOKEN_PROGRAM_ID, &data).unwrap().transfer_fee_config, None);
+    }
+}
@@ -0,0 +1,685 @@
+//! Orca Whirlpool state + concentrated liquidity swap math (mirrors whirlpools'
+//! `tick_math.rs`, `swap_math.rs` and `swap_manager.rs`)
+//!
+//! Prices are Q64.64 sqrt prices. A swap steps from the current sqrt price to
+//! the next initialized tick, crossing it (and applying its `liquidity_net`)
+//! whenever the step reaches it. Only exact-in swaps are quoted.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use spl_math::uint::U256;
+use std::collections::BTreeMap;
+use crate::pool_utils::common::{
+    ceil_div, read_i32, read_pubkey, read_u128, read_u16, saturating_u128,
+};
+
+pub const WHIRLPOOL_LEN: usize = 653;
+pub const TICK_ARRAY_LEN: usize = 9988;
+// sha256("account:<name>")[..8]
+pub const WHIRLPOOL_DISCRIMINATOR: [u8; 8] = [63, 149, 209, 12, 225, 128, 99, 9];
+pub const TICK_ARRAY_DISCRIMINATOR: [u8; 8] = [69, 97, 189, 190, 110, 7, 66, 187];
+
+pub const TICK_ARRAY_SIZE: i32 = 88;
+const TICK_LEN: usize = 113;
+
+pub const MIN_TICK_INDEX: i32 = -443636;
+pub const MAX_TICK_INDEX: i32 = 443636;
+pub const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+pub const MAX_SQRT_PRICE_X64: u128 = 79226673515401279992447579055;
+
+// fee_rate is in hundredths of a bip
+pub const FEE_RATE_DENOMINATOR: u128 = 1_000_000;
+
+/// The `Whirlpool` fields we quote + swap with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Whirlpool {
+    pub tick_spacing: u16,
+    pub fee_rate: u16,
+    pub liquidity: u128,
+    pub sqrt_price: u128,
+    pub tick_current_index: i32,
+    pub token_mint_a: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub token_mint_b: Pubkey,
+    pub token_vault_b: Pubkey,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tick {
+    pub initialized: bool,
+    pub liquidity_net: i128,
+}
+
+/// `TICK_ARRAY_SIZE` ticks, `ticks[i]` is tick `start_tick_index + i * tick_spacing`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TickArray {
+    pub start_tick_index: i32,
+    pub ticks: Vec<Tick>,
+    pub whirlpool: Pubkey,
+}
+
+/// None if `data` isn't a `Whirlpool` account
+pub fn unpack_whirlpool(data: &[u8]) -> Option<Whirlpool> {
+    if data.len() != WHIRLPOOL_LEN || data[..8] != WHIRLPOOL_DISCRIMINATOR {
+        return None;
+    }
+    Some(Whirlpool {
+        // whirlpools_config @ 8, bump @ 40
+        tick_spacing: read_u16(data, 41),
+        fee_rate: read_u16(data, 45),
+        liquidity: read_u128(data, 49),
+        sqrt_price: read_u128(data, 65),
+        tick_current_index: read_i32(data, 81),
+        token_mint_a: read_pubkey(data, 101),
+        token_vault_a: read_pubkey(data, 133),
+        token_mint_b: read_pubkey(data, 181),
+        token_vault_b: read_pubkey(data, 213),
+    })
+}
+
+/// None if `data` isn't a `TickArray` account
+pub fn unpack_tick_array(data: &[u8]) -> Option<TickArray> {
+    if data.len() != TICK_ARRAY_LEN || data[..8] != TICK_ARRAY_DISCRIMINATOR {
+        return None;
+    }
+    let ticks = (0..TICK_ARRAY_SIZE as usize)
+        .map(|i| {
+            let offset = 12 + i * TICK_LEN;
+            Tick {
+                initialized: data[offset] != 0,
+                liquidity_net: read_u128(data, offset + 1) as i128,
+            }
+        })
+        .collect();
+    Some(TickArray {
+        start_tick_index: read_i32(data, 8),
+        ticks,
+        whirlpool: read_pubkey(data, 12 + TICK_ARRAY_SIZE as usize * TICK_LEN),
+    })
+}
+
+/// start tick of the tick array holding `tick_index`
+pub fn tick_array_start_index(tick_index: i32, tick_spacing: u16) -> i32 {
+    let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+    tick_index.div_euclid(ticks_in_array) * ticks_in_array
+}
+
+/// the three tick arrays a swap from `tick_current_index` walks through (in order)
+pub fn swap_tick_array_starts(
+    tick_current_index: i32,
+    tick_spacing: u16,
+    a_to_b: bool,
+) -> [i32; 3] {
+    let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+    // b -> a starts at the next initializable tick, which can be in the next array
+    let shift = if a_to_b { 0 } else { tick_spacing as i32 };
+    let start = tick_array_start_index(tick_current_index + shift, tick_spacing);
+    let step = if a_to_b {
+        -ticks_in_array
+    } else {
+        ticks_in_array
+    };
+    [start, start + step, start + 2 * step]
+}
+
+pub fn tick_array_address(
+    whirlpool: &Pubkey,
+    start_tick_index: i32,
+    program_id: &Pubkey,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"tick_array",
+            whirlpool.as_ref(),
+            start_tick_index.to_string().as_bytes(),
+        ],
+        program_id,
+    )
+    .0
+}
+
+pub fn oracle_address(whirlpool: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"oracle", whirlpool.as_ref()], program_id).0
+}
+
+fn mul_shift_96(ratio: U256, factor: u128) -> U256 {
+    (ratio * U256::from(factor)) >> 96
+}
+
+/// Q64.64 sqrt(1.0001^tick)
+pub fn sqrt_price_from_tick_index(tick: i32) -> u128 {
+    if tick >= 0 {
+        // Q32.96 ratios, shifted down to Q64.64 at the end
+        let mut ratio = if tick & 1 != 0 {
+            U256::from(79232123823359799118286999567_u128)
+        } else {
+            U256::from(79228162514264337593543950336_u128)
+        };
+        let factors: [u128; 18] = [
+            79236085330515764027303304731,
+            79244008939048815603706035061,
+            79259858533276714757314932305,
+            79291567232598584799939703904,
+            79355022692464371645785046466,
+            79482085999252804386437311141,
+            79736823300114093921829183326,
+            80248749790819932309965073892,
+            81282483887344747381513967011,
+            83390072131320151908154831281,
+            87770609709833776024991924138,
+            97234110755111693312479820773,
+            119332217159966728226237229890,
+            179736315981702064433883588727,
+            407748233172238350107850275304,
+            2098478828474011932436660412517,
+            55581415166113811149459800483533,
+            38992368544603139932233054999993551,
+        ];
+        for (bit, factor) in factors.iter().enumerate() {
+            if tick & (2 << bit) != 0 {
+                ratio = mul_shift_96(ratio, *factor);
+            }
+        }
+        (ratio >> 32).as_u128()
+    } else {
+        let abs_tick = tick.abs();
+        let mut ratio: u128 = if abs_tick & 1 != 0 {
+            18445821805675392311
+        } else {
+            18446744073709551616
+        };
+        let factors: [u128; 18] = [
+            18444899583751176498,
+            18443055278223354162,
+            18439367220385604838,
+            18431993317065449817,
+            18417254355718160513,
+            18387811781193591352,
+            18329067761203520168,
+            18212142134806087854,
+            17980523815641551639,
+            17526086738831147013,
+            16651378430235024244,
+            15030750278693429944,
+            12247334978882834399,
+            8131365268884726200,
+            3584323654723342297,
+            696457651847595233,
+            26294789957452057,
+            37481735321082,
+        ];
+        for (bit, factor) in factors.iter().enumerate() {
+            if abs_tick & (2 << bit) != 0 {
+                ratio = (ratio * factor) >> 64;
+            }
+        }
+        ratio
+    }
+}
+
+fn div_round(numerator: U256, denominator: U256, round_up: bool) -> U256 {
+    if round_up {
+        ceil_div(numerator, denominator)
+    } else {
+        numerator / denominator
+    }
+}
+
+/// token a between two sqrt prices: L * (upper - lower) / (upper * lower)
+pub fn get_amount_delta_a(
+    sqrt_price_0: u128,
+    sqrt_price_1: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> u128 {
+    let (lower, upper) = if sqrt_price_0 < sqrt_price_1 {
+        (sqrt_price_0, sqrt_price_1)
+    } else {
+        (sqrt_price_1, sqrt_price_0)
+    };
+    let product = U256::from(liquidity) * U256::from(upper - lower);
+    let numerator = match product.checked_mul(U256::one() << 64) {
+        Some(numerator) => numerator,
+        None => return u128::MAX,
+    };
+    let denominator = U256::from(upper) * U256::from(lower);
+    saturating_u128(div_round(numerator, denominator, round_up))
+}
+
+/// token b between two sqrt prices: L * (upper - lower)
+pub fn get_amount_delta_b(
+    sqrt_price_0: u128,
+    sqrt_price_1: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> u128 {
+    let (lower, upper) = if sqrt_price_0 < sqrt_price_1 {
+        (sqrt_price_0, sqrt_price_1)
+    } else {
+        (sqrt_price_1, sqrt_price_0)
+    };
+    let product = U256::from(liquidity) * U256::from(upper - lower);
+    let amount = product >> 64;
+    if round_up && product.low_u64() != 0 {
+        saturating_u128(amount + 1)
+    } else {
+        saturating_u128(amount)
+    }
+}
+
+/// sqrt price after `amount` of the input token is added (a in rounds up, b in rounds down)
+pub fn get_next_sqrt_price_from_input(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount: u128,
+    a_to_b: bool,
+) -> u128 {
+    if amount == 0 {
+        return sqrt_price;
+    }
+    if a_to_b {
+        // L * p / (L + amount * p)
+        let liquidity_x64 = U256::from(liquidity) << 64;
+        let product = U256::from(sqrt_price) * U256::from(amount);
+        let numerator = U256::from(liquidity) * U256::from(sqrt_price);
+        match numerator.checked_mul(U256::one() << 64) {
+            Some(numerator) => saturating_u128(div_round(numerator, liquidity_x64 + product, true)),
+            None => {
+                let denominator = liquidity_x64 / U256::from(sqrt_price) + U256::from(amount);
+                saturating_u128(div_round(liquidity_x64, denominator, true))
+            }
+        }
+    } else {
+        // p + amount / L
+        let delta = (U256::from(amount) << 64) / U256::from(liquidity);
+        saturating_u128(U256::from(sqrt_price) + delta)
+    }
+}
+
+/// one exact-in step towards `sqrt_price_target`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SwapStep {
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub fee_amount: u128,
+    pub next_sqrt_price: u128,
+}
+
+pub fn compute_swap_step(
+    amount_remaining: u128,
+    fee_rate: u16,
+    liquidity: u128,
+    sqrt_price_current: u128,
+    sqrt_price_target: u128,
+    a_to_b: bool,
+) -> SwapStep {
+    let fee_rate = fee_rate as u128;
+    let amount_less_fee = saturating_u128(
+        U256::from(amount_remaining) * U256::from(FEE_RATE_DENOMINATOR - fee_rate)
+            / U256::from(FEE_RATE_DENOMINATOR),
+    );
+
+    // input needed to reach the target (a in when a -> b)
+    let amount_in_delta = |price_0: u128, price_1: u128| {
+        if a_to_b {
+            get_amount_delta_a(price_0, price_1, liquidity, true)
+        } else {
+            get_amount_delta_b(price_0, price_1, liquidity, true)
+        }
+    };
+    let amount_to_target = amount_in_delta(sqrt_price_current, sqrt_price_target);
+    let is_max_swap = amount_to_target <= amount_less_fee;
+    let next_sqrt_price = if is_max_swap {
+        sqrt_price_target
+    } else {
+        get_next_sqrt_price_from_input(sqrt_price_current, liquidity, amount_less_fee, a_to_b)
+    };
+
+    let amount_in = if next_sqrt_price == sqrt_price_target {
+        amount_to_target
+    } else {
+        amount_in_delta(sqrt_price_current, next_sqrt_price)
+    };
+    let amount_out = if a_to_b {
+        get_amount_delta_b(sqrt_price_current, next_sqrt_price, liquidity, false)
+    } else {
+        get_amount_delta_a(sqrt_price_current, next_sqrt_price, liquidity, false)
+    };
+    let fee_amount = if is_max_swap {
+        saturating_u128(div_round(
+            U256::from(amount_in) * U256::from(fee_rate),
+            U256::from(FEE_RATE_DENOMINATOR - fee_rate),
+            true,
+        ))
+    } else {
+        amount_remaining.saturating_sub(amount_in)
+    };
+
+    SwapStep {
+        amount_in,
+        amount_out,
+        fee_amount,
+        next_sqrt_price,
+    }
+}
+
+/// largest tick whose sqrt price is at most `sqrt_price`
+pub fn tick_index_from_sqrt_price(sqrt_price: u128) -> i32 {
+    let (mut lo, mut hi) = (MIN_TICK_INDEX, MAX_TICK_INDEX);
+    if sqrt_price <= MIN_SQRT_PRICE_X64 {
+        return lo;
+    }
+    if sqrt_price >= MAX_SQRT_PRICE_X64 {
+        return hi;
+    }
+    // sqrt_price_from_tick_index(lo) <= sqrt_price < sqrt_price_from_tick_index(hi)
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if sqrt_price_from_tick_index(mid) <= sqrt_price {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/// exact-in swap of `amount_in`, crossing the initialized ticks in `ticks`
+/// (tick index -> liquidity_net) of the tick arrays covering
+/// `[tick_range.0, tick_range.1)`. The steps are summed into one `SwapStep`
+/// ending at the final sqrt price. None if the swap would leave that range
+/// (the swap would fail on chain without more tick arrays)
+pub fn swap(
+    whirlpool: &Whirlpool,
+    ticks: &BTreeMap<i32, i128>,
+    tick_range: (i32, i32),
+    amount_in: u128,
+    a_to_b: bool,
+) -> Option<SwapStep> {
+    swap_to_state(whirlpool, ticks, tick_range, amount_in, a_to_b).map(|(step, _)| step)
+}
+
+/// `swap` + the whirlpool after it (sqrt price, liquidity and current tick moved)
+pub fn swap_to_state(
+    whirlpool: &Whirlpool,
+    ticks: &BTreeMap<i32, i128>,
+    tick_range: (i32, i32),
+    amount_in: u128,
+    a_to_b: bool,
+) -> Option<(SwapStep, Whirlpool)> {
+    // swap amounts are u64 on chain
+    if amount_in > u64::MAX as u128 {
+        return None;
+    }
+    let lower = tick_range.0.max(MIN_TICK_INDEX);
+    let upper = tick_range.1.min(MAX_TICK_INDEX);
+
+    let mut amount_remaining = amount_in;
+    let mut total = SwapStep {
+        next_sqrt_price: whirlpool.sqrt_price,
+        ..SwapStep::default()
+    };
+    let mut sqrt_price = whirlpool.sqrt_price;
+    let mut tick_current_index = whirlpool.tick_current_index;
+    let mut liquidity = whirlpool.liquidity;
+
+    while amount_remaining > 0 {
+        let next_tick = if a_to_b {
+            if tick_current_index < lower {
+                return None;
+            }
+            ticks.range(lower..=tick_current_index).next_back()
+        } else {
+            if tick_current_index >= upper {
+                return None;
+            }
+            ticks.range(tick_current_index + 1..upper).next()
+        };
+        // no initialized tick left -- step to the edge of the tick arrays
+        let (next_tick_index, liquidity_net) = match next_tick {
+            Some((tick_index, liquidity_net)) => (*tick_index, Some(*liquidity_net)),
+            None => (if a_to_b { lower } else { upper }, None),
+        };
+        let next_sqrt_price = sqrt_price_from_tick_index(next_tick_index);
+
+        let step = compute_swap_step(
+            amount_remaining,
+            whirlpool.fee_rate,
+            liquidity,
+            sqrt_price,
+            next_sqrt_price,
+            a_to_b,
+        );
+        amount_remaining = amount_remaining.saturating_sub(step.amount_in + step.fee_amount);
+        total.amount_in += step.amount_in;
+        total.amount_out += step.amount_out;
+        total.fee_amount += step.fee_amount;
+        total.next_sqrt_price = step.next_sqrt_price;
+        sqrt_price = step.next_sqrt_price;
+
+        if step.next_sqrt_price != next_sqrt_price {
+            // input used up before the next tick
+            tick_current_index = tick_index_from_sqrt_price(sqrt_price);
+            break;
+        }
+        match liquidity_net {
+            Some(liquidity_net) => {
+                // crossing right -> left removes the liquidity added left -> right
+                let liquidity_delta = if a_to_b {
+                    -liquidity_net
+                } else {
+                    liquidity_net
+                };
+                liquidity = if liquidity_delta < 0 {
+                    liquidity.checked_sub(liquidity_delta.unsigned_abs())?
+                } else {
+                    liquidity.checked_add(liquidity_delta as u128)?
+                };
+            }
+            None if amount_remaining > 0 => return None,
+            None => {}
+        }
+        tick_current_index = if a_to_b {
+            next_tick_index - 1
+        } else {
+            next_tick_index
+        };
+    }
+    let after = Whirlpool {
+        liquidity,
+        sqrt_price,
+        tick_current_index,
+        ..whirlpool.clone()
+    };
+    Some((total, after))
+}
+
+/// amount out of `swap`
+pub fn get_amount_out(
+    whirlpool: &Whirlpool,
+    ticks: &BTreeMap<i32, i128>,
+    tick_range: (i32, i32),
+    amount_in: u128,
+    a_to_b: bool,
+) -> Option<u128> {
+    swap(whirlpool, ticks, tick_range, amount_in, a_to_b).map(|step| step.amount_out)
+}
+
+/// price of a in b of a Q64.64 sqrt price
+pub fn price_from_sqrt_price(sqrt_price: u128) -> f64 {
+    let sqrt_price = sqrt_price as f64 / (1u128 << 64) as f64;
+    sqrt_price * sqrt_price
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const Q64: u128 = 1 << 64;
+
+    fn pool(liquidity: u128, fee_rate: u16) -> Whirlpool {
+        Whirlpool {
+            tick_spacing: 64,
+            fee_rate,
+            liquidity,
+            sqrt_price: Q64, // price 1 @ tick 0
+            ..Whirlpool::default()
+        }
+    }
+
+    #[test]
+    fn tick_math_bounds() {
+        assert_eq!(sqrt_price_from_tick_index(0), Q64);
+        assert_eq!(
+            sqrt_price_from_tick_index(MIN_TICK_INDEX),
+            MIN_SQRT_PRICE_X64
+        );
+        assert_eq!(
+            sqrt_price_from_tick_index(MAX_TICK_INDEX),
+            MAX_SQRT_PRICE_X64
+        );
+        assert!(sqrt_price_from_tick_index(-1) < Q64 && Q64 < sqrt_price_from_tick_index(1));
+
+        assert_eq!(tick_array_start_index(0, 64), 0);
+        assert_eq!(tick_array_start_index(-1, 64), -5632);
+        assert_eq!(
+            swap_tick_array_starts(-1, 64, true),
+            [-5632, -11264, -16896]
+        );
+        // the next initializable tick (5632) is in the next array
+        assert_eq!(
+            swap_tick_array_starts(5600, 64, false),
+            [5632, 11264, 16896]
+        );
+    }
+
+    #[test]
+    fn quotes_like_constant_product_between_ticks() {
+        // L = 1e12 @ price 1 == virtual reserves of 1e12 on both sides
+        let whirlpool = pool(1_000_000_000_000, 0);
+        let ticks = BTreeMap::new();
+        let out = get_amount_out(&whirlpool, &ticks, (-5632, 5632), 1_000_000, true).unwrap();
+        assert!((999_998..=999_999).contains(&out));
+        let out = get_amount_out(&whirlpool, &ticks, (-5632, 5632), 1_000_000, false).unwrap();
+        assert!((999_998..=999_999).contains(&out));
+
+        // 0.3% fee
+        let whirlpool = pool(1_000_000_000_000, 3000);
+        let out = get_amount_out(&whirlpool, &ticks, (-5632, 5632), 1_000_000, true).unwrap();
+        assert!((996_998..=997_000).contains(&out));
+
+        // the whole input is either swapped or paid as fees
+        let step = swap(&whirlpool, &ticks, (-5632, 5632), 1_000_000, true).unwrap();
+        assert_eq!(step.amount_in + step.fee_amount, 1_000_000);
+        assert_eq!(step.fee_amount, 3_000);
+        assert!(step.next_sqrt_price < Q64);
+        assert_eq!(price_from_sqrt_price(Q64), 1.);
+
+        let (_, after) = swap_to_state(&whirlpool, &ticks, (-5632, 5632), 1_000_000, true).unwrap();
+        assert_eq!(after.sqrt_price, step.next_sqrt_price);
+        assert_eq!(after.tick_current_index, -1);
+        assert_eq!(after.liquidity, whirlpool.liquidity);
+        // the same trade again gets less out of the moved pool
+        let first = get_amount_out(&whirlpool, &ticks, (-5632, 5632), 1_000_000, true).unwrap();
+        let second = get_amount_out(&after, &ticks, (-5632, 5632), 1_000_000, true).unwrap();
+        assert!(second < first);
+    }
+
+    #[test]
+    fn ticks_from_sqrt_prices() {
+        assert_eq!(tick_index_from_sqrt_price(Q64), 0);
+        assert_eq!(tick_index_from_sqrt_price(Q64 - 1), -1);
+        assert_eq!(tick_index_from_sqrt_price(MIN_SQRT_PRICE_X64), MIN_TICK_INDEX);
+        assert_eq!(tick_index_from_sqrt_price(MAX_SQRT_PRICE_X64), MAX_TICK_INDEX);
+        for tick in [-70_000, -64, -1, 1, 64, 70_000] {
+            let sqrt_price = sqrt_price_from_tick_index(tick);
+            assert_eq!(tick_index_from_sqrt_price(sqrt_price), tick);
+            assert_eq!(tick_index_from_sqrt_price(sqrt_price + 1), tick);
+            assert_eq!(tick_index_from_sqrt_price(sqrt_price - 1), tick - 1);
+        }
+    }
+
+    #[test]
+    fn crossing_moves_liquidity() {
+        let liquidity = 1_000_000_000;
+        let whirlpool = pool(liquidity, 0);
+        // [-64, 64) and a position ending at -128, with a gap in between
+        let ticks: BTreeMap<i32, i128> = [
+            (-128, -(liquidity as i128)),
+            (-64, liquidity as i128),
+            (64, -(liquidity as i128)),
+        ]
+        .into_iter()
+        .collect();
+        let (_, after) = swap_to_state(&whirlpool, &ticks, (-5632, 5632), 1_000_000, true).unwrap();
+        assert!((-64..0).contains(&after.tick_current_index));
+        assert_eq!(after.liquidity, liquidity);
+        let (_, after) = swap_to_state(&whirlpool, &ticks, (-5632, 5632), 4_000_000, true).unwrap();
+        assert!(after.tick_current_index < -128);
+        assert_eq!(after.liquidity, liquidity);
+    }
+
+    #[test]
+    fn crosses_initialized_ticks() {
+        let liquidity = 1_000_000_000;
+        let whirlpool = pool(liquidity, 0);
+        // all of the liquidity is in [-64, 64)
+        let ticks: BTreeMap<i32, i128> = [(-64, liquidity as i128), (64, -(liquidity as i128))]
+            .into_iter()
+            .collect();
+
+        // b out of [-64, 0] -- L * (1 - sqrt(1.0001^-64))
+        let max_out = get_amount_delta_b(sqrt_price_from_tick_index(-64), Q64, liquidity, false);
+        let out = get_amount_out(&whirlpool, &ticks, (-5632, 5632), 1_000_000, true).unwrap();
+        assert!(out < max_out);
+
+        // past -64 there is no liquidity left and the swap runs off the tick arrays
+        assert_eq!(
+            get_amount_out(&whirlpool, &ticks, (-5632, 5632), 10_000_000, true),
+            None
+        );
+
+        // a position ending at -128 -> the swap crosses the gap and keeps filling
+        let mut deeper = ticks.clone();
+        deeper.insert(-128, -(liquidity as i128));
+        let out = get_amount_out(&whirlpool, &deeper, (-5632, 5632), 4_000_000, true).unwrap();
+        assert!(out > max_out);
+    }
+
+    #[test]
+    fn unpacks_accounts() {
+        let mut data = vec![0; WHIRLPOOL_LEN];
+        assert!(unpack_whirlpool(&data).is_none());
+
+        let mint_a = Pubkey::new_unique();
+        data[..8].copy_from_slice(&WHIRLPOOL_DISCRIMINATOR);
+        data[41..43].copy_from_slice(&64_u16.to_le_bytes());
+        data[45..47].copy_from_slice(&3000_u16.to_le_bytes());
+        data[65..81].copy_from_slice(&Q64.to_le_bytes());
+        data[81..85].copy_from_slice(&(-7_i32).to_le_bytes());
+        data[101..133].copy_from_slice(&mint_a.to_bytes());
+
+        let whirlpool = unpack_whirlpool(&data).unwrap();
+        assert_eq!((whirlpool.tick_spacing, whirlpool.fee_rate), (64, 3000));
+        assert_eq!(whirlpool.sqrt_price, Q64);
+        assert_eq!(whirlpool.tick_current_index, -7);
+        assert_eq!(whirlpool.token_mint_a, mint_a);
+
+        let mut data = vec![0; TICK_ARRAY_LEN];
+        data[..8].copy_from_slice(&TICK_ARRAY_DISCRIMINATOR);
+        data[8..12].copy_from_slice(&(-5632_i32).to_le_bytes());
+        let tick = 12 + 2 * TICK_LEN;
+        data[tick] = 1;
+        data[tick + 1..tick + 17].copy_from_slice(&(-42_i128).to_le_bytes());
+
+        let tick_array = unpack_tick_array(&data).unwrap();
+        assert_eq!(tick_array.start_tick_index, -5632);
+        assert_eq!(tick_array.ticks.len(), TICK_ARRAY_SIZE as usize);
+        assert_eq!(
+            tick_array.ticks[2],
+            Tick {
+                initialized: true,
+                liquidity_net: -42
+            }
+        );
+    }
+}
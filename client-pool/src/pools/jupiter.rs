@@ -1,50 +1,53 @@
-use core::panic;
-
-use std::collections::HashMap;
 use std::fmt::Debug;
 use serde::{Deserialize, Serialize};
-use crate::pool::PoolOperations;
-use crate::serialize::token::{WrappedPubkey};
-
-use crate::utils::{derive_token_address};
+use solana_sdk::account::Account;
+use crate::serialize::token::WrappedPubkey;
+use crate::pool::{PoolOperations, Quote};
+use crate::error::QuoteError;
 
-use solana_sdk::pubkey::Pubkey;
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::Cluster;
+use anchor_client::Program;
 
-use anchor_client::{Program, Cluster};
 use solana_sdk::instruction::Instruction;
 
-use solana_sdk::clock::Epoch;
-use solana_sdk::account::Account;
-use solana_sdk::account_info::AccountInfo;
+use tmp::accounts as tmp_accounts;
+use tmp::instruction as tmp_ix;
+
+use crate::utils::derive_token_address;
+use crate::pool_utils::jupiter::{FetchedRoute, RouteRequest};
 use crate::constants::*;
 
-#[derive(Deserialize, Serialize, Debug)]
+fn default_slippage_bps() -> u16 {
+    50
+}
+
+/// A Jupiter route from `input_mint` to `output_mint` used as a single edge,
+/// the route comes from `route_file` (a JSON fixture) or `quote_api_url`
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct JupiterPool {
-    pub own_address: WrappedPubkey,
-    pub base_mint: WrappedPubkey,
-    pub quote_mint: WrappedPubkey,
-    pub base_vault: WrappedPubkey,
-    pub quote_vault: WrappedPubkey,
-    pub swap_program_id: WrappedPubkey,
-    pub taker_fee_pct: f64,
-    #[serde(skip)]
-    pub accounts: Option<Vec<Option<Account>>>,
+    pub input_mint: WrappedPubkey,
+    pub output_mint: WrappedPubkey,
+    pub input_decimals: u64,
+    pub output_decimals: u64,
+    // amount (of the input) routes are quoted for, the most the edge can take
+    pub quote_amount: u64,
+    #[serde(default = "default_slippage_bps")]
+    pub slippage_bps: u16,
+    #[serde(default)]
+    pub route_file: Option<String>,
+    #[serde(default)]
+    pub quote_api_url: Option<String>,
+    // to set later
     #[serde(skip)]
-    pub open_orders: Option<HashMap<String, String>>,
+    pub route: Option<FetchedRoute>, // kept fresh by `spawn_route_refresher`
 }
 
-fn account_info<'a>(pk: &'a Pubkey, account: &'a mut Account) -> AccountInfo<'a> {
-    AccountInfo::new(
-        pk,
-        false,
-        true,
-        &mut account.lamports,
-        &mut account.data,
-        &account.owner,
-        false,
-        Epoch::default(),
-    )
+impl JupiterPool {
+    fn is_route_direction(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> bool {
+        *mint_in == self.input_mint.0 && *mint_out == self.output_mint.0
+    }
 }
 
 impl PoolOperations for JupiterPool {
@@ -53,91 +56,134 @@ impl PoolOperations for JupiterPool {
     }
 
     fn get_update_accounts(&self) -> Vec<Pubkey> {
-        vec![
-            self.own_address.0,
-            self.base_vault.0,
-            self.quote_vault.0,
-        ]
+        // routes aren't streamed (see `route_request`), the mints only tell
+        // the pool was loaded
+        vec![self.input_mint.0, self.output_mint.0]
     }
 
-    fn set_update_accounts(
-        &mut self,
-        accounts: Vec<Option<Account>>,
-        cluster: Cluster,
-    ) {
-        self.accounts = Some(accounts);
-        // Load open orders from a file or other source as needed
-        self.open_orders = Some(HashMap::new()); // Placeholder
+    fn set_update_accounts(&mut self, _accounts: Vec<Option<Account>>, _cluster: Cluster) {}
+
+    fn route_request(&self) -> Option<RouteRequest> {
+        Some(RouteRequest {
+            input_mint: self.input_mint.0,
+            output_mint: self.output_mint.0,
+            amount: self.quote_amount,
+            slippage_bps: self.slippage_bps,
+            route_file: self.route_file.clone(),
+            quote_api_url: self.quote_api_url.clone(),
+        })
+    }
+
+    fn set_route(&mut self, route: Option<FetchedRoute>) {
+        self.route = route;
     }
 
-    fn mint_2_addr(&self, _mint: &Pubkey) -> Pubkey {
-        panic!("Function not implemented");
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError> {
+        // no vaults -- the route's pools hold the liquidity
+        if *mint == self.input_mint.0 || *mint == self.output_mint.0 {
+            Ok(*mint)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
     }
 
     fn get_mints(&self) -> Vec<Pubkey> {
-        let mut mints = vec![
-            self.base_mint.0,
-            self.quote_mint.0,
-        ];
+        let mut mints = vec![self.input_mint.0, self.output_mint.0];
+        // Sort to ensure consistency across different pools
         mints.sort();
         mints
     }
 
-    fn mint_2_scale(&self, mint: &Pubkey) -> u64 {
-        // Implement logic to return the scale based on the mint
-        panic!("Invalid mint provided");
+    fn mint_2_scale(&self, mint: &Pubkey) -> Result<u64, QuoteError> {
+        if *mint == self.input_mint.0 {
+            Ok(self.input_decimals)
+        } else if *mint == self.output_mint.0 {
+            Ok(self.output_decimals)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
     }
 
-    fn get_quote_with_amounts_scaled(
+    fn get_quote(
         &self,
-        amount_in: u128,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        if !self.is_route_direction(mint_in, mint_out) {
+            return Err(QuoteError::InvalidMints { mint_in: *mint_in, mint_out: *mint_out });
+        }
+        let (route, _) = self.route.as_ref().ok_or(QuoteError::NotLoaded)?;
+        route.get_quote(scaled_amount_in)
+    }
+
+    fn simulate_swap(
+        &mut self,
+        scaled_amount_in: u128,
         mint_in: &Pubkey,
-        _mint_out: &Pubkey,
-    ) -> u128 {
-        // Logic to calculate the quote based on the amount in
-        // Placeholder logic
-        amount_in / 2 // Replace with actual calculation
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let quote = self.get_quote(scaled_amount_in, mint_in, mint_out)?;
+        if let Some((route, _)) = self.route.as_mut() {
+            // the route's pools are drained by what's already been swapped
+            route.consume(scaled_amount_in, quote.amount_out);
+        }
+        Ok(quote)
     }
 
-    fn swap_ix(
+    fn get_quote_exact_out(
         &self,
+        amount_out: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Option<u128> {
+        if !self.is_route_direction(mint_in, mint_out) {
+            return None;
+        }
+        let (route, _) = self.route.as_ref()?;
+        route.get_amount_in(amount_out)
+    }
+
+    fn swap_ix(&self,
         program: &Program,
         owner: &Pubkey,
         mint_in: &Pubkey,
-        _mint_out: &Pubkey,
+        mint_out: &Pubkey
     ) -> Vec<Instruction> {
-        let base_ata = derive_token_address(owner, &self.base_mint);
-        let quote_ata = derive_token_address(owner, &self.quote_mint);
+        let (route, instruction) = self.route.as_ref().unwrap();
+        let user_source_token = derive_token_address(owner, mint_in);
+        let user_destination_token = derive_token_address(owner, mint_out);
 
-        // Construct the swap instruction
-        let swap_ix = program
+        let mut swap_ix = program
             .request()
-            .accounts(tmp_accounts::RaydiumSwap {
-                token_swap: self.address.0, 
-                authority: authority_pda,
-                user_transfer_authority: *owner,
-                user_src,
-                pool_src,
-                user_dst,
-                pool_dst,
-                lp_mint: self.lp_token_mint.0,
-                fee_account: self.fee_account.0,
+            .accounts(tmp_accounts::JupiterSwap {
+                jupiter_program: *JUPITER_PROGRAM_ID,
                 token_program: *TOKEN_PROGRAM_ID,
-                swap_program: *RAYDIUM_PROGRAM_ID,
-                swap_state,
+                user_authority: *owner,
+                user_source_token,
+                user_destination_token,
+            })
+            .args(tmp_ix::JupiterSwap {
+                amount_in: 0,
+                minimum_amount_out: 0,
+                route_data: instruction.data.clone(),
             })
-            .args(tmp_ix::RaydiumSwap { })
             .instructions()
             .unwrap();
-    
+
+        // the route instruction's accounts go in as remaining accounts
+        swap_ix[0].accounts.extend(route.account_metas(
+            owner,
+            &user_source_token,
+            &user_destination_token,
+        ));
+        swap_ix
     }
 
-    fn can_trade(
-        &self,
+    fn can_trade(&self,
         mint_in: &Pubkey,
-        _mint_out: &Pubkey,
+        mint_out: &Pubkey
     ) -> bool {
-        // Check if trading is possible based on the current state
-        true // Placeholder
+        self.is_route_direction(mint_in, mint_out) && self.route.is_some()
     }
 }
@@ -0,0 +1,219 @@
+use std::fmt::Debug;
+use serde::{Deserialize, Serialize};
+use solana_sdk::account::Account;
+use crate::serialize::token::{unpack_token_account, WrappedPubkey};
+use crate::pool::{PoolOperations, Quote};
+use crate::error::QuoteError;
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::Cluster;
+use anchor_client::Program;
+
+use solana_sdk::instruction::Instruction;
+
+use tmp::accounts as tmp_accounts;
+use tmp::instruction as tmp_ix;
+
+use crate::utils::derive_token_address;
+use crate::pool_utils::lifinity::{
+    authority_address, spot_rate, swap, unpack_amm, unpack_pyth_price, Amm, PythPrice,
+};
+use crate::constants::*;
+
+/// Lifinity v2 pool, quoted around its Pyth oracle's price
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LifinityPool {
+    pub address: WrappedPubkey, // amm
+    pub token_a_mint: WrappedPubkey,
+    pub token_b_mint: WrappedPubkey,
+    pub token_a_account: WrappedPubkey,
+    pub token_b_account: WrappedPubkey,
+    pub decimals_a: u64,
+    pub decimals_b: u64,
+    pub pool_mint: WrappedPubkey,
+    pub fee_account: WrappedPubkey,
+    pub oracle_main_account: WrappedPubkey,
+    pub oracle_sub_account: WrappedPubkey,
+    pub oracle_pc_account: WrappedPubkey,
+    // to set later
+    #[serde(skip)]
+    pub amm: Option<Amm>,
+    #[serde(skip)]
+    pub oracle: Option<PythPrice>,
+    #[serde(skip)]
+    pub reserve_a: u128,
+    #[serde(skip)]
+    pub reserve_b: u128,
+    #[serde(skip)]
+    pub slot: u64, // latest slot seen -- how old the oracle price is
+}
+
+impl LifinityPool {
+    fn a_to_b(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> Result<bool, QuoteError> {
+        if *mint_in == self.token_a_mint.0 && *mint_out == self.token_b_mint.0 {
+            Ok(true)
+        } else if *mint_in == self.token_b_mint.0 && *mint_out == self.token_a_mint.0 {
+            Ok(false)
+        } else {
+            Err(QuoteError::InvalidMints { mint_in: *mint_in, mint_out: *mint_out })
+        }
+    }
+}
+
+impl PoolOperations for LifinityPool {
+    fn swap_ix(&self,
+        program: &Program,
+        owner: &Pubkey,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey
+    ) -> Vec<Instruction> {
+        let a_to_b = self.a_to_b(mint_in, mint_out).unwrap();
+        let (swap_source, swap_destination) = if a_to_b {
+            (self.token_a_account.0, self.token_b_account.0)
+        } else {
+            (self.token_b_account.0, self.token_a_account.0)
+        };
+
+        program
+            .request()
+            .accounts(tmp_accounts::LifinitySwap {
+                authority: authority_address(&self.address.0, &LIFINITY_PROGRAM_ID),
+                amm: self.address.0,
+                user_transfer_authority: *owner,
+                source_info: derive_token_address(owner, mint_in),
+                destination_info: derive_token_address(owner, mint_out),
+                swap_source,
+                swap_destination,
+                pool_mint: self.pool_mint.0,
+                fee_account: self.fee_account.0,
+                token_program: *TOKEN_PROGRAM_ID,
+                oracle_main_account: self.oracle_main_account.0,
+                oracle_sub_account: self.oracle_sub_account.0,
+                oracle_pc_account: self.oracle_pc_account.0,
+                lifinity_program: *LIFINITY_PROGRAM_ID,
+            })
+            .args(tmp_ix::LifinitySwap { amount_in: 0, minimum_amount_out: 0 })
+            .instructions()
+            .unwrap()
+    }
+
+    fn get_quote(
+        &self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let a_to_b = self.a_to_b(mint_in, mint_out)?;
+        let (amm, oracle) = match (&self.amm, &self.oracle) {
+            (Some(amm), Some(oracle)) => (amm, oracle),
+            _ => return Err(QuoteError::NotLoaded),
+        };
+        // the program refuses to trade on a price which isn't being published
+        let oracle_price = oracle
+            .atom_price(self.decimals_a, self.decimals_b, self.slot)
+            .ok_or_else(|| {
+                QuoteError::Untradable("oracle price is stale or isn't being published".to_string())
+            })?;
+        let result = swap(
+            amm,
+            oracle_price,
+            self.reserve_a,
+            self.reserve_b,
+            scaled_amount_in,
+            a_to_b,
+        ).ok_or(QuoteError::InsufficientLiquidity)?;
+        Ok(Quote::from_swap_result(&result, spot_rate(oracle_price, a_to_b)))
+    }
+
+    fn simulate_swap(
+        &mut self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let quote = self.get_quote(scaled_amount_in, mint_in, mint_out)?;
+        if let Some((reserve_in, reserve_out)) = quote.new_reserves {
+            // the oracle's price doesn't move, only the vaults do
+            if self.a_to_b(mint_in, mint_out)? {
+                self.reserve_a = reserve_in;
+                self.reserve_b = reserve_out;
+            } else {
+                self.reserve_b = reserve_in;
+                self.reserve_a = reserve_out;
+            }
+        }
+        Ok(quote)
+    }
+
+    fn get_update_accounts(&self) -> Vec<Pubkey> {
+        vec![
+            self.address.0,
+            self.token_a_account.0,
+            self.token_b_account.0,
+            self.oracle_main_account.0,
+        ]
+    }
+
+    fn can_trade(&self,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey
+    ) -> bool {
+        let (amm, oracle) = match (&self.amm, &self.oracle) {
+            (Some(amm), Some(oracle)) => (amm, oracle),
+            _ => return false,
+        };
+        let reserve_out = match self.a_to_b(mint_in, mint_out) {
+            Ok(true) => self.reserve_b,
+            Ok(false) => self.reserve_a,
+            Err(_) => return false,
+        };
+        amm.freeze_trade == 0 && oracle.is_tradable(self.slot) && reserve_out > 0
+    }
+
+    fn set_update_accounts(&mut self, accounts: Vec<Option<Account>>, _cluster: Cluster) {
+        self.amm = accounts[0].as_ref().and_then(|a| unpack_amm(&a.data));
+        self.reserve_a = accounts[1]
+            .as_ref()
+            .map_or(0, |a| unpack_token_account(&a.data).amount as u128);
+        self.reserve_b = accounts[2]
+            .as_ref()
+            .map_or(0, |a| unpack_token_account(&a.data).amount as u128);
+        self.oracle = accounts[3].as_ref().and_then(|a| unpack_pyth_price(&a.data));
+    }
+
+    fn set_slot(&mut self, slot: u64) {
+        self.slot = slot;
+    }
+
+    fn get_name(&self) -> String {
+        "Lifinity".to_string()
+    }
+
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError> {
+        if *mint == self.token_a_mint.0 {
+            Ok(self.token_a_account.0)
+        } else if *mint == self.token_b_mint.0 {
+            Ok(self.token_b_account.0)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
+    }
+
+    fn mint_2_scale(&self, mint: &Pubkey) -> Result<u64, QuoteError> {
+        if *mint == self.token_a_mint.0 {
+            Ok(self.decimals_a)
+        } else if *mint == self.token_b_mint.0 {
+            Ok(self.decimals_b)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
+    }
+
+    fn get_mints(&self) -> Vec<Pubkey> {
+        let mut mints = vec![self.token_a_mint.0, self.token_b_mint.0];
+        // Sort to ensure consistency across different pools
+        mints.sort();
+        mints
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use solana_sdk::account::Account;
+use crate::serialize::token::WrappedPubkey;
+use crate::pool::{price_impact_bps, PoolOperations, Quote};
+use crate::error::QuoteError;
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::Cluster;
+use anchor_client::Program;
+
+use solana_sdk::instruction::{AccountMeta, Instruction};
+
+use tmp::accounts as tmp_accounts;
+use tmp::instruction as tmp_ix;
+
+use crate::utils::{derive_token_address, token_program_of};
+use crate::pool_utils::meteora::{
+    apply_swap, bin_array_address, bin_array_index, event_authority_address, swap,
+    unpack_bin_array, unpack_lb_pair, Bin, BinsSwap, LbPair, MAX_BIN_PER_ARRAY,
+};
+use crate::constants::*;
+
+// bin arrays streamed on each side of the one holding `active_id`
+pub const BIN_ARRAY_WINDOW: i64 = 3;
+// bin arrays passed to a swap (starting at the active bin's array)
+pub const MAX_SWAP_BIN_ARRAYS: usize = 3;
+
+/// Meteora DLMM pool (`LbPair`)
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MeteoraPool {
+    pub address: WrappedPubkey, // lb pair
+    pub token_x_mint: WrappedPubkey,
+    pub token_y_mint: WrappedPubkey,
+    pub reserve_x: WrappedPubkey,
+    pub reserve_y: WrappedPubkey,
+    pub decimals_x: u64,
+    pub decimals_y: u64,
+    pub oracle: WrappedPubkey,
+    pub bin_step: u16,
+    // the pool's active bin when the JSON was written, the bin array window
+    // is centred on it (update accounts can't change once streaming)
+    pub active_id: i32,
+    // to set later
+    #[serde(skip)]
+    pub lb_pair: Option<LbPair>,
+    #[serde(skip)]
+    pub bins: BTreeMap<i32, Bin>, // bins with liquidity
+    #[serde(skip)]
+    pub bin_array_indexes: BTreeSet<i64>, // bin arrays which exist on chain
+    #[serde(skip)]
+    pub token_programs: HashMap<Pubkey, Pubkey>, // see `set_token_programs`
+}
+
+fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+impl MeteoraPool {
+    fn swap_for_y(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> Result<bool, QuoteError> {
+        if *mint_in == self.token_x_mint.0 && *mint_out == self.token_y_mint.0 {
+            Ok(true)
+        } else if *mint_in == self.token_y_mint.0 && *mint_out == self.token_x_mint.0 {
+            Ok(false)
+        } else {
+            Err(QuoteError::InvalidMints { mint_in: *mint_in, mint_out: *mint_out })
+        }
+    }
+
+    /// loaded bin arrays a swap from `active_id` walks through (in order),
+    /// arrays which don't exist have no liquidity and are skipped on chain too
+    fn swap_bin_arrays(&self, active_id: i32, swap_for_y: bool) -> Vec<i64> {
+        let active_index = bin_array_index(active_id);
+        if swap_for_y {
+            self.bin_array_indexes
+                .range(..=active_index)
+                .rev()
+                .take(MAX_SWAP_BIN_ARRAYS)
+                .copied()
+                .collect()
+        } else {
+            self.bin_array_indexes
+                .range(active_index..)
+                .take(MAX_SWAP_BIN_ARRAYS)
+                .copied()
+                .collect()
+        }
+    }
+
+    fn swap(
+        &self,
+        scaled_amount_in: u128,
+        swap_for_y: bool,
+        current_timestamp: i64,
+    ) -> Result<BinsSwap, QuoteError> {
+        let lb_pair = self.lb_pair.as_ref().ok_or(QuoteError::NotLoaded)?;
+        self.swap_bin_range(lb_pair, swap_for_y)
+            .and_then(|bin_range| {
+                swap(
+                    lb_pair,
+                    &self.bins,
+                    bin_range,
+                    scaled_amount_in,
+                    swap_for_y,
+                    current_timestamp,
+                )
+            })
+            // not enough liquidity in the bin arrays -- would fail on chain
+            .ok_or(QuoteError::InsufficientLiquidity)
+    }
+
+    /// bins the swap can reach with the bin arrays it is passed
+    fn swap_bin_range(&self, lb_pair: &LbPair, swap_for_y: bool) -> Option<(i32, i32)> {
+        let last_index = *self.swap_bin_arrays(lb_pair.active_id, swap_for_y).last()? as i32;
+        if swap_for_y {
+            Some((last_index * MAX_BIN_PER_ARRAY, lb_pair.active_id))
+        } else {
+            Some((lb_pair.active_id, (last_index + 1) * MAX_BIN_PER_ARRAY - 1))
+        }
+    }
+}
+
+fn quote(amount_in: u128, total: &BinsSwap, swap_for_y: bool) -> Quote {
+    // the first bin's price is the spot price
+    let price = total.start_price as f64 / (1u128 << 64) as f64;
+    let spot_rate = if swap_for_y { price } else { 1. / price };
+    Quote {
+        amount_out: total.amount_out,
+        fee_paid: total.fee_amount,
+        price_impact_bps: price_impact_bps(
+            amount_in - total.fee_amount,
+            total.amount_out,
+            spot_rate,
+        ),
+        new_reserves: None, // liquidity is spread over bins
+    }
+}
+
+impl PoolOperations for MeteoraPool {
+    fn swap_ix(&self,
+        program: &Program,
+        owner: &Pubkey,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey
+    ) -> Vec<Instruction> {
+        let swap_for_y = self.swap_for_y(mint_in, mint_out).unwrap();
+        let active_id = self.lb_pair.as_ref().map_or(self.active_id, |p| p.active_id);
+
+        let mut swap_ix = program
+            .request()
+            .accounts(tmp_accounts::MeteoraSwap {
+                lb_pair: self.address.0,
+                // no bitmap extension (the DLMM program ID == None)
+                bin_array_bitmap_extension: *METEORA_PROGRAM_ID,
+                reserve_x: self.reserve_x.0,
+                reserve_y: self.reserve_y.0,
+                user_token_in: derive_token_address(owner, mint_in),
+                user_token_out: derive_token_address(owner, mint_out),
+                token_x_mint: self.token_x_mint.0,
+                token_y_mint: self.token_y_mint.0,
+                oracle: self.oracle.0,
+                user: *owner,
+                token_x_program: token_program_of(&self.token_programs, &self.token_x_mint.0),
+                token_y_program: token_program_of(&self.token_programs, &self.token_y_mint.0),
+                event_authority: event_authority_address(&METEORA_PROGRAM_ID),
+                dlmm_program: *METEORA_PROGRAM_ID,
+            })
+            .args(tmp_ix::MeteoraSwap { amount_in: 0, minimum_amount_out: 0 })
+            .instructions()
+            .unwrap();
+
+        // the bin arrays go in as remaining accounts
+        swap_ix[0].accounts.extend(
+            self.swap_bin_arrays(active_id, swap_for_y)
+                .into_iter()
+                .map(|index| {
+                    AccountMeta::new(bin_array_address(&self.address.0, index, &METEORA_PROGRAM_ID), false)
+                }),
+        );
+        swap_ix
+    }
+
+    fn get_quote(
+        &self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let swap_for_y = self.swap_for_y(mint_in, mint_out)?;
+        let total = self.swap(scaled_amount_in, swap_for_y, unix_timestamp())?;
+        Ok(quote(scaled_amount_in, &total, swap_for_y))
+    }
+
+    fn simulate_swap(
+        &mut self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let swap_for_y = self.swap_for_y(mint_in, mint_out)?;
+        let current_timestamp = unix_timestamp();
+        let total = self.swap(scaled_amount_in, swap_for_y, current_timestamp)?;
+        if let Some(lb_pair) = self.lb_pair.as_mut() {
+            apply_swap(lb_pair, &mut self.bins, &total, swap_for_y, current_timestamp);
+        }
+        Ok(quote(scaled_amount_in, &total, swap_for_y))
+    }
+
+    fn get_update_accounts(&self) -> Vec<Pubkey> {
+        let active_index = bin_array_index(self.active_id);
+        let mut accounts = vec![self.address.0];
+        accounts.extend(
+            (active_index - BIN_ARRAY_WINDOW..=active_index + BIN_ARRAY_WINDOW)
+                .map(|index| bin_array_address(&self.address.0, index, &METEORA_PROGRAM_ID)),
+        );
+        accounts
+    }
+
+    fn n_required_update_accounts(&self) -> usize {
+        1 // bin arrays without liquidity don't exist
+    }
+
+    fn can_trade(&self,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey
+    ) -> bool {
+        let lb_pair = match &self.lb_pair {
+            Some(lb_pair) => lb_pair,
+            None => return false,
+        };
+        let swap_for_y = match self.swap_for_y(mint_in, mint_out) {
+            Ok(swap_for_y) => swap_for_y,
+            Err(_) => return false,
+        };
+        lb_pair.status == 0 && self.swap_bin_range(lb_pair, swap_for_y).is_some()
+    }
+
+    fn set_update_accounts(&mut self, accounts: Vec<Option<Account>>, _cluster: Cluster) {
+        self.lb_pair = accounts[0].as_ref().and_then(|a| unpack_lb_pair(&a.data));
+
+        self.bins.clear();
+        self.bin_array_indexes.clear();
+        for account in accounts[1..].iter().flatten() {
+            let bin_array = match unpack_bin_array(&account.data) {
+                Some(bin_array) if bin_array.lb_pair == self.address.0 => bin_array,
+                _ => continue,
+            };
+            let lower_bin_id = bin_array.index as i32 * MAX_BIN_PER_ARRAY;
+            for (i, bin) in bin_array.bins.into_iter().enumerate() {
+                if bin.amount_x > 0 || bin.amount_y > 0 {
+                    self.bins.insert(lower_bin_id + i as i32, bin);
+                }
+            }
+            self.bin_array_indexes.insert(bin_array.index);
+        }
+    }
+
+    fn has_token_program_per_mint(&self) -> bool {
+        true
+    }
+
+    fn set_token_programs(&mut self, token_programs: &HashMap<Pubkey, Pubkey>) {
+        self.token_programs = token_programs.clone();
+    }
+
+    fn get_name(&self) -> String {
+        "Meteora".to_string()
+    }
+
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError> {
+        if *mint == self.token_x_mint.0 {
+            Ok(self.reserve_x.0)
+        } else if *mint == self.token_y_mint.0 {
+            Ok(self.reserve_y.0)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
+    }
+
+    fn mint_2_scale(&self, mint: &Pubkey) -> Result<u64, QuoteError> {
+        if *mint == self.token_x_mint.0 {
+            Ok(self.decimals_x)
+        } else if *mint == self.token_y_mint.0 {
+            Ok(self.decimals_y)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
+    }
+
+    fn get_mints(&self) -> Vec<Pubkey> {
+        let mut mints = vec![self.token_x_mint.0, self.token_y_mint.0];
+        // Sort to ensure consistency across different pools
+        mints.sort();
+        mints
+    }
+}
@@ -4,17 +4,26 @@ pub use orca::*;
 pub mod raydium;
 pub use raydium::*;
 
+pub mod serum;
+pub use serum::*;
+
 pub mod jupiter;
 pub use jupiter::*;
 
-// pub mod meteora;
-// pub use meteora::*;
+pub mod whirlpool;
+pub use whirlpool::*;
+
+pub mod meteora;
+pub use meteora::*;
+
+pub mod phoenix;
+pub use phoenix::*;
 
-// pub mod phoenix;
-// pub use phoenix::*;
+pub mod lifinity;
+pub use lifinity::*;
 
-// pub mod lifinity;
-// pub use lifinity::*;
 
 
 
+pub mod token_2022;
+pub use token_2022::*;
@@ -5,7 +5,8 @@ use serde::{Deserialize, Serialize};
 use solana_sdk::account::Account;
 use crate::serialize::token::{Token, WrappedPubkey, unpack_token_account};
 use crate::serialize::pool::JSONFeeStructure; 
-use crate::pool::PoolOperations;
+use crate::pool::{PoolOperations, Quote};
+use crate::error::QuoteError;
 
 use anchor_client::solana_sdk::pubkey::Pubkey;
 use anchor_client::Cluster;
@@ -19,7 +20,7 @@ use tmp::instruction as tmp_ix;
 use crate::pool_utils::base::CurveType;
 use crate::utils::{str2pubkey, derive_token_address};
 use crate::pool_utils::{
-    orca::{get_pool_quote_with_amounts},
+    orca::{get_pool_quote, get_pool_quote_exact_out},
     fees::Fees,
 };
 use crate::constants::*;
@@ -44,6 +45,48 @@ pub struct OrcaPool {
     pub pool_amounts: HashMap<String, u128>
 }
 
+impl OrcaPool {
+    fn fees(&self) -> Fees {
+        let trader_fee = &self.fee_structure.trader_fee;
+        let owner_fee = &self.fee_structure.owner_fee;
+        Fees {
+            trade_fee_numerator: trader_fee.numerator,
+            trade_fee_denominator: trader_fee.denominator,
+            owner_trade_fee_numerator: owner_fee.numerator,
+            owner_trade_fee_denominator: owner_fee.denominator,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        }
+    }
+
+    fn swap_curve_type(&self) -> Result<CurveType, QuoteError> {
+        match self.curve_type {
+            0 => Ok(CurveType::ConstantProduct),
+            2 => Ok(CurveType::Stable),
+            _ => Err(QuoteError::Untradable(format!(
+                "invalid curve type: {:?}", self.curve_type
+            ))),
+        }
+    }
+
+    /// (reserve_in, reserve_out)
+    fn reserves(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> Result<(u128, u128), QuoteError> {
+        if !self.tokens.contains_key(&mint_in.to_string()) 
+            || !self.tokens.contains_key(&mint_out.to_string()) {
+            return Err(QuoteError::InvalidMints { mint_in: *mint_in, mint_out: *mint_out });
+        }
+        match (
+            self.pool_amounts.get(&mint_in.to_string()),
+            self.pool_amounts.get(&mint_out.to_string()),
+        ) {
+            (Some(reserve_in), Some(reserve_out)) => Ok((*reserve_in, *reserve_out)),
+            _ => Err(QuoteError::NotLoaded),
+        }
+    }
+}
+
 impl PoolOperations for OrcaPool {
     fn swap_ix(&self, 
         program: &Program,
@@ -63,8 +106,8 @@ impl PoolOperations for OrcaPool {
             &ORCA_PROGRAM_ID 
         );
 
-        let pool_src = self.mint_2_addr(mint_in);
-        let pool_dst = self.mint_2_addr(mint_out);
+        let pool_src = self.mint_2_addr(mint_in).unwrap();
+        let pool_dst = self.mint_2_addr(mint_out).unwrap();
 
         let swap_ix = program
             .request()
@@ -89,49 +132,73 @@ impl PoolOperations for OrcaPool {
         swap_ix
     }
 
-    fn get_quote_with_amounts_scaled(
+    fn get_quote(
         &self, 
         scaled_amount_in: u128, 
         mint_in: &Pubkey,
         mint_out: &Pubkey,
-    ) -> u128 {
-        
-        let pool_src_amount = self.pool_amounts.get(&mint_in.to_string()).unwrap();
-        let pool_dst_amount = self.pool_amounts.get(&mint_out.to_string()).unwrap();
-
-        // compute fees 
-        let trader_fee = &self.fee_structure.trader_fee;
-        let owner_fee = &self.fee_structure.owner_fee;
-        let fees = Fees {
-            trade_fee_numerator: trader_fee.numerator,
-            trade_fee_denominator: trader_fee.denominator,
-            owner_trade_fee_numerator: owner_fee.numerator,
-            owner_trade_fee_denominator: owner_fee.denominator,
-            owner_withdraw_fee_numerator: 0,
-            owner_withdraw_fee_denominator: 0,
-            host_fee_numerator: 0,
-            host_fee_denominator: 0,
-        };
-        let ctype = if self.curve_type == 0 { 
-            CurveType::ConstantProduct 
-        } else if self.curve_type == 2 {
-            CurveType::Stable
-        } else { 
-            panic!("invalid self curve type: {:?}", self.curve_type);
-        };
+    ) -> Result<Quote, QuoteError> {
+        let (pool_src_amount, pool_dst_amount) = self.reserves(mint_in, mint_out)?;
 
         // get quote -- works for either constant product or stable swap 
-        
-        
-        get_pool_quote_with_amounts(
+        get_pool_quote(
             scaled_amount_in,
-            ctype,
+            self.swap_curve_type()?,
             self.amp, 
-            &fees, 
-            *pool_src_amount, 
-            *pool_dst_amount, 
-            None,
-        ).unwrap()
+            &self.fees(), 
+            pool_src_amount, 
+            pool_dst_amount, 
+        )
+    }
+
+    fn simulate_swap(
+        &mut self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let quote = self.get_quote(scaled_amount_in, mint_in, mint_out)?;
+        if let Some((pool_src_amount, pool_dst_amount)) = quote.new_reserves {
+            self.pool_amounts.insert(mint_in.to_string(), pool_src_amount);
+            self.pool_amounts.insert(mint_out.to_string(), pool_dst_amount);
+        }
+        Ok(quote)
+    }
+
+    fn get_quote_exact_out(
+        &self,
+        amount_out: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Option<u128> {
+        let (pool_src_amount, pool_dst_amount) = self.reserves(mint_in, mint_out).ok()?;
+        get_pool_quote_exact_out(
+            amount_out,
+            self.swap_curve_type().ok()?,
+            self.amp,
+            &self.fees(),
+            pool_src_amount,
+            pool_dst_amount,
+        )
+    }
+
+    fn get_constant_product_reserves(
+        &self,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Option<(u128, u128, f64)> {
+        if self.curve_type != 0 { return None; }
+        let pool_src_amount = self.pool_amounts.get(&mint_in.to_string())?;
+        let pool_dst_amount = self.pool_amounts.get(&mint_out.to_string())?;
+
+        let trader_fee = &self.fee_structure.trader_fee;
+        let owner_fee = &self.fee_structure.owner_fee;
+        let fee = [trader_fee, owner_fee]
+            .iter()
+            .filter(|f| f.numerator > 0)
+            .map(|f| f.numerator as f64 / f.denominator as f64)
+            .sum();
+        Some((*pool_src_amount, *pool_dst_amount, fee))
     }
 
     fn get_update_accounts(&self) -> Vec<Pubkey> {
@@ -139,7 +206,7 @@ impl PoolOperations for OrcaPool {
         let accounts = self
             .get_mints()
             .iter()
-            .map(|mint| self.mint_2_addr(mint))
+            .filter_map(|mint| self.mint_2_addr(mint).ok())
             .collect();        
         accounts 
     }
@@ -178,16 +245,16 @@ impl PoolOperations for OrcaPool {
         "Orca".to_string()
     }
 
-    fn mint_2_addr(&self, mint: &Pubkey) -> Pubkey {
-        let token = self.tokens.get(&mint.to_string()).unwrap();
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError> {
+        let token = self.tokens.get(&mint.to_string()).ok_or(QuoteError::UnknownMint(*mint))?;
         
-        token.addr.0
+        Ok(token.addr.0)
     }
 
-    fn mint_2_scale(&self, mint: &Pubkey) -> u64 {
-        let token = self.tokens.get(&mint.to_string()).unwrap();
+    fn mint_2_scale(&self, mint: &Pubkey) -> Result<u64, QuoteError> {
+        let token = self.tokens.get(&mint.to_string()).ok_or(QuoteError::UnknownMint(*mint))?;
 
-        token.scale
+        Ok(token.scale)
     }
 
     fn get_mints(&self) -> Vec<Pubkey> {
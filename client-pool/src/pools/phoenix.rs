@@ -0,0 +1,199 @@
+use std::fmt::Debug;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use solana_sdk::account::Account;
+use crate::serialize::token::WrappedPubkey;
+use crate::pool::{price_impact_bps, PoolOperations, Quote};
+use crate::error::QuoteError;
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::Cluster;
+use anchor_client::Program;
+
+use solana_sdk::instruction::Instruction;
+
+use tmp::accounts as tmp_accounts;
+use tmp::instruction as tmp_ix;
+
+use crate::utils::derive_token_address;
+use crate::pool_utils::phoenix::{
+    log_authority_address, unpack_market, Fill, PhoenixMarket, Side, MARKET_STATUS_ACTIVE,
+};
+use crate::constants::*;
+
+/// Phoenix order-book market, swapped through with immediate-or-cancel orders
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PhoenixPool {
+    pub address: WrappedPubkey, // market
+    pub base_mint: WrappedPubkey,
+    pub quote_mint: WrappedPubkey,
+    pub base_vault: WrappedPubkey,
+    pub quote_vault: WrappedPubkey,
+    pub base_decimals: u64,
+    pub quote_decimals: u64,
+    // to set later
+    #[serde(skip)]
+    pub market: Option<PhoenixMarket>,
+}
+
+fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+impl PhoenixPool {
+    fn side(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> Result<Side, QuoteError> {
+        if *mint_in == self.quote_mint.0 && *mint_out == self.base_mint.0 {
+            Ok(Side::Bid)
+        } else if *mint_in == self.base_mint.0 && *mint_out == self.quote_mint.0 {
+            Ok(Side::Ask)
+        } else {
+            Err(QuoteError::InvalidMints { mint_in: *mint_in, mint_out: *mint_out })
+        }
+    }
+
+    fn fill(
+        &self,
+        scaled_amount_in: u128,
+        side: Side,
+        current_timestamp: i64,
+    ) -> Result<(Quote, Fill), QuoteError> {
+        let market = self.market.as_ref().ok_or(QuoteError::NotLoaded)?;
+        let amount_in = scaled_amount_in.min(u64::MAX as u128) as u64;
+        let fill = market
+            .fill(amount_in, side, current_timestamp)
+            .ok_or_else(|| QuoteError::Untradable("market has no lot sizes".to_string()))?;
+        let spot_rate = market
+            .spot_rate(side, current_timestamp)
+            .ok_or(QuoteError::InsufficientLiquidity)?;
+        let quote = Quote {
+            amount_out: fill.amount_out,
+            fee_paid: fill.fee,
+            price_impact_bps: price_impact_bps(
+                fill.amount_in_matched,
+                fill.amount_out_matched,
+                spot_rate,
+            ),
+            new_reserves: None, // an order book has no reserves
+        };
+        Ok((quote, fill))
+    }
+}
+
+impl PoolOperations for PhoenixPool {
+    fn swap_ix(&self,
+        program: &Program,
+        owner: &Pubkey,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey
+    ) -> Vec<Instruction> {
+        let side = self.side(mint_in, mint_out).unwrap();
+
+        program
+            .request()
+            .accounts(tmp_accounts::PhoenixSwap {
+                phoenix_program: *PHOENIX_PROGRAM_ID,
+                log_authority: log_authority_address(&PHOENIX_PROGRAM_ID),
+                market: self.address.0,
+                trader: *owner,
+                base_account: derive_token_address(owner, &self.base_mint.0),
+                quote_account: derive_token_address(owner, &self.quote_mint.0),
+                base_vault: self.base_vault.0,
+                quote_vault: self.quote_vault.0,
+                token_program: *TOKEN_PROGRAM_ID,
+            })
+            .args(tmp_ix::PhoenixSwap {
+                amount_in: 0,
+                minimum_amount_out: 0,
+                is_bid: side == Side::Bid,
+            })
+            .instructions()
+            .unwrap()
+    }
+
+    fn get_quote(
+        &self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let side = self.side(mint_in, mint_out)?;
+        self.fill(scaled_amount_in, side, unix_timestamp()).map(|(quote, _)| quote)
+    }
+
+    fn simulate_swap(
+        &mut self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let side = self.side(mint_in, mint_out)?;
+        let current_timestamp = unix_timestamp();
+        let (quote, fill) = self.fill(scaled_amount_in, side, current_timestamp)?;
+        if let Some(market) = self.market.as_mut() {
+            market.apply_fill(&fill, side, current_timestamp);
+        }
+        Ok(quote)
+    }
+
+    fn get_update_accounts(&self) -> Vec<Pubkey> {
+        vec![self.address.0]
+    }
+
+    fn can_trade(&self,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey
+    ) -> bool {
+        let market = match &self.market {
+            Some(market) => market,
+            None => return false,
+        };
+        let side = match self.side(mint_in, mint_out) {
+            Ok(side) => side,
+            Err(_) => return false,
+        };
+        // is there a resting order we can take?
+        market.header.status == MARKET_STATUS_ACTIVE
+            && market.book(side, unix_timestamp()).next().is_some()
+    }
+
+    fn set_update_accounts(&mut self, accounts: Vec<Option<Account>>, _cluster: Cluster) {
+        self.market = accounts[0]
+            .as_ref()
+            .and_then(|a| unpack_market(&a.data))
+            .filter(|market| market.header.base_mint == self.base_mint.0);
+    }
+
+    fn get_name(&self) -> String {
+        "Phoenix".to_string()
+    }
+
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError> {
+        if *mint == self.base_mint.0 {
+            Ok(self.base_vault.0)
+        } else if *mint == self.quote_mint.0 {
+            Ok(self.quote_vault.0)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
+    }
+
+    fn mint_2_scale(&self, mint: &Pubkey) -> Result<u64, QuoteError> {
+        if *mint == self.base_mint.0 {
+            Ok(self.base_decimals)
+        } else if *mint == self.quote_mint.0 {
+            Ok(self.quote_decimals)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
+    }
+
+    fn get_mints(&self) -> Vec<Pubkey> {
+        let mut mints = vec![self.base_mint.0, self.quote_mint.0];
+        // Sort to ensure consistency across different pools
+        mints.sort();
+        mints
+    }
+}
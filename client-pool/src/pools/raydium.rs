@@ -1,12 +1,12 @@
-use std::collections::HashMap;
 use std::fmt::Debug;
 use serde::{Deserialize, Serialize};
 use solana_sdk::account::Account;
-use crate::serialize::token::{Token, WrappedPubkey, unpack_token_account};
-use crate::serialize::pool::JSONFeeStructure;
-use crate::pool::PoolOperations;
+use crate::serialize::token::{WrappedPubkey, unpack_token_account};
+use crate::pool::{PoolOperations, Quote};
+use crate::error::QuoteError;
 
 use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::solana_sdk::system_program;
 use anchor_client::Cluster;
 use anchor_client::Program;
 
@@ -15,162 +15,253 @@ use solana_sdk::instruction::Instruction;
 use tmp::accounts as tmp_accounts;
 use tmp::instruction as tmp_ix;
 
-use crate::pool_utils::base::CurveType;
-use crate::utils::{str2pubkey, derive_token_address};
-use crate::pool_utils::{
-    raydium::{get_pool_quote_with_amounts},
-    fees::Fees,
+use crate::utils::derive_token_address;
+use crate::pool_utils::raydium::{
+    get_amount_in, swap, total_without_take_pnl, unpack_amm_info, unpack_open_orders_totals,
 };
 use crate::constants::*;
 
+/// Raydium AMM v4 pool (+ the serum/openbook market it is attached to)
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct RaydiumPool {
-    pub address: WrappedPubkey,
-    pub nonce: u64,
+    pub address: WrappedPubkey, // amm id
     pub authority: WrappedPubkey,
-    pub lp_token_mint: WrappedPubkey,
-    pub fee_account: WrappedPubkey,
-    pub token_ids: Vec<String>,
-    pub tokens: HashMap<String, Token>,
-    pub fee_structure: JSONFeeStructure,
-    #[serde(default)]
-    pub pool_amounts: HashMap<String, u128>,
+    pub open_orders: WrappedPubkey,
+    pub target_orders: WrappedPubkey,
+    pub lp_mint: WrappedPubkey,
+    pub coin_mint: WrappedPubkey,
+    pub pc_mint: WrappedPubkey,
+    pub coin_decimals: u64,
+    pub pc_decimals: u64,
+    pub coin_vault: WrappedPubkey,
+    pub pc_vault: WrappedPubkey,
+    pub market_program: WrappedPubkey,
+    pub market: WrappedPubkey,
+    pub market_bids: WrappedPubkey,
+    pub market_asks: WrappedPubkey,
+    pub market_event_queue: WrappedPubkey,
+    pub market_coin_vault: WrappedPubkey,
+    pub market_pc_vault: WrappedPubkey,
+    pub market_vault_signer: WrappedPubkey,
+    // swap fee -- refreshed from the amm account on every update
+    pub fee_numerator: u64,
+    pub fee_denominator: u64,
+    // to set later -- PnL-adjusted reserves
+    #[serde(skip)]
+    pub coin_reserve: u128,
+    #[serde(skip)]
+    pub pc_reserve: u128,
+}
+
+/// `owner`'s raydium swap state (a PDA of the arb program) -- every raydium
+/// swap writes it, so it has to exist before the first one
+pub fn raydium_swap_state(program_id: &Pubkey, owner: &Pubkey) -> Pubkey {
+    let (swap_state, _) =
+        Pubkey::find_program_address(&[b"raydium_swap_state", owner.as_ref()], program_id);
+    swap_state
+}
+
+/// creates `owner`'s raydium swap state (paid by `owner`)
+pub fn initialize_raydium_swap_state_ix(program: &Program, owner: &Pubkey) -> Vec<Instruction> {
+    program
+        .request()
+        .accounts(tmp_accounts::InitializeRaydiumSwapState {
+            swap_state: raydium_swap_state(&program.id(), owner),
+            authority: *owner,
+            system_program: system_program::id(),
+        })
+        .args(tmp_ix::InitializeRaydiumSwapState {})
+        .instructions()
+        .unwrap()
+}
+
+impl RaydiumPool {
+    /// (reserve_in, reserve_out) for a swap `mint_in` -> `mint_out`
+    fn reserves(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> Result<(u128, u128), QuoteError> {
+        if *mint_in == self.coin_mint.0 && *mint_out == self.pc_mint.0 {
+            Ok((self.coin_reserve, self.pc_reserve))
+        } else if *mint_in == self.pc_mint.0 && *mint_out == self.coin_mint.0 {
+            Ok((self.pc_reserve, self.coin_reserve))
+        } else {
+            Err(QuoteError::InvalidMints { mint_in: *mint_in, mint_out: *mint_out })
+        }
+    }
 }
 
 impl PoolOperations for RaydiumPool {
-    fn swap_ix(&self, 
+    fn swap_ix(&self,
         program: &Program,
         owner: &Pubkey,
-        mint_in: &Pubkey, 
+        mint_in: &Pubkey,
         mint_out: &Pubkey
     ) -> Vec<Instruction> {
-        let (swap_state, _) = Pubkey::find_program_address(
-            &[b"swap_state"], 
-            &program.id()
-        );
-        let user_src = derive_token_address(owner, mint_in);
-        let user_dst = derive_token_address(owner, mint_out); 
-
-        let (authority_pda, _) = Pubkey::find_program_address(
-            &[&self.address.to_bytes()],
-            &RAYDIUM_PROGRAM_ID 
-        );
-
-        let pool_src = self.mint_2_addr(mint_in);
-        let pool_dst = self.mint_2_addr(mint_out);
+        let swap_state = raydium_swap_state(&program.id(), owner);
+        let user_source_token = derive_token_address(owner, mint_in);
+        let user_destination_token = derive_token_address(owner, mint_out);
 
         let swap_ix = program
             .request()
             .accounts(tmp_accounts::RaydiumSwap {
-                token_swap: self.address.0, 
-                authority: authority_pda,
-                user_transfer_authority: *owner,
-                user_src,
-                pool_src,
-                user_dst,
-                pool_dst,
-                lp_mint: self.lp_token_mint.0,
-                fee_account: self.fee_account.0,
+                amm_program: *RAYDIUM_PROGRAM_ID,
+                amm_id: self.address.0,
+                amm_authority: self.authority.0,
+                amm_open_orders: self.open_orders.0,
+                amm_target_orders: self.target_orders.0,
+                pool_coin_token_account: self.coin_vault.0,
+                pool_pc_token_account: self.pc_vault.0,
+                serum_program_id: self.market_program.0,
+                serum_market: self.market.0,
+                serum_bids: self.market_bids.0,
+                serum_asks: self.market_asks.0,
+                serum_event_queue: self.market_event_queue.0,
+                serum_coin_vault_account: self.market_coin_vault.0,
+                serum_pc_vault_account: self.market_pc_vault.0,
+                serum_vault_signer: self.market_vault_signer.0,
+                user_source_token,
+                user_destination_token,
+                user_authority: *owner,
                 token_program: *TOKEN_PROGRAM_ID,
-                swap_program: *RAYDIUM_PROGRAM_ID,
                 swap_state,
             })
-            .args(tmp_ix::RaydiumSwap { })
+            .args(tmp_ix::RaydiumSwap { amount_in: 0, minimum_amount_out: 0 })
             .instructions()
             .unwrap();
 
         swap_ix
     }
 
-    fn get_quote_with_amounts_scaled(
-        &self, 
-        scaled_amount_in: u128, 
+    fn get_quote(
+        &self,
+        scaled_amount_in: u128,
         mint_in: &Pubkey,
         mint_out: &Pubkey,
-    ) -> u128 {
-        let pool_src_amount = self.pool_amounts.get(&mint_in.to_string()).unwrap();
-        let pool_dst_amount = self.pool_amounts.get(&mint_out.to_string()).unwrap();
-
-        // Compute fees
-        let trader_fee = &self.fee_structure.trader_fee;
-        let fees = Fees {
-            trade_fee_numerator: trader_fee.numerator,
-            trade_fee_denominator: trader_fee.denominator,
-            owner_trade_fee_numerator: 0,
-            owner_trade_fee_denominator: 0,
-            owner_withdraw_fee_numerator: 0,
-            owner_withdraw_fee_denominator: 0,
-            host_fee_numerator: 0,
-            host_fee_denominator: 0,
-        };
-
-        // Get quote using Raydium's logic
-        get_pool_quote_with_amounts(
+    ) -> Result<Quote, QuoteError> {
+        let (reserve_in, reserve_out) = self.reserves(mint_in, mint_out)?;
+        let result = swap(
             scaled_amount_in,
-            ctype,
-            self.amp, 
-            &fees, 
-            *pool_src_amount, 
-            *pool_dst_amount, 
-            None,
-        ).unwrap()
+            reserve_in,
+            reserve_out,
+            self.fee_numerator,
+            self.fee_denominator,
+        ).ok_or(QuoteError::InsufficientLiquidity)?;
+        Ok(Quote::from_swap_result(&result, reserve_out as f64 / reserve_in as f64))
+    }
+
+    fn simulate_swap(
+        &mut self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let quote = self.get_quote(scaled_amount_in, mint_in, mint_out)?;
+        if let Some((reserve_in, reserve_out)) = quote.new_reserves {
+            if *mint_in == self.coin_mint.0 {
+                self.coin_reserve = reserve_in;
+                self.pc_reserve = reserve_out;
+            } else {
+                self.pc_reserve = reserve_in;
+                self.coin_reserve = reserve_out;
+            }
+        }
+        Ok(quote)
+    }
+
+    fn get_quote_exact_out(
+        &self,
+        amount_out: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Option<u128> {
+        let (reserve_in, reserve_out) = self.reserves(mint_in, mint_out).ok()?;
+        get_amount_in(
+            amount_out,
+            reserve_in,
+            reserve_out,
+            self.fee_numerator,
+            self.fee_denominator,
+        )
+    }
+
+    fn get_constant_product_reserves(
+        &self,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Option<(u128, u128, f64)> {
+        let (reserve_in, reserve_out) = self.reserves(mint_in, mint_out).ok()?;
+        if self.fee_denominator == 0 { return None; }
+        let fee = self.fee_numerator as f64 / self.fee_denominator as f64;
+        Some((reserve_in, reserve_out, fee))
     }
 
     fn get_update_accounts(&self) -> Vec<Pubkey> {
-        self.get_mints()
-            .iter()
-            .map(|mint| self.mint_2_addr(mint))
-            .collect()        
+        vec![
+            self.address.0, // fees + need_take_pnl
+            self.coin_vault.0,
+            self.pc_vault.0,
+            self.open_orders.0,
+        ]
     }
 
-    fn can_trade(&self, 
+    fn can_trade(&self,
         _mint_in: &Pubkey,
         _mint_out: &Pubkey
     ) -> bool {
-        for amount in self.pool_amounts.values() {
-            if *amount == 0 { return false; }
-        }
-        true
+        self.coin_reserve > 0 && self.pc_reserve > 0
     }
 
-    fn set_update_accounts(&mut self, accounts: Vec<Option<Account>>, _cluster: Cluster) { 
-        let ids: Vec<String> = self
-            .get_mints()
-            .iter()
-            .map(|mint| mint.to_string())
-            .collect();
-        
-        let acc_data0 = &accounts[0].as_ref().unwrap().data;
-        let acc_data1 = &accounts[1].as_ref().unwrap().data;
-
-        let amount0 = unpack_token_account(acc_data0).amount as u128;
-        let amount1 = unpack_token_account(acc_data1).amount as u128;
-
-        self.pool_amounts.insert(ids[0].clone(), amount0);
-        self.pool_amounts.insert(ids[1].clone(), amount1);
+    fn set_update_accounts(&mut self, accounts: Vec<Option<Account>>, _cluster: Cluster) {
+        let data = |i: usize| accounts.get(i).and_then(|a| a.as_ref()).map(|a| a.data.as_slice());
+        let (amm, coin_vault_data, pc_vault_data, open_orders_data) =
+            match (data(0).and_then(unpack_amm_info), data(1), data(2), data(3)) {
+                (Some(amm), Some(coin_vault), Some(pc_vault), Some(open_orders)) => {
+                    (amm, coin_vault, pc_vault, open_orders)
+                }
+                _ => {
+                    // closed / uninitialized amm or a missing vault -- nothing to trade
+                    self.coin_reserve = 0;
+                    self.pc_reserve = 0;
+                    return;
+                }
+            };
+        let (oo_coin_total, oo_pc_total) = unpack_open_orders_totals(open_orders_data)
+            .unwrap_or((0, 0));
+        let coin_amount = unpack_token_account(coin_vault_data).amount;
+        let pc_amount = unpack_token_account(pc_vault_data).amount;
+
+        self.fee_numerator = amm.swap_fee_numerator;
+        self.fee_denominator = amm.swap_fee_denominator;
+        self.coin_reserve = total_without_take_pnl(coin_amount, oo_coin_total, amm.need_take_pnl_coin);
+        self.pc_reserve = total_without_take_pnl(pc_amount, oo_pc_total, amm.need_take_pnl_pc);
     }
 
     fn get_name(&self) -> String {
         "Raydium".to_string()
     }
 
-    fn mint_2_addr(&self, mint: &Pubkey) -> Pubkey {
-        let token = self.tokens.get(&mint.to_string()).unwrap();
-        token.addr.0
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError> {
+        if *mint == self.coin_mint.0 {
+            Ok(self.coin_vault.0)
+        } else if *mint == self.pc_mint.0 {
+            Ok(self.pc_vault.0)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
     }
 
-    fn mint_2_scale(&self, mint: &Pubkey) -> u64 {
-        let token = self.tokens.get(&mint.to_string()).unwrap();
-        token.scale
+    fn mint_2_scale(&self, mint: &Pubkey) -> Result<u64, QuoteError> {
+        if *mint == self.coin_mint.0 {
+            Ok(self.coin_decimals)
+        } else if *mint == self.pc_mint.0 {
+            Ok(self.pc_decimals)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
     }
 
     fn get_mints(&self) -> Vec<Pubkey> {
-        let mut mints: Vec<Pubkey> = self.token_ids
-            .iter()
-            .map(|k| str2pubkey(k))
-            .collect();
-        // Sort to ensure consistency across different pools 
+        let mut mints = vec![self.coin_mint.0, self.pc_mint.0];
+        // Sort to ensure consistency across different pools
         mints.sort();
         mints
     }
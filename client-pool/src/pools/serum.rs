@@ -1,10 +1,8 @@
-use core::panic;
-
-use std::collections::HashMap;
 use std::fmt::Debug;
 use serde;
 use serde::{Deserialize, Serialize};
-use crate::pool::PoolOperations;
+use crate::pool::{price_impact_bps, PoolOperations, Quote};
+use crate::error::QuoteError;
 use crate::serialize::token::{WrappedPubkey};
 
 use crate::utils::{derive_token_address}; 
@@ -27,16 +25,16 @@ use solana_sdk::account::Account;
 use solana_sdk::account_info::AccountInfo;
 use crate::constants::*;
 use crate::pool_utils::serum::*;
+use crate::open_orders::OpenOrdersRegistry;
 
 use anchor_spl::dex::serum_dex::{
     matching::Side,
 };
 
-use std::str::FromStr;
 use tmp::accounts as tmp_accounts;
 use tmp::instruction as tmp_instructions;
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SerumPool {
     pub own_address: WrappedPubkey,
@@ -56,7 +54,7 @@ pub struct SerumPool {
     #[serde(skip)]
     pub accounts: Option<Vec<Option<Account>>>,
     #[serde(skip)]
-    pub open_orders: Option<HashMap<String, String>>
+    pub open_orders: Option<Pubkey>, // the owner's, from the registry -- see `setup_open_orders`
 }
 
 fn account_info<'a>(pk: &'a Pubkey, account: &'a mut Account) -> AccountInfo<'a> {
@@ -75,6 +73,10 @@ fn account_info<'a>(pk: &'a Pubkey, account: &'a mut Account) -> AccountInfo<'a>
 struct Iteration { 
     amount_in: u64, 
     amount_out: u64,
+    fee: u64, // in the input token
+    // what was matched against the book before fees
+    amount_in_matched: u64,
+    amount_out_matched: u64,
 }
 
 // bid: quote -> base 
@@ -110,6 +112,7 @@ fn bid_iteration(
 
         pc_qty_remaining -= trade_qty * trade_price.get();
         iteration.amount_out += trade_qty * base_lot_size; 
+        iteration.amount_out_matched += trade_qty * base_lot_size;
 
         best_offer_ref.set_quantity(best_offer_ref.quantity() - trade_qty);
 
@@ -126,6 +129,8 @@ fn bid_iteration(
     let native_pc_qty_remaining =
         start_amount_in - native_accum_fill_price - native_taker_fee;
     iteration.amount_in = native_pc_qty_remaining; 
+    iteration.fee += native_taker_fee;
+    iteration.amount_in_matched += native_accum_fill_price;
 
     done
 }
@@ -181,9 +186,203 @@ fn ask_iteration(
     iteration.amount_out += net_taker_pc_qty;
     iteration.amount_in = unfilled_qty * coin_lot_size; 
 
+    // the fee is taken in quote, priced in base at the fill's price
+    let native_filled_qty = (max_qty / coin_lot_size - unfilled_qty) * coin_lot_size;
+    if native_taker_pc_qty > 0 {
+        iteration.fee += (native_taker_fee as u128 * native_filled_qty as u128
+            / native_taker_pc_qty as u128) as u64;
+    }
+    iteration.amount_in_matched += native_filled_qty;
+    iteration.amount_out_matched += native_taker_pc_qty;
+
     done
 }
 
+// bid backwards: the quote it takes to buy `amount_out` base 
+// (None if the asks can't fill it)
+fn bid_exact_out(
+    amount_out: u64,
+    fee_tier: &FeeTier, 
+    ob: &mut OrderBookState,
+) -> Option<u64> {
+    let quote_lot_size = ob.market_state.pc_lot_size;
+    let base_lot_size = ob.market_state.coin_lot_size;
+
+    let mut base_qty_remaining = match amount_out % base_lot_size {
+        0 => amount_out / base_lot_size,
+        _ => amount_out / base_lot_size + 1,
+    };
+    let mut amount_in = 0;
+    while base_qty_remaining > 0 {
+        let best_ask = ob.asks.find_min()?; 
+        let best_offer_ref = ob.asks.get_mut(best_ask).unwrap().as_leaf_mut().unwrap();
+
+        let trade_qty = best_offer_ref.quantity().min(base_qty_remaining);
+        base_qty_remaining -= trade_qty;
+
+        // each level's fill pays its own (rounded up) fee
+        let native_fill_price = trade_qty * best_offer_ref.price().get() * quote_lot_size;
+        amount_in += native_fill_price + fee_tier.taker_fee(native_fill_price);
+
+        let best_offer_id = best_offer_ref.order_id();
+        ob.asks.remove_by_key(best_offer_id).unwrap();
+    }
+    Some(amount_in)
+}
+
+// ask backwards: the base it takes to get `amount_out` quote after fees
+// (None if the bids can't fill it)
+fn ask_exact_out(
+    amount_out: u64,
+    fee_tier: &FeeTier, 
+    ob: &mut OrderBookState,
+) -> Option<u64> {
+    let pc_lot_size = ob.market_state.pc_lot_size;
+    let coin_lot_size = ob.market_state.coin_lot_size;
+
+    let mut pc_qty_remaining = amount_out;
+    let mut base_qty = 0;
+    while pc_qty_remaining > 0 {
+        let best_bid = ob.bids.find_max()?;
+        let best_bid_ref = ob.bids.get_mut(best_bid).unwrap().as_leaf_mut().unwrap();
+
+        // the level's fill, fee included, which nets what's left
+        let mut native_pc_qty = pc_qty_remaining + fee_tier.taker_fee(pc_qty_remaining);
+        while native_pc_qty - fee_tier.taker_fee(native_pc_qty) < pc_qty_remaining {
+            native_pc_qty += 1;
+        }
+        let native_pc_per_lot = best_bid_ref.price().get() * pc_lot_size;
+        let trade_qty = match native_pc_qty % native_pc_per_lot {
+            0 => native_pc_qty / native_pc_per_lot,
+            _ => native_pc_qty / native_pc_per_lot + 1,
+        }
+        .min(best_bid_ref.quantity());
+
+        let native_taker_pc_qty = trade_qty * native_pc_per_lot;
+        let net_taker_pc_qty = native_taker_pc_qty - fee_tier.taker_fee(native_taker_pc_qty);
+        pc_qty_remaining = pc_qty_remaining.saturating_sub(net_taker_pc_qty);
+        base_qty += trade_qty;
+
+        let best_bid_id = best_bid_ref.order_id();
+        ob.bids.remove_by_key(best_bid_id).unwrap();
+    }
+    Some(base_qty * coin_lot_size)
+}
+
+fn invalid_market<E: Debug>(e: E) -> QuoteError {
+    QuoteError::Untradable(format!("invalid market: {:?}", e))
+}
+
+// quote atoms per base atom of the best order a taker on `side` matches 
+fn best_price(ob: &OrderBookState, side: Side) -> Option<f64> {
+    let price = match side {
+        Side::Bid => ob.asks.get(ob.asks.find_min()?)?.as_leaf()?.price(),
+        Side::Ask => ob.bids.get(ob.bids.find_max()?)?.as_leaf()?.price(),
+    };
+    let native_price = price.get() as f64 * ob.market_state.pc_lot_size as f64;
+    Some(native_price / ob.market_state.coin_lot_size as f64)
+}
+
+impl SerumPool {
+    fn side(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> Result<Side, QuoteError> {
+        if *mint_in == self.quote_mint.0 && *mint_out == self.base_mint.0 {
+            Ok(Side::Bid)
+        } else if *mint_in == self.base_mint.0 && *mint_out == self.quote_mint.0 {
+            Ok(Side::Ask)
+        } else {
+            Err(QuoteError::InvalidMints { mint_in: *mint_in, mint_out: *mint_out })
+        }
+    }
+
+    /// runs `f` on a copy of the order book (so it can be matched against)
+    fn simulate<T>(&self, f: impl FnOnce(&mut OrderBookState) -> T) -> Result<T, QuoteError> {
+        // clone accounts for simulation (improve later?)
+        let mut accounts = self.accounts.clone().ok_or(QuoteError::NotLoaded)?;
+        self.with_order_book(&mut accounts, f)
+    }
+
+    /// runs `f` on the order book in `accounts`, whatever it matches is
+    /// written back to them
+    fn with_order_book<T>(
+        &self,
+        accounts: &mut [Option<Account>],
+        f: impl FnOnce(&mut OrderBookState) -> T,
+    ) -> Result<T, QuoteError> {
+        let (market_acc, bid_acc, ask_acc) = match accounts {
+            [Some(market_acc), Some(bid_acc), Some(ask_acc), ..] => (market_acc, bid_acc, ask_acc),
+            _ => return Err(QuoteError::NotLoaded),
+        };
+
+        let market_acc_info = &account_info(&self.own_address.0, market_acc);
+        let bids_acc = &account_info(&self.bids.0, bid_acc);
+        let asks_acc = &account_info(&self.asks.0, ask_acc);
+
+        let mut market = Market::load(
+            market_acc_info, 
+            &SERUM_PROGRAM_ID
+        ).map_err(invalid_market)?;
+        let mut bids = market.load_bids_mut(bids_acc).map_err(invalid_market)?;
+        let mut asks = market.load_asks_mut(asks_acc).map_err(invalid_market)?;
+
+        let mut ob = OrderBookState {
+            bids: bids.deref_mut(),
+            asks: asks.deref_mut(),
+            market_state: market.deref_mut(),
+        };
+        Ok(f(&mut ob))
+    }
+
+    /// quote of an IOC order matched against the order book in `accounts`
+    fn match_orders(
+        &self,
+        accounts: &mut [Option<Account>],
+        amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let side = self.side(mint_in, mint_out)?;
+        let market_pk = self.own_address.0; 
+        let fee_tier = FeeTier::from_srm_and_msrm_balances(&market_pk, 0, 0);
+        let mut iteration = Iteration {
+            amount_in: amount_in.min(u64::MAX as u128) as u64,
+            amount_out: 0,
+            fee: 0,
+            amount_in_matched: 0,
+            amount_out_matched: 0,
+        };
+
+        let best_price = self.with_order_book(accounts, |ob| {
+            let best_price = best_price(ob, side);
+            loop {
+                let done = match side {
+                    // bid: quote -> base
+                    Side::Bid => bid_iteration(&mut iteration, &fee_tier, ob),
+                    // ask: base -> quote
+                    Side::Ask => ask_iteration(&mut iteration, &fee_tier, ob),
+                };
+                if done { break; }
+            }
+            best_price
+        })?;
+        let best_price = best_price.ok_or(QuoteError::InsufficientLiquidity)?;
+        let spot_rate = match side {
+            Side::Bid => 1. / best_price,
+            Side::Ask => best_price,
+        };
+
+        Ok(Quote {
+            amount_out: iteration.amount_out as u128,
+            fee_paid: iteration.fee as u128,
+            price_impact_bps: price_impact_bps(
+                iteration.amount_in_matched as u128,
+                iteration.amount_out_matched as u128,
+                spot_rate,
+            ),
+            new_reserves: None, // an order book has no reserves
+        })
+    }
+}
+
 impl PoolOperations for SerumPool {
 
     fn get_name(&self) -> String {
@@ -201,26 +400,23 @@ impl PoolOperations for SerumPool {
     fn set_update_accounts(
         &mut self, 
         accounts: Vec<Option<Account>>,
-        cluster: Cluster,
+        _cluster: Cluster,
     ) {
         self.accounts = Some(accounts);
-        let oo_path = match cluster { 
-            Cluster::Localnet => {
-                "./serum_open_orders.json"
-            }, 
-            Cluster::Mainnet => {
-                panic!("TODO"); // idk 
-                "./serum_open_orders.json"
-            },
-            _ => panic!("clsuter {} not supported", cluster)
-        };
-        let oo_str = std::fs::read_to_string(oo_path).unwrap();
-        let oo_book: HashMap<String, String> = serde_json::from_str(&oo_str).unwrap();
-        self.open_orders = Some(oo_book); 
     }
 
-    fn mint_2_addr(&self, _mint: &Pubkey) -> Pubkey {
-        panic!("ahhh")
+    fn set_open_orders(&mut self, registry: &OpenOrdersRegistry) {
+        self.open_orders = registry.get(&self.own_address.0);
+    }
+
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError> {
+        if *mint == self.base_mint.0 {
+            Ok(self.base_vault.0)
+        } else if *mint == self.quote_mint.0 {
+            Ok(self.quote_vault.0)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
     }
 
     fn get_mints(&self) -> Vec<Pubkey> {
@@ -232,83 +428,52 @@ impl PoolOperations for SerumPool {
         mints
     }
 
-    fn mint_2_scale(&self, mint: &Pubkey) -> u64 {
+    fn mint_2_scale(&self, mint: &Pubkey) -> Result<u64, QuoteError> {
         if *mint == self.base_mint.0 {
-            self.base_scale
+            Ok(self.base_scale)
         } else if *mint == self.quote_mint.0 { 
-            self.quote_scale
+            Ok(self.quote_scale)
         } else { 
-            panic!("invalid mint bro")
+            Err(QuoteError::UnknownMint(*mint))
         }
     }
 
-    fn get_quote_with_amounts_scaled(
+    fn get_quote(
         &self, 
         amount_in: u128, 
         mint_in: &Pubkey,
-        _mint_out: &Pubkey,
-    ) -> u128 {
-
-        let market_pk = self.own_address.0; 
-        let fee_tier = FeeTier::from_srm_and_msrm_balances(&market_pk, 0, 0);
-        let mut iteration = Iteration {
-            amount_in: amount_in as u64,
-            amount_out: 0,
-        };
-
-        let market_acc = &self.accounts.as_ref().unwrap()[0];
-        let bids_acc = &self.accounts.as_ref().unwrap()[1];
-        let asks_acc = &self.accounts.as_ref().unwrap()[2];
-        
-        // clone accounts for simulation (improve later?)
-        let market_acc = &mut market_acc.clone().unwrap();
-        let bid_acc = &mut bids_acc.clone().unwrap();
-        let ask_acc = &mut asks_acc.clone().unwrap();
-
-        let market_acc_info = &account_info(&self.own_address.0, market_acc);
-        let bids_acc = &account_info(&self.bids.0, bid_acc);
-        let asks_acc = &account_info(&self.asks.0, ask_acc);
-
-        let mut market = Market::load(
-            market_acc_info, 
-            &SERUM_PROGRAM_ID
-        ).unwrap();
-        let mut bids = market.load_bids_mut(bids_acc).unwrap();
-        let mut asks = market.load_asks_mut(asks_acc).unwrap();
-
-        let mut ob = OrderBookState {
-            bids: bids.deref_mut(),
-            asks: asks.deref_mut(),
-            market_state: market.deref_mut(),
-        };
-        
-        if *mint_in == self.quote_mint.0 {
-            // bid: quote -> base
-            loop {
-                let done = bid_iteration(
-                    &mut iteration, 
-                    &fee_tier, 
-                    &mut ob, 
-                );
-                if done { break; }
-            }
-            iteration.amount_out as u128
-
-        } else if *mint_in == self.base_mint.0 {
-            // ask: base -> quote
-            loop {
-                let done = ask_iteration(
-                    &mut iteration, 
-                    &fee_tier, 
-                    &mut ob, 
-                );
-                if done { break; }
-            }
-            iteration.amount_out as u128
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let mut accounts = self.accounts.clone().ok_or(QuoteError::NotLoaded)?;
+        self.match_orders(&mut accounts, amount_in, mint_in, mint_out)
+    }
 
-        } else { 
-            panic!("invalid mints");
-        }
+    fn simulate_swap(
+        &mut self,
+        amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        // match against the pool's own accounts so the fills stick
+        let mut accounts = self.accounts.take().ok_or(QuoteError::NotLoaded)?;
+        let quote = self.match_orders(&mut accounts, amount_in, mint_in, mint_out);
+        self.accounts = Some(accounts);
+        quote
+    }
+    fn get_quote_exact_out(
+        &self,
+        amount_out: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Option<u128> {
+        let side = self.side(mint_in, mint_out).ok()?;
+        let fee_tier = FeeTier::from_srm_and_msrm_balances(&self.own_address.0, 0, 0);
+        let amount_out = u64::try_from(amount_out).ok()?;
+        let amount_in = self.simulate(|ob| match side {
+            Side::Bid => bid_exact_out(amount_out, &fee_tier, ob),
+            Side::Ask => ask_exact_out(amount_out, &fee_tier, ob),
+        }).ok()??;
+        Some(amount_in as u128)
     }
 
     fn swap_ix(&self, 
@@ -318,10 +483,8 @@ impl PoolOperations for SerumPool {
         _mint_out: &Pubkey
     ) -> Vec<Instruction> {
 
-        let oos = self.open_orders.as_ref().unwrap(); 
-        let open_orders = Pubkey::from_str(
-            oos.get(&self.own_address.0.to_string()).unwrap()
-        ).unwrap();
+        // `can_trade` skips markets without one
+        let open_orders = self.open_orders.unwrap();
 
         let (swap_state, _) = Pubkey::find_program_address(
             &[b"swap_state"], 
@@ -367,45 +530,20 @@ impl PoolOperations for SerumPool {
 
     fn can_trade(&self, 
         mint_in: &Pubkey,
-        _mint_out: &Pubkey
+        mint_out: &Pubkey
     ) -> bool {
-
-        let market_acc = &self.accounts.as_ref().unwrap()[0];
-        let bids_acc = &self.accounts.as_ref().unwrap()[1];
-        let asks_acc = &self.accounts.as_ref().unwrap()[2];
-        
-        // clone accounts for simulation (improve later?)
-        let market_acc = &mut market_acc.clone().unwrap();
-        let bid_acc = &mut bids_acc.clone().unwrap();
-        let ask_acc = &mut asks_acc.clone().unwrap();
-
-        let market_acc_info = &account_info(&self.own_address.0, market_acc);
-        let bids_acc = &account_info(&self.bids.0, bid_acc);
-        let asks_acc = &account_info(&self.asks.0, ask_acc);
-
-        let market = Market::load(
-            market_acc_info, 
-            &SERUM_PROGRAM_ID
-        ).unwrap();
-        let bids = market.load_bids_mut(bids_acc).unwrap();
-        let asks = market.load_asks_mut(asks_acc).unwrap();
+        if self.open_orders.is_none() {
+            return false;
+        }
+        let side = match self.side(mint_in, mint_out) {
+            Ok(side) => side,
+            Err(_) => return false,
+        };
 
         // is there a bid or ask we can trade with??? 
-        if *mint_in == self.quote_mint.0 {
-            // bid: quote -> base
-            match asks.find_min() { // min = best ask 
-                Some(_) => true, 
-                None => false
-            }
-        } else if *mint_in == self.base_mint.0 {
-            // ask: base -> quote
-            match bids.find_max() {
-                Some(_) => true, 
-                None => false
-            }
-
-        } else { 
-            panic!("invalid mints");
-        }
+        self.simulate(|ob| match side {
+            Side::Bid => ob.asks.find_min().is_some(), // min = best ask 
+            Side::Ask => ob.bids.find_max().is_some(),
+        }).unwrap_or(false)
     }
 }
\ No newline at end of file
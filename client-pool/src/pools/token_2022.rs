@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use solana_sdk::account::Account;
+use crate::pool::{PoolOperations, Quote};
+use crate::error::QuoteError;
+use crate::open_orders::OpenOrdersRegistry;
+use crate::pool_utils::jupiter::{FetchedRoute, RouteRequest};
+
+use anchor_client::solana_client::rpc_client::RpcClient;
+use anchor_client::solana_sdk::epoch_schedule::EpochSchedule;
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::Cluster;
+use anchor_client::Program;
+
+use solana_sdk::instruction::Instruction;
+
+use crate::utils::{derive_token_address, derive_token_address_with_program};
+use crate::pool_utils::token_2022::{unpack_mint, Mint};
+use crate::constants::*;
+
+/// A pool trading Token-2022 mints -- wraps the pool's own quotes with the
+/// mints' transfer fees (withheld from what's sent into the pool and from
+/// what it sends back) and points its swaps at the Token-2022 program + ATAs
+///
+/// Pools mixing a Token-2022 mint with a classic one only trade when their
+/// swap takes a token program per mint (whirlpool swap_v2, dlmm). The others
+/// (raydium, serum, lifinity, phoenix, ...) pass a single token program for
+/// both sides and are refused, see `can_trade`
+#[derive(Debug, Clone)]
+pub struct Token2022Pool {
+    pub pool: Box<dyn PoolOperations>,
+    pub mints: HashMap<Pubkey, Mint>,  // the pool's mints owned by the Token-2022 program
+    pub epoch: u64,                    // picks the transfer fee of each mint
+    pub epoch_schedule: EpochSchedule, // the epoch of a slot -- see `set_slot`
+}
+
+impl Token2022Pool {
+    fn post_fee_amount(&self, mint: &Pubkey, amount: u128) -> Result<u128, QuoteError> {
+        match self.mints.get(mint) {
+            Some(mint) => {
+                let amount = u64::try_from(amount).map_err(|_| QuoteError::CalculationFailure)?;
+                Ok(mint.post_fee_amount(amount, self.epoch) as u128)
+            }
+            None => Ok(amount),
+        }
+    }
+
+    fn pre_fee_amount(&self, mint: &Pubkey, amount: u128) -> Option<u128> {
+        match self.mints.get(mint) {
+            Some(mint) => {
+                let amount = u64::try_from(amount).ok()?;
+                mint.pre_fee_amount(amount, self.epoch).map(|amount| amount as u128)
+            }
+            None => Some(amount),
+        }
+    }
+
+    fn has_transfer_fees(&self) -> bool {
+        self.mints.values().any(|mint| mint.has_transfer_fee())
+    }
+
+    // both sides are Token-2022 mints, so one token program does for both
+    fn is_single_program(&self) -> bool {
+        self.pool.get_mints().iter().all(|mint| self.mints.contains_key(mint))
+    }
+
+    /// the pool's quote of what reaches it from `amount_in`, minus the fee on the way out
+    fn quote(
+        &self,
+        amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+        inner_quote: impl FnOnce(u128) -> Result<Quote, QuoteError>,
+    ) -> Result<Quote, QuoteError> {
+        let received = self.post_fee_amount(mint_in, amount_in)?;
+        let quote = inner_quote(received)?;
+        Ok(Quote {
+            amount_out: self.post_fee_amount(mint_out, quote.amount_out)?,
+            fee_paid: quote.fee_paid + (amount_in - received),
+            ..quote
+        })
+    }
+}
+
+impl PoolOperations for Token2022Pool {
+    fn swap_ix(&self,
+        program: &Program,
+        owner: &Pubkey,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey
+    ) -> Vec<Instruction> {
+        let mut ixs = self.pool.swap_ix(program, owner, mint_in, mint_out);
+        // the wrapped pool derives classic ATAs + passes the classic token
+        // program (but for the pools which take one per mint)
+        let token_accounts: HashMap<Pubkey, Pubkey> = self
+            .mints
+            .keys()
+            .map(|mint| {
+                (
+                    derive_token_address(owner, mint),
+                    derive_token_address_with_program(owner, mint, &TOKEN_2022_PROGRAM_ID),
+                )
+            })
+            .collect();
+        let single_program = self.is_single_program();
+        for ix in ixs.iter_mut() {
+            for meta in ix.accounts.iter_mut() {
+                if let Some(token_account) = token_accounts.get(&meta.pubkey) {
+                    meta.pubkey = *token_account;
+                } else if single_program && meta.pubkey == *TOKEN_PROGRAM_ID {
+                    meta.pubkey = *TOKEN_2022_PROGRAM_ID;
+                }
+            }
+        }
+        ixs
+    }
+
+    fn get_quote(
+        &self,
+        amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        self.quote(amount_in, mint_in, mint_out, |amount_in| {
+            self.pool.get_quote(amount_in, mint_in, mint_out)
+        })
+    }
+
+    fn simulate_swap(
+        &mut self,
+        amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let received = self.post_fee_amount(mint_in, amount_in)?;
+        let quote = self.pool.simulate_swap(received, mint_in, mint_out)?;
+        self.quote(amount_in, mint_in, mint_out, |_| Ok(quote))
+    }
+
+    fn get_quote_exact_out(
+        &self,
+        amount_out: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Option<u128> {
+        // the pool has to send the fee on top, and be sent `amount_in` after its fee
+        let sent = self.pre_fee_amount(mint_out, amount_out)?;
+        let received = self.pool.get_quote_exact_out(sent, mint_in, mint_out)?;
+        self.pre_fee_amount(mint_in, received)
+    }
+
+    fn get_constant_product_reserves(
+        &self,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Option<(u128, u128, f64)> {
+        // transfer fees aren't a fraction of the amount (rounded + capped)
+        if self.has_transfer_fees() {
+            return None;
+        }
+        self.pool.get_constant_product_reserves(mint_in, mint_out)
+    }
+
+    fn get_update_accounts(&self) -> Vec<Pubkey> {
+        self.pool.get_update_accounts()
+    }
+
+    fn n_required_update_accounts(&self) -> usize {
+        self.pool.n_required_update_accounts()
+    }
+
+    fn set_update_accounts(&mut self, accounts: Vec<Option<Account>>, cluster: Cluster) {
+        self.pool.set_update_accounts(accounts, cluster)
+    }
+
+    fn set_slot(&mut self, slot: u64) {
+        self.epoch = self.epoch_schedule.get_epoch(slot);
+        self.pool.set_slot(slot)
+    }
+
+    fn set_open_orders(&mut self, registry: &OpenOrdersRegistry) {
+        self.pool.set_open_orders(registry)
+    }
+
+    fn route_request(&self) -> Option<RouteRequest> {
+        self.pool.route_request()
+    }
+
+    fn set_route(&mut self, route: Option<FetchedRoute>) {
+        self.pool.set_route(route)
+    }
+
+    fn has_token_program_per_mint(&self) -> bool {
+        self.pool.has_token_program_per_mint()
+    }
+
+    fn set_token_programs(&mut self, token_programs: &HashMap<Pubkey, Pubkey>) {
+        self.pool.set_token_programs(token_programs)
+    }
+
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError> {
+        self.pool.mint_2_addr(mint)
+    }
+
+    fn mint_2_scale(&self, mint: &Pubkey) -> Result<u64, QuoteError> {
+        self.pool.mint_2_scale(mint)
+    }
+
+    fn get_mints(&self) -> Vec<Pubkey> {
+        self.pool.get_mints()
+    }
+
+    fn get_name(&self) -> String {
+        self.pool.get_name()
+    }
+
+    fn can_trade(&self,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey
+    ) -> bool {
+        // a mixed pool can't be sent a single token program for both sides
+        (self.is_single_program() || self.pool.has_token_program_per_mint())
+            && self.pool.can_trade(mint_in, mint_out)
+    }
+}
+
+/// `pool` wrapped with the Token-2022 mints (of `mints`) it trades, their
+/// fees picked by the epoch of `slot` -- pools of classic mints only are left
+/// as they are
+pub fn wrap_token_2022(
+    mut pool: Box<dyn PoolOperations>,
+    mints: &HashMap<Pubkey, Mint>,
+    epoch_schedule: &EpochSchedule,
+    slot: u64,
+) -> Box<dyn PoolOperations> {
+    let pool_mints: HashMap<Pubkey, Mint> = pool
+        .get_mints()
+        .iter()
+        .filter_map(|mint| {
+            let mint_info = mints.get(mint)?;
+            if mint_info.token_program != *TOKEN_2022_PROGRAM_ID {
+                return None;
+            }
+            Some((*mint, mint_info.clone()))
+        })
+        .collect();
+    if pool_mints.is_empty() {
+        return pool;
+    }
+    let token_programs: HashMap<Pubkey, Pubkey> = pool_mints
+        .keys()
+        .map(|mint| (*mint, *TOKEN_2022_PROGRAM_ID))
+        .collect();
+    pool.set_token_programs(&token_programs);
+    Box::new(Token2022Pool {
+        pool,
+        mints: pool_mints,
+        epoch: epoch_schedule.get_epoch(slot),
+        epoch_schedule: epoch_schedule.clone(),
+    })
+}
+
+/// every mint of `mints` which could be fetched + unpacked
+pub fn fetch_mints(connection: &RpcClient, mints: &[Pubkey]) -> HashMap<Pubkey, Mint> {
+    let mut unpacked = HashMap::new();
+    // max 100 accounts per get_multiple_accounts
+    for mint_chunk in mints.chunks(99) {
+        let accounts = connection.get_multiple_accounts(mint_chunk).unwrap();
+        for (mint, account) in mint_chunk.iter().zip(accounts) {
+            let mint_info = account.and_then(|account| unpack_mint(&account.owner, &account.data));
+            if let Some(mint_info) = mint_info {
+                unpacked.insert(*mint, mint_info);
+            }
+        }
+    }
+    unpacked
+}
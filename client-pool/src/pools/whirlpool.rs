@@ -0,0 +1,271 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Debug;
+use serde::{Deserialize, Serialize};
+use solana_sdk::account::Account;
+use crate::serialize::token::WrappedPubkey;
+use crate::pool::{price_impact_bps, PoolOperations, Quote};
+use crate::error::QuoteError;
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::Cluster;
+use anchor_client::Program;
+
+use solana_sdk::instruction::Instruction;
+
+use tmp::accounts as tmp_accounts;
+use tmp::instruction as tmp_ix;
+
+use crate::utils::{derive_token_address, token_program_of};
+use crate::pool_utils::whirlpool::{
+    oracle_address, price_from_sqrt_price, swap_to_state, swap_tick_array_starts, tick_array_address,
+    tick_array_start_index, unpack_tick_array, unpack_whirlpool, Whirlpool, TICK_ARRAY_SIZE,
+};
+use crate::constants::*;
+
+// tick arrays streamed on each side of the one holding `tick_current_index`
+pub const TICK_ARRAY_WINDOW: i32 = 3;
+
+/// Orca Whirlpool (concentrated liquidity) pool
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WhirlpoolPool {
+    pub address: WrappedPubkey,
+    pub token_mint_a: WrappedPubkey,
+    pub token_mint_b: WrappedPubkey,
+    pub token_vault_a: WrappedPubkey,
+    pub token_vault_b: WrappedPubkey,
+    pub decimals_a: u64,
+    pub decimals_b: u64,
+    pub tick_spacing: u16,
+    // hundredths of a bip -- refreshed from the whirlpool account on every update
+    pub fee_rate: u16,
+    // the pool's tick when the JSON was written, the tick array window is
+    // centred on it (update accounts can't change once streaming)
+    pub tick_current_index: i32,
+    // to set later
+    #[serde(skip)]
+    pub whirlpool: Option<Whirlpool>,
+    #[serde(skip)]
+    pub ticks: BTreeMap<i32, i128>, // initialized tick -> liquidity_net
+    #[serde(skip)]
+    pub tick_array_starts: HashSet<i32>, // tick arrays which exist on chain
+    #[serde(skip)]
+    pub token_programs: HashMap<Pubkey, Pubkey>, // see `set_token_programs`
+}
+
+impl WhirlpoolPool {
+    fn ticks_in_array(&self) -> i32 {
+        TICK_ARRAY_SIZE * self.tick_spacing as i32
+    }
+
+    /// start ticks of the streamed tick arrays
+    fn tick_array_window(&self) -> Vec<i32> {
+        let start = tick_array_start_index(self.tick_current_index, self.tick_spacing);
+        (-TICK_ARRAY_WINDOW..=TICK_ARRAY_WINDOW)
+            .map(|i| start + i * self.ticks_in_array())
+            .collect()
+    }
+
+    fn a_to_b(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> Result<bool, QuoteError> {
+        if *mint_in == self.token_mint_a.0 && *mint_out == self.token_mint_b.0 {
+            Ok(true)
+        } else if *mint_in == self.token_mint_b.0 && *mint_out == self.token_mint_a.0 {
+            Ok(false)
+        } else {
+            Err(QuoteError::InvalidMints { mint_in: *mint_in, mint_out: *mint_out })
+        }
+    }
+
+    /// quote + the whirlpool after the swap
+    fn swap(
+        &self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<(Quote, Whirlpool), QuoteError> {
+        let a_to_b = self.a_to_b(mint_in, mint_out)?;
+        let whirlpool = self.whirlpool.as_ref().ok_or(QuoteError::NotLoaded)?;
+        let (step, after) = self.swap_tick_range(whirlpool, a_to_b)
+            .and_then(|tick_range| {
+                swap_to_state(whirlpool, &self.ticks, tick_range, scaled_amount_in, a_to_b)
+            })
+            // runs past the loaded tick arrays -- would fail on chain
+            .ok_or(QuoteError::InsufficientLiquidity)?;
+
+        let price = price_from_sqrt_price(whirlpool.sqrt_price);
+        let spot_rate = if a_to_b { price } else { 1. / price };
+        let quote = Quote {
+            amount_out: step.amount_out,
+            fee_paid: step.fee_amount,
+            price_impact_bps: price_impact_bps(step.amount_in, step.amount_out, spot_rate),
+            new_reserves: None, // liquidity is spread over ticks
+        };
+        Ok((quote, after))
+    }
+
+    /// ticks covered by the loaded tick arrays the swap would use
+    fn swap_tick_range(&self, whirlpool: &Whirlpool, a_to_b: bool) -> Option<(i32, i32)> {
+        let starts: Vec<i32> =
+            swap_tick_array_starts(whirlpool.tick_current_index, self.tick_spacing, a_to_b)
+                .iter()
+                .copied()
+                .take_while(|start| self.tick_array_starts.contains(start))
+                .collect();
+        let lower = *starts.iter().min()?;
+        let upper = *starts.iter().max()? + self.ticks_in_array();
+        Some((lower, upper))
+    }
+}
+
+impl PoolOperations for WhirlpoolPool {
+    fn swap_ix(&self,
+        program: &Program,
+        owner: &Pubkey,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey
+    ) -> Vec<Instruction> {
+        let a_to_b = self.a_to_b(mint_in, mint_out).unwrap();
+        let tick_current_index = self
+            .whirlpool
+            .as_ref()
+            .map_or(self.tick_current_index, |w| w.tick_current_index);
+        let [tick_array_0, tick_array_1, tick_array_2] =
+            swap_tick_array_starts(tick_current_index, self.tick_spacing, a_to_b)
+                .map(|start| tick_array_address(&self.address.0, start, &WHIRLPOOL_PROGRAM_ID));
+
+        program
+            .request()
+            .accounts(tmp_accounts::OrcaSwap {
+                token_program_a: token_program_of(&self.token_programs, &self.token_mint_a.0),
+                token_program_b: token_program_of(&self.token_programs, &self.token_mint_b.0),
+                memo_program: *MEMO_PROGRAM_ID,
+                token_authority: *owner,
+                whirlpool: self.address.0,
+                token_mint_a: self.token_mint_a.0,
+                token_mint_b: self.token_mint_b.0,
+                token_owner_account_a: derive_token_address(owner, &self.token_mint_a.0),
+                token_vault_a: self.token_vault_a.0,
+                token_owner_account_b: derive_token_address(owner, &self.token_mint_b.0),
+                token_vault_b: self.token_vault_b.0,
+                tick_array_0,
+                tick_array_1,
+                tick_array_2,
+                oracle: oracle_address(&self.address.0, &WHIRLPOOL_PROGRAM_ID),
+                whirlpool_program: *WHIRLPOOL_PROGRAM_ID,
+            })
+            .args(tmp_ix::OrcaSwap { amount_in: 0, minimum_amount_out: 0, a_to_b })
+            .instructions()
+            .unwrap()
+    }
+
+    fn get_quote(
+        &self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        self.swap(scaled_amount_in, mint_in, mint_out).map(|(quote, _)| quote)
+    }
+
+    fn simulate_swap(
+        &mut self,
+        scaled_amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let (quote, whirlpool) = self.swap(scaled_amount_in, mint_in, mint_out)?;
+        self.whirlpool = Some(whirlpool);
+        Ok(quote)
+    }
+
+    fn get_update_accounts(&self) -> Vec<Pubkey> {
+        let mut accounts = vec![self.address.0];
+        accounts.extend(
+            self.tick_array_window()
+                .into_iter()
+                .map(|start| tick_array_address(&self.address.0, start, &WHIRLPOOL_PROGRAM_ID)),
+        );
+        accounts
+    }
+
+    fn n_required_update_accounts(&self) -> usize {
+        1 // uninitialized tick arrays don't exist
+    }
+
+    fn can_trade(&self,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey
+    ) -> bool {
+        let whirlpool = match &self.whirlpool {
+            Some(whirlpool) => whirlpool,
+            None => return false,
+        };
+        let a_to_b = match self.a_to_b(mint_in, mint_out) {
+            Ok(a_to_b) => a_to_b,
+            Err(_) => return false,
+        };
+        whirlpool.liquidity > 0 && self.swap_tick_range(whirlpool, a_to_b).is_some()
+    }
+
+    fn set_update_accounts(&mut self, accounts: Vec<Option<Account>>, _cluster: Cluster) {
+        self.whirlpool = accounts[0].as_ref().and_then(|a| unpack_whirlpool(&a.data));
+        if let Some(whirlpool) = &self.whirlpool {
+            self.fee_rate = whirlpool.fee_rate;
+        }
+
+        self.ticks.clear();
+        self.tick_array_starts.clear();
+        for account in accounts[1..].iter().flatten() {
+            let tick_array = match unpack_tick_array(&account.data) {
+                Some(tick_array) if tick_array.whirlpool == self.address.0 => tick_array,
+                _ => continue,
+            };
+            for (i, tick) in tick_array.ticks.iter().enumerate() {
+                if tick.initialized {
+                    let tick_index = tick_array.start_tick_index + i as i32 * self.tick_spacing as i32;
+                    self.ticks.insert(tick_index, tick.liquidity_net);
+                }
+            }
+            self.tick_array_starts.insert(tick_array.start_tick_index);
+        }
+    }
+
+    fn has_token_program_per_mint(&self) -> bool {
+        true
+    }
+
+    fn set_token_programs(&mut self, token_programs: &HashMap<Pubkey, Pubkey>) {
+        self.token_programs = token_programs.clone();
+    }
+
+    fn get_name(&self) -> String {
+        "Whirlpool".to_string()
+    }
+
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError> {
+        if *mint == self.token_mint_a.0 {
+            Ok(self.token_vault_a.0)
+        } else if *mint == self.token_mint_b.0 {
+            Ok(self.token_vault_b.0)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
+    }
+
+    fn mint_2_scale(&self, mint: &Pubkey) -> Result<u64, QuoteError> {
+        if *mint == self.token_mint_a.0 {
+            Ok(self.decimals_a)
+        } else if *mint == self.token_mint_b.0 {
+            Ok(self.decimals_b)
+        } else {
+            Err(QuoteError::UnknownMint(*mint))
+        }
+    }
+
+    fn get_mints(&self) -> Vec<Pubkey> {
+        let mut mints = vec![self.token_mint_a.0, self.token_mint_b.0];
+        // Sort to ensure consistency across different pools
+        mints.sort();
+        mints
+    }
+}
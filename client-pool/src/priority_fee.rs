@@ -0,0 +1,140 @@
+//! Priority fee estimates from the fees recently paid to lock an account
+//!
+//! The estimator itself is shared with the other bots (see the
+//! `priority-fees` crate) -- this module picks a route's writable accounts and
+//! keeps their fees fresh from the rpc. Queries are made off the send path, by
+//! a refresher thread over the streamed pool accounts (`spawn_refresher`), so
+//! pricing a route only reads what's already been fetched.
+
+use anchor_client::solana_client::client_error::ClientError;
+use anchor_client::solana_client::rpc_client::RpcClient;
+use anchor_client::solana_client::rpc_request::RpcRequest;
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use log::{debug, warn};
+use serde_json::json;
+use solana_sdk::instruction::Instruction;
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+pub use priority_fees::{
+    RecentPrioritizationFee, Urgency, DEFAULT_REFRESH_INTERVAL, DEFAULT_WINDOW_SLOTS,
+    RECENT_PRIORITIZATION_FEES_METHOD,
+};
+
+/// Rolling per-account history of recent prioritization fees
+pub type PriorityFeeEstimator = priority_fees::PriorityFeeEstimator<Pubkey>;
+
+/// the accounts a route write-locks (other than its signers), sorted
+pub fn writable_accounts(route: &[Instruction]) -> Vec<Pubkey> {
+    let accounts: BTreeSet<Pubkey> = route
+        .iter()
+        .flat_map(|ix| ix.accounts.iter())
+        .filter(|meta| meta.is_writable && !meta.is_signer)
+        .map(|meta| meta.pubkey)
+        .collect();
+    accounts.into_iter().collect()
+}
+
+/// the recent prioritization fees paid to write-lock `account`
+pub fn fetch_recent_fees(
+    connection: &RpcClient,
+    account: &Pubkey,
+) -> Result<Vec<RecentPrioritizationFee>, ClientError> {
+    connection.send(
+        RpcRequest::Custom {
+            method: RECENT_PRIORITIZATION_FEES_METHOD,
+        },
+        json!([[account.to_string()]]),
+    )
+}
+
+/// query the recent fees of the stale `accounts`, returns how many were
+/// refreshed -- an account which fails keeps its old history. The estimator
+/// is only locked to pick the stale accounts + record each response
+pub fn refresh(
+    estimator: &RwLock<PriorityFeeEstimator>,
+    connection: &RpcClient,
+    accounts: &[Pubkey],
+) -> usize {
+    let stale = estimator.read().unwrap().stale_accounts(accounts, Instant::now());
+    let mut n_refreshed = 0;
+    for account in stale {
+        let fees = match fetch_recent_fees(connection, &account) {
+            Ok(fees) => fees,
+            Err(e) => {
+                warn!("can't get recent prioritization fees of {}: {}", account, e);
+                continue;
+            }
+        };
+        debug!("{} recent prioritization fees of {}", fees.len(), account);
+        estimator.write().unwrap().record(account, &fees);
+        n_refreshed += 1;
+    }
+    n_refreshed
+}
+
+/// keep the fees of `accounts` fresh, re-querying them every
+/// `refresh_interval` on a thread of its own
+pub fn spawn_refresher(
+    connection: RpcClient,
+    estimator: Arc<RwLock<PriorityFeeEstimator>>,
+    accounts: Vec<Pubkey>,
+) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        let n_refreshed = refresh(&estimator, &connection, &accounts);
+        debug!("refreshed the prioritization fees of {} accounts", n_refreshed);
+        let refresh_interval = estimator.read().unwrap().refresh_interval;
+        thread::sleep(refresh_interval);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::AccountMeta;
+
+    fn fees(fees: &[(u64, u64)]) -> Vec<RecentPrioritizationFee> {
+        fees.iter()
+            .map(|(slot, prioritization_fee)| RecentPrioritizationFee {
+                slot: *slot,
+                prioritization_fee: *prioritization_fee,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prices_routes_by_their_writable_accounts() {
+        let [hot, cold, read_only, signer] = [(); 4].map(|_| Pubkey::new_unique());
+        let mut estimator = PriorityFeeEstimator::default();
+        estimator.record(hot, &fees(&[(1, 10), (2, 20), (3, 30), (4, 40)]));
+        estimator.record(cold, &fees(&[(1, 0), (2, 0), (3, 5), (4, 5)]));
+        estimator.record(read_only, &fees(&[(1, 1_000_000)]));
+        estimator.record(signer, &fees(&[(1, 1_000_000)]));
+
+        let route = vec![
+            Instruction::new_with_bytes(
+                Pubkey::new_unique(),
+                &[],
+                vec![
+                    AccountMeta::new(signer, true),
+                    AccountMeta::new(cold, false),
+                    AccountMeta::new_readonly(read_only, false),
+                ],
+            ),
+            Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![AccountMeta::new(hot, false)]),
+        ];
+        let mut expected = vec![cold, hot];
+        expected.sort();
+        assert_eq!(writable_accounts(&route), expected);
+
+        let price_for = |route: &[Instruction], urgency| {
+            estimator.price_for(&writable_accounts(route), urgency)
+        };
+        assert_eq!(price_for(&route, Urgency::Low), 10);
+        assert_eq!(price_for(&route, Urgency::Max), 40);
+        assert_eq!(price_for(&route[..1], Urgency::High), 5);
+        assert_eq!(price_for(&[], Urgency::Max), 0);
+    }
+}
@@ -150,4 +150,62 @@ pub fn unpack_token_account(data: &[u8]) -> TokenAccount {
         delegated_amount: u64::from_le_bytes(*delegated_amount),
         close_authority: unpack_coption_key(close_authority).unwrap(),
     }
-}
\ No newline at end of file
+}
+
+// TOKEN-2022 EXTENSIONS
+
+pub const MINT_LEN: usize = 82;
+pub const ACCOUNT_LEN: usize = 165;
+// extended mints are padded to `ACCOUNT_LEN` so both kinds keep their
+// account type byte here, the TLV entries follow it
+pub const ACCOUNT_TYPE_OFFSET: usize = ACCOUNT_LEN;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum AccountType {
+    Uninitialized,
+    Mint,
+    Account,
+}
+
+// extension types we read
+pub const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+pub const EXTENSION_TRANSFER_FEE_AMOUNT: u16 = 2;
+
+/// account type of a Token-2022 mint or account with extensions, None for
+/// classic (unextended) layouts
+pub fn unpack_account_type(data: &[u8]) -> Option<AccountType> {
+    let account_type = *data.get(ACCOUNT_TYPE_OFFSET)?;
+    AccountType::try_from_primitive(account_type).ok()
+}
+
+/// the (extension type, value) entries of a Token-2022 mint or account, in
+/// order -- empty for classic layouts. A zero type marks the start of unused
+/// space, a truncated entry ends the list
+pub fn unpack_extensions(data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut extensions = vec![];
+    if unpack_account_type(data).is_none() {
+        return extensions;
+    }
+    let mut offset = ACCOUNT_TYPE_OFFSET + 1;
+    while offset + 4 <= data.len() {
+        let (extension_type, length) = array_refs![array_ref![data, offset, 4], 2, 2];
+        let extension_type = u16::from_le_bytes(*extension_type);
+        let length = u16::from_le_bytes(*length) as usize;
+        let start = offset + 4;
+        if extension_type == 0 || start + length > data.len() {
+            break;
+        }
+        extensions.push((extension_type, &data[start..start + length]));
+        offset = start + length;
+    }
+    extensions
+}
+
+/// value of the extension of type `extension_type`, if the account has it
+pub fn find_extension(data: &[u8], extension_type: u16) -> Option<&[u8]> {
+    unpack_extensions(data)
+        .into_iter()
+        .find(|(tipe, _)| *tipe == extension_type)
+        .map(|(_, value)| value)
+}
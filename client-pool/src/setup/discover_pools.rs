@@ -0,0 +1,104 @@
+use anchor_client::solana_client::rpc_client::RpcClient;
+use anchor_client::solana_sdk::commitment_config::CommitmentConfig;
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::Cluster;
+use clap::Parser;
+use log::info;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use client::discover::{
+    discover_pools, AccountSource, DiscoveredPool, FixtureAccounts, PoolFilter,
+};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about = "Build the pool JSON dirs from on-chain program accounts", long_about = None)]
+pub struct Args {
+    /// localnet | mainnet | an RPC url
+    #[clap(short, long, default_value = "localnet")]
+    pub cluster: String,
+    /// read recorded `solana account --output json` files instead of RPC
+    #[clap(long)]
+    pub fixtures: Option<String>,
+    /// pools are written to <out-dir>/<type>/<address>.json
+    #[clap(long, default_value = "../pools/")]
+    pub out_dir: String,
+    /// also write every pool (with its "type") into one JSON-lines file
+    #[clap(long)]
+    pub snapshot: Option<String>,
+    /// min reserve on each side of a pool, in whole tokens
+    #[clap(long, default_value = "0")]
+    pub min_liquidity: f64,
+    /// only keep pools where both mints are allow-listed (repeatable)
+    #[clap(long = "mint")]
+    pub mints: Vec<String>,
+}
+
+fn write_pools(out_dir: &str, pools: &[DiscoveredPool]) {
+    for pool in pools {
+        let dir = Path::new(out_dir).join(pool.tipe.tag());
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.json", pool.address));
+        fs::write(path, serde_json::to_string_pretty(&pool.json).unwrap()).unwrap();
+    }
+}
+
+fn write_snapshot(snapshot_path: &str, pools: &[DiscoveredPool]) {
+    let mut file = fs::File::create(snapshot_path).unwrap();
+    for pool in pools {
+        writeln!(file, "{}", pool.tagged_json()).unwrap();
+    }
+}
+
+fn discover(source: &impl AccountSource, filter: &PoolFilter, args: &Args) {
+    let pools = discover_pools(source, filter).unwrap();
+    for tag in ["orca", "raydium", "serum"] {
+        let n_pools = pools.iter().filter(|p| p.tipe.tag() == tag).count();
+        info!("Discovered {:?} {} pools", n_pools, tag);
+    }
+
+    write_pools(&args.out_dir, &pools);
+    if let Some(snapshot_path) = &args.snapshot {
+        write_snapshot(snapshot_path, &pools);
+    }
+    info!("Wrote {:?} pools", pools.len());
+}
+
+fn main() {
+    let args = Args::parse();
+    env_logger::init();
+
+    let mints = if args.mints.is_empty() {
+        None
+    } else {
+        Some(
+            args.mints
+                .iter()
+                .map(|mint| Pubkey::from_str(mint).expect("invalid mint"))
+                .collect::<HashSet<Pubkey>>(),
+        )
+    };
+    let filter = PoolFilter {
+        min_liquidity: args.min_liquidity,
+        mints,
+    };
+
+    if let Some(fixture_dir) = &args.fixtures {
+        info!("Using fixtures: {}", fixture_dir);
+        let source = FixtureAccounts::load(fixture_dir).unwrap();
+        discover(&source, &filter, &args);
+        return;
+    }
+
+    let connection_url = match args.cluster.as_str() {
+        "localnet" => Cluster::Localnet.url().to_string(),
+        "mainnet" => Cluster::Mainnet.url().to_string(),
+        url => url.to_string(),
+    };
+    info!("Using connection: {}", connection_url);
+    let connection = RpcClient::new_with_commitment(connection_url, CommitmentConfig::confirmed());
+    discover(&connection, &filter, &args);
+}
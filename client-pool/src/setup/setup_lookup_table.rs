@@ -0,0 +1,319 @@
+use anchor_client::solana_client::rpc_client::RpcClient;
+use anchor_client::solana_sdk::commitment_config::CommitmentConfig;
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::solana_sdk::signature::read_keypair_file;
+use anchor_client::solana_sdk::signature::{Keypair, Signer};
+use anchor_client::{Client, Cluster};
+use clap::{Parser, Subcommand};
+use log::{info, warn};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::transaction::Transaction;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+use client::constants::*;
+use client::lookup_table::{
+    close_lookup_table_ix, create_lookup_table_ix, deactivate_lookup_table_ix,
+    extend_lookup_table_ix, fetch_lookup_table_accounts, pack_lookup_tables, pool_account_groups,
+    LookupTable, LookupTableAccount, LookupTableIndex, LOOKUP_TABLE_INDEX_PATH,
+    MAX_EXTEND_ADDRESSES,
+};
+use client::open_orders::{OpenOrdersRegistry, OPEN_ORDERS_PATH};
+use client::pool::{default_pool_dirs, load_pools, PoolOperations};
+use client::pool_utils::jupiter::refresh_routes;
+use client::pools::{fetch_mints, wrap_token_2022};
+use client::stream::PoolStateCache;
+use client::utils::PoolQuote;
+
+use indicatif::ProgressBar;
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about = "Manage the lookup tables holding the pools' swap accounts", long_about = None)]
+pub struct Args {
+    /// localnet | mainnet | an RPC url
+    #[clap(short, long, default_value = "mainnet")]
+    pub cluster: String,
+    #[clap(short, long, default_value = "../../../mainnet.key")]
+    pub keypair: String,
+    /// table index JSON (read by the bot)
+    #[clap(long, default_value = LOOKUP_TABLE_INDEX_PATH)]
+    pub index: String,
+    /// print what would be sent without sending anything
+    #[clap(long)]
+    pub dry_run: bool,
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// pack the pools' accounts into tables, creating + extending them as needed
+    Sync {
+        /// self-describing pool snapshots (mixed dirs or .jsonl files) loaded on top of the per-DEX dirs
+        #[clap(long = "pools")]
+        pool_snapshots: Vec<String>,
+        /// serum open orders registry (serum swaps reference the owner's open orders)
+        #[clap(long, default_value = OPEN_ORDERS_PATH)]
+        open_orders: String,
+    },
+    /// deactivate the stale tables, + close the ones deactivated long enough ago
+    Close,
+}
+
+fn send(connection: &RpcClient, ixs: &[Instruction], owner: &Keypair) -> bool {
+    let recent_hash = connection.get_latest_blockhash().unwrap();
+    let tx =
+        Transaction::new_signed_with_payer(ixs, Some(&owner.pubkey()), &[owner], recent_hash);
+    match connection.send_and_confirm_transaction(&tx) {
+        Ok(signature) => {
+            info!("signature: {}", signature);
+            true
+        }
+        Err(e) => {
+            warn!("error: {:#?}", e);
+            false
+        }
+    }
+}
+
+/// the on-chain state of the indexed tables -- tables which are gone, or
+/// which the owner can't manage, are dropped from the index
+fn refresh_index(
+    connection: &RpcClient,
+    index: &mut LookupTableIndex,
+    owner: &Pubkey,
+) -> Vec<LookupTableAccount> {
+    let addresses: Vec<Pubkey> = index.0.iter().map(|indexed| indexed.table.address).collect();
+    let onchain = fetch_lookup_table_accounts(connection, &addresses);
+    let mut accounts = vec![];
+    for (address, account) in addresses.iter().zip(onchain) {
+        match account {
+            Some(account) if account.authority == Some(*owner) => {
+                let stale = index.get(address).unwrap().stale;
+                index.upsert(account.table.clone(), stale || !account.is_active());
+                accounts.push(account);
+            }
+            Some(_) => {
+                warn!("dropping lookup table {}: not the owner's", address);
+                index.remove(address);
+            }
+            None => {
+                warn!("dropping lookup table {}: no longer exists", address);
+                index.remove(address);
+            }
+        }
+    }
+    accounts
+}
+
+fn sync(
+    args: &Args,
+    pool_snapshots: &[String],
+    open_orders: &str,
+    cluster: Cluster,
+    connection: &RpcClient,
+    owner: &Keypair,
+) {
+    let provider = Client::new_with_options(
+        cluster.clone(),
+        Rc::new(read_keypair_file(&args.keypair).unwrap()),
+        CommitmentConfig::confirmed(),
+    );
+    let program = provider.program(*ARB_PROGRAM_ID);
+
+    let open_orders = OpenOrdersRegistry::load(open_orders).unwrap();
+    let mut pools = load_pools(&default_pool_dirs(pool_snapshots));
+    for pool in pools.iter_mut() {
+        pool.set_open_orders(&open_orders);
+    }
+    info!("Loaded {:?} pools", pools.len());
+
+    // token-2022 pools swap through the owner's token-2022 ATAs
+    let pool_mints: HashSet<Pubkey> = pools.iter().flat_map(|pool| pool.get_mints()).collect();
+    let mints = fetch_mints(connection, &pool_mints.into_iter().collect::<Vec<_>>());
+    let epoch_schedule = connection.get_epoch_schedule().unwrap();
+    let slot = connection.get_slot().unwrap();
+    let pools: Vec<Box<dyn PoolOperations>> = pools
+        .into_iter()
+        .map(|pool| wrap_token_2022(pool, &mints, &epoch_schedule, slot))
+        .collect();
+
+    // swaps are built from the pools' on-chain state
+    let pools: Vec<PoolQuote> = pools
+        .into_iter()
+        .enumerate()
+        .map(|(pool_id, pool)| PoolQuote::new(Arc::new(RwLock::new(pool)), pool_id))
+        .collect();
+    let mut pool_cache = PoolStateCache::new(pools.clone(), cluster);
+    pool_cache.fetch(connection);
+    refresh_routes(&pools, &JUPITER_PROGRAM_ID);
+    let pools: Vec<Box<dyn PoolOperations>> = pools
+        .iter()
+        .filter(|pool| pool_cache.is_loaded(pool.1))
+        .map(|pool| pool.read().clone())
+        .collect();
+    info!("Loaded the state of {:?} pools", pools.len());
+
+    let groups = pool_account_groups(&pools, &program, &owner.pubkey());
+
+    let mut index = LookupTableIndex::load(&args.index).unwrap();
+    let accounts = refresh_index(connection, &mut index, &owner.pubkey());
+    // only active tables can be extended (+ used)
+    let existing: Vec<LookupTable> = accounts
+        .into_iter()
+        .filter(|account| account.is_active())
+        .map(|account| account.table)
+        .collect();
+    let packed = pack_lookup_tables(&groups, &existing);
+
+    let n_new = packed.tables.iter().filter(|plan| plan.address.is_none()).count();
+    let n_addresses: usize = packed.tables.iter().map(|plan| plan.new_addresses.len()).sum();
+    println!(
+        "{} pools in {} tables: creating {}, adding {} addresses, {} stale ({} pools skipped)",
+        groups.len(),
+        packed.tables.len(),
+        n_new,
+        n_addresses,
+        packed.stale.len(),
+        packed.skipped,
+    );
+    if args.dry_run {
+        for plan in &packed.tables {
+            let address = plan.address.map(|a| a.to_string());
+            println!(
+                "  {}: {} + {} addresses",
+                address.unwrap_or_else(|| "(new)".to_string()),
+                plan.addresses.len(),
+                plan.new_addresses.len()
+            );
+        }
+        for address in &packed.stale {
+            println!("  {}: stale", address);
+        }
+        return;
+    }
+
+    for address in &packed.stale {
+        let table = index.get(address).unwrap().table.clone();
+        index.upsert(table, true);
+    }
+    index.save(&args.index).unwrap();
+
+    let mut last_slot = 0;
+    for plan in packed.tables {
+        let mut table = LookupTable {
+            address: Pubkey::default(),
+            addresses: plan.addresses,
+        };
+        table.address = match plan.address {
+            Some(address) => address,
+            None => {
+                // a table's address is seeded by its slot, one table per slot
+                let mut recent_slot = connection.get_slot().unwrap();
+                while recent_slot <= last_slot {
+                    std::thread::sleep(std::time::Duration::from_millis(400));
+                    recent_slot = connection.get_slot().unwrap();
+                }
+                last_slot = recent_slot;
+
+                let (create_ix, address) =
+                    create_lookup_table_ix(&owner.pubkey(), &owner.pubkey(), recent_slot);
+                println!("creating lookup table {}...", address);
+                if !send(connection, &[create_ix], owner) {
+                    warn!("failed to create a lookup table");
+                    continue;
+                }
+                address
+            }
+        };
+
+        let chunks: Vec<&[Pubkey]> = plan.new_addresses.chunks(MAX_EXTEND_ADDRESSES).collect();
+        let pb = ProgressBar::new(chunks.len() as u64);
+        for chunk in chunks {
+            let extend_ix =
+                extend_lookup_table_ix(&table.address, &owner.pubkey(), &owner.pubkey(), chunk);
+            if !send(connection, &[extend_ix], owner) {
+                // the table's later addresses would be out of order
+                warn!("failed to extend lookup table {}", table.address);
+                break;
+            }
+            // only record addresses which made it on chain
+            table.addresses.extend_from_slice(chunk);
+            index.upsert(table.clone(), false);
+            index.save(&args.index).unwrap();
+            pb.inc(1);
+        }
+        pb.finish();
+        index.upsert(table.clone(), false);
+        index.save(&args.index).unwrap();
+        println!("lookup table {}: {} addresses", table.address, table.addresses.len());
+    }
+    println!("{} lookup tables in use", index.active_tables().len());
+}
+
+fn close(args: &Args, connection: &RpcClient, owner: &Keypair) {
+    let mut index = LookupTableIndex::load(&args.index).unwrap();
+    let accounts = refresh_index(connection, &mut index, &owner.pubkey());
+    if !args.dry_run {
+        index.save(&args.index).unwrap();
+    }
+    let current_slot = connection.get_slot().unwrap();
+
+    let mut reclaimed = 0;
+    for account in accounts {
+        let address = account.table.address;
+        if !index.get(&address).unwrap().stale {
+            continue;
+        }
+        if account.is_active() {
+            println!("deactivating lookup table {}...", address);
+            if !args.dry_run {
+                send(connection, &[deactivate_lookup_table_ix(&address, &owner.pubkey())], owner);
+            }
+        } else if account.is_closable(current_slot) {
+            println!("closing lookup table {}...", address);
+            if args.dry_run {
+                continue;
+            }
+            let lamports = connection.get_balance(&address).unwrap();
+            let close_ix = close_lookup_table_ix(&address, &owner.pubkey(), &owner.pubkey());
+            if send(connection, &[close_ix], owner) {
+                index.remove(&address);
+                index.save(&args.index).unwrap();
+                reclaimed += lamports;
+            }
+        } else {
+            println!("lookup table {} is cooling down", address);
+        }
+    }
+    println!(
+        "reclaimed {:?} SOL, {} lookup tables still indexed",
+        reclaimed as f64 / LAMPORTS_PER_SOL,
+        index.0.len()
+    );
+}
+
+fn main() {
+    let args = Args::parse();
+    env_logger::init();
+
+    let owner = read_keypair_file(&args.keypair).unwrap();
+    let cluster = match args.cluster.as_str() {
+        "localnet" => Cluster::Localnet,
+        "mainnet" => Cluster::Mainnet,
+        url => Cluster::Custom(url.to_string(), url.replace("http", "ws")),
+    };
+    info!("Using connection: {}", cluster.url());
+    let connection = RpcClient::new_with_commitment(cluster.url(), CommitmentConfig::confirmed());
+
+    match &args.command {
+        Command::Sync {
+            pool_snapshots,
+            open_orders,
+        } => sync(&args, pool_snapshots, open_orders, cluster, &connection, &owner),
+        Command::Close => close(&args, &connection, &owner),
+    }
+}
@@ -1,153 +1,292 @@
 use anchor_client::solana_client::rpc_client::RpcClient;
-
 use anchor_client::solana_sdk::commitment_config::CommitmentConfig;
-
-use anchor_client::solana_sdk::signature::{Keypair, Signer};
+use anchor_client::solana_sdk::pubkey::Pubkey;
 use anchor_client::solana_sdk::signature::read_keypair_file;
-
+use anchor_client::solana_sdk::signature::{Keypair, Signer};
 use anchor_client::{Client, Cluster};
-
-use client::pools::SerumPool;
-
+use anchor_spl::dex::serum_dex::instruction::{close_open_orders, settle_funds};
+use clap::{Parser, Subcommand};
+use log::{info, warn};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::Instruction;
 use solana_sdk::transaction::Transaction;
-
+use std::collections::HashMap;
 use std::rc::Rc;
-
-use std::collections::{HashMap};
-
-use std::vec;
-
-
-
-
-
-
-
+use std::str::FromStr;
 
 use tmp::accounts as tmp_accounts;
 use tmp::instruction as tmp_instructions;
 
-
-use client::utils::{read_json_dir};
-use client::pool::{PoolType, PoolDir};
 use client::constants::*;
+use client::open_orders::{
+    verify_open_orders, OpenOrdersRegistry, OpenOrdersState, OPEN_ORDERS_LEN, OPEN_ORDERS_PATH,
+};
+use client::pools::SerumPool;
+use client::utils::{derive_token_address, read_json_dir};
 
 use indicatif::ProgressBar;
 
-fn main() {
-    let cluster = Cluster::Localnet;
-
-    env_logger::init();
-    // let owner_kp_path = "../../../mainnet.key";     
-    let owner_kp_path = "../mainnet-fork/localnet_owner.key";   
-    let owner = read_keypair_file(owner_kp_path.clone()).unwrap();   
-
-    // ** setup RPC connection 
-    let connection = RpcClient::new_with_commitment(
-        cluster.url(), 
-        CommitmentConfig::confirmed()
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about = "Manage the owner's serum open orders accounts", long_about = None)]
+pub struct Args {
+    /// localnet | mainnet | an RPC url
+    #[clap(short, long, default_value = "localnet")]
+    pub cluster: String,
+    #[clap(short, long, default_value = "../mainnet-fork/localnet_owner.key")]
+    pub keypair: String,
+    #[clap(long, default_value = "../pools/serum/")]
+    pub serum_dir: String,
+    /// market -> open orders JSON (read by the bot)
+    #[clap(long, default_value = OPEN_ORDERS_PATH)]
+    pub registry: String,
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// verify the registered accounts + create the missing ones
+    Create,
+    /// settle + close open orders accounts to reclaim their rent
+    Close {
+        /// only close these markets' accounts (repeatable, default: all registered)
+        #[clap(long = "market")]
+        markets: Vec<String>,
+    },
+}
+
+fn load_markets(serum_dir: &str) -> HashMap<Pubkey, SerumPool> {
+    let mut markets = HashMap::new();
+    for pool_path in read_json_dir(&serum_dir.to_string()) {
+        let json_str = std::fs::read_to_string(&pool_path).unwrap();
+        match serde_json::from_str::<SerumPool>(&json_str) {
+            Ok(pool) => {
+                markets.insert(pool.own_address.0, pool);
+            }
+            Err(e) => warn!("skipping serum market {}: {}", pool_path, e),
+        }
+    }
+    markets
+}
+
+fn fetch_accounts(connection: &RpcClient, pubkeys: &[Pubkey]) -> Vec<Option<Account>> {
+    let mut accounts = vec![];
+    // max 100 accounts per get_multiple_accounts
+    for chunk in pubkeys.chunks(99) {
+        accounts.extend(connection.get_multiple_accounts(chunk).unwrap());
+    }
+    accounts
+}
+
+/// the registered accounts which check out on chain (the others are dropped)
+fn verified_open_orders(
+    connection: &RpcClient,
+    registry: &mut OpenOrdersRegistry,
+    owner: &Pubkey,
+) -> HashMap<Pubkey, (OpenOrdersState, u64)> {
+    let entries: Vec<(Pubkey, Pubkey)> = registry.0.iter().map(|(m, oo)| (*m, *oo)).collect();
+    let open_orders: Vec<Pubkey> = entries.iter().map(|(_, oo)| *oo).collect();
+    let accounts = fetch_accounts(connection, &open_orders);
+
+    let mut verified = HashMap::new();
+    for ((market, open_orders), account) in entries.into_iter().zip(accounts) {
+        let account = account.as_ref();
+        let onchain = account.map(|account| (&account.owner, account.data.as_slice()));
+        match verify_open_orders(&market, owner, &SERUM_PROGRAM_ID, onchain) {
+            Ok(state) => {
+                verified.insert(market, (state, account.unwrap().lamports));
+            }
+            Err(e) => {
+                warn!("dropping open orders {} of market {}: {}", open_orders, market, e);
+                registry.remove(&market);
+            }
+        }
+    }
+    verified
+}
+
+fn send(connection: &RpcClient, ixs: &[Instruction], signers: &[&Keypair]) -> bool {
+    let recent_hash = connection.get_latest_blockhash().unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        ixs,
+        Some(&signers[0].pubkey()),
+        signers,
+        recent_hash,
+    );
+    match connection.send_and_confirm_transaction(&tx) {
+        Ok(signature) => {
+            info!("signature: {}", signature);
+            true
+        }
+        Err(e) => {
+            warn!("error: {:#?}", e);
+            false
+        }
+    }
+}
+
+fn create(args: &Args, cluster: Cluster, connection: &RpcClient, owner: Keypair) {
+    let mut registry = OpenOrdersRegistry::load(&args.registry).unwrap();
+    let verified = verified_open_orders(connection, &mut registry, &owner.pubkey());
+    registry.save(&args.registry).unwrap();
+
+    let markets = load_markets(&args.serum_dir);
+    let mut missing: Vec<&SerumPool> = markets
+        .values()
+        .filter(|pool| !verified.contains_key(&pool.own_address.0))
+        .collect();
+    missing.sort_by_key(|pool| pool.own_address.0);
+
+    let rent_exemption_amount = connection
+        .get_minimum_balance_for_rent_exemption(OPEN_ORDERS_LEN)
+        .unwrap();
+    let total_rent = rent_exemption_amount * missing.len() as u64;
+    println!(
+        "# open orders: {} registered, {} to create -- rent: {:?} SOL",
+        verified.len(),
+        missing.len(),
+        total_rent as f64 / LAMPORTS_PER_SOL
     );
 
     let provider = Client::new_with_options(
-        cluster, 
-        Rc::new(owner), 
-        CommitmentConfig::confirmed() 
+        cluster,
+        Rc::new(read_keypair_file(&args.keypair).unwrap()),
+        CommitmentConfig::confirmed(),
     );
     let program = provider.program(*ARB_PROGRAM_ID);
-    let owner = read_keypair_file(owner_kp_path.clone()).unwrap();   
-
-    let serum_dir = PoolDir {
-        tipe: PoolType::SerumPoolType,
-        dir_path: "../pools/serum/".to_string(),
-    };    
-
-    let pool_paths = read_json_dir(&serum_dir.dir_path);
-
-    let max_space = 3228; 
-    let max_rent_exemption_amount = connection
-        .get_minimum_balance_for_rent_exemption(max_space)
-        .unwrap();
-    let total_fee = max_rent_exemption_amount * pool_paths.len() as u64; 
-    let lamports_per_sol = 1000000000; 
-    let sol_fee = total_fee as f64 / lamports_per_sol as f64;
-    println!("# open orders: {:?} USDC cost: {:?}", pool_paths.len(), sol_fee * 90_f64);
-    
-    // return; 
-    
-    let mut market_to_open_orders = HashMap::new(); 
-    
-    let pb = ProgressBar::new(pool_paths.len() as u64);
-
-    for pool_path in pool_paths {
-        let json_str = std::fs::read_to_string(&pool_path).unwrap();
-        let pool: SerumPool = serde_json::from_str(&json_str).unwrap(); 
-        
-        // do a swap and check the amount         
-        let mut PROGRAM_LAYOUT_VERSIONS = HashMap::new(); 
-        PROGRAM_LAYOUT_VERSIONS.insert("4ckmDgGdxQoPDLUkDT3vHgSAkzA3QRdNq5ywwY4sUSJn", 1);
-        PROGRAM_LAYOUT_VERSIONS.insert("BJ3jrUzddfuSrZHXSCxMUUQsjKEyLmuuyZebkcaFp2fg", 1);
-        PROGRAM_LAYOUT_VERSIONS.insert("EUqojwWA2rd19FZrzeBncJsm38Jm1hEhE3zsmX3bRc2o", 2);
-        PROGRAM_LAYOUT_VERSIONS.insert("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin", 3);
-
-        let LAYOUT_V1_SPAN = 3220; 
-        let LAYOUT_V2_SPAN = 3228; 
-
-        let layout_v = PROGRAM_LAYOUT_VERSIONS.get(SERUM_PROGRAM_ID.to_string().as_str()).unwrap();
-        let space = if *layout_v == 1 { LAYOUT_V1_SPAN } else { LAYOUT_V2_SPAN };
-        
-        let open_orders = Keypair::new(); 
-        
-        let rent_exemption_amount = connection
-            .get_minimum_balance_for_rent_exemption(space)
-            .unwrap();
 
+    let pb = ProgressBar::new(missing.len() as u64);
+    for pool in missing {
+        let open_orders = Keypair::new();
         let create_account_ix = solana_sdk::system_instruction::create_account(
             &owner.pubkey(),
             &open_orders.pubkey(),
             rent_exemption_amount,
-            space as u64,
+            OPEN_ORDERS_LEN as u64,
             &SERUM_PROGRAM_ID,
         );
-
-        let init_ix = program.request()
+        let init_ix = program
+            .request()
             .accounts(tmp_accounts::InitOpenOrder {
-                open_orders: open_orders.pubkey(), 
-                authority: owner.pubkey(), 
-                market: pool.own_address.0, 
+                open_orders: open_orders.pubkey(),
+                authority: owner.pubkey(),
+                market: pool.own_address.0,
                 dex_program: *SERUM_PROGRAM_ID,
                 rent: solana_sdk::sysvar::rent::id(),
             })
             .args(tmp_instructions::InitOpenOrder {})
             .instructions()
             .unwrap();
+        let ixs = vec![vec![create_account_ix], init_ix].concat();
 
-        let ixs = vec![
-            vec![create_account_ix], 
-            init_ix, 
-        ].concat();
-
-        // wrap as tx 
-        let recent_hash = connection.get_latest_blockhash().unwrap();
-        let tx = Transaction::new_signed_with_payer(
-            &ixs,
-            Some(&owner.pubkey()),
-            &[&owner, &open_orders],
-            recent_hash,
-        );
-        match connection.send_transaction(&tx) {
-            Err(e) => {
-                println!("error: {:#?}", e);
+        // only record accounts which made it on chain
+        if send(connection, &ixs, &[&owner, &open_orders]) {
+            registry.insert(pool.own_address.0, open_orders.pubkey());
+            registry.save(&args.registry).unwrap();
+        }
+        pb.inc(1);
+    }
+    pb.finish();
+    println!("{} open orders accounts registered", registry.len());
+}
+
+fn close(args: &Args, markets: &[String], connection: &RpcClient, owner: Keypair) {
+    let mut registry = OpenOrdersRegistry::load(&args.registry).unwrap();
+    let verified = verified_open_orders(connection, &mut registry, &owner.pubkey());
+    registry.save(&args.registry).unwrap();
+
+    let pools = load_markets(&args.serum_dir);
+    let mut to_close: Vec<Pubkey> = if markets.is_empty() {
+        verified.keys().cloned().collect()
+    } else {
+        markets
+            .iter()
+            .map(|market| Pubkey::from_str(market).expect("invalid market"))
+            .collect()
+    };
+    to_close.sort();
+
+    let mut reclaimed = 0;
+    for market in to_close {
+        let (state, lamports) = match verified.get(&market) {
+            Some(verified) => *verified,
+            None => {
+                warn!("no open orders registered for market {}", market);
+                continue;
             }
-            Ok(_v) => { }
+        };
+        if state.has_open_orders() {
+            warn!("skipping market {}: orders still open", market);
+            continue;
+        }
+        let open_orders = registry.get(&market).unwrap();
+
+        let mut ixs = vec![];
+        if state.has_unsettled_funds() {
+            let pool = match pools.get(&market) {
+                Some(pool) => pool,
+                None => {
+                    warn!("skipping market {}: unsettled funds + no market JSON", market);
+                    continue;
+                }
+            };
+            let settle_ix = settle_funds(
+                &SERUM_PROGRAM_ID,
+                &market,
+                &TOKEN_PROGRAM_ID,
+                &open_orders,
+                &owner.pubkey(),
+                &pool.base_vault.0,
+                &derive_token_address(&owner.pubkey(), &pool.base_mint.0),
+                &pool.quote_vault.0,
+                &derive_token_address(&owner.pubkey(), &pool.quote_mint.0),
+                None,
+                &pool.vault_signer.0,
+            )
+            .unwrap();
+            ixs.push(settle_ix);
         }
+        let close_ix = close_open_orders(
+            &SERUM_PROGRAM_ID,
+            &open_orders,
+            &owner.pubkey(),
+            &owner.pubkey(),
+            &market,
+        )
+        .unwrap();
+        ixs.push(close_ix);
 
-        market_to_open_orders.insert(pool.own_address.0.to_string(), open_orders.pubkey().to_string());
-        
-        pb.inc(1);
+        println!("closing open orders {} of market {}...", open_orders, market);
+        if send(connection, &ixs, &[&owner]) {
+            registry.remove(&market);
+            registry.save(&args.registry).unwrap();
+            reclaimed += lamports;
+        }
     }
+    println!(
+        "reclaimed {:?} SOL, {} open orders accounts still registered",
+        reclaimed as f64 / LAMPORTS_PER_SOL,
+        registry.len()
+    );
+}
 
-    // save open orders accounts as .JSON 
-    let json_market_oo = serde_json::to_string(&market_to_open_orders).unwrap();
-    std::fs::write("./serum_open_orders.json", json_market_oo).unwrap();
+fn main() {
+    let args = Args::parse();
+    env_logger::init();
 
-}
\ No newline at end of file
+    let owner = read_keypair_file(&args.keypair).unwrap();
+    let cluster = match args.cluster.as_str() {
+        "localnet" => Cluster::Localnet,
+        "mainnet" => Cluster::Mainnet,
+        url => Cluster::Custom(url.to_string(), url.replace("http", "ws")),
+    };
+    info!("Using connection: {}", cluster.url());
+    let connection = RpcClient::new_with_commitment(cluster.url(), CommitmentConfig::confirmed());
+
+    match &args.command {
+        Command::Create => create(&args, cluster, &connection, owner),
+        Command::Close { markets } => close(&args, markets, &connection, owner),
+    }
+}
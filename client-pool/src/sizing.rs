@@ -0,0 +1,239 @@
+//! Input sizing for a fixed route
+//!
+//! The output of a route is concave in its input (each hop has diminishing
+//! returns), so net profit `out(x) - x - fees(x)` has a single maximum. For
+//! routes made only of x * y = k pools that maximum has a closed form,
+//! otherwise it is found with a golden-section search over exact quotes.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+
+use crate::cycles::Cycle;
+
+/// Range of scaled input amounts we are willing to swap for a start mint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeBounds {
+    pub min_amount_in: u128,
+    pub max_amount_in: u128,
+}
+
+/// Best input for a route and what it yields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sizing {
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub profit: i128, // net of fees
+}
+
+// golden-section stops once the bracket is this small (relative)
+const SEARCH_TOLERANCE: f64 = 1e-4;
+const MAX_SEARCH_ITERATIONS: usize = 100;
+const INV_PHI: f64 = 0.618_033_988_749_895;
+
+/// `amount_out - amount_in` less the fees (`fee_percentage` of the input)
+pub fn net_profit(amount_in: u128, amount_out: u128, fee_percentage: f64) -> i128 {
+    let fees = (amount_in as f64 * fee_percentage).round() as i128;
+    amount_out as i128 - amount_in as i128 - fees
+}
+
+fn evaluate(cycle: &Cycle, token_mints: &[Pubkey], fee_percentage: f64, amount_in: u128) -> Sizing {
+    let amount_out = cycle.quote(token_mints, amount_in);
+    Sizing {
+        amount_in,
+        amount_out,
+        profit: net_profit(amount_in, amount_out, fee_percentage),
+    }
+}
+
+/// Collapse a route of x * y = k pools into one virtual pool
+/// `out = g x B / (A + g x)`, returns `(A, B, g)` or None if any hop isn't
+/// constant product. Two hops (a1, b1, g1), (a2, b2, g2) compose into
+/// A = a1 a2 / (a2 + g2 b1), B = g2 b1 b2 / (a2 + g2 b1), g = g1.
+fn virtual_constant_product(cycle: &Cycle, token_mints: &[Pubkey]) -> Option<(f64, f64, f64)> {
+    // a pool swapped through twice isn't two independent hops
+    if cycle.reuses_pools() {
+        return None;
+    }
+    let mut virtual_pool: Option<(f64, f64, f64)> = None;
+    for (i, pool) in cycle.pools.iter().enumerate() {
+        let mint_in = &token_mints[cycle.mint_idxs[i]];
+        let mint_out = &token_mints[cycle.mint_idxs[i + 1]];
+        let (a, b, fee) = pool.read().get_constant_product_reserves(mint_in, mint_out)?;
+        let (a, b, g) = (a as f64, b as f64, 1.0 - fee);
+
+        virtual_pool = Some(match virtual_pool {
+            None => (a, b, g),
+            Some((a0, b0, g0)) => {
+                let denom = a + g * b0;
+                (a0 * a / denom, g * b0 * b / denom, g0)
+            }
+        });
+    }
+    virtual_pool
+}
+
+/// closed-form argmax of `g x B / (A + g x) - (1 + p) x`
+fn constant_product_optimum(a: f64, b: f64, g: f64, fee_percentage: f64) -> Option<f64> {
+    let x = ((g * a * b / (1.0 + fee_percentage)).sqrt() - a) / g;
+    if x.is_finite() && x > 0.0 {
+        Some(x)
+    } else {
+        None
+    }
+}
+
+/// golden-section search for the max profit in [lo, hi] (integer amounts)
+fn golden_section(
+    cycle: &Cycle,
+    token_mints: &[Pubkey],
+    fee_percentage: f64,
+    lo: u128,
+    hi: u128,
+) -> Sizing {
+    let eval = |x: f64| evaluate(cycle, token_mints, fee_percentage, x.round() as u128);
+    let (mut lo, mut hi) = (lo as f64, hi as f64);
+    let mut x1 = hi - INV_PHI * (hi - lo);
+    let mut x2 = lo + INV_PHI * (hi - lo);
+    let mut s1 = eval(x1);
+    let mut s2 = eval(x2);
+
+    for _ in 0..MAX_SEARCH_ITERATIONS {
+        if hi - lo <= (hi * SEARCH_TOLERANCE).max(1.0) {
+            break;
+        }
+        if s1.profit < s2.profit {
+            lo = x1;
+            x1 = x2;
+            s1 = s2;
+            x2 = lo + INV_PHI * (hi - lo);
+            s2 = eval(x2);
+        } else {
+            hi = x2;
+            x2 = x1;
+            s2 = s1;
+            x1 = hi - INV_PHI * (hi - lo);
+            s1 = eval(x1);
+        }
+    }
+    if s1.profit >= s2.profit {
+        s1
+    } else {
+        s2
+    }
+}
+
+/// Input amount in `bounds` which maximizes the route's net profit (using
+/// exact integer quotes), or None if no amount in the bounds is profitable
+pub fn optimal_amount_in(
+    cycle: &Cycle,
+    token_mints: &[Pubkey],
+    bounds: &SizeBounds,
+    fee_percentage: f64,
+) -> Option<Sizing> {
+    let SizeBounds {
+        min_amount_in: lo,
+        max_amount_in: hi,
+    } = *bounds;
+    if hi == 0 || lo > hi {
+        return None;
+    }
+
+    let best = match virtual_constant_product(cycle, token_mints) {
+        Some((a, b, g)) => {
+            // closed form, then polish the rounding with a tight exact search
+            let x = constant_product_optimum(a, b, g, fee_percentage)?;
+            let x = (x.round() as u128).clamp(lo, hi);
+            let width = (x / 100).max(1);
+            let candidates = [
+                golden_section(
+                    cycle,
+                    token_mints,
+                    fee_percentage,
+                    x.saturating_sub(width).max(lo),
+                    (x + width).min(hi),
+                ),
+                evaluate(cycle, token_mints, fee_percentage, x),
+            ];
+            candidates.into_iter().max_by_key(|s| s.profit).unwrap()
+        }
+        None => golden_section(cycle, token_mints, fee_percentage, lo, hi),
+    };
+
+    if best.profit > 0 {
+        Some(best)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cycles::{build_edges, find_negative_cycles};
+    use crate::tests::mock::{mock_graph, MockPool};
+
+    fn best_by_scan(cycle: &Cycle, mints: &[Pubkey], lo: u128, hi: u128) -> Sizing {
+        let step = ((hi - lo) / 10_000).max(1);
+        (lo..=hi)
+            .step_by(step as usize)
+            .map(|x| evaluate(cycle, mints, 0.0, x))
+            .max_by_key(|s| s.profit)
+            .unwrap()
+    }
+
+    fn mispriced_cycle(constant_product: bool) -> (Cycle, Vec<Pubkey>) {
+        let mut pools = vec![
+            MockPool::new(0, 1, 1_000_000_000, 1_050_000_000),
+            MockPool::new(1, 2, 2_000_000_000, 2_000_000_000),
+            MockPool::new(2, 0, 500_000_000, 500_000_000),
+        ];
+        if !constant_product {
+            pools[1].constant_product = false;
+        }
+        let (graph, mints) = mock_graph(3, pools);
+        let edges = build_edges(&graph, &mints);
+        let cycle = find_negative_cycles(mints.len(), &edges, &[0], 3, 4)
+            .remove(0)
+            .rotate_to(0)
+            .unwrap();
+        (cycle, mints)
+    }
+
+    #[test]
+    fn closed_form_matches_scan() {
+        let (cycle, mints) = mispriced_cycle(true);
+        let bounds = SizeBounds {
+            min_amount_in: 1,
+            max_amount_in: 100_000_000,
+        };
+        let sizing = optimal_amount_in(&cycle, &mints, &bounds, 0.0).unwrap();
+        let scan = best_by_scan(&cycle, &mints, 1, 100_000_000);
+        assert!(sizing.profit >= scan.profit);
+    }
+
+    #[test]
+    fn golden_section_matches_scan() {
+        let (cycle, mints) = mispriced_cycle(false);
+        let bounds = SizeBounds {
+            min_amount_in: 1,
+            max_amount_in: 100_000_000,
+        };
+        let sizing = optimal_amount_in(&cycle, &mints, &bounds, 0.0).unwrap();
+        let scan = best_by_scan(&cycle, &mints, 1, 100_000_000);
+        // within 0.1% of the brute force optimum
+        assert!(sizing.profit * 1000 >= scan.profit * 999);
+    }
+
+    #[test]
+    fn respects_bounds() {
+        let (cycle, mints) = mispriced_cycle(true);
+        let bounds = SizeBounds {
+            min_amount_in: 1_000,
+            max_amount_in: 2_000,
+        };
+        let sizing = optimal_amount_in(&cycle, &mints, &bounds, 0.0).unwrap();
+        assert!(sizing.amount_in >= 1_000 && sizing.amount_in <= 2_000);
+
+        // fees larger than the edge == nothing to do
+        assert!(optimal_amount_in(&cycle, &mints, &bounds, 0.5).is_none());
+    }
+}
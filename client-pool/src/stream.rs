@@ -0,0 +1,560 @@
+//! Live pool state over websockets
+//!
+//! Every pool update account is streamed with `accountSubscribe` (or a whole
+//! program with `programSubscribe`) and each notification is routed to the
+//! owning pool's `set_update_accounts`. All subscriptions share one socket
+//! (thousands of accounts would otherwise be thousands of connections) but
+//! the node doesn't order notifications across accounts -- the cache
+//! remembers the slot every account was last updated at and drops anything
+//! older. A dropped socket is re-opened (with backoff, see
+//! `reconnect_accounts`) and the pools re-fetched, the notifications missed
+//! in between never come.
+
+use anchor_client::solana_client::rpc_client::RpcClient;
+use anchor_client::solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use anchor_client::solana_client::rpc_response::{Response, RpcKeyedAccount};
+use anchor_client::solana_sdk::commitment_config::CommitmentConfig;
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::Cluster;
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_sdk::account::Account;
+
+use log::{debug, warn};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tungstenite::Message;
+
+use crate::error::StreamError;
+use crate::utils::PoolQuote;
+
+/// One account notification (or fetched account) at `slot`
+#[derive(Debug, Clone)]
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    pub slot: u64,
+    pub account: Option<Account>,
+}
+
+/// What a subscription streams
+#[derive(Debug, Clone, Copy)]
+enum Subscribed {
+    Account(Pubkey),
+    Program, // notifications name their account
+}
+
+/// Open websocket subscriptions -- keep alive for as long as updates are
+/// needed, dropping them closes the socket
+pub struct Subscriptions {
+    n_subscriptions: usize,
+    exit: Arc<AtomicBool>,
+}
+
+impl Subscriptions {
+    pub fn len(&self) -> usize {
+        self.n_subscriptions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// close the socket, which ends every subscription (done by the reader
+    /// thread once its current read returns)
+    pub fn shutdown(self) {
+        self.exit.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        self.exit.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Subscribe to `accounts` (accountSubscribe) and every account owned by
+/// `programs` (programSubscribe) over a single websocket, all notifications
+/// are merged into the returned channel. Returns once every subscription was
+/// confirmed.
+pub fn stream_accounts(
+    ws_url: &str,
+    accounts: &[Pubkey],
+    programs: &[Pubkey],
+    commitment: CommitmentConfig,
+) -> Result<(Subscriptions, Receiver<AccountUpdate>), StreamError> {
+    let (mut socket, _) = tungstenite::connect(ws_url)?;
+    let account_config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(commitment),
+        ..RpcAccountInfoConfig::default()
+    };
+    let program_config = RpcProgramAccountsConfig {
+        account_config: account_config.clone(),
+        ..RpcProgramAccountsConfig::default()
+    };
+
+    // request id -> what it subscribes to
+    let mut requested = HashMap::new();
+    let subscribes = accounts
+        .iter()
+        .map(|pubkey| (Subscribed::Account(*pubkey), json!([pubkey.to_string(), account_config])))
+        .chain(programs.iter().map(|program_id| {
+            (Subscribed::Program, json!([program_id.to_string(), program_config]))
+        }));
+    for (id, (subscribed, params)) in subscribes.enumerate() {
+        let method = match subscribed {
+            Subscribed::Account(_) => "accountSubscribe",
+            Subscribed::Program => "programSubscribe",
+        };
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        socket.write_message(Message::Text(request.to_string()))?;
+        requested.insert(id as u64, subscribed);
+    }
+
+    // confirmations come back interleaved with the first notifications
+    let (sender, receiver) = channel();
+    let mut subscribed = HashMap::new(); // subscription id -> what it streams
+    while subscribed.len() < requested.len() {
+        let message = socket.read_message()?;
+        if let Some(update) = parse_message(message, &requested, &mut subscribed)? {
+            let _ = sender.send(update);
+        }
+    }
+
+    let exit = Arc::new(AtomicBool::new(false));
+    let subscriptions = Subscriptions {
+        n_subscriptions: subscribed.len(),
+        exit: exit.clone(),
+    };
+    thread::spawn(move || {
+        while !exit.load(Ordering::Relaxed) {
+            let message = match socket.read_message() {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("account stream closed: {}", e);
+                    return;
+                }
+            };
+            match parse_message(message, &requested, &mut subscribed) {
+                Ok(Some(update)) => {
+                    if sender.send(update).is_err() {
+                        break; // receiver dropped
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("bad account notification: {}", e),
+            }
+        }
+        let _ = socket.close(None);
+        let _ = socket.write_pending();
+        debug!("account stream closed");
+    });
+
+    Ok((subscriptions, receiver))
+}
+
+/// first delay before re-opening a closed stream
+pub const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(500);
+/// the delay doubles with every failed attempt up to this
+pub const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Delay before the next reconnect attempt -- doubles on every failure (up to
+/// `max`) and is back to `min` once connected
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(RECONNECT_MIN_BACKOFF, RECONNECT_MAX_BACKOFF)
+    }
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self { min, max, next: min }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+}
+
+/// `stream_accounts`, retried after each `backoff` delay until it connects
+pub fn reconnect_accounts(
+    ws_url: &str,
+    accounts: &[Pubkey],
+    programs: &[Pubkey],
+    commitment: CommitmentConfig,
+    backoff: &mut Backoff,
+) -> (Subscriptions, Receiver<AccountUpdate>) {
+    loop {
+        match stream_accounts(ws_url, accounts, programs, commitment) {
+            Ok(stream) => {
+                backoff.reset();
+                return stream;
+            }
+            Err(e) => {
+                let delay = backoff.next_delay();
+                warn!("can't stream accounts: {}, retrying in {:?}", e, delay);
+                thread::sleep(delay);
+            }
+        }
+    }
+}
+
+/// A subscription confirmation is recorded in `subscribed` (None), a
+/// notification is decoded into its update
+fn parse_message(
+    message: Message,
+    requested: &HashMap<u64, Subscribed>,
+    subscribed: &mut HashMap<u64, Subscribed>,
+) -> Result<Option<AccountUpdate>, StreamError> {
+    let text = match message {
+        Message::Text(text) => text,
+        _ => return Ok(None), // pings, close frames
+    };
+    let message: Value = serde_json::from_str(&text)?;
+
+    if let Some(id) = message["id"].as_u64() {
+        if let Some(error) = message.get("error") {
+            return Err(StreamError::Rejected(error.to_string()));
+        }
+        let (target, sub_id) = match (requested.get(&id), message["result"].as_u64()) {
+            (Some(target), Some(sub_id)) => (target, sub_id),
+            _ => return Err(StreamError::UnexpectedMessage(text)),
+        };
+        subscribed.insert(sub_id, *target);
+        return Ok(None);
+    }
+
+    let params = &message["params"];
+    let target = match params["subscription"].as_u64().and_then(|id| subscribed.get(&id)) {
+        Some(target) => target,
+        None => return Err(StreamError::UnexpectedMessage(text)),
+    };
+    let update = match target {
+        Subscribed::Account(pubkey) => {
+            let response: Response<UiAccount> = serde_json::from_value(params["result"].clone())?;
+            AccountUpdate {
+                pubkey: *pubkey,
+                slot: response.context.slot,
+                account: response.value.decode(),
+            }
+        }
+        Subscribed::Program => {
+            let response: Response<RpcKeyedAccount> =
+                serde_json::from_value(params["result"].clone())?;
+            let pubkey = Pubkey::from_str(&response.value.pubkey)
+                .map_err(|_| StreamError::UnexpectedMessage(text.clone()))?;
+            AccountUpdate {
+                pubkey,
+                slot: response.context.slot,
+                account: response.value.account.decode(),
+            }
+        }
+    };
+    Ok(Some(update))
+}
+
+/// Latest update accounts of every pool, pushed into the pools (shared with
+/// the exchange graph) as they change
+pub struct PoolStateCache {
+    pools: Vec<PoolQuote>,                        // pool id -> pool
+    owners: HashMap<Pubkey, Vec<(usize, usize)>>, // account -> (pool id, position in its update accounts)
+    accounts: Vec<Vec<Option<Account>>>,
+    seen: Vec<Vec<bool>>,  // None accounts could be missing on chain or not fetched yet
+    required: Vec<usize>,  // `n_required_update_accounts` of each pool
+    account_slots: HashMap<Pubkey, u64>,
+    pool_slots: Vec<u64>, // latest slot of any of the pool's accounts
+    latest_slot: u64,     // latest slot of any account
+    cluster: Cluster,
+}
+
+impl PoolStateCache {
+    /// `pools[i]` must have id `i`
+    pub fn new(pools: Vec<PoolQuote>, cluster: Cluster) -> Self {
+        let mut owners: HashMap<Pubkey, Vec<(usize, usize)>> = HashMap::new();
+        let mut accounts = vec![];
+        let mut seen = vec![];
+        let mut required = vec![];
+        for (pool_id, pool) in pools.iter().enumerate() {
+            assert_eq!(pool.1, pool_id, "pool ids must match their position");
+            let pool_read = pool.read();
+            let update_accounts = pool_read.get_update_accounts();
+            for (i, pubkey) in update_accounts.iter().enumerate() {
+                owners.entry(*pubkey).or_default().push((pool_id, i));
+            }
+            accounts.push(vec![None; update_accounts.len()]);
+            seen.push(vec![false; update_accounts.len()]);
+            required.push(pool_read.n_required_update_accounts());
+        }
+
+        Self {
+            pool_slots: vec![0; pools.len()],
+            pools,
+            owners,
+            accounts,
+            seen,
+            required,
+            account_slots: HashMap::new(),
+            latest_slot: 0,
+            cluster,
+        }
+    }
+
+    /// every account the pools need (sorted, de-duplicated)
+    pub fn update_accounts(&self) -> Vec<Pubkey> {
+        let mut pubkeys: Vec<Pubkey> = self.owners.keys().cloned().collect();
+        pubkeys.sort();
+        pubkeys
+    }
+
+    /// slot the pool was last updated at, None if it was never fully loaded
+    pub fn last_updated_slot(&self, pool_id: usize) -> Option<u64> {
+        if self.is_loaded(pool_id) {
+            Some(self.pool_slots[pool_id])
+        } else {
+            None
+        }
+    }
+
+    /// true once every update account of the pool was seen (and the
+    /// required ones exist)
+    pub fn is_loaded(&self, pool_id: usize) -> bool {
+        self.seen[pool_id].iter().all(|seen| *seen)
+            && self.accounts[pool_id][..self.required[pool_id]]
+                .iter()
+                .all(|a| a.is_some())
+    }
+
+    /// one-shot load of every update account over RPC (chunks of 99),
+    /// returns the pools which were updated
+    pub fn fetch(&mut self, connection: &RpcClient) -> HashSet<usize> {
+        let mut updated = HashSet::new();
+        for chunk in self.update_accounts().chunks(99) {
+            let response = match connection
+                .get_multiple_accounts_with_commitment(chunk, connection.commitment())
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("Failed to get accounts: {}", e);
+                    continue;
+                }
+            };
+            let slot = response.context.slot;
+            for (pubkey, account) in chunk.iter().zip(response.value) {
+                updated.extend(self.apply(AccountUpdate {
+                    pubkey: *pubkey,
+                    slot,
+                    account,
+                }));
+            }
+        }
+        updated
+    }
+
+    /// route an update to the pools which use the account, returns the ids
+    /// of the pools whose state changed (stale or unknown updates are ignored)
+    pub fn apply(&mut self, update: AccountUpdate) -> Vec<usize> {
+        let owners = match self.owners.get(&update.pubkey) {
+            Some(owners) => owners,
+            None => return vec![],
+        };
+        if let Some(slot) = self.account_slots.get(&update.pubkey) {
+            if update.slot < *slot {
+                debug!("stale update for {} @ {}", update.pubkey, update.slot);
+                return vec![];
+            }
+        }
+        self.account_slots.insert(update.pubkey, update.slot);
+        self.latest_slot = self.latest_slot.max(update.slot);
+
+        let mut updated = vec![];
+        for (pool_id, i) in owners.clone() {
+            self.accounts[pool_id][i] = update.account.clone();
+            self.seen[pool_id][i] = true;
+            self.pool_slots[pool_id] = self.pool_slots[pool_id].max(update.slot);
+            // pools read every update account so wait until they're all in
+            if !self.is_loaded(pool_id) {
+                continue;
+            }
+            let mut pool = self.pools[pool_id].0.write().unwrap();
+            pool.set_slot(self.latest_slot);
+            pool.set_update_accounts(self.accounts[pool_id].clone(), self.cluster.clone());
+            updated.push(pool_id);
+        }
+        updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cycles::{build_edges, find_cycles_touching};
+    use crate::pool::PoolOperations;
+    use crate::tests::mock::{mock_graph_from_quotes, mock_mint, mock_reserve_account, MockPool};
+    use crate::tests::ws::MockPubsubServer;
+    use std::sync::{Arc, RwLock};
+    use std::time::Duration;
+
+    fn pool_with_accounts(idx0: usize, idx1: usize, reserve: u128, accounts: [u8; 2]) -> MockPool {
+        let mut pool = MockPool::new(idx0, idx1, reserve, reserve);
+        pool.update_accounts = accounts
+            .iter()
+            .map(|a| Pubkey::new_from_array([*a; 32]))
+            .collect();
+        pool
+    }
+
+    fn quotes(pools: Vec<MockPool>) -> Vec<PoolQuote> {
+        pools
+            .into_iter()
+            .enumerate()
+            .map(|(id, pool)| {
+                let pool: Box<dyn PoolOperations> = Box::new(pool);
+                PoolQuote::new(Arc::new(RwLock::new(pool)), id)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn routes_updates_and_drops_stale_ones() {
+        // account 100 is shared by pools 0 and 1
+        let pools = quotes(vec![
+            pool_with_accounts(0, 1, 1_000, [100, 101]),
+            pool_with_accounts(1, 2, 1_000, [100, 102]),
+        ]);
+        let mut cache = PoolStateCache::new(pools, Cluster::Localnet);
+        let key = |a: u8| Pubkey::new_from_array([a; 32]);
+        let update = |a: u8, slot: u64, reserve: u64| AccountUpdate {
+            pubkey: key(a),
+            slot,
+            account: Some(mock_reserve_account(reserve)),
+        };
+        assert_eq!(cache.update_accounts(), vec![key(100), key(101), key(102)]);
+
+        // pools wait for every update account
+        assert!(cache.apply(update(100, 5, 2_000)).is_empty());
+        assert_eq!(cache.apply(update(101, 5, 3_000)), vec![0]);
+        assert_eq!(cache.apply(update(102, 6, 4_000)), vec![1]);
+        assert_eq!(cache.last_updated_slot(1), Some(6));
+
+        assert_eq!(cache.apply(update(100, 7, 5_000)), vec![0, 1]);
+        assert!(cache.apply(update(100, 6, 1)).is_empty()); // stale
+        assert!(cache.apply(update(200, 8, 1)).is_empty()); // unknown
+        assert_eq!(cache.last_updated_slot(0), Some(7));
+
+        // reserves were pushed into the shared pool: [5_000, 3_000]
+        let expected = MockPool::new(0, 1, 5_000, 3_000);
+        let (mint0, mint1) = (mock_mint(0), mock_mint(1));
+        assert_eq!(
+            cache.pools[0]
+                .read()
+                .get_quote_with_amounts_scaled(1_000, &mint1, &mint0),
+            expected.get_quote_with_amounts_scaled(1_000, &mint1, &mint0)
+        );
+    }
+
+    #[test]
+    fn waits_for_optional_accounts_to_be_seen() {
+        // account 22 is optional and doesn't exist
+        let mut pool = pool_with_accounts(0, 1, 1_000, [20, 21]);
+        pool.update_accounts.push(Pubkey::new_from_array([22; 32]));
+        pool.required_update_accounts = Some(2);
+        let mut cache = PoolStateCache::new(quotes(vec![pool]), Cluster::Localnet);
+        let update = |a: u8, account: Option<Account>| AccountUpdate {
+            pubkey: Pubkey::new_from_array([a; 32]),
+            slot: 1,
+            account,
+        };
+
+        assert!(cache.apply(update(20, Some(mock_reserve_account(2_000)))).is_empty());
+        assert!(cache.apply(update(21, None)).is_empty()); // required
+        assert!(cache.apply(update(22, None)).is_empty());
+        assert!(!cache.is_loaded(0));
+        assert_eq!(cache.apply(update(21, Some(mock_reserve_account(3_000)))), vec![0]);
+        assert!(cache.is_loaded(0));
+    }
+
+    #[test]
+    fn backs_off_until_connected() {
+        let mut backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(5));
+        let delays: Vec<Duration> = (0..4).map(|_| backoff.next_delay()).collect();
+        assert_eq!(delays, [1, 2, 4, 5].map(Duration::from_millis));
+
+        // connecting starts the next outage from the shortest delay again
+        let server = MockPubsubServer::start();
+        let pubkey = Pubkey::new_from_array([30; 32]);
+        server.script(pubkey, vec![(3, mock_reserve_account(1_000))]);
+        let (subscriptions, updates) = reconnect_accounts(
+            &server.url(),
+            &[pubkey],
+            &[],
+            CommitmentConfig::confirmed(),
+            &mut backoff,
+        );
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(updates.recv_timeout(Duration::from_secs(5)).unwrap().slot, 3);
+        assert_eq!(backoff.next_delay(), Duration::from_millis(1));
+    }
+
+    #[test]
+    fn streams_updates_from_websocket() {
+        // 0 -> 1 -> 2 -> 0 starts fair, then pool 2 is repriced
+        let pools = quotes(vec![
+            pool_with_accounts(0, 1, 1_000_000_000, [10, 11]),
+            pool_with_accounts(1, 2, 1_000_000_000, [12, 13]),
+            pool_with_accounts(2, 0, 1_000_000_000, [14, 15]),
+        ]);
+        let mut cache = PoolStateCache::new(pools.clone(), Cluster::Localnet);
+        let pubkeys = cache.update_accounts();
+
+        let server = MockPubsubServer::start();
+        for pubkey in &pubkeys {
+            let mut notifications = vec![(10, mock_reserve_account(1_000_000_000))];
+            if *pubkey == Pubkey::new_from_array([15; 32]) {
+                notifications.push((12, mock_reserve_account(1_200_000_000)));
+                notifications.push((11, mock_reserve_account(1))); // stale
+            }
+            server.script(*pubkey, notifications);
+        }
+
+        let (subscriptions, updates) =
+            stream_accounts(&server.url(), &pubkeys, &[], CommitmentConfig::confirmed()).unwrap();
+        assert_eq!(subscriptions.len(), pubkeys.len());
+        assert_eq!(server.n_connections(), 1);
+
+        let mut changed = HashSet::new();
+        for _ in 0..pubkeys.len() + 2 {
+            let update = updates.recv_timeout(Duration::from_secs(5)).unwrap();
+            changed.extend(cache.apply(update));
+        }
+        subscriptions.shutdown();
+
+        assert_eq!(changed, HashSet::from([0, 1, 2]));
+        assert_eq!(cache.last_updated_slot(2), Some(12));
+
+        // only cycles through the repriced pool are searched
+        let (graph, mints) = mock_graph_from_quotes(3, &pools);
+        let edges = build_edges(&graph, &mints);
+        let cycles = find_cycles_touching(mints.len(), &edges, &HashSet::from([2]), 3);
+        assert_eq!(cycles.len(), 1);
+        assert!(cycles[0].pools.iter().any(|p| p.1 == 2));
+        assert!(cycles[0].quote(&mints, 1_000_000) > 1_000_000);
+    }
+}
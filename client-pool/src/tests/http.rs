@@ -0,0 +1,158 @@
+//! Local stand-in for the Jupiter quote API
+//!
+//! Answers `GET /quote` with a route's quote and `POST /swap-instructions`
+//! with its swap instruction, one request per connection, and keeps every
+//! request (line + body) for the test to look at.
+use anchor_client::solana_sdk::pubkey::Pubkey;
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::pool_utils::jupiter::SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR;
+
+pub struct MockQuoteServer {
+    port: u16,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockQuoteServer {
+    /// serves `route` (a `JupiterRoute` as JSON)
+    pub fn start(route: Value) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let server_requests = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+                serve(stream, &route, &server_requests);
+            }
+        });
+        Self { port, requests }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    /// "<request line> <body>" of every request so far
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn serve(stream: TcpStream, route: &Value, requests: &Mutex<Vec<String>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).is_err() || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    if reader.read_exact(&mut body).is_err() {
+        return;
+    }
+    let request_line = request_line.trim().to_string();
+    requests
+        .lock()
+        .unwrap()
+        .push(format!("{} {}", request_line, String::from_utf8_lossy(&body)));
+
+    let (status, response) = if request_line.starts_with("GET /quote?") {
+        ("200 OK", route["quote"].clone())
+    } else if request_line.starts_with("POST /swap-instructions") {
+        ("200 OK", json!({ "swapInstruction": route["swapInstruction"] }))
+    } else {
+        ("404 Not Found", json!({ "error": "not found" }))
+    };
+    let response = response.to_string();
+    let mut stream = stream;
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        response.len(),
+        response
+    );
+}
+
+/// a one step `JupiterRoute` (as JSON) swapping `in_amount` of `input_mint`
+/// for `out_amount` of `output_mint` with the program `program_id`
+pub fn route_json(
+    program_id: &Pubkey,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    in_amount: u64,
+    out_amount: u64,
+) -> Value {
+    let mut data = SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR.to_vec();
+    data.push(0); // id
+    data.extend_from_slice(&1_u32.to_le_bytes()); // route plan: 1 step
+    data.extend_from_slice(&[17, 1]); // swap (whirlpool, a_to_b)
+    data.extend_from_slice(&[100, 0, 1]); // percent, input + output index
+    data.extend_from_slice(&in_amount.to_le_bytes());
+    data.extend_from_slice(&out_amount.to_le_bytes());
+    data.extend_from_slice(&50_u16.to_le_bytes()); // slippage_bps
+    data.push(0); // platform_fee_bps
+
+    // 13 fixed accounts + the step's pool accounts
+    let accounts: Vec<Value> = (0..16_u8)
+        .map(|i| {
+            json!({
+                "pubkey": Pubkey::new_from_array([100 + i; 32]).to_string(),
+                "isSigner": i == 2,
+                "isWritable": ![0, 1, 7, 8, 10, 11, 12].contains(&i),
+            })
+        })
+        .collect();
+
+    let amm_key = Pubkey::new_from_array([200; 32]).to_string();
+    json!({
+        "quote": {
+            "inputMint": input_mint.to_string(),
+            "inAmount": in_amount.to_string(),
+            "outputMint": output_mint.to_string(),
+            "outAmount": out_amount.to_string(),
+            "otherAmountThreshold": (out_amount - out_amount / 200).to_string(),
+            "swapMode": "ExactIn",
+            "slippageBps": 50,
+            "priceImpactPct": "0",
+            "routePlan": [{
+                "swapInfo": {
+                    "ammKey": amm_key,
+                    "label": "Whirlpool",
+                    "inputMint": input_mint.to_string(),
+                    "outputMint": output_mint.to_string(),
+                    "inAmount": in_amount.to_string(),
+                    "outAmount": out_amount.to_string(),
+                    "feeAmount": "0",
+                    "feeMint": input_mint.to_string(),
+                },
+                "percent": 100,
+            }],
+        },
+        "swapInstruction": {
+            "programId": program_id.to_string(),
+            "accounts": accounts,
+            "data": base64::encode(&data),
+        },
+    })
+}
@@ -0,0 +1,220 @@
+//! In-memory constant-product pools for testing the search without an RPC
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::{Cluster, Program};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::error::QuoteError;
+use crate::pool::{PoolOperations, Quote};
+use crate::pool_utils::base::SwapResult;
+use crate::utils::{PoolEdge, PoolGraph, PoolIndex, PoolQuote};
+
+pub fn mock_mint(idx: usize) -> Pubkey {
+    Pubkey::new_from_array([idx as u8 + 1; 32])
+}
+
+/// x * y = k pool with a 0.3% fee and 6 decimal mints
+#[derive(Debug, Clone)]
+pub struct MockPool {
+    pub mint_idxs: [usize; 2],
+    pub reserves: [u128; 2],
+    pub fee_numerator: u128,
+    pub fee_denominator: u128,
+    pub constant_product: bool, // expose the reserves for closed-form sizing
+    pub update_accounts: Vec<Pubkey>, // account i holds reserve i (see `mock_reserve_account`)
+    pub required_update_accounts: Option<usize>, // None == all of them
+}
+
+impl MockPool {
+    pub fn new(idx0: usize, idx1: usize, reserve0: u128, reserve1: u128) -> Self {
+        Self {
+            mint_idxs: [idx0, idx1],
+            reserves: [reserve0, reserve1],
+            fee_numerator: 3,
+            fee_denominator: 1000,
+            constant_product: true,
+            update_accounts: vec![],
+            required_update_accounts: None,
+        }
+    }
+
+    fn side(&self, mint: &Pubkey) -> usize {
+        if *mint == mock_mint(self.mint_idxs[0]) {
+            0
+        } else if *mint == mock_mint(self.mint_idxs[1]) {
+            1
+        } else {
+            panic!("invalid mint {}", mint)
+        }
+    }
+}
+
+impl PoolOperations for MockPool {
+    fn get_name(&self) -> String {
+        "Mock".to_string()
+    }
+
+    fn get_update_accounts(&self) -> Vec<Pubkey> {
+        self.update_accounts.clone()
+    }
+
+    fn n_required_update_accounts(&self) -> usize {
+        self.required_update_accounts
+            .unwrap_or(self.update_accounts.len())
+    }
+
+    fn set_update_accounts(&mut self, accounts: Vec<Option<Account>>, _cluster: Cluster) {
+        for (reserve, account) in self.reserves.iter_mut().zip(accounts) {
+            let data = account.unwrap().data;
+            *reserve = u64::from_le_bytes(data[..8].try_into().unwrap()) as u128;
+        }
+    }
+
+    fn mint_2_addr(&self, mint: &Pubkey) -> Result<Pubkey, QuoteError> {
+        Ok(*mint)
+    }
+
+    fn get_mints(&self) -> Vec<Pubkey> {
+        let mut mints: Vec<Pubkey> = self.mint_idxs.iter().map(|i| mock_mint(*i)).collect();
+        mints.sort();
+        mints
+    }
+
+    fn mint_2_scale(&self, _mint: &Pubkey) -> Result<u64, QuoteError> {
+        Ok(6)
+    }
+
+    fn get_quote(
+        &self,
+        amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        if !self.get_mints().contains(mint_in) || !self.get_mints().contains(mint_out) {
+            return Err(QuoteError::InvalidMints {
+                mint_in: *mint_in,
+                mint_out: *mint_out,
+            });
+        }
+        let reserve_in = self.reserves[self.side(mint_in)];
+        let reserve_out = self.reserves[self.side(mint_out)];
+        let amount_in_less_fees = amount_in * (self.fee_denominator - self.fee_numerator);
+        let amount_out = reserve_out * amount_in_less_fees
+            / (reserve_in * self.fee_denominator + amount_in_less_fees);
+        let result = SwapResult {
+            new_swap_source_amount: reserve_in + amount_in,
+            new_swap_destination_amount: reserve_out - amount_out,
+            source_amount_swapped: amount_in,
+            destination_amount_swapped: amount_out,
+            trade_fee: amount_in - amount_in_less_fees / self.fee_denominator,
+            owner_fee: 0,
+        };
+        Ok(Quote::from_swap_result(
+            &result,
+            reserve_out as f64 / reserve_in as f64,
+        ))
+    }
+
+    fn simulate_swap(
+        &mut self,
+        amount_in: u128,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Result<Quote, QuoteError> {
+        let quote = self.get_quote(amount_in, mint_in, mint_out)?;
+        let (reserve_in, reserve_out) = quote.new_reserves.unwrap();
+        self.reserves[self.side(mint_in)] = reserve_in;
+        self.reserves[self.side(mint_out)] = reserve_out;
+        Ok(quote)
+    }
+
+    fn get_constant_product_reserves(
+        &self,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Option<(u128, u128, f64)> {
+        if !self.constant_product {
+            return None;
+        }
+        Some((
+            self.reserves[self.side(mint_in)],
+            self.reserves[self.side(mint_out)],
+            self.fee_numerator as f64 / self.fee_denominator as f64,
+        ))
+    }
+
+    // mock pools can't be swapped on chain -- a no-op ix which names the
+    // swap (mints) and locks the pool's update accounts
+    fn swap_ix(
+        &self,
+        _program: &Program,
+        _owner: &Pubkey,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+    ) -> Vec<Instruction> {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(*mint_in, false),
+            AccountMeta::new_readonly(*mint_out, false),
+        ];
+        accounts.extend(self.update_accounts.iter().map(|a| AccountMeta::new(*a, false)));
+        vec![Instruction {
+            program_id: Pubkey::default(),
+            accounts,
+            data: vec![],
+        }]
+    }
+
+    fn can_trade(&self, _mint_in: &Pubkey, _mint_out: &Pubkey) -> bool {
+        self.reserves.iter().all(|r| *r > 0)
+    }
+}
+
+/// account holding a mock pool reserve
+pub fn mock_reserve_account(reserve: u64) -> Account {
+    Account {
+        lamports: 1,
+        data: reserve.to_le_bytes().to_vec(),
+        owner: Pubkey::default(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// graph over mints `0..n_mints` with both directions of each pool (pool id = position)
+pub fn mock_graph(n_mints: usize, pools: Vec<MockPool>) -> (PoolGraph, Vec<Pubkey>) {
+    let quotes: Vec<PoolQuote> = pools
+        .into_iter()
+        .enumerate()
+        .map(|(id, pool)| {
+            let pool: Box<dyn PoolOperations> = Box::new(pool);
+            PoolQuote::new(Arc::new(RwLock::new(pool)), id)
+        })
+        .collect();
+    mock_graph_from_quotes(n_mints, &quotes)
+}
+
+/// same as `mock_graph` but sharing already wrapped pools
+pub fn mock_graph_from_quotes(n_mints: usize, quotes: &[PoolQuote]) -> (PoolGraph, Vec<Pubkey>) {
+    let token_mints: Vec<Pubkey> = (0..n_mints).map(mock_mint).collect();
+    let mut graph = PoolGraph::new();
+
+    for quote in quotes {
+        let mints = quote.read().get_mints();
+        let idx0 = token_mints.iter().position(|m| *m == mints[0]).unwrap();
+        let idx1 = token_mints.iter().position(|m| *m == mints[1]).unwrap();
+        for (src, dst) in [(idx0, idx1), (idx1, idx0)] {
+            graph
+                .0
+                .entry(PoolIndex(src))
+                .or_insert_with(|| PoolEdge(HashMap::new()))
+                .0
+                .entry(PoolIndex(dst))
+                .or_default()
+                .push(quote.clone());
+        }
+    }
+    (graph, token_mints)
+}
@@ -1 +1,8 @@
-pub mod quotes;
\ No newline at end of file
+#[cfg(test)]
+pub mod http;
+#[cfg(test)]
+pub mod mock;
+#[cfg(test)]
+pub mod quotes;
+#[cfg(test)]
+pub mod ws;
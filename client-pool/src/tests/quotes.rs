@@ -15,6 +15,7 @@ use tmp::accounts as tmp_accounts;
 use tmp::instruction as tmp_ix;
 
 use crate::utils::{derive_token_address, read_json_dir};
+use crate::error::QuoteError;
 use crate::pool::{PoolType, PoolOperations, pool_factory};
 use crate::constants::*;
 
@@ -27,23 +28,44 @@ fn serum() {
 }
 
 #[test]
-fn aldrin() {
-    let pool_dir = "../pools/aldrin/".to_string();
-    let pool_tipe = PoolType::AldrinPoolType; 
+fn raydium() {
+    let pool_dir = "../pools/raydium/".to_string();
+    let pool_tipe = PoolType::RaydiumPoolType; 
     test_all_pool_quotes(pool_dir, pool_tipe);
 }
 
 #[test]
-fn saber() {
-    let pool_dir = "../pools/saber/".to_string();
-    let pool_tipe = PoolType::SaberPoolType; 
+fn jupiter() {
+    let pool_dir = "../pools/jupiter/".to_string();
+    let pool_tipe = PoolType::JupiterPoolType; 
     test_all_pool_quotes(pool_dir, pool_tipe);
 }
 
 #[test]
-fn mercurial() {
-    let pool_dir = "../pools/mercurial/".to_string();
-    let pool_tipe = PoolType::MercurialPoolType; 
+fn whirlpool() {
+    let pool_dir = "../pools/whirlpool/".to_string();
+    let pool_tipe = PoolType::WhirlpoolPoolType; 
+    test_all_pool_quotes(pool_dir, pool_tipe);
+}
+
+#[test]
+fn meteora() {
+    let pool_dir = "../pools/meteora/".to_string();
+    let pool_tipe = PoolType::MeteoraPoolType; 
+    test_all_pool_quotes(pool_dir, pool_tipe);
+}
+
+#[test]
+fn phoenix() {
+    let pool_dir = "../pools/phoenix/".to_string();
+    let pool_tipe = PoolType::PhoenixPoolType; 
+    test_all_pool_quotes(pool_dir, pool_tipe);
+}
+
+#[test]
+fn lifinity() {
+    let pool_dir = "../pools/lifinity/".to_string();
+    let pool_tipe = PoolType::LifinityPoolType; 
     test_all_pool_quotes(pool_dir, pool_tipe);
 }
 
@@ -85,8 +107,14 @@ fn test_all_pool_quotes(
     
     for pool_path in pool_paths {        
 
-        let contents = std::fs::read_to_string(&pool_path).unwrap();
-        let mut pool = pool_factory(&pool_tipe, &contents);
+        let mut pool = match pool_factory(&pool_tipe, &pool_path) {
+            Ok(pool) => pool,
+            Err(e) => {
+                println!("skipping pool: {}", e);
+                err_count += 1;
+                continue
+            }
+        };
 
         // println!("{}", pool_path);
         let err_flag = test_pool_quote(
@@ -114,12 +142,16 @@ fn test_pool_quote(
             .get_multiple_accounts(&update_accounts)
             .unwrap();
     pool.set_update_accounts(accounts, Cluster::Localnet);
+    // routes aren't streamed, a jupiter pool's is fetched here
+    if let Some(request) = pool.route_request() {
+        pool.set_route(request.fetch(&JUPITER_PROGRAM_ID).ok());
+    }
 
     // get a quote 
     let pool_mints = pool.get_mints(); 
     let mint_in = &pool_mints[0];
     let mint_out = &pool_mints[1];
-    let src_scale = pool.mint_2_scale(mint_in);
+    let src_scale = pool.mint_2_scale(mint_in).unwrap();
 
     let src_ata = derive_token_address(&owner.pubkey(), mint_in);
     let _dst_ata = derive_token_address(&owner.pubkey(), mint_out);
@@ -147,22 +179,26 @@ fn test_pool_quote(
     let mut loop_count = 0; 
     let mut quote_out_amount;
     loop {
-        quote_out_amount = pool.get_quote_with_amounts_scaled(
-            amount_in, 
-            mint_in, 
-            mint_out
-        );
+        quote_out_amount = match pool.get_quote(amount_in, mint_in, mint_out) {
+            Ok(quote) => quote.amount_out,
+            // too small to get anything out
+            Err(QuoteError::CalculationFailure) => 0,
+            Err(e) => {
+                println!("pool path: {}", pool_path);
+                println!("pool cant quote: {}", e);
+                return 1;
+            }
+        };
         // println!("quote: {}", quote_out_amount);
 
-        if quote_out_amount == 0 {
-            amount_in += 100 * 10_u128.pow(src_scale as u32);
+        if quote_out_amount > 0 {
+            break;
         } else if loop_count > 100_000 {
             println!("pool path: {}", pool_path);
             println!("loop count error!");
             return 1; // error             
-        } else { 
-            break; 
         }
+        amount_in += 100 * 10_u128.pow(src_scale as u32);
         loop_count += 1 
     }
 
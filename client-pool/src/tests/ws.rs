@@ -0,0 +1,116 @@
+//! Local stand-in for the validator's pubsub websocket
+//!
+//! Answers `accountSubscribe` requests and then replays the notifications
+//! scripted for that account, any number of subscriptions per socket.
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_sdk::account::Account;
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tungstenite::{accept, Message};
+
+type Script = Arc<Mutex<HashMap<Pubkey, Vec<(u64, Account)>>>>;
+
+pub struct MockPubsubServer {
+    port: u16,
+    script: Script,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockPubsubServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let script: Script = Arc::new(Mutex::new(HashMap::new()));
+
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let server_script = script.clone();
+        let server_connections = connections.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+                server_connections.fetch_add(1, Ordering::SeqCst);
+                let script = server_script.clone();
+                thread::spawn(move || serve(stream, script));
+            }
+        });
+        Self {
+            port,
+            script,
+            connections,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://127.0.0.1:{}", self.port)
+    }
+
+    /// sockets accepted so far
+    pub fn n_connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// notifications (slot, account) sent right after `pubkey` is subscribed to
+    pub fn script(&self, pubkey: Pubkey, notifications: Vec<(u64, Account)>) {
+        self.script.lock().unwrap().insert(pubkey, notifications);
+    }
+}
+
+fn serve(stream: std::net::TcpStream, script: Script) {
+    let mut socket = match accept(stream) {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    let mut next_sub_id = 0;
+
+    while let Ok(message) = socket.read_message() {
+        let request: Value = match message {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        if request["method"] != "accountSubscribe" {
+            let reply = json!({"jsonrpc": "2.0", "result": true, "id": request["id"]});
+            let _ = socket.write_message(Message::Text(reply.to_string()));
+            continue;
+        }
+        let sub_id = next_sub_id;
+        next_sub_id += 1;
+        let reply = json!({"jsonrpc": "2.0", "result": sub_id, "id": request["id"]});
+        socket
+            .write_message(Message::Text(reply.to_string()))
+            .unwrap();
+
+        let pubkey = Pubkey::from_str(request["params"][0].as_str().unwrap()).unwrap();
+        let notifications = script
+            .lock()
+            .unwrap()
+            .get(&pubkey)
+            .cloned()
+            .unwrap_or_default();
+        for (slot, account) in notifications {
+            let value = UiAccount::encode(&pubkey, &account, UiAccountEncoding::Base64, None, None);
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "accountNotification",
+                "params": {
+                    "result": {"context": {"slot": slot}, "value": value},
+                    "subscription": sub_id,
+                },
+            });
+            socket
+                .write_message(Message::Text(notification.to_string()))
+                .unwrap();
+        }
+    }
+}
@@ -3,10 +3,14 @@ use crate::pool::PoolOperations;
 use anchor_client::solana_sdk::pubkey::Pubkey;
 use std::collections::HashMap;
 use std::fs;
-use std::rc::Rc;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 use std::str::FromStr;
 
+// pool files in `dir` (.json or .jsonl) -- or `dir` itself if it's a single pool file
 pub fn read_json_dir(dir: &String) -> Vec<String> {
+    if std::path::Path::new(dir).is_file() {
+        return vec![dir.clone()];
+    }
     let _paths = fs::read_dir(dir).unwrap();
     let mut paths = Vec::new();
     for path in _paths {
@@ -14,7 +18,7 @@ pub fn read_json_dir(dir: &String) -> Vec<String> {
         let path_str = p;
         match path_str.extension() {
             Some(ex) => {
-                if ex == "json" {
+                if ex == "json" || ex == "jsonl" {
                     let path = path_str.to_str().unwrap().to_string();
                     paths.push(path);
                 }
@@ -30,10 +34,25 @@ pub fn str2pubkey(s: &str) -> Pubkey {
 }
 
 pub fn derive_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    derive_token_address_with_program(owner, mint, &TOKEN_PROGRAM_ID)
+}
+
+// `mint`'s program in `token_programs` (mint -> its token program), the
+// classic token program for mints which aren't in it
+pub fn token_program_of(token_programs: &HashMap<Pubkey, Pubkey>, mint: &Pubkey) -> Pubkey {
+    token_programs.get(mint).copied().unwrap_or(*TOKEN_PROGRAM_ID)
+}
+
+// ATAs are derived with the program owning the mint (token or token-2022)
+pub fn derive_token_address_with_program(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Pubkey {
     let (pda, _) = Pubkey::find_program_address(
         &[
             &owner.to_bytes(),
-            &TOKEN_PROGRAM_ID.to_bytes(),
+            &token_program.to_bytes(),
             &mint.to_bytes(),
         ],
         &ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -41,12 +60,18 @@ pub fn derive_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
     pda
 }
 
+// pool + its id (= position in the loaded pool list) so cycles can be keyed by pool
+// -- the lock lets the pool state cache push account updates into the graph
 #[derive(Debug, Clone)]
-pub struct PoolQuote(pub Rc<Box<dyn PoolOperations>>);
+pub struct PoolQuote(pub Arc<RwLock<Box<dyn PoolOperations>>>, pub usize);
 
 impl PoolQuote {
-    pub fn new(quote: Rc<Box<dyn PoolOperations>>) -> Self {
-        Self(quote)
+    pub fn new(quote: Arc<RwLock<Box<dyn PoolOperations>>>, id: usize) -> Self {
+        Self(quote, id)
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, Box<dyn PoolOperations>> {
+        self.0.read().unwrap()
     }
 }
 
@@ -0,0 +1,428 @@
+//! v0 transactions -- legacy messages + address table lookups
+//!
+//! A legacy message lists every account key it uses (32 bytes each), which
+//! caps a route at a few swaps. A v0 message keeps the signers + programs in
+//! its own key list and loads every other account found in one of its lookup
+//! tables by index. Accounts are indexed static keys first, then each table's
+//! writable loads, then each table's readonly loads -- at most 256 in all.
+//!
+//! Compiled + serialized here (not with the SDK's versioned types) and sent
+//! as a base64 encoded wire transaction.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::{CompiledInstruction, Instruction};
+use solana_sdk::message::MessageHeader;
+
+use std::collections::HashMap;
+
+use crate::error::MessageCompileError;
+use crate::lookup_table::LookupTable;
+
+/// max serialized transaction size (= packet data size)
+pub const PACKET_DATA_SIZE: usize = 1232;
+/// most accounts a message can index (indexes are u8s)
+pub const MAX_ACCOUNTS: usize = 256;
+
+const MESSAGE_VERSION_PREFIX: u8 = 0x80;
+
+/// The accounts a message loads from one lookup table
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageAddressTableLookup {
+    pub account_key: Pubkey,
+    pub writable_indexes: Vec<u8>,
+    pub readonly_indexes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct V0Message {
+    pub header: MessageHeader,
+    pub account_keys: Vec<Pubkey>, // static keys
+    pub recent_blockhash: Hash,
+    pub instructions: Vec<CompiledInstruction>,
+    pub address_table_lookups: Vec<MessageAddressTableLookup>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct KeyMeta {
+    is_signer: bool,
+    is_writable: bool,
+    is_program: bool,
+}
+
+impl V0Message {
+    /// `ixs` paid for by `payer`, with every account which can be loaded from
+    /// one of `lookup_tables` (the first table holding it) loaded from it
+    pub fn try_compile(
+        payer: &Pubkey,
+        ixs: &[Instruction],
+        lookup_tables: &[LookupTable],
+        recent_blockhash: Hash,
+    ) -> Result<Self, MessageCompileError> {
+        // every key (in first use order) with its merged metas
+        let mut keys = vec![*payer];
+        let mut metas: HashMap<Pubkey, KeyMeta> = HashMap::new();
+        metas.insert(
+            *payer,
+            KeyMeta {
+                is_signer: true,
+                is_writable: true,
+                is_program: false,
+            },
+        );
+        for ix in ixs {
+            let program_meta = (ix.program_id, false, false, true);
+            let account_metas = ix
+                .accounts
+                .iter()
+                .map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable, false));
+            for (key, is_signer, is_writable, is_program) in
+                account_metas.chain(std::iter::once(program_meta))
+            {
+                let meta = metas.entry(key).or_insert_with(|| {
+                    keys.push(key);
+                    KeyMeta::default()
+                });
+                meta.is_signer |= is_signer;
+                meta.is_writable |= is_writable;
+                meta.is_program |= is_program;
+            }
+        }
+
+        // table loads, the others are static
+        let table_indexes: Vec<HashMap<&Pubkey, usize>> = lookup_tables
+            .iter()
+            .map(|table| {
+                let mut indexes = HashMap::new();
+                for (i, address) in table.addresses.iter().enumerate() {
+                    indexes.entry(address).or_insert(i);
+                }
+                indexes
+            })
+            .collect();
+        let mut lookups: Vec<(MessageAddressTableLookup, Vec<Pubkey>, Vec<Pubkey>)> =
+            lookup_tables
+                .iter()
+                .map(|table| {
+                    let lookup = MessageAddressTableLookup {
+                        account_key: table.address,
+                        ..MessageAddressTableLookup::default()
+                    };
+                    (lookup, vec![], vec![])
+                })
+                .collect();
+        let mut static_keys = [vec![], vec![], vec![], vec![]];
+        for key in &keys {
+            let meta = metas[key];
+            let table = if meta.is_signer || meta.is_program {
+                None
+            } else {
+                table_indexes
+                    .iter()
+                    .enumerate()
+                    .find_map(|(t, indexes)| Some((t, *indexes.get(key)?)))
+            };
+            match table {
+                Some((t, index)) if index < MAX_ACCOUNTS => {
+                    let (lookup, writable, readonly) = &mut lookups[t];
+                    if meta.is_writable {
+                        lookup.writable_indexes.push(index as u8);
+                        writable.push(*key);
+                    } else {
+                        lookup.readonly_indexes.push(index as u8);
+                        readonly.push(*key);
+                    }
+                }
+                _ => {
+                    // writable signers, readonly signers, writable + readonly non-signers
+                    let group = match (meta.is_signer, meta.is_writable) {
+                        (true, true) => 0,
+                        (true, false) => 1,
+                        (false, true) => 2,
+                        (false, false) => 3,
+                    };
+                    static_keys[group].push(*key);
+                }
+            }
+        }
+        lookups.retain(|(lookup, _, _)| {
+            !lookup.writable_indexes.is_empty() || !lookup.readonly_indexes.is_empty()
+        });
+
+        let header = MessageHeader {
+            num_required_signatures: (static_keys[0].len() + static_keys[1].len()) as u8,
+            num_readonly_signed_accounts: static_keys[1].len() as u8,
+            num_readonly_unsigned_accounts: static_keys[3].len() as u8,
+        };
+        let account_keys: Vec<Pubkey> = static_keys.concat();
+        let mut all_keys = account_keys.clone();
+        all_keys.extend(lookups.iter().flat_map(|(_, writable, _)| writable));
+        all_keys.extend(lookups.iter().flat_map(|(_, _, readonly)| readonly));
+        if all_keys.len() > MAX_ACCOUNTS {
+            return Err(MessageCompileError::TooManyAccounts(all_keys.len()));
+        }
+
+        let positions: HashMap<&Pubkey, u8> = all_keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (key, i as u8))
+            .collect();
+        let instructions = ixs
+            .iter()
+            .map(|ix| CompiledInstruction {
+                program_id_index: positions[&ix.program_id],
+                accounts: ix.accounts.iter().map(|meta| positions[&meta.pubkey]).collect(),
+                data: ix.data.clone(),
+            })
+            .collect();
+
+        Ok(Self {
+            header,
+            account_keys,
+            recent_blockhash,
+            instructions,
+            address_table_lookups: lookups.into_iter().map(|(lookup, _, _)| lookup).collect(),
+        })
+    }
+
+    /// the signed bytes
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec![MESSAGE_VERSION_PREFIX];
+        buf.push(self.header.num_required_signatures);
+        buf.push(self.header.num_readonly_signed_accounts);
+        buf.push(self.header.num_readonly_unsigned_accounts);
+        push_compact_u16(&mut buf, self.account_keys.len());
+        for key in &self.account_keys {
+            buf.extend_from_slice(&key.to_bytes());
+        }
+        buf.extend_from_slice(self.recent_blockhash.as_ref());
+        push_compact_u16(&mut buf, self.instructions.len());
+        for ix in &self.instructions {
+            buf.push(ix.program_id_index);
+            push_compact_u16(&mut buf, ix.accounts.len());
+            buf.extend_from_slice(&ix.accounts);
+            push_compact_u16(&mut buf, ix.data.len());
+            buf.extend_from_slice(&ix.data);
+        }
+        push_compact_u16(&mut buf, self.address_table_lookups.len());
+        for lookup in &self.address_table_lookups {
+            buf.extend_from_slice(&lookup.account_key.to_bytes());
+            push_compact_u16(&mut buf, lookup.writable_indexes.len());
+            buf.extend_from_slice(&lookup.writable_indexes);
+            push_compact_u16(&mut buf, lookup.readonly_indexes.len());
+            buf.extend_from_slice(&lookup.readonly_indexes);
+        }
+        buf
+    }
+
+    pub fn signer_keys(&self) -> &[Pubkey] {
+        &self.account_keys[..self.header.num_required_signatures as usize]
+    }
+
+    /// serialized size of the message once signed
+    pub fn transaction_size(&self) -> usize {
+        let n_signatures = self.header.num_required_signatures as usize;
+        compact_u16_len(n_signatures) + 64 * n_signatures + self.serialize().len()
+    }
+}
+
+/// A signed v0 transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionedTransaction {
+    pub signatures: Vec<Signature>,
+    pub message: V0Message,
+}
+
+impl VersionedTransaction {
+    /// `message` signed by each of its signers (found in `keypairs`)
+    pub fn try_new(message: V0Message, keypairs: &[&Keypair]) -> Result<Self, MessageCompileError> {
+        let message_bytes = message.serialize();
+        let signatures = message
+            .signer_keys()
+            .iter()
+            .map(|key| {
+                let keypair = keypairs
+                    .iter()
+                    .find(|keypair| keypair.pubkey() == *key)
+                    .ok_or(MessageCompileError::MissingSigner(*key))?;
+                Ok(keypair.sign_message(&message_bytes))
+            })
+            .collect::<Result<Vec<Signature>, MessageCompileError>>()?;
+        Ok(Self {
+            signatures,
+            message,
+        })
+    }
+
+    /// the wire format
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        push_compact_u16(&mut buf, self.signatures.len());
+        for signature in &self.signatures {
+            buf.extend_from_slice(signature.as_ref());
+        }
+        buf.extend(self.message.serialize());
+        buf
+    }
+
+    pub fn to_base64(&self) -> String {
+        base64::encode(self.serialize())
+    }
+}
+
+/// bytes of `n` encoded as a compact-u16 (the length prefix of arrays)
+pub fn compact_u16_len(n: usize) -> usize {
+    match n {
+        0..=0x7f => 1,
+        0x80..=0x3fff => 2,
+        _ => 3,
+    }
+}
+
+fn push_compact_u16(buf: &mut Vec<u8>, n: usize) {
+    let mut rem = n as u16;
+    loop {
+        let byte = (rem & 0x7f) as u8;
+        rem >>= 7;
+        if rem == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::AccountMeta;
+
+    fn swap_ix(program: &Pubkey, owner: &Pubkey, pool_accounts: &[Pubkey]) -> Instruction {
+        let mut accounts = vec![AccountMeta::new_readonly(*owner, true)];
+        for (i, account) in pool_accounts.iter().enumerate() {
+            // vaults are written, the rest read
+            accounts.push(match i % 2 {
+                0 => AccountMeta::new(*account, false),
+                _ => AccountMeta::new_readonly(*account, false),
+            });
+        }
+        Instruction {
+            program_id: *program,
+            accounts,
+            data: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn encodes_compact_u16s() {
+        for (n, bytes) in [
+            (0, vec![0]),
+            (0x7f, vec![0x7f]),
+            (0x80, vec![0x80, 0x01]),
+            (0x3fff, vec![0xff, 0x7f]),
+            (0x4000, vec![0x80, 0x80, 0x01]),
+        ] {
+            let mut buf = vec![];
+            push_compact_u16(&mut buf, n);
+            assert_eq!(buf, bytes);
+            assert_eq!(compact_u16_len(n), bytes.len());
+        }
+    }
+
+    #[test]
+    fn loads_accounts_from_tables() {
+        let owner = Keypair::new();
+        let program = Pubkey::new_unique();
+        let pool_accounts: Vec<Pubkey> = (0..40).map(|_| Pubkey::new_unique()).collect();
+        let ixs: Vec<Instruction> = pool_accounts
+            .chunks(8)
+            .map(|accounts| swap_ix(&program, &owner.pubkey(), accounts))
+            .collect();
+
+        // too many keys for a legacy transaction
+        let legacy = V0Message::try_compile(&owner.pubkey(), &ixs, &[], Hash::default()).unwrap();
+        assert_eq!(legacy.account_keys.len(), 42);
+        assert!(legacy.transaction_size() > PACKET_DATA_SIZE);
+
+        let tables = [
+            LookupTable {
+                address: Pubkey::new_unique(),
+                addresses: pool_accounts[..30].to_vec(),
+            },
+            LookupTable {
+                address: Pubkey::new_unique(),
+                // the program can't be loaded, a signer neither
+                addresses: [&[program, owner.pubkey()], &pool_accounts[25..]].concat(),
+            },
+            LookupTable {
+                address: Pubkey::new_unique(),
+                addresses: pool_accounts.clone(), // everything is already loaded
+            },
+        ];
+        let message =
+            V0Message::try_compile(&owner.pubkey(), &ixs, &tables, Hash::default()).unwrap();
+        assert_eq!(message.account_keys, vec![owner.pubkey(), program]);
+        assert_eq!(
+            (
+                message.header.num_required_signatures,
+                message.header.num_readonly_signed_accounts,
+                message.header.num_readonly_unsigned_accounts
+            ),
+            (1, 0, 1)
+        );
+        assert_eq!(message.address_table_lookups.len(), 2);
+        let lookup = &message.address_table_lookups[1];
+        assert_eq!(lookup.writable_indexes, vec![7, 9, 11, 13, 15]); // accounts 30, 32, ..
+        assert_eq!(lookup.readonly_indexes, vec![8, 10, 12, 14, 16]);
+        assert!(message.transaction_size() <= PACKET_DATA_SIZE);
+
+        // indexes resolve to the instructions' accounts
+        let mut resolved = message.account_keys.clone();
+        for writable in [true, false] {
+            for lookup in &message.address_table_lookups {
+                let table = tables.iter().find(|t| t.address == lookup.account_key).unwrap();
+                let indexes = if writable {
+                    &lookup.writable_indexes
+                } else {
+                    &lookup.readonly_indexes
+                };
+                resolved.extend(indexes.iter().map(|i| table.addresses[*i as usize]));
+            }
+        }
+        for (ix, compiled) in ixs.iter().zip(&message.instructions) {
+            assert_eq!(resolved[compiled.program_id_index as usize], ix.program_id);
+            let accounts: Vec<Pubkey> = compiled
+                .accounts
+                .iter()
+                .map(|i| resolved[*i as usize])
+                .collect();
+            let expected: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+            assert_eq!(accounts, expected);
+        }
+
+        let tx = VersionedTransaction::try_new(message.clone(), &[&owner]).unwrap();
+        let bytes = tx.serialize();
+        assert_eq!(bytes.len(), message.transaction_size());
+        assert_eq!((bytes[0], bytes[65]), (1, MESSAGE_VERSION_PREFIX));
+        assert_eq!(
+            VersionedTransaction::try_new(message, &[&Keypair::new()]),
+            Err(MessageCompileError::MissingSigner(owner.pubkey()))
+        );
+    }
+
+    #[test]
+    fn rejects_too_many_accounts() {
+        let owner = Pubkey::new_unique();
+        let program = Pubkey::new_unique();
+        let pool_accounts: Vec<Pubkey> = (0..300).map(|_| Pubkey::new_unique()).collect();
+        let ixs: Vec<Instruction> = pool_accounts
+            .chunks(30)
+            .map(|accounts| swap_ix(&program, &owner, accounts))
+            .collect();
+        assert_eq!(
+            V0Message::try_compile(&owner, &ixs, &[], Hash::default()),
+            Err(MessageCompileError::TooManyAccounts(302))
+        );
+    }
+}
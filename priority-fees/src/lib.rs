@@ -0,0 +1,208 @@
+//! Priority fee estimates from the fees recently paid to lock an account
+//!
+//! `getRecentPrioritizationFees` reports, per recent slot, the lowest priority
+//! fee (micro lamports per unit) which landed a transaction write-locking the
+//! queried accounts. A route competes with the other writers of its pool
+//! accounts, so each writable account keeps a rolling history of its own fees
+//! and a route is priced at the highest percentile among its accounts.
+//! Accounts are queried one at a time -- a query for many accounts only
+//! reports transactions which locked all of them.
+//!
+//! The history is kept by account key, whichever solana version's `Pubkey`
+//! that is -- the bots query the rpc + pick a route's accounts themselves.
+
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// slots of fees kept per account (about what the rpc keeps)
+pub const DEFAULT_WINDOW_SLOTS: u64 = 150;
+/// how long an account's fees are used before they're re-queried
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+/// rpc method reporting the recent fees of the accounts it's given
+pub const RECENT_PRIORITIZATION_FEES_METHOD: &str = "getRecentPrioritizationFees";
+
+/// How badly a route needs to land -- the percentile of recent fees it bids
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Urgency {
+    Low,
+    Medium,
+    High,
+    Max,
+}
+
+impl Urgency {
+    pub fn percentile(&self) -> f64 {
+        match self {
+            Urgency::Low => 25.0,
+            Urgency::Medium => 50.0,
+            Urgency::High => 75.0,
+            Urgency::Max => 95.0,
+        }
+    }
+}
+
+/// An entry of a `getRecentPrioritizationFees` response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentPrioritizationFee {
+    pub slot: u64,
+    pub prioritization_fee: u64,
+}
+
+/// Rolling per-account history of recent prioritization fees
+#[derive(Debug)]
+pub struct PriorityFeeEstimator<K> {
+    pub window_slots: u64,
+    pub refresh_interval: Duration,
+    history: HashMap<K, VecDeque<RecentPrioritizationFee>>, // oldest slot first
+    refreshed: HashMap<K, Instant>,
+}
+
+impl<K: Copy + Eq + Hash> Default for PriorityFeeEstimator<K> {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW_SLOTS, DEFAULT_REFRESH_INTERVAL)
+    }
+}
+
+impl<K: Copy + Eq + Hash> PriorityFeeEstimator<K> {
+    pub fn new(window_slots: u64, refresh_interval: Duration) -> Self {
+        Self {
+            window_slots,
+            refresh_interval,
+            history: HashMap::new(),
+            refreshed: HashMap::new(),
+        }
+    }
+
+    /// add the fees of slots newer than `account`'s history + drop the fees
+    /// which fell out of the window, `account` counts as refreshed
+    pub fn record(&mut self, account: K, fees: &[RecentPrioritizationFee]) {
+        let mut fees = fees.to_vec();
+        fees.sort_by_key(|fee| fee.slot);
+
+        let history = self.history.entry(account).or_default();
+        for fee in fees {
+            // responses overlap -- only the new slots are added
+            match history.back() {
+                Some(last) if fee.slot <= last.slot => continue,
+                _ => history.push_back(fee),
+            }
+        }
+        if let Some(newest_slot) = history.back().map(|fee| fee.slot) {
+            while let Some(oldest) = history.front() {
+                if oldest.slot + self.window_slots > newest_slot {
+                    break;
+                }
+                history.pop_front();
+            }
+        }
+        self.refreshed.insert(account, Instant::now());
+    }
+
+    /// the `percentile` (0-100, nearest rank) of `account`'s recent fees --
+    /// None without any history
+    pub fn percentile(&self, account: &K, percentile: f64) -> Option<u64> {
+        let history = self.history.get(account).filter(|h| !h.is_empty())?;
+        let mut fees: Vec<u64> = history.iter().map(|fee| fee.prioritization_fee).collect();
+        fees.sort_unstable();
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * fees.len() as f64).ceil() as usize;
+        Some(fees[rank.clamp(1, fees.len()) - 1])
+    }
+
+    /// price (micro lamports per unit) for a route writing `accounts` to land
+    /// at `urgency` -- the highest among them, 0 if none of them have history
+    pub fn price_for(&self, accounts: &[K], urgency: Urgency) -> u64 {
+        accounts
+            .iter()
+            .filter_map(|account| self.percentile(account, urgency.percentile()))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// the `accounts` which weren't refreshed in the last `refresh_interval`
+    pub fn stale_accounts(&self, accounts: &[K], now: Instant) -> Vec<K> {
+        accounts
+            .iter()
+            .filter(|account| match self.refreshed.get(account) {
+                Some(refreshed) => now.duration_since(*refreshed) >= self.refresh_interval,
+                None => true,
+            })
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fees(fees: &[(u64, u64)]) -> Vec<RecentPrioritizationFee> {
+        fees.iter()
+            .map(|(slot, prioritization_fee)| RecentPrioritizationFee {
+                slot: *slot,
+                prioritization_fee: *prioritization_fee,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_a_rolling_history() {
+        let account = 1_u8;
+        let mut estimator = PriorityFeeEstimator::new(4, DEFAULT_REFRESH_INTERVAL);
+        estimator.record(account, &fees(&[(11, 400), (10, 100), (12, 200)]));
+        assert_eq!(estimator.percentile(&account, 50.0), Some(200));
+
+        // overlapping slots aren't counted twice + old slots fall out
+        estimator.record(account, &fees(&[(12, 900), (13, 300), (14, 0)]));
+        assert_eq!(estimator.percentile(&account, 0.0), Some(0));
+        assert_eq!(estimator.percentile(&account, 50.0), Some(200));
+        assert_eq!(estimator.percentile(&account, 100.0), Some(400));
+        assert_eq!(estimator.history[&account].len(), 4);
+        assert_eq!(estimator.percentile(&2, 50.0), None);
+
+        let parsed: Vec<RecentPrioritizationFee> = serde_json::from_value(serde_json::json!([
+            { "slot": 348125, "prioritizationFee": 1000 }
+        ]))
+        .unwrap();
+        assert_eq!(parsed, fees(&[(348125, 1000)]));
+    }
+
+    #[test]
+    fn prices_the_highest_account() {
+        let [hot, cold, unseen] = [1_u8, 2, 3];
+        let mut estimator = PriorityFeeEstimator::default();
+        estimator.record(hot, &fees(&[(1, 10), (2, 20), (3, 30), (4, 40)]));
+        estimator.record(cold, &fees(&[(1, 0), (2, 0), (3, 5), (4, 5)]));
+
+        assert_eq!(estimator.price_for(&[cold, hot], Urgency::Low), 10);
+        assert_eq!(estimator.price_for(&[cold, hot], Urgency::Medium), 20);
+        assert_eq!(estimator.price_for(&[cold, hot, unseen], Urgency::Max), 40);
+        assert_eq!(estimator.price_for(&[cold], Urgency::High), 5);
+        assert_eq!(estimator.price_for(&[], Urgency::Max), 0);
+    }
+
+    #[test]
+    fn refreshes_stale_accounts() {
+        let [fresh, stale, unseen] = [1_u8, 2, 3];
+        let mut estimator = PriorityFeeEstimator::new(DEFAULT_WINDOW_SLOTS, Duration::from_secs(2));
+        let now = Instant::now();
+        estimator
+            .refreshed
+            .insert(fresh, now - Duration::from_secs(1));
+        estimator
+            .refreshed
+            .insert(stale, now - Duration::from_secs(3));
+        assert_eq!(
+            estimator.stale_accounts(&[fresh, stale, unseen], now),
+            vec![stale, unseen]
+        );
+
+        estimator.record(stale, &[]);
+        assert!(estimator
+            .stale_accounts(&[stale], Instant::now())
+            .is_empty());
+    }
+}
@@ -1,20 +1,235 @@
-use crate::utils::solana::send_transaction;
+use crate::config::JitoConfig;
+use crate::error::{MevBotError, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
 use solana_client::rpc_client::RpcClient;
+use solana_sdk::hash::Hash;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
 use solana_sdk::transaction::Transaction;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const MAX_BUNDLE_SIZE: usize = 5;
+pub const BUNDLE_STATUS_TIMEOUT: Duration = Duration::from_secs(30);
+pub const BUNDLE_STATUS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const BUNDLES_PATH: &str = "/api/v1/bundles";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BundleOutcome {
+    Landed { slot: u64 },
+    Failed { error: String },
+    // unknown to the block engine, or still pending at the timeout (outbid,
+    // expired blockhash...)
+    Dropped,
+}
+
+// where the block engine is with a bundle it was sent in the last 5 minutes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum InflightBundleState {
+    // not a bundle id it knows of (never received, or too old)
+    Invalid,
+    // not failed, not landed yet
+    Pending,
+    // every region failed to get it into a block (or it was auctioned off)
+    Failed,
+    // landed on chain -- confirmed with getBundleStatuses
+    Landed,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InflightBundleStatus {
+    pub bundle_id: String,
+    pub status: InflightBundleState,
+    pub landed_slot: Option<u64>,
+}
+
+impl InflightBundleStatus {
+    // None while the bundle is still in flight, or landed -- the landing is
+    // confirmed by its `BundleStatus`
+    pub fn outcome(&self) -> Option<BundleOutcome> {
+        match self.status {
+            InflightBundleState::Pending | InflightBundleState::Landed => None,
+            InflightBundleState::Failed => Some(BundleOutcome::Failed {
+                error: format!("bundle {} failed to land", self.bundle_id),
+            }),
+            InflightBundleState::Invalid => Some(BundleOutcome::Dropped),
+        }
+    }
+}
+
+// a landed bundle, as getBundleStatuses reports it
+#[derive(Debug, Clone, Deserialize)]
+pub struct BundleStatus {
+    pub bundle_id: String,
+    pub transactions: Vec<String>, // signatures, in bundle order
+    pub slot: u64,
+    pub confirmation_status: String, // processed | confirmed | finalized
+    pub err: Value,                  // {"Ok": null} when every transaction succeeded
+}
+
+impl BundleStatus {
+    // None until the landing is confirmed -- a processed slot can still be
+    // skipped
+    pub fn outcome(&self) -> Option<BundleOutcome> {
+        if self.confirmation_status == "processed" {
+            return None;
+        }
+        if self.err.get("Ok").is_some() {
+            Some(BundleOutcome::Landed { slot: self.slot })
+        } else {
+            Some(BundleOutcome::Failed {
+                error: format!("bundle {} landed in slot {} but failed: {}", self.bundle_id, self.slot, self.err),
+            })
+        }
+    }
+}
 
 pub struct FlashbotsClient {
-    rpc_client: RpcClient,
+    rpc_client: Arc<RpcClient>,
+    http_client: reqwest::Client,
+    config: JitoConfig,
 }
 
 impl FlashbotsClient {
-    pub fn new(rpc_client: RpcClient) -> Self {
-        Self { rpc_client }
+    pub fn new(rpc_client: RpcClient, config: JitoConfig) -> Self {
+        Self {
+            rpc_client: Arc::new(rpc_client),
+            http_client: reqwest::Client::new(),
+            config,
+        }
     }
 
-    pub async fn send_bundle(&self, txs: &[Transaction]) -> Result<(), Box<dyn std::error::Error>> {
-        for tx in txs {
-            send_transaction(&self.rpc_client, tx).await?;
+    // `txs` followed by a transfer of the tip from `payer` -- bundles execute
+    // all or nothing, so the tip is only paid when every transaction lands
+    pub fn build_bundle(
+        &self,
+        txs: &[Transaction],
+        payer: &Keypair,
+        recent_blockhash: Hash,
+    ) -> Result<Vec<Transaction>> {
+        if txs.is_empty() || txs.len() >= MAX_BUNDLE_SIZE {
+            return Err(MevBotError::BundleError(format!(
+                "a bundle takes 1 to {} transactions besides the tip, got {}",
+                MAX_BUNDLE_SIZE - 1,
+                txs.len()
+            )));
         }
-        Ok(())
+        let tip_ix = system_instruction::transfer(
+            &payer.pubkey(),
+            &self.config.tip_account,
+            self.config.tip_lamports,
+        );
+        let tip_tx = Transaction::new_signed_with_payer(
+            &[tip_ix],
+            Some(&payer.pubkey()),
+            &[payer],
+            recent_blockhash,
+        );
+
+        let mut bundle = txs.to_vec();
+        bundle.push(tip_tx);
+        Ok(bundle)
+    }
+
+    // returns the bundle id
+    pub async fn send_bundle(&self, bundle: &[Transaction]) -> Result<String> {
+        let encoded_txs = bundle
+            .iter()
+            .map(|tx| {
+                bincode::serialize(tx)
+                    .map(base64::encode)
+                    .map_err(|e| MevBotError::BundleError(e.to_string()))
+            })
+            .collect::<Result<Vec<String>>>()?;
+        self.request("sendBundle", json!([encoded_txs, { "encoding": "base64" }]))
+            .await
+    }
+
+    // the pending, failed + invalid states of a bundle sent in the last 5
+    // minutes -- None if it isn't reported at all
+    pub async fn get_inflight_bundle_status(&self, bundle_id: &str) -> Result<Option<InflightBundleStatus>> {
+        self.first_status("getInflightBundleStatuses", bundle_id).await
     }
-}
\ No newline at end of file
+
+    // the slot, confirmation + error of a landed bundle -- None until it's
+    // reported landed
+    pub async fn get_bundle_status(&self, bundle_id: &str) -> Result<Option<BundleStatus>> {
+        self.first_status("getBundleStatuses", bundle_id).await
+    }
+
+    async fn first_status<T: serde::de::DeserializeOwned>(&self, method: &str, bundle_id: &str) -> Result<Option<T>> {
+        let response: Value = self.request(method, json!([[bundle_id]])).await?;
+        let status = match response["value"].get(0) {
+            Some(status) if !status.is_null() => Some(serde_json::from_value(status.clone())?),
+            _ => None,
+        };
+        Ok(status)
+    }
+
+    pub async fn wait_for_bundle(
+        &self,
+        bundle_id: &str,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<BundleOutcome> {
+        let start = Instant::now();
+        loop {
+            let outcome = match self.get_inflight_bundle_status(bundle_id).await? {
+                Some(status) if status.status == InflightBundleState::Landed => self
+                    .get_bundle_status(bundle_id)
+                    .await?
+                    .and_then(|status| status.outcome()),
+                Some(status) => status.outcome(),
+                None => None,
+            };
+            if let Some(outcome) = outcome {
+                return Ok(outcome);
+            }
+            if start.elapsed() >= timeout {
+                return Ok(BundleOutcome::Dropped);
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    pub async fn submit_bundle(&self, txs: &[Transaction], payer: &Keypair) -> Result<BundleOutcome> {
+        // the rpc client blocks, keep it off the runtime's threads
+        let rpc_client = self.rpc_client.clone();
+        let recent_blockhash = tokio::task::spawn_blocking(move || rpc_client.get_latest_blockhash())
+            .await
+            .map_err(|e| MevBotError::BundleError(e.to_string()))??;
+        let bundle = self.build_bundle(txs, payer, recent_blockhash)?;
+        let bundle_id = self.send_bundle(&bundle).await?;
+        log::info!("sent bundle {} ({} txs)", bundle_id, bundle.len());
+
+        let outcome = self
+            .wait_for_bundle(&bundle_id, BUNDLE_STATUS_TIMEOUT, BUNDLE_STATUS_POLL_INTERVAL)
+            .await?;
+        log::info!("bundle {}: {:?}", bundle_id, outcome);
+        Ok(outcome)
+    }
+
+    async fn request<T: serde::de::DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        let url = format!("{}{}", self.config.block_engine_url.trim_end_matches('/'), BUNDLES_PATH);
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let response: Value = self
+            .http_client
+            .post(url)
+            .json(&body)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            return Err(MevBotError::BundleError(format!("{} failed: {}", method, error)));
+        }
+        Ok(serde_json::from_value(response["result"].clone())?)
+    }
+}
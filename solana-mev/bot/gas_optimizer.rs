@@ -1,16 +1,120 @@
+use crate::bot::priority_fee_estimator::{writable_accounts, PriorityFeeEstimator, Urgency};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
 use solana_sdk::transaction::Transaction;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
 
-pub struct GasOptimizer {}
+const COMPUTE_BUDGET_PROGRAM_ID: &str = "ComputeBudget111111111111111111111111111111";
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+
+pub struct GasOptimizer {
+    estimator: Arc<RwLock<PriorityFeeEstimator>>,
+    watched: Arc<RwLock<BTreeSet<Pubkey>>>, // accounts the refresher keeps fresh
+    urgency: Urgency,
+}
 
 impl GasOptimizer {
-    pub fn new(_rpc_client: solana_client::rpc_client::RpcClient) -> Self {
-        Self {}
+    // prices from `estimator`, the fees of the accounts it's asked about
+    // refreshed by a refresher over `watched` (see `spawn_refresher`)
+    pub fn new(
+        estimator: Arc<RwLock<PriorityFeeEstimator>>,
+        watched: Arc<RwLock<BTreeSet<Pubkey>>>,
+    ) -> Self {
+        Self {
+            estimator,
+            watched,
+            urgency: Urgency::Medium,
+        }
+    }
+
+    // the more the market moves, the sooner an opportunity is gone
+    pub fn update(&mut self, market_conditions: &crate::models::market_conditions::MarketConditions) {
+        self.urgency = match market_conditions.volatility {
+            v if v < 0.01 => Urgency::Low,
+            v if v < 0.05 => Urgency::Medium,
+            v if v < 0.1 => Urgency::High,
+            _ => Urgency::Max,
+        };
     }
 
-    pub fn update(&mut self, _market_conditions: &crate::models::market_conditions::MarketConditions) {}
+    // prices each transaction at the recent fees of the accounts it writes,
+    // only reading what the refresher already fetched. a priced transaction
+    // is re-signed by `payer` with its original blockhash; unpriced ones, +
+    // the ones other keys have to sign, are returned as they were
+    pub async fn optimize(&self, txs: &[Transaction], payer: &Keypair) -> Vec<Transaction> {
+        let mut optimized_txs = Vec::with_capacity(txs.len());
+        for tx in txs {
+            let accounts = writable_accounts(&tx.message);
+            self.watched.write().unwrap().extend(accounts.iter().copied());
+            let micro_lamports = self.estimator.read().unwrap().price_for(&accounts, self.urgency);
+            if micro_lamports == 0
+                || has_compute_unit_price(&tx.message)
+                || !is_signed_by_payer_only(&tx.message, payer)
+            {
+                optimized_txs.push(tx.clone());
+                continue;
+            }
 
-    pub async fn optimize(&self, txs: &[Transaction]) -> Vec<Transaction> {
-        let optimized_txs = txs.to_vec();
+            let mut instructions = vec![set_compute_unit_price_ix(micro_lamports)];
+            instructions.extend(decompile(&tx.message));
+            let message = Message::new(&instructions, Some(&payer.pubkey()));
+            optimized_txs.push(Transaction::new(&[payer], message, tx.message.recent_blockhash));
+        }
         optimized_txs
     }
-}
\ No newline at end of file
+}
+
+fn compute_budget_program_id() -> Pubkey {
+    Pubkey::from_str(COMPUTE_BUDGET_PROGRAM_ID).unwrap()
+}
+
+pub fn set_compute_unit_price_ix(micro_lamports: u64) -> Instruction {
+    let mut data = vec![SET_COMPUTE_UNIT_PRICE];
+    data.extend_from_slice(&micro_lamports.to_le_bytes());
+    Instruction {
+        program_id: compute_budget_program_id(),
+        accounts: vec![],
+        data,
+    }
+}
+
+// only the payer's signature can be redone here
+fn is_signed_by_payer_only(message: &Message, payer: &Keypair) -> bool {
+    message.header.num_required_signatures == 1
+        && message.account_keys.first() == Some(&payer.pubkey())
+}
+
+fn has_compute_unit_price(message: &Message) -> bool {
+    let program_id = compute_budget_program_id();
+    message.instructions.iter().any(|ix| {
+        message.account_keys[ix.program_id_index as usize] == program_id
+            && ix.data.first() == Some(&SET_COMPUTE_UNIT_PRICE)
+    })
+}
+
+fn decompile(message: &Message) -> Vec<Instruction> {
+    message
+        .instructions
+        .iter()
+        .map(|ix| Instruction {
+            program_id: message.account_keys[ix.program_id_index as usize],
+            accounts: ix
+                .accounts
+                .iter()
+                .map(|i| {
+                    let i = *i as usize;
+                    AccountMeta {
+                        pubkey: message.account_keys[i],
+                        is_signer: message.is_signer(i),
+                        is_writable: message.is_writable(i),
+                    }
+                })
+                .collect(),
+            data: ix.data.clone(),
+        })
+        .collect()
+}
@@ -1,5 +1,5 @@
 pub mod solana_mev_bot;
-pub mod flashbots_client;
+pub mod flashbot_client;
 pub mod simulation_engine;
 pub mod optimizer;
 pub mod risk_manager;
@@ -9,6 +9,7 @@ pub mod monitoring_manager;
 pub mod copy_trade_manager;
 pub mod sniping_manager;
 pub mod gas_optimizer;
+pub mod priority_fee_estimator;
 pub mod path_finder;
 pub mod trade_executor;
 pub mod cross_chain_manager;
@@ -0,0 +1,76 @@
+// the estimator is shared with client-pool (see the priority-fees crate),
+// this side picks a message's writable accounts + queries their fees
+use serde_json::json;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_request::RpcRequest;
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+pub use priority_fees::{
+    RecentPrioritizationFee, Urgency, DEFAULT_REFRESH_INTERVAL, DEFAULT_WINDOW_SLOTS,
+    RECENT_PRIORITIZATION_FEES_METHOD,
+};
+
+pub type PriorityFeeEstimator = priority_fees::PriorityFeeEstimator<Pubkey>;
+
+// accounts the route write-locks, signers aside -- those are the ones other
+// transactions compete for
+pub fn writable_accounts(route: &Message) -> Vec<Pubkey> {
+    let accounts: BTreeSet<Pubkey> = route
+        .account_keys
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| route.is_writable(*i) && !route.is_signer(*i))
+        .map(|(_, key)| *key)
+        .collect();
+    accounts.into_iter().collect()
+}
+
+// one request per stale account: a multi-account query only reports
+// transactions which locked all of them. an account which fails keeps its
+// old history (the others are still refreshed), returns how many were
+pub fn refresh(
+    estimator: &RwLock<PriorityFeeEstimator>,
+    rpc_client: &RpcClient,
+    accounts: &[Pubkey],
+) -> usize {
+    let stale_accounts = estimator.read().unwrap().stale_accounts(accounts, Instant::now());
+    let mut n_refreshed = 0;
+    for account in stale_accounts {
+        let fees: std::result::Result<Vec<RecentPrioritizationFee>, _> = rpc_client.send(
+            RpcRequest::Custom {
+                method: RECENT_PRIORITIZATION_FEES_METHOD,
+            },
+            json!([[account.to_string()]]),
+        );
+        match fees {
+            Ok(fees) => {
+                estimator.write().unwrap().record(account, &fees);
+                n_refreshed += 1;
+            }
+            Err(e) => log::warn!("can't get recent prioritization fees of {}: {}", account, e),
+        }
+    }
+    n_refreshed
+}
+
+// keeps the fees of the `watched` accounts fresh off the send path,
+// re-querying them every `refresh_interval` on a thread of its own (the rpc
+// client blocks)
+pub fn spawn_refresher(
+    rpc_client: RpcClient,
+    estimator: Arc<RwLock<PriorityFeeEstimator>>,
+    watched: Arc<RwLock<BTreeSet<Pubkey>>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        let accounts: Vec<Pubkey> = watched.read().unwrap().iter().copied().collect();
+        let n_refreshed = refresh(&estimator, &rpc_client, &accounts);
+        log::debug!("refreshed the prioritization fees of {} accounts", n_refreshed);
+        let refresh_interval = estimator.read().unwrap().refresh_interval;
+        thread::sleep(refresh_interval);
+    })
+}
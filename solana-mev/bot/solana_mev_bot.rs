@@ -1,3 +1,5 @@
+use crate::bot::priority_fee_estimator::{spawn_refresher, PriorityFeeEstimator};
+use crate::config::JitoConfig;
 use crate::dex::dex_integration::DexIntegration;
 use crate::models::market_conditions::MarketConditions;
 use crate::models::mev_opportunity::MevOpportunity;
@@ -7,8 +9,8 @@ use solana_client::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Keypair;
 use solana_sdk::transaction::Transaction;
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
 use tokio::sync::Mutex;
 
 pub struct SolanaMevBot {
@@ -40,9 +42,10 @@ impl SolanaMevBot {
         target_accounts: HashMap<Pubkey, AccountInfo>,
         profit_threshold: f64,
         dex_integrations: Vec<Box<dyn DexIntegration>>,
+        jito_config: JitoConfig,
     ) -> Self {
         let rpc_client = RpcClient::new(rpc_url.to_string());
-        let flashbots_client = Arc::new(Mutex::new(FlashbotsClient::new(rpc_client.clone())));
+        let flashbots_client = Arc::new(Mutex::new(FlashbotsClient::new(rpc_client.clone(), jito_config)));
         let simulation_engine = Arc::new(Mutex::new(SimulationEngine::new(rpc_client.clone())));
         let optimizer = Arc::new(Mutex::new(Optimizer::new(rpc_client.clone())));
         let risk_manager = Arc::new(Mutex::new(RiskManager::new(rpc_client.clone())));
@@ -51,7 +54,12 @@ impl SolanaMevBot {
         let monitoring_manager = Arc::new(Mutex::new(MonitoringManager::new(rpc_client.clone())));
         let copy_trade_manager = Arc::new(Mutex::new(CopyTradeManager::new(rpc_client.clone())));
         let sniping_manager = Arc::new(Mutex::new(SnipingManager::new(rpc_client.clone())));
-        let gas_optimizer = Arc::new(Mutex::new(GasOptimizer::new(rpc_client.clone())));
+        // one fee history, refreshed off the send path for every account the
+        // optimized transactions write
+        let fee_estimator = Arc::new(RwLock::new(PriorityFeeEstimator::default()));
+        let fee_accounts = Arc::new(RwLock::new(BTreeSet::new()));
+        spawn_refresher(rpc_client.clone(), fee_estimator.clone(), fee_accounts.clone());
+        let gas_optimizer = Arc::new(Mutex::new(GasOptimizer::new(fee_estimator, fee_accounts)));
         let path_finder = Arc::new(Mutex::new(PathFinder::new(rpc_client.clone())));
         let trade_executor = Arc::new(Mutex::new(TradeExecutor::new(rpc_client.clone())));
         let cross_chain_manager = Arc::new(Mutex::new(CrossChainManager::new(rpc_client.clone())));
@@ -106,7 +114,7 @@ impl SolanaMevBot {
             all_opportunities.extend(cross_chain_opportunities);
 
             let profitable_txns = self.optimize_and_filter_txns(&all_opportunities).await;
-            let gas_optimized_txns = self.gas_optimizer.lock().await.optimize(&profitable_txns).await;
+            let gas_optimized_txns = self.gas_optimizer.lock().await.optimize(&profitable_txns, &self.payer_keypair).await;
 
             let executed_txns = self.trade_executor.lock().await.execute_transactions(&gas_optimized_txns).await;
             self.monitoring_manager.lock().await.log_and_monitor(&executed_txns, &market_conditions);
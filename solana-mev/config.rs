@@ -1,5 +1,6 @@
 use serde::Deserialize;
 use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
@@ -8,6 +9,8 @@ pub struct Config {
     pub dexes: DexesConfig,
     pub monitoring: MonitoringConfig,
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub jito: JitoConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -31,6 +34,23 @@ pub struct DexesConfig {
     pub orca_program_id: Pubkey,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct JitoConfig {
+    pub block_engine_url: String,
+    pub tip_account: Pubkey,
+    pub tip_lamports: u64,
+}
+
+impl Default for JitoConfig {
+    fn default() -> Self {
+        Self {
+            block_engine_url: "https://mainnet.block-engine.jito.wtf".to_string(),
+            tip_account: Pubkey::from_str("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5").unwrap(),
+            tip_lamports: 10_000,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct MonitoringConfig {
     pub dashboard_port: u16,
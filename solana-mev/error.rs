@@ -14,6 +14,9 @@ pub enum MevBotError {
     #[error("Decimal error: {0}")]
     DecimalError(#[from] rust_decimal::Error),
 
+    #[error("Bundle error: {0}")]
+    BundleError(String),
+
     #[error("Custom error: {0}")]
     Custom(String),
 }
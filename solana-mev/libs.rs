@@ -36,6 +36,8 @@
 //!
 
 pub mod bot;
+pub mod config;
+pub mod error;
 pub mod dex;
 pub mod strategies;
 pub mod models;
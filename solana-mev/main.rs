@@ -52,6 +52,7 @@ async fn main() {
         ],
         config.bot.profit_threshold,
         metrics,
+        config.jito.clone(),
     );
 
     tokio::spawn(async move {
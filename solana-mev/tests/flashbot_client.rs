@@ -0,0 +1,233 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use mev_bot_solana::bot::flashbot_client::{BundleOutcome, FlashbotsClient};
+use mev_bot_solana::config::JitoConfig;
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// a block engine which answers each request with the next canned `result`
+// and records the json-rpc requests it was sent
+struct MockBlockEngine {
+    url: String,
+    requests: Arc<Mutex<Vec<Value>>>,
+}
+
+impl MockBlockEngine {
+    async fn start(results: Vec<Value>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let results = Arc::new(Mutex::new(VecDeque::from(results)));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(serve(stream, recorded.clone(), results.clone()));
+            }
+        });
+        Self { url, requests }
+    }
+
+    fn requests(&self) -> Vec<Value> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn serve(
+    mut stream: TcpStream,
+    requests: Arc<Mutex<Vec<Value>>>,
+    results: Arc<Mutex<VecDeque<Value>>>,
+) {
+    let mut buf = Vec::new();
+    loop {
+        // read the headers, then a content-length body
+        let header_end = loop {
+            if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break i + 4;
+            }
+            let mut chunk = [0u8; 4096];
+            let n = stream.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                return;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        };
+        let headers = String::from_utf8_lossy(&buf[..header_end]).to_lowercase();
+        assert!(headers.starts_with("post /api/v1/bundles "), "{}", headers);
+        let content_length: usize = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map(|len| len.trim().parse().unwrap())
+            .unwrap();
+        while buf.len() < header_end + content_length {
+            let mut chunk = [0u8; 4096];
+            let n = stream.read(&mut chunk).await.unwrap();
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        let request: Value = serde_json::from_slice(&buf[header_end..header_end + content_length]).unwrap();
+        buf.drain(..header_end + content_length);
+
+        let result = results.lock().unwrap().pop_front().unwrap();
+        let body = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }).to_string();
+        requests.lock().unwrap().push(request);
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    }
+}
+
+fn client(block_engine_url: &str, tip_account: Pubkey) -> FlashbotsClient {
+    FlashbotsClient::new(
+        RpcClient::new("http://127.0.0.1:1".to_string()),
+        JitoConfig {
+            block_engine_url: block_engine_url.to_string(),
+            tip_account,
+            tip_lamports: 1_000,
+        },
+    )
+}
+
+fn transfer_tx(payer: &Keypair) -> Transaction {
+    let ix = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+    Transaction::new_signed_with_payer(&[ix], Some(&payer.pubkey()), &[payer], Hash::default())
+}
+
+fn inflight_status(status: &str, landed_slot: Option<u64>) -> Value {
+    json!({
+        "context": { "slot": 280999028 },
+        "value": [{
+            "bundle_id": "892b79ed49138bfb3aa5441f0df6e06ef34f9ee8f3976c15b323605bae0cf51d",
+            "status": status,
+            "landed_slot": landed_slot,
+        }],
+    })
+}
+
+fn bundle_status(confirmation_status: &str, err: Value) -> Value {
+    json!({
+        "context": { "slot": 280999030 },
+        "value": [{
+            "bundle_id": "892b79ed49138bfb3aa5441f0df6e06ef34f9ee8f3976c15b323605bae0cf51d",
+            "transactions": [
+                "3bC2M9fiACSjkTXZDgeNAuQ4ScTsdKGwR42ytFdhUvikqTmBheUxfsR1fDVsM5ADCMMspuwGkdm1uKbU246x5aE3",
+                "8t9hKYEYNbLvNqiSzP96S13XF1C2f1ro271Kdf7bkZ6EpjPLuDff1ywRy4gfaGSTubsM2FeYGDoT64ZwPm1cQUt",
+            ],
+            "slot": 280999020,
+            "confirmation_status": confirmation_status,
+            "err": err,
+        }],
+    })
+}
+
+#[tokio::test]
+async fn test_bundle_wire_format() {
+    let tip_account = Pubkey::new_unique();
+    let bundle_id = "892b79ed49138bfb3aa5441f0df6e06ef34f9ee8f3976c15b323605bae0cf51d";
+    let block_engine = MockBlockEngine::start(vec![
+        json!(bundle_id),
+        inflight_status("Pending", None),
+        inflight_status("Landed", Some(280999020)),
+        bundle_status("confirmed", json!({ "Ok": null })),
+    ])
+    .await;
+    let client = client(&block_engine.url, tip_account);
+
+    let payer = Keypair::new();
+    let txs = vec![transfer_tx(&payer), transfer_tx(&payer)];
+    let bundle = client.build_bundle(&txs, &payer, Hash::default()).unwrap();
+    assert_eq!(bundle.len(), 3);
+    let tip_tx = &bundle[2];
+    let tip_ix = &tip_tx.message.instructions[0];
+    assert_eq!(tip_tx.message.account_keys[tip_ix.accounts[1] as usize], tip_account);
+    assert!(tip_tx.verify().is_ok());
+
+    let sent_id = client.send_bundle(&bundle).await.unwrap();
+    assert_eq!(sent_id, bundle_id);
+    let outcome = client
+        .wait_for_bundle(&sent_id, Duration::from_secs(5), Duration::from_millis(10))
+        .await
+        .unwrap();
+    assert_eq!(outcome, BundleOutcome::Landed { slot: 280999020 });
+
+    let requests = block_engine.requests();
+    assert_eq!(requests.len(), 4);
+    assert_eq!(requests[0]["jsonrpc"], "2.0");
+    assert_eq!(requests[0]["method"], "sendBundle");
+    let encoded_txs = requests[0]["params"][0].as_array().unwrap();
+    assert_eq!(encoded_txs.len(), 3);
+    let tx: Transaction = bincode::deserialize(&base64::decode(encoded_txs[2].as_str().unwrap()).unwrap()).unwrap();
+    assert_eq!(&tx, tip_tx);
+    assert_eq!(requests[0]["params"][1], json!({ "encoding": "base64" }));
+    // the landing is confirmed with getBundleStatuses
+    let methods: Vec<&Value> = requests[1..].iter().map(|request| &request["method"]).collect();
+    assert_eq!(
+        methods,
+        ["getInflightBundleStatuses", "getInflightBundleStatuses", "getBundleStatuses"]
+    );
+    for request in &requests[1..] {
+        assert_eq!(request["jsonrpc"], "2.0");
+        assert_eq!(request["params"], json!([[bundle_id]]));
+    }
+}
+
+#[tokio::test]
+async fn test_bundle_outcomes() {
+    let block_engine = MockBlockEngine::start(vec![
+        inflight_status("Pending", None),
+        inflight_status("Failed", None),
+        inflight_status("Invalid", None),
+        inflight_status("Pending", None),
+        json!({ "context": { "slot": 280999029 }, "value": [] }),
+        // landed, but only processed -- then landed with a failed transaction
+        inflight_status("Landed", Some(280999020)),
+        bundle_status("processed", json!({ "Ok": null })),
+        inflight_status("Landed", Some(280999020)),
+        bundle_status("finalized", json!({ "Err": { "InstructionError": [0, { "Custom": 1 }] } })),
+    ])
+    .await;
+    let client = client(&block_engine.url, Pubkey::new_unique());
+
+    let outcome = client
+        .wait_for_bundle("failed", Duration::from_secs(5), Duration::from_millis(10))
+        .await
+        .unwrap();
+    assert!(matches!(outcome, BundleOutcome::Failed { .. }));
+
+    // unknown to the block engine -- no point waiting for it
+    let outcome = client
+        .wait_for_bundle("invalid", Duration::from_secs(5), Duration::from_millis(10))
+        .await
+        .unwrap();
+    assert_eq!(outcome, BundleOutcome::Dropped);
+
+    let outcome = client
+        .wait_for_bundle("pending", Duration::from_millis(5), Duration::from_millis(10))
+        .await
+        .unwrap();
+    assert_eq!(outcome, BundleOutcome::Dropped);
+
+    let outcome = client
+        .wait_for_bundle("landed", Duration::from_secs(5), Duration::from_millis(10))
+        .await
+        .unwrap();
+    assert!(matches!(outcome, BundleOutcome::Failed { .. }));
+    assert_eq!(block_engine.requests().len(), 9);
+
+    let payer = Keypair::new();
+    let txs: Vec<Transaction> = (0..5).map(|_| transfer_tx(&payer)).collect();
+    assert!(client.build_bundle(&txs, &payer, Hash::default()).is_err());
+    assert!(client.build_bundle(&[], &payer, Hash::default()).is_err());
+}
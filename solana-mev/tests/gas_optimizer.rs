@@ -0,0 +1,69 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
+
+use mev_bot_solana::bot::gas_optimizer::{set_compute_unit_price_ix, GasOptimizer};
+use mev_bot_solana::bot::priority_fee_estimator::{
+    PriorityFeeEstimator, RecentPrioritizationFee, Urgency,
+};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+
+fn swap_tx(payer: &Keypair, pool: Pubkey, recent_blockhash: Hash) -> Transaction {
+    let swap_ix = Instruction {
+        program_id: Pubkey::new_unique(),
+        accounts: vec![
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new(pool, false),
+        ],
+        data: vec![1, 2, 3],
+    };
+    Transaction::new_signed_with_payer(&[swap_ix], Some(&payer.pubkey()), &[payer], recent_blockhash)
+}
+
+#[tokio::test]
+async fn test_priced_transactions_are_re_signed() {
+    let payer = Keypair::new();
+    let [hot_pool, cold_pool] = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let mut estimator = PriorityFeeEstimator::default();
+    let fees = [(1, 5_000), (2, 7_000), (3, 9_000)].map(|(slot, prioritization_fee)| {
+        RecentPrioritizationFee {
+            slot,
+            prioritization_fee,
+        }
+    });
+    estimator.record(hot_pool, &fees);
+    let micro_lamports = estimator.price_for(&[hot_pool], Urgency::Medium);
+    assert!(micro_lamports > 0);
+
+    let watched = Arc::new(RwLock::new(BTreeSet::new()));
+    let optimizer = GasOptimizer::new(Arc::new(RwLock::new(estimator)), watched.clone());
+    let recent_blockhash = Hash::new_unique();
+    let txs = vec![
+        swap_tx(&payer, hot_pool, recent_blockhash),
+        swap_tx(&payer, cold_pool, recent_blockhash),
+    ];
+    let optimized = optimizer.optimize(&txs, &payer).await;
+
+    // the priced tx leads with the compute unit price + is signed by the payer
+    let priced = &optimized[0];
+    assert!(priced.verify().is_ok());
+    assert_eq!(priced.message.recent_blockhash, recent_blockhash);
+    assert_eq!(priced.message.account_keys[0], payer.pubkey());
+    let price_ix = &priced.message.instructions[0];
+    let expected = set_compute_unit_price_ix(micro_lamports);
+    assert_eq!(
+        priced.message.account_keys[price_ix.program_id_index as usize],
+        expected.program_id
+    );
+    assert_eq!(price_ix.data, expected.data);
+    assert_eq!(priced.message.instructions.len(), 2);
+
+    // no fees known for the cold pool yet: sent as it was, + watched so the
+    // refresher fetches them
+    assert_eq!(optimized[1], txs[1]);
+    assert!(optimized[1].verify().is_ok());
+    assert_eq!(*watched.read().unwrap(), [hot_pool, cold_pool].into_iter().collect());
+}
@@ -49,6 +49,7 @@ async fn test_mev_bot_integration() {
         ],
         config.bot.profit_threshold,
         metrics,
+        config.jito.clone(),
     );
 
     tokio::spawn(async move {
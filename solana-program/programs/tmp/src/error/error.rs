@@ -12,4 +12,6 @@ pub enum ErrorCode {
     RaydiumSwapFailed,
     #[msg("Invalid Raydium pool state")]
     InvalidRaydiumPool,
+    #[msg("Jupiter route isn't a shared accounts route for this swap's accounts")]
+    InvalidJupiterRoute,
 }
\ No newline at end of file
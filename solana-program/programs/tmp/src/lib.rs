@@ -11,6 +11,8 @@ pub mod error;
 use swaps::orca::*;
 use swaps::raydium::*;
 use swaps::meteora::*;
+use swaps::phoenix::*;
+use swaps::lifinity::*;
 use swaps::jupiter::*;
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
@@ -32,8 +34,9 @@ pub mod tmp {
         ctx: Context<OrcaSwap>,
         amount_in: u64,
         minimum_amount_out: u64,
+        a_to_b: bool,
     ) -> Result<()> {
-        ctx.accounts.process_swap(amount_in, minimum_amount_out)
+        ctx.accounts.process_swap(amount_in, minimum_amount_out, a_to_b)
     }
 
     pub fn raydium_swap(
@@ -44,21 +47,40 @@ pub mod tmp {
         ctx.accounts.process_swap(amount_in, minimum_amount_out)
     }
 
-    pub fn meteora_swap(
-        ctx: Context<MeteoraSwap>,
+    pub fn meteora_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, MeteoraSwap<'info>>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Result<()> {
+        // remaining accounts == the bin arrays to swap through
+        ctx.accounts.process_swap(amount_in, minimum_amount_out, ctx.remaining_accounts)
+    }
+
+    pub fn phoenix_swap(
+        ctx: Context<PhoenixSwap>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        is_bid: bool,
+    ) -> Result<()> {
+        ctx.accounts.process_swap(amount_in, minimum_amount_out, is_bid)
+    }
+
+    pub fn lifinity_swap(
+        ctx: Context<LifinitySwap>,
         amount_in: u64,
         minimum_amount_out: u64,
     ) -> Result<()> {
         ctx.accounts.process_swap(amount_in, minimum_amount_out)
     }
 
-    pub fn jupiter_swap(
-        ctx: Context<JupiterSwap>,
+    pub fn jupiter_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, JupiterSwap<'info>>,
         amount_in: u64,
         minimum_amount_out: u64,
         route_data: Vec<u8>,
     ) -> Result<()> {
-        ctx.accounts.process_swap(amount_in, minimum_amount_out, route_data)
+        // remaining accounts == the route instruction's accounts, in order
+        ctx.accounts.process_swap(amount_in, minimum_amount_out, route_data, ctx.remaining_accounts)
     }
 }
 
@@ -1,23 +1,34 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{instruction::Instruction, program::invoke};
-use anchor_spl::token::{Token, TokenAccount};
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use crate::error::ErrorCode;
+use crate::swaps::swap_amount;
 
-// Jupiter program ID
-pub const JUPITER_PROGRAM_ID: &str = "JUP4Fb2cqiRUcaTHdrPC8h2gNsA2ETXiPDD33WcGuJB";
+// Jupiter v6 program ID
+pub const JUPITER_PROGRAM_ID: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+// sha256("global:shared_accounts_route")[..8]
+pub const SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR: [u8; 8] = [193, 32, 155, 51, 65, 214, 156, 129];
+// in_amount u64 + quoted_out_amount u64 + slippage_bps u16 + platform_fee_bps u8
+const ROUTE_ARGS_TAIL_LEN: usize = 19;
 
+// where the user's accounts sit in the shared accounts route's account list
+const USER_TRANSFER_AUTHORITY_INDEX: usize = 2;
+const SOURCE_TOKEN_ACCOUNT_INDEX: usize = 3;
+const DESTINATION_TOKEN_ACCOUNT_INDEX: usize = 6;
+
+/// Jupiter shared accounts route -- `route_data` is the route instruction's
+/// data and the remaining accounts are its full ordered account list
 #[derive(Accounts)]
 pub struct JupiterSwap<'info> {
     /// CHECK: Validated by Jupiter program
     #[account(address = JUPITER_PROGRAM_ID.parse::<Pubkey>().unwrap())]
     pub jupiter_program: AccountInfo<'info>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub user_authority: Signer<'info>,
     #[account(mut)]
-    pub user_source_token: Account<'info, TokenAccount>,
+    pub user_source_token: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub user_destination_token: Account<'info, TokenAccount>,
-    /// CHECK: Remaining accounts will be populated based on the route
-    pub remaining_accounts: UncheckedAccount<'info>,
+    pub user_destination_token: InterfaceAccount<'info, TokenAccount>,
 }
 
 impl<'info> JupiterSwap<'info> {
@@ -26,43 +37,52 @@ impl<'info> JupiterSwap<'info> {
         amount_in: u64,
         minimum_amount_out: u64,
         route_data: Vec<u8>,
+        route_accounts: &[AccountInfo<'info>],
     ) -> Result<()> {
-        // Create route data with amount_in and minimum_amount_out
-        let mut final_route_data = Vec::with_capacity(route_data.len() + 16);
-        final_route_data.extend_from_slice(&amount_in.to_le_bytes());
-        final_route_data.extend_from_slice(&minimum_amount_out.to_le_bytes());
-        final_route_data.extend_from_slice(&route_data);
+        // the route has to be for our signer + token accounts
+        let is_ours = |index: usize, key: Pubkey| {
+            route_accounts.get(index).map(|a| a.key()) == Some(key)
+        };
+        if route_data.len() < SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR.len() + ROUTE_ARGS_TAIL_LEN
+            || route_data[..8] != SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR
+            || !is_ours(USER_TRANSFER_AUTHORITY_INDEX, self.user_authority.key())
+            || !is_ours(SOURCE_TOKEN_ACCOUNT_INDEX, self.user_source_token.key())
+            || !is_ours(DESTINATION_TOKEN_ACCOUNT_INDEX, self.user_destination_token.key())
+        {
+            return err!(ErrorCode::InvalidJupiterRoute);
+        }
+
+        let amount_in = swap_amount(amount_in, self.user_source_token.amount);
+
+        // the quote's amounts are replaced with ours: the route's own
+        // slippage check then enforces `minimum_amount_out`
+        let mut data = route_data;
+        let tail = data.len() - ROUTE_ARGS_TAIL_LEN;
+        data[tail..tail + 8].copy_from_slice(&amount_in.to_le_bytes());
+        data[tail + 8..tail + 16].copy_from_slice(&minimum_amount_out.to_le_bytes());
+        data[tail + 16..tail + 18].copy_from_slice(&0u16.to_le_bytes()); // slippage_bps
 
-        // Get remaining accounts for the route
-        let accounts: Vec<AccountMeta> = vec![
-            AccountMeta::new_readonly(self.token_program.key(), false),
-            AccountMeta::new_readonly(self.user_authority.key(), true),
-            AccountMeta::new(self.user_source_token.key(), false),
-            AccountMeta::new(self.user_destination_token.key(), false),
-        ];
+        let accounts = route_accounts
+            .iter()
+            .map(|a| {
+                if a.is_writable {
+                    AccountMeta::new(a.key(), a.is_signer)
+                } else {
+                    AccountMeta::new_readonly(a.key(), a.is_signer)
+                }
+            })
+            .collect();
 
-        // Create the instruction
         let ix = Instruction {
             program_id: self.jupiter_program.key(),
             accounts,
-            data: final_route_data,
+            data,
         };
 
-        // Collect account infos
-        let account_infos = vec![
-            self.jupiter_program.to_account_info(),
-            self.token_program.to_account_info(),
-            self.user_authority.to_account_info(),
-            self.user_source_token.to_account_info(),
-            self.user_destination_token.to_account_info(),
-            self.remaining_accounts.to_account_info(),
-        ];
+        let mut account_infos = vec![self.jupiter_program.to_account_info()];
+        account_infos.extend(route_accounts.iter().cloned());
 
-        // Invoke the Jupiter swap instruction
-        invoke(
-            &ix,
-            &account_infos,
-        )?;
+        invoke(&ix, &account_infos)?;
 
         Ok(())
     }
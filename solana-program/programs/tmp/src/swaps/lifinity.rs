@@ -0,0 +1,99 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use crate::swaps::swap_amount;
+
+// Lifinity v2 program ID
+pub const LIFINITY_PROGRAM_ID: &str = "2wT8Yq49kHgDzXuPxZSaeLaH1qbmGXtEyPy64bL7aD3c";
+// sha256("global:swap")[..8]
+pub const LIFINITY_SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+#[derive(Accounts)]
+pub struct LifinitySwap<'info> {
+    /// CHECK: The amm's authority PDA, validated by the Lifinity program
+    pub authority: AccountInfo<'info>,
+    /// CHECK: Validated by the Lifinity program
+    #[account(mut)]
+    pub amm: AccountInfo<'info>,
+    pub user_transfer_authority: Signer<'info>,
+    #[account(mut)]
+    pub source_info: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub destination_info: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub swap_source: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub swap_destination: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: Validated by the Lifinity program
+    #[account(mut)]
+    pub pool_mint: AccountInfo<'info>,
+    /// CHECK: Validated by the Lifinity program
+    #[account(mut)]
+    pub fee_account: AccountInfo<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Pyth price account, validated by the Lifinity program
+    pub oracle_main_account: AccountInfo<'info>,
+    /// CHECK: Validated by the Lifinity program
+    pub oracle_sub_account: AccountInfo<'info>,
+    /// CHECK: Validated by the Lifinity program
+    pub oracle_pc_account: AccountInfo<'info>,
+    /// CHECK: Lifinity program
+    #[account(address = LIFINITY_PROGRAM_ID.parse::<Pubkey>().unwrap())]
+    pub lifinity_program: AccountInfo<'info>,
+}
+
+impl<'info> LifinitySwap<'info> {
+    pub fn process_swap(&self, amount_in: u64, minimum_amount_out: u64) -> Result<()> {
+        let amount_in = swap_amount(amount_in, self.source_info.amount);
+
+        let mut data = Vec::with_capacity(24);
+        data.extend_from_slice(&LIFINITY_SWAP_DISCRIMINATOR);
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&minimum_amount_out.to_le_bytes());
+
+        // in the order the lifinity swap instruction expects
+        let accounts = vec![
+            AccountMeta::new_readonly(self.authority.key(), false),
+            AccountMeta::new(self.amm.key(), false),
+            AccountMeta::new_readonly(self.user_transfer_authority.key(), true),
+            AccountMeta::new(self.source_info.key(), false),
+            AccountMeta::new(self.destination_info.key(), false),
+            AccountMeta::new(self.swap_source.key(), false),
+            AccountMeta::new(self.swap_destination.key(), false),
+            AccountMeta::new(self.pool_mint.key(), false),
+            AccountMeta::new(self.fee_account.key(), false),
+            AccountMeta::new_readonly(self.token_program.key(), false),
+            AccountMeta::new_readonly(self.oracle_main_account.key(), false),
+            AccountMeta::new_readonly(self.oracle_sub_account.key(), false),
+            AccountMeta::new_readonly(self.oracle_pc_account.key(), false),
+        ];
+
+        let instruction = Instruction {
+            program_id: self.lifinity_program.key(),
+            accounts,
+            data,
+        };
+
+        anchor_lang::solana_program::program::invoke(
+            &instruction,
+            &[
+                self.lifinity_program.to_account_info(),
+                self.authority.to_account_info(),
+                self.amm.to_account_info(),
+                self.user_transfer_authority.to_account_info(),
+                self.source_info.to_account_info(),
+                self.destination_info.to_account_info(),
+                self.swap_source.to_account_info(),
+                self.swap_destination.to_account_info(),
+                self.pool_mint.to_account_info(),
+                self.fee_account.to_account_info(),
+                self.token_program.to_account_info(),
+                self.oracle_main_account.to_account_info(),
+                self.oracle_sub_account.to_account_info(),
+                self.oracle_pc_account.to_account_info(),
+            ],
+        )?;
+
+        Ok(())
+    }
+}
@@ -1,54 +1,46 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount};
-use dynamic_amm::{self, cpi::accounts::Swap as MeteoraSwapAccounts, program::DynamicAmm};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use crate::swaps::swap_amount;
 
-// Meteora program ID
+// Meteora DLMM program ID
 pub const METEORA_PROGRAM_ID: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+// sha256("global:swap")[..8]
+pub const DLMM_SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
 
+/// DLMM swap -- the bin arrays the swap walks through are passed as
+/// remaining accounts, starting at the active bin's array
 #[derive(Accounts)]
 pub struct MeteoraSwap<'info> {
-    /// CHECK: Validated by Meteora program
+    /// CHECK: Validated by the DLMM program
     #[account(mut)]
-    pub pool: AccountInfo<'info>,
-    
-    /// CHECK: Validated by Meteora program
+    pub lb_pair: AccountInfo<'info>,
+    /// CHECK: Validated by the DLMM program (the DLMM program ID when the pair has none)
+    pub bin_array_bitmap_extension: AccountInfo<'info>,
     #[account(mut)]
-    pub a_vault: AccountInfo<'info>,
-    /// CHECK: Validated by Meteora program
+    pub reserve_x: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub b_vault: AccountInfo<'info>,
-    
+    pub reserve_y: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub a_token_vault: Account<'info, TokenAccount>,
+    pub user_token_in: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub b_token_vault: Account<'info, TokenAccount>,
-    
-    /// CHECK: Validated by Meteora program
+    pub user_token_out: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: Validated by the DLMM program
+    pub token_x_mint: AccountInfo<'info>,
+    /// CHECK: Validated by the DLMM program
+    pub token_y_mint: AccountInfo<'info>,
+    /// CHECK: Validated by the DLMM program
     #[account(mut)]
-    pub a_vault_lp_mint: AccountInfo<'info>,
-    /// CHECK: Validated by Meteora program
-    #[account(mut)]
-    pub b_vault_lp_mint: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub a_vault_lp: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub b_vault_lp: Account<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub admin_token_fee: Account<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub user_source_token: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_destination_token: Account<'info, TokenAccount>,
-    
+    pub oracle: AccountInfo<'info>,
     pub user: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-    /// CHECK: Validated by Meteora program
-    pub vault_program: AccountInfo<'info>,
-    
-    pub meteora_program: Program<'info, DynamicAmm>,
+    // one per side -- either can be Token-2022
+    pub token_x_program: Interface<'info, TokenInterface>,
+    pub token_y_program: Interface<'info, TokenInterface>,
+    /// CHECK: Validated by the DLMM program
+    pub event_authority: AccountInfo<'info>,
+    /// CHECK: Meteora DLMM program
+    #[account(address = METEORA_PROGRAM_ID.parse::<Pubkey>().unwrap())]
+    pub dlmm_program: AccountInfo<'info>,
 }
 
 impl<'info> MeteoraSwap<'info> {
@@ -56,36 +48,62 @@ impl<'info> MeteoraSwap<'info> {
         &self,
         amount_in: u64,
         minimum_amount_out: u64,
+        bin_arrays: &[AccountInfo<'info>],
     ) -> Result<()> {
-        let cpi_accounts = MeteoraSwapAccounts {
-            pool: self.pool.to_account_info(),
-            a_vault: self.a_vault.to_account_info(),
-            b_vault: self.b_vault.to_account_info(),
-            a_token_vault: self.a_token_vault.to_account_info(),
-            b_token_vault: self.b_token_vault.to_account_info(),
-            a_vault_lp_mint: self.a_vault_lp_mint.to_account_info(),
-            b_vault_lp_mint: self.b_vault_lp_mint.to_account_info(),
-            a_vault_lp: self.a_vault_lp.to_account_info(),
-            b_vault_lp: self.b_vault_lp.to_account_info(),
-            admin_token_fee: self.admin_token_fee.to_account_info(),
-            user_source_token: self.user_source_token.to_account_info(),
-            user_destination_token: self.user_destination_token.to_account_info(),
-            user: self.user.to_account_info(),
-            token_program: self.token_program.to_account_info(),
-            vault_program: self.vault_program.to_account_info(),
+        let amount_in = swap_amount(amount_in, self.user_token_in.amount);
+
+        let mut data = Vec::with_capacity(24);
+        data.extend_from_slice(&DLMM_SWAP_DISCRIMINATOR);
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&minimum_amount_out.to_le_bytes());
+
+        // in the order the DLMM swap instruction expects, optional accounts
+        // which aren't used are passed as the DLMM program ID
+        let mut accounts = vec![
+            AccountMeta::new(self.lb_pair.key(), false),
+            AccountMeta::new_readonly(self.bin_array_bitmap_extension.key(), false),
+            AccountMeta::new(self.reserve_x.key(), false),
+            AccountMeta::new(self.reserve_y.key(), false),
+            AccountMeta::new(self.user_token_in.key(), false),
+            AccountMeta::new(self.user_token_out.key(), false),
+            AccountMeta::new_readonly(self.token_x_mint.key(), false),
+            AccountMeta::new_readonly(self.token_y_mint.key(), false),
+            AccountMeta::new(self.oracle.key(), false),
+            AccountMeta::new_readonly(self.dlmm_program.key(), false), // host_fee_in
+            AccountMeta::new_readonly(self.user.key(), true),
+            AccountMeta::new_readonly(self.token_x_program.key(), false),
+            AccountMeta::new_readonly(self.token_y_program.key(), false),
+            AccountMeta::new_readonly(self.event_authority.key(), false),
+            AccountMeta::new_readonly(self.dlmm_program.key(), false),
+        ];
+        accounts.extend(bin_arrays.iter().map(|a| AccountMeta::new(a.key(), false)));
+
+        let instruction = Instruction {
+            program_id: self.dlmm_program.key(),
+            accounts,
+            data,
         };
 
-        let cpi_ctx = CpiContext::new(
-            self.meteora_program.to_account_info(),
-            cpi_accounts,
-        );
+        let mut account_infos = vec![
+            self.dlmm_program.to_account_info(),
+            self.lb_pair.to_account_info(),
+            self.bin_array_bitmap_extension.to_account_info(),
+            self.reserve_x.to_account_info(),
+            self.reserve_y.to_account_info(),
+            self.user_token_in.to_account_info(),
+            self.user_token_out.to_account_info(),
+            self.token_x_mint.to_account_info(),
+            self.token_y_mint.to_account_info(),
+            self.oracle.to_account_info(),
+            self.user.to_account_info(),
+            self.token_x_program.to_account_info(),
+            self.token_y_program.to_account_info(),
+            self.event_authority.to_account_info(),
+        ];
+        account_infos.extend(bin_arrays.iter().cloned());
 
-        dynamic_amm::cpi::swap(
-            cpi_ctx,
-            amount_in,
-            minimum_amount_out,
-        )?;
+        anchor_lang::solana_program::program::invoke(&instruction, &account_infos)?;
 
         Ok(())
     }
-}
\ No newline at end of file
+}
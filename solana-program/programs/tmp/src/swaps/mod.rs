@@ -1,11 +1,26 @@
 // File: program/src/swaps/mod.rs
 
 pub mod jupiter;
+pub mod lifinity;
 pub mod meteora;
 pub mod orca;
+pub mod phoenix;
 pub mod raydium;
 
 pub use jupiter::*;
+pub use lifinity::*;
 pub use meteora::*;
 pub use orca::*;
-pub use raydium::*;
\ No newline at end of file
+pub use phoenix::*;
+pub use raydium::*;
+
+/// The amount a swap sells: `amount_in`, or the whole balance of the source
+/// account when it's 0. A route's later hops are sent with 0, so each hop
+/// swaps exactly what the previous one delivered.
+pub fn swap_amount(amount_in: u64, source_balance: u64) -> u64 {
+    if amount_in == 0 {
+        source_balance
+    } else {
+        amount_in
+    }
+}
@@ -1,25 +1,40 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
-use anchor_spl::token::{Token, TokenAccount};
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use crate::swaps::swap_amount;
 
 // Whirlpool program ID
 pub const WHIRLPOOL_PROGRAM_ID: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+// SPL memo program (v2) -- swap_v2 takes it for transfers which require a memo
+pub const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+// sha256("global:swap_v2")[..8]
+pub const WHIRLPOOL_SWAP_V2_DISCRIMINATOR: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
 
+/// Whirlpool swap_v2 -- each side has its own token program, so a pool
+/// pairing a Token-2022 mint with a classic one can be swapped
 #[derive(Accounts)]
 pub struct OrcaSwap<'info> {
-    pub token_program: Program<'info, Token>,
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    /// CHECK: SPL memo program
+    #[account(address = MEMO_PROGRAM_ID.parse::<Pubkey>().unwrap())]
+    pub memo_program: AccountInfo<'info>,
     pub token_authority: Signer<'info>,
     /// CHECK: This is the Orca Whirlpool state account that's validated by the Whirlpool program
     #[account(mut)]
     pub whirlpool: AccountInfo<'info>,
+    /// CHECK: Validated by the Whirlpool program
+    pub token_mint_a: AccountInfo<'info>,
+    /// CHECK: Validated by the Whirlpool program
+    pub token_mint_b: AccountInfo<'info>,
     #[account(mut)]
-    pub token_owner_account_a: Account<'info, TokenAccount>,
+    pub token_owner_account_a: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub token_vault_a: Account<'info, TokenAccount>,
+    pub token_vault_a: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub token_owner_account_b: Account<'info, TokenAccount>,
+    pub token_owner_account_b: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub token_vault_b: Account<'info, TokenAccount>,
+    pub token_vault_b: InterfaceAccount<'info, TokenAccount>,
     /// CHECK: This is the tick array account that's validated by the Whirlpool program
     #[account(mut)]
     pub tick_array_0: AccountInfo<'info>,
@@ -42,22 +57,36 @@ impl<'info> OrcaSwap<'info> {
         &self,
         amount_in: u64,
         minimum_amount_out: u64,
+        a_to_b: bool,
     ) -> Result<()> {
+        let source_balance = if a_to_b {
+            self.token_owner_account_a.amount
+        } else {
+            self.token_owner_account_b.amount
+        };
+        let amount_in = swap_amount(amount_in, source_balance);
+
         let ix_data = {
-            let mut data = Vec::with_capacity(32);
-            data.extend_from_slice(&[2]); // Swap instruction discriminator
+            let mut data = Vec::with_capacity(43);
+            data.extend_from_slice(&WHIRLPOOL_SWAP_V2_DISCRIMINATOR);
             data.extend_from_slice(&amount_in.to_le_bytes());
-            data.extend_from_slice(&minimum_amount_out.to_le_bytes());
-            data.extend_from_slice(&[0]); // sqrt_price_limit (0 means no limit)
-            data.extend_from_slice(&[1]); // amount_specified_is_input
-            data.extend_from_slice(&[1]); // a_to_b
+            data.extend_from_slice(&minimum_amount_out.to_le_bytes()); // other_amount_threshold
+            data.extend_from_slice(&0u128.to_le_bytes()); // sqrt_price_limit (0 == no limit)
+            data.push(1); // amount_specified_is_input
+            data.push(a_to_b as u8);
+            data.push(0); // remaining_accounts_info: None (no transfer hooks)
             data
         };
 
+        // in the order the whirlpool swap_v2 instruction expects
         let accounts = vec![
-            AccountMeta::new(self.whirlpool.key(), false),
-            AccountMeta::new_readonly(self.token_program.key(), false),
+            AccountMeta::new_readonly(self.token_program_a.key(), false),
+            AccountMeta::new_readonly(self.token_program_b.key(), false),
+            AccountMeta::new_readonly(self.memo_program.key(), false),
             AccountMeta::new_readonly(self.token_authority.key(), true),
+            AccountMeta::new(self.whirlpool.key(), false),
+            AccountMeta::new_readonly(self.token_mint_a.key(), false),
+            AccountMeta::new_readonly(self.token_mint_b.key(), false),
             AccountMeta::new(self.token_owner_account_a.key(), false),
             AccountMeta::new(self.token_vault_a.key(), false),
             AccountMeta::new(self.token_owner_account_b.key(), false),
@@ -77,9 +106,14 @@ impl<'info> OrcaSwap<'info> {
         anchor_lang::solana_program::program::invoke(
             &instruction,
             &[
-                self.whirlpool.to_account_info(),
-                self.token_program.to_account_info(),
+                self.whirlpool_program.to_account_info(),
+                self.token_program_a.to_account_info(),
+                self.token_program_b.to_account_info(),
+                self.memo_program.to_account_info(),
                 self.token_authority.to_account_info(),
+                self.whirlpool.to_account_info(),
+                self.token_mint_a.to_account_info(),
+                self.token_mint_b.to_account_info(),
                 self.token_owner_account_a.to_account_info(),
                 self.token_vault_a.to_account_info(),
                 self.token_owner_account_b.to_account_info(),
@@ -0,0 +1,137 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use crate::swaps::swap_amount;
+
+// Phoenix program ID
+pub const PHOENIX_PROGRAM_ID: &str = "PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY";
+// `PhoenixInstruction::Swap`
+pub const PHOENIX_SWAP_TAG: u8 = 0;
+// `OrderPacket::ImmediateOrCancel`
+const IMMEDIATE_OR_CANCEL: u8 = 2;
+// `SelfTradeBehavior::CancelProvide`
+const CANCEL_PROVIDE: u8 = 1;
+
+// lot sizes in the market header
+const BASE_LOT_SIZE_OFFSET: usize = 112;
+const QUOTE_LOT_SIZE_OFFSET: usize = 192;
+
+/// Phoenix swap -- an immediate-or-cancel order against the book,
+/// bids buy base with quote and asks sell base for quote
+#[derive(Accounts)]
+pub struct PhoenixSwap<'info> {
+    /// CHECK: Phoenix program
+    #[account(address = PHOENIX_PROGRAM_ID.parse::<Pubkey>().unwrap())]
+    pub phoenix_program: AccountInfo<'info>,
+    /// CHECK: Validated by the Phoenix program
+    pub log_authority: AccountInfo<'info>,
+    /// CHECK: Validated by the Phoenix program
+    #[account(mut)]
+    pub market: AccountInfo<'info>,
+    pub trader: Signer<'info>,
+    #[account(mut)]
+    pub base_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub quote_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+fn read_lot_size(data: &[u8], offset: usize) -> Result<u64> {
+    let bytes = data
+        .get(offset..offset + 8)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let lot_size = u64::from_le_bytes(bytes.try_into().unwrap());
+    if lot_size == 0 {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+    Ok(lot_size)
+}
+
+impl<'info> PhoenixSwap<'info> {
+    pub fn process_swap(
+        &self,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        is_bid: bool,
+    ) -> Result<()> {
+        let source_balance = if is_bid {
+            self.quote_account.amount
+        } else {
+            self.base_account.amount
+        };
+        let amount_in = swap_amount(amount_in, source_balance);
+
+        let (base_lot_size, quote_lot_size) = {
+            let data = self.market.try_borrow_data()?;
+            (
+                read_lot_size(&data, BASE_LOT_SIZE_OFFSET)?,
+                read_lot_size(&data, QUOTE_LOT_SIZE_OFFSET)?,
+            )
+        };
+        // bids spend quote lots for base lots, asks the other way around
+        let (num_base_lots, num_quote_lots, min_base_lots, min_quote_lots) = if is_bid {
+            (0, amount_in / quote_lot_size, minimum_amount_out / base_lot_size, 0)
+        } else {
+            (amount_in / base_lot_size, 0, 0, minimum_amount_out / quote_lot_size)
+        };
+
+        let ix_data = {
+            let mut data = Vec::with_capacity(64);
+            data.push(PHOENIX_SWAP_TAG);
+            data.push(IMMEDIATE_OR_CANCEL);
+            data.push(if is_bid { 0 } else { 1 }); // side
+            data.push(0); // price_in_ticks: None (market order)
+            data.extend_from_slice(&num_base_lots.to_le_bytes());
+            data.extend_from_slice(&num_quote_lots.to_le_bytes());
+            data.extend_from_slice(&min_base_lots.to_le_bytes()); // min_base_lots_to_fill
+            data.extend_from_slice(&min_quote_lots.to_le_bytes()); // min_quote_lots_to_fill
+            data.push(CANCEL_PROVIDE); // self_trade_behavior
+            data.push(0); // match_limit: None
+            data.extend_from_slice(&0u128.to_le_bytes()); // client_order_id
+            data.push(0); // use_only_deposited_funds
+            data.push(0); // last_valid_slot: None
+            data.push(0); // last_valid_unix_timestamp_in_seconds: None
+            data
+        };
+
+        // in the order the phoenix swap instruction expects
+        let accounts = vec![
+            AccountMeta::new_readonly(self.phoenix_program.key(), false),
+            AccountMeta::new_readonly(self.log_authority.key(), false),
+            AccountMeta::new(self.market.key(), false),
+            AccountMeta::new_readonly(self.trader.key(), true),
+            AccountMeta::new(self.base_account.key(), false),
+            AccountMeta::new(self.quote_account.key(), false),
+            AccountMeta::new(self.base_vault.key(), false),
+            AccountMeta::new(self.quote_vault.key(), false),
+            AccountMeta::new_readonly(self.token_program.key(), false),
+        ];
+
+        let instruction = Instruction {
+            program_id: self.phoenix_program.key(),
+            accounts,
+            data: ix_data,
+        };
+
+        anchor_lang::solana_program::program::invoke(
+            &instruction,
+            &[
+                self.phoenix_program.to_account_info(),
+                self.log_authority.to_account_info(),
+                self.market.to_account_info(),
+                self.trader.to_account_info(),
+                self.base_account.to_account_info(),
+                self.quote_account.to_account_info(),
+                self.base_vault.to_account_info(),
+                self.quote_vault.to_account_info(),
+                self.token_program.to_account_info(),
+            ],
+        )?;
+
+        Ok(())
+    }
+}
@@ -1,16 +1,21 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{instruction::Instruction, program::invoke};
-use anchor_spl::token::{Token, TokenAccount};
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
 
 use crate::error::ErrorCode;
 use crate::state::RaydiumSwapState;
+use crate::swaps::swap_amount;
 
 // Raydium AMM program ID
 pub const RAYDIUM_AMM_V4_PROGRAM_ID: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
 
 #[derive(Accounts)]
 pub struct RaydiumSwap<'info> {
+    /// CHECK: Raydium AMM v4 program
+    #[account(address = RAYDIUM_AMM_V4_PROGRAM_ID.parse::<Pubkey>().unwrap())]
+    pub amm_program: AccountInfo<'info>,
     /// CHECK: Validated by Raydium program
+    #[account(mut)]
     pub amm_id: AccountInfo<'info>,
     /// CHECK: Validated by Raydium program
     #[account(mut)]
@@ -20,6 +25,9 @@ pub struct RaydiumSwap<'info> {
     pub amm_open_orders: AccountInfo<'info>,
     /// CHECK: Validated by Raydium program
     #[account(mut)]
+    pub amm_target_orders: AccountInfo<'info>,
+    /// CHECK: Validated by Raydium program
+    #[account(mut)]
     pub pool_coin_token_account: AccountInfo<'info>,
     /// CHECK: Validated by Raydium program
     #[account(mut)]
@@ -47,11 +55,11 @@ pub struct RaydiumSwap<'info> {
     /// CHECK: Validated by Raydium program
     pub serum_vault_signer: AccountInfo<'info>,
     #[account(mut)]
-    pub user_source_token: Account<'info, TokenAccount>,
+    pub user_source_token: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub user_destination_token: Account<'info, TokenAccount>,
+    pub user_destination_token: InterfaceAccount<'info, TokenAccount>,
     pub user_authority: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     #[account(mut)]
     pub swap_state: Account<'info, RaydiumSwapState>,
 }
@@ -62,12 +70,17 @@ impl<'info> RaydiumSwap<'info> {
         amount_in: u64,
         minimum_amount_out: u64,
     ) -> Result<()> {
+        let amount_in = swap_amount(amount_in, self.user_source_token.amount);
+
+        // 18 accounts, in the order the AMM v4 swap instruction expects
         let ix = Instruction {
-            program_id: self.amm_id.key(),
+            program_id: self.amm_program.key(),
             accounts: vec![
+                AccountMeta::new_readonly(self.token_program.key(), false),
                 AccountMeta::new(self.amm_id.key(), false),
-                AccountMeta::new(self.amm_authority.key(), false),
+                AccountMeta::new_readonly(self.amm_authority.key(), false),
                 AccountMeta::new(self.amm_open_orders.key(), false),
+                AccountMeta::new(self.amm_target_orders.key(), false),
                 AccountMeta::new(self.pool_coin_token_account.key(), false),
                 AccountMeta::new(self.pool_pc_token_account.key(), false),
                 AccountMeta::new_readonly(self.serum_program_id.key(), false),
@@ -81,7 +94,6 @@ impl<'info> RaydiumSwap<'info> {
                 AccountMeta::new(self.user_source_token.key(), false),
                 AccountMeta::new(self.user_destination_token.key(), false),
                 AccountMeta::new_readonly(self.user_authority.key(), true),
-                AccountMeta::new_readonly(self.token_program.key(), false),
             ],
             data: self.build_swap_instruction_data(amount_in, minimum_amount_out),
         };
@@ -89,9 +101,12 @@ impl<'info> RaydiumSwap<'info> {
         invoke(
             &ix,
             &[
+                self.amm_program.to_account_info(),
+                self.token_program.to_account_info(),
                 self.amm_id.to_account_info(),
                 self.amm_authority.to_account_info(),
                 self.amm_open_orders.to_account_info(),
+                self.amm_target_orders.to_account_info(),
                 self.pool_coin_token_account.to_account_info(),
                 self.pool_pc_token_account.to_account_info(),
                 self.serum_program_id.to_account_info(),
@@ -105,7 +120,6 @@ impl<'info> RaydiumSwap<'info> {
                 self.user_source_token.to_account_info(),
                 self.user_destination_token.to_account_info(),
                 self.user_authority.to_account_info(),
-                self.token_program.to_account_info(),
             ],
         ).map_err(|_| ErrorCode::RaydiumSwapFailed)?;
 